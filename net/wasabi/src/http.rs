@@ -8,7 +8,8 @@ use noli::net::lookup_host;
 use noli::net::SocketAddr;
 use noli::net::TcpStream;
 use saba_core::error::Error;
-use saba_core::http::HttpResponse;
+use saba_core::http::{Fetcher, HttpResponse};
+use saba_core::url::Url;
 
 pub struct HttpClient {}
 
@@ -77,12 +78,22 @@ impl HttpClient {
             received.extend_from_slice(&buf[..bytes_read]);
         }
 
-        match core::str::from_utf8(&received) {
-            Ok(s) => HttpResponse::new(s.to_string()),
-            Err(e) => Err(Error::Network(format!(
-                "Failed to parse response: {:#?}",
-                e
-            ))),
+        // The body is decoded later, once its encoding is known.
+        HttpResponse::from_bytes(&received)
+    }
+}
+
+impl Fetcher for HttpClient {
+    fn fetch(&self, url: &Url) -> Result<HttpResponse, Error> {
+        let port = match url.port().parse::<u16>() {
+            Ok(port) => port,
+            Err(_) => return Err(Error::Network(format!("Invalid port: {}", url.port()))),
+        };
+        let mut path = url.path();
+        if !url.searchpart().is_empty() {
+            path.push('?');
+            path.push_str(&url.searchpart());
         }
+        self.get(url.host(), port, path)
     }
 }
@@ -5,5 +5,12 @@ pub enum Error {
     Network(String),
     UnexpectedInput(String),
     InvalidUI(String),
+    /// A DOM operation would make an invalid tree, like a node that's its
+    /// own ancestor.
+    /// https://webidl.spec.whatwg.org/#hierarchyrequesterror
+    HierarchyRequest(String),
+    /// A DOM operation was given a node that isn't where it should be.
+    /// https://webidl.spec.whatwg.org/#notfounderror
+    NotFound(String),
     Other(String),
 }
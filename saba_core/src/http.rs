@@ -1,4 +1,6 @@
 use crate::error::Error;
+use crate::renderer::html::encoding::sniff::sniff;
+use crate::url::Url;
 
 use alloc::{
     format,
@@ -6,50 +8,78 @@ use alloc::{
     vec::Vec,
 };
 
+/// Fetches resources that a page needs, like linked style sheets. The
+/// embedder provides it, since the network is platform-specific.
+pub trait Fetcher {
+    fn fetch(&self, url: &Url) -> Result<HttpResponse, Error>;
+}
+
 #[derive(Debug, Clone)]
 pub struct HttpResponse {
     version: String,
     status_code: u32,
     reason: String,
     headers: Vec<Header>,
-    body: String,
+    body: Vec<u8>,
 }
 
 impl HttpResponse {
     pub fn new(raw_response: String) -> Result<Self, Error> {
-        let preproccessed_response = raw_response.trim_start().replace("\n\r", "\n");
-        let (status_line, remaining) = match preproccessed_response.split_once("\n") {
-            Some((status_line, remaining)) => (status_line, remaining),
-            None => {
-                return Err(Error::Network(format!(
-                    "invalid http response: {}",
-                    preproccessed_response
-                )))
-            }
+        Self::from_bytes(raw_response.as_bytes())
+    }
+
+    /// Parses a response whose body can be in any encoding. Only the status
+    /// line and headers, which are expected to be ASCII, are decoded here.
+    pub fn from_bytes(raw_response: &[u8]) -> Result<Self, Error> {
+        let start = raw_response
+            .iter()
+            .position(|b| !b.is_ascii_whitespace())
+            .unwrap_or(raw_response.len());
+        let raw_response = &raw_response[start..];
+
+        let invalid = || {
+            Error::Network(format!(
+                "invalid http response: {}",
+                String::from_utf8_lossy(raw_response)
+            ))
         };
 
-        let (headers, body) = match remaining.split_once("\n\n") {
-            Some((h, b)) => {
-                let mut headers = Vec::new();
-                for header in h.split("\n") {
-                    let splitted_headers: Vec<&str> = header.splitn(2, ":").collect();
-                    headers.push(Header::new(
-                        splitted_headers[0].trim().to_string(),
-                        splitted_headers[1].trim().to_string(),
-                    ));
-                }
-                (headers, b)
+        let (head, body) = match find_empty_line(raw_response) {
+            Some((head_end, body_start)) => {
+                (&raw_response[..head_end], &raw_response[body_start..])
             }
-            None => (Vec::new(), remaining),
+            // Without an empty line, everything after the status line is
+            // the body.
+            None => match raw_response.iter().position(|b| *b == b'\n') {
+                Some(i) => (&raw_response[..i], &raw_response[i + 1..]),
+                None => return Err(invalid()),
+            },
         };
 
-        let statuses: Vec<&str> = status_line.split(" ").collect();
+        let head = String::from_utf8_lossy(head).replace("\r\n", "\n");
+        let mut lines = head.split('\n');
+        let status_line = lines.next().unwrap_or("").trim_end();
+
+        let mut headers = Vec::new();
+        for header in lines {
+            if let Some((name, value)) = header.split_once(':') {
+                headers.push(Header::new(
+                    name.trim().to_string(),
+                    value.trim().to_string(),
+                ));
+            }
+        }
+
+        let statuses: Vec<&str> = status_line.splitn(3, ' ').collect();
+        if statuses.len() < 3 {
+            return Err(invalid());
+        }
         Ok(Self {
             version: statuses[0].to_string(),
-            status_code: statuses[1].parse().unwrap(),
+            status_code: statuses[1].parse().map_err(|_| invalid())?,
             reason: statuses[2].to_string(),
             headers,
-            body: body.to_string(),
+            body: body.to_vec(),
         })
     }
 
@@ -69,7 +99,14 @@ impl HttpResponse {
         self.headers.clone()
     }
 
+    /// Returns the body decoded in the encoding that its `Content-Type`
+    /// header, byte order mark or `<meta>` element declares.
     pub fn body(&self) -> String {
+        let content_type = self.header_value("Content-Type").ok();
+        sniff(&self.body, content_type.as_deref()).decode(&self.body)
+    }
+
+    pub fn body_bytes(&self) -> Vec<u8> {
         self.body.clone()
     }
 
@@ -83,6 +120,25 @@ impl HttpResponse {
     }
 }
 
+/// Returns the end of the headers and the start of the body, which are
+/// separated by an empty line.
+fn find_empty_line(bytes: &[u8]) -> Option<(usize, usize)> {
+    for (i, b) in bytes.iter().enumerate() {
+        if *b != b'\n' {
+            continue;
+        }
+
+        let rest = &bytes[i + 1..];
+        if rest.starts_with(b"\n") {
+            return Some((i, i + 2));
+        }
+        if rest.starts_with(b"\r\n") {
+            return Some((i, i + 3));
+        }
+    }
+    None
+}
+
 #[derive(Debug, Clone)]
 pub struct Header {
     name: String,
@@ -141,4 +197,18 @@ mod tests {
         let res = HttpResponse::new(raw);
         assert!(res.is_err());
     }
+
+    #[test]
+    fn test_body_in_legacy_encoding() {
+        let raw = b"HTTP/1.1 200 OK\r\nContent-Type: text/html; charset=Shift_JIS\r\n\r\n\x93\xfa\x96\x7b";
+        let res = HttpResponse::from_bytes(raw).expect("failed to parse http response");
+        assert_eq!(res.status_code(), 200);
+        assert_eq!(res.body_bytes(), b"\x93\xfa\x96\x7b".to_vec());
+        assert_eq!(res.body(), "日本");
+
+        let raw = b"HTTP/1.1 404 Not Found\n\n<meta charset=euc-jp>\xc6\xfc";
+        let res = HttpResponse::from_bytes(raw).expect("failed to parse http response");
+        assert_eq!(res.reason(), "Not Found");
+        assert_eq!(res.body(), "<meta charset=euc-jp>日");
+    }
 }
@@ -0,0 +1,878 @@
+//! Finds the value of each property of each element from the style sheets.
+//! https://drafts.csswg.org/css-cascade-4/
+
+use crate::renderer::css::cssom::{CssRule, Declaration, QualifiedRule, StyleSheet};
+use crate::renderer::css::media::Viewport;
+use crate::renderer::css::parser::{parse_stylesheet, ComponentValue};
+use crate::renderer::css::rule_hash::RuleHash;
+use crate::renderer::css::style::{
+    compute_value, initial_value, is_css_wide_keyword, is_inherited, is_longhand, is_valid,
+    keyword, property_names, ComputeContext, ComputedStyle, BORDER_STYLES,
+};
+use crate::renderer::css::token::CssToken;
+use crate::renderer::css::variables::{
+    compute_custom_properties, contains_var, is_custom_property, substitute_var,
+};
+use crate::renderer::dom::bloom::BloomFilter;
+use crate::renderer::dom::node::Node;
+use crate::renderer::dom::selector::{element_hashes, Specificity};
+use alloc::collections::BTreeMap;
+use alloc::format;
+use alloc::rc::Rc;
+use alloc::string::{String, ToString};
+use alloc::vec;
+use alloc::vec::Vec;
+use core::cell::RefCell;
+
+/// The default styles of HTML elements.
+/// https://html.spec.whatwg.org/multipage/rendering.html
+const USER_AGENT_STYLE_SHEET: &str = "
+html, address, blockquote, body, center, dd, details, dialog, dir, div, dl, dt,
+fieldset, figcaption, figure, footer, form, h1, h2, h3, h4, h5, h6, header,
+hgroup, hr, legend, main, menu, nav, ol, p, pre, search, section, summary, ul {
+  display: block;
+}
+area, base, basefont, datalist, head, link, meta, noembed, noframes, param, rp,
+script, style, template, title, [hidden] {
+  display: none;
+}
+li { display: list-item; }
+
+body { margin: 8px; }
+p, blockquote, figure, listing, plaintext, pre, xmp, dl, ol, ul, dir, menu {
+  margin-top: 1em;
+  margin-bottom: 1em;
+}
+blockquote, figure { margin-left: 40px; margin-right: 40px; }
+dd { margin-left: 40px; }
+dir, menu, ol, ul { padding-left: 40px; }
+ol ol, ol ul, ul ol, ul ul { margin-top: 0; margin-bottom: 0; }
+hr { border-style: inset; border-width: 1px; margin-top: 0.5em; margin-bottom: 0.5em; }
+
+h1 { font-size: 2em; margin-top: 0.67em; margin-bottom: 0.67em; }
+h2 { font-size: 1.5em; margin-top: 0.83em; margin-bottom: 0.83em; }
+h3 { font-size: 1.17em; margin-top: 1em; margin-bottom: 1em; }
+h4 { margin-top: 1.33em; margin-bottom: 1.33em; }
+h5 { font-size: 0.83em; margin-top: 1.67em; margin-bottom: 1.67em; }
+h6 { font-size: 0.67em; margin-top: 2.33em; margin-bottom: 2.33em; }
+h1, h2, h3, h4, h5, h6, b, strong, th { font-weight: bolder; }
+address, cite, dfn, em, i, var { font-style: italic; }
+code, kbd, listing, plaintext, pre, samp, tt, xmp { font-family: monospace; }
+listing, plaintext, pre, xmp { white-space: pre; }
+small { font-size: smaller; }
+big { font-size: larger; }
+center { text-align: center; }
+u, ins { text-decoration: underline; }
+s, strike, del { text-decoration: line-through; }
+a[href] { color: blue; text-decoration: underline; }
+";
+
+/// https://drafts.csswg.org/css-cascade-4/#cascading-origins
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Origin {
+    UserAgent,
+    Author,
+}
+
+/// The order of declarations in the cascade, from lowest to highest.
+/// https://drafts.csswg.org/css-cascade-4/#cascade-sort
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+struct Priority {
+    /// The origin and importance.
+    precedence: u8,
+    /// Declarations in the `style` attribute win over any selector.
+    inline: bool,
+    specificity: Specificity,
+}
+
+impl Priority {
+    fn new(origin: Origin, important: bool, inline: bool, specificity: Specificity) -> Self {
+        // Important declarations go in the reverse order of the origins.
+        let precedence = match (origin, important) {
+            (Origin::UserAgent, false) => 0,
+            (Origin::Author, false) => 1,
+            (Origin::Author, true) => 2,
+            (Origin::UserAgent, true) => 3,
+        };
+        Self {
+            precedence,
+            inline,
+            specificity,
+        }
+    }
+}
+
+/// The value of a declaration that won the cascade.
+#[derive(Debug, Clone)]
+struct Cascaded {
+    /// The shorthand that the value is of. A shorthand with `var()` can't be
+    /// expanded until the value is substituted, so each of its longhands
+    /// gets the whole value.
+    /// https://drafts.csswg.org/css-variables-1/#pending-substitution-value
+    shorthand: Option<String>,
+    value: Vec<ComponentValue>,
+}
+
+impl Cascaded {
+    /// Returns the value of the longhand `name` with `var()` substituted, or
+    /// `None` if it's invalid at computed-value time.
+    /// https://drafts.csswg.org/css-variables-1/#invalid-at-computed-value-time
+    fn substitute(
+        &self,
+        name: &str,
+        custom_properties: &BTreeMap<String, Vec<ComponentValue>>,
+    ) -> Option<Vec<ComponentValue>> {
+        if !contains_var(&self.value) {
+            return Some(self.value.clone());
+        }
+        let value = substitute_var(&self.value, &mut |n| custom_properties.get(n).cloned())?;
+        match &self.shorthand {
+            Some(shorthand) => expand_shorthand(shorthand, &value)
+                .into_iter()
+                .find(|(longhand, _)| longhand == name)
+                .map(|(_, value)| value),
+            None => Some(value),
+        }
+    }
+}
+
+/// Computes the styles of elements from the user-agent style sheet, the
+/// author style sheets and the `style` attributes.
+#[derive(Debug, Clone)]
+pub struct StyleEngine {
+    style_sheets: Vec<(Origin, StyleSheet)>,
+    viewport: Viewport,
+    /// The style rules whose `@media` and `@supports` conditions are true
+    /// for the viewport, in order.
+    rules: Vec<(Origin, QualifiedRule)>,
+    /// The selectors of `rules` by the ID, class or type that they need.
+    rule_hash: RuleHash,
+}
+
+impl StyleEngine {
+    /// `author_style_sheets` are usually `Window::style_sheets()`.
+    pub fn new(author_style_sheets: &[StyleSheet]) -> Self {
+        let mut style_sheets = vec![(Origin::UserAgent, parse_stylesheet(USER_AGENT_STYLE_SHEET))];
+        for style_sheet in author_style_sheets {
+            style_sheets.push((Origin::Author, style_sheet.clone()));
+        }
+        let mut engine = Self {
+            style_sheets,
+            viewport: Viewport::default(),
+            rules: Vec::new(),
+            rule_hash: RuleHash::default(),
+        };
+        engine.collect_rules();
+        engine
+    }
+
+    /// Sets the viewport that media queries, `vw` and `vh` are evaluated
+    /// against. When the window is resized, call this and then
+    /// `style_document()` again.
+    pub fn set_viewport(&mut self, viewport: Viewport) {
+        self.viewport = viewport;
+        self.collect_rules();
+    }
+
+    fn collect_rules(&mut self) {
+        let mut rules = Vec::new();
+        for (origin, style_sheet) in &self.style_sheets {
+            if style_sheet.media.matches(&self.viewport) {
+                self.collect_rules_in(*origin, &style_sheet.rules, &mut rules);
+            }
+        }
+        self.rule_hash = RuleHash::new(rules.iter().map(|(_, rule)| rule));
+        self.rules = rules;
+    }
+
+    /// https://drafts.csswg.org/css-conditional-3/#processing
+    fn collect_rules_in(
+        &self,
+        origin: Origin,
+        rules: &[CssRule],
+        collected: &mut Vec<(Origin, QualifiedRule)>,
+    ) {
+        for rule in rules {
+            match rule {
+                CssRule::Qualified(rule) => collected.push((origin, rule.clone())),
+                // An imported style sheet is in the cascade where `@import`
+                // is, before the rules after it.
+                // https://drafts.csswg.org/css-cascade-4/#import-processing
+                CssRule::Import(rule) if rule.media.matches(&self.viewport) => {
+                    if let Some(style_sheet) = &rule.style_sheet {
+                        self.collect_rules_in(origin, &style_sheet.rules, collected)
+                    }
+                }
+                CssRule::Media(rule) if rule.media.matches(&self.viewport) => {
+                    self.collect_rules_in(origin, &rule.rules, collected)
+                }
+                CssRule::Supports(rule) if rule.condition.matches() => {
+                    self.collect_rules_in(origin, &rule.rules, collected)
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Computes the style of every element in `document` and stores it in
+    /// the element with `Node::set_computed_style()`.
+    pub fn style_document(&self, document: &Rc<RefCell<Node>>) {
+        self.style_children(document, None, &mut BloomFilter::new());
+    }
+
+    /// `ancestors` holds the `element_hashes()` of `node` and its ancestors.
+    fn style_children(
+        &self,
+        node: &Rc<RefCell<Node>>,
+        parent_style: Option<&ComputedStyle>,
+        ancestors: &mut BloomFilter,
+    ) {
+        for child in node.borrow().children() {
+            if child.borrow().get_element().is_none() {
+                continue;
+            }
+            let style = self.compute_style_with_filter(&child, parent_style, Some(ancestors));
+            child.borrow_mut().set_computed_style(style.clone());
+
+            let hashes = element_hashes(&child);
+            for hash in &hashes {
+                ancestors.insert(*hash);
+            }
+            self.style_children(&child, Some(&style), ancestors);
+            for hash in hashes {
+                ancestors.remove(hash);
+            }
+        }
+    }
+
+    /// Computes the style of the element `node`, whose parent element has
+    /// `parent_style`. The root element has no parent style.
+    /// https://drafts.csswg.org/css-cascade-4/#value-stages
+    pub fn compute_style(
+        &self,
+        node: &Rc<RefCell<Node>>,
+        parent_style: Option<&ComputedStyle>,
+    ) -> ComputedStyle {
+        self.compute_style_with_filter(node, parent_style, None)
+    }
+
+    /// `ancestors` is a bloom filter of the ancestors of `node`, if there is
+    /// one, which rules out selectors quickly.
+    fn compute_style_with_filter(
+        &self,
+        node: &Rc<RefCell<Node>>,
+        parent_style: Option<&ComputedStyle>,
+        ancestors: Option<&BloomFilter>,
+    ) -> ComputedStyle {
+        let cascaded = self.cascade(node, ancestors);
+
+        let specified_custom_properties = cascaded
+            .iter()
+            .filter(|(name, _)| is_custom_property(name))
+            .map(|(name, cascaded)| (name.clone(), cascaded.value.clone()))
+            .collect();
+        let mut style = ComputedStyle::default();
+        style.set_custom_properties(compute_custom_properties(
+            &specified_custom_properties,
+            parent_style.map(|p| p.custom_properties()),
+        ));
+
+        let (mut names, rest): (Vec<&str>, Vec<&str>) = property_names().partition(|n| {
+            *n == "font-size"
+                || *n == "color"
+                || (n.starts_with("border-") && n.ends_with("-style"))
+        });
+        // `currentcolor` in `color` doesn't depend on the font size, but
+        // `em` in any other property does.
+        names.sort_by_key(|n| *n != "font-size");
+        names.extend(rest);
+
+        for name in names {
+            let value = {
+                let context = ComputeContext {
+                    parent: parent_style,
+                    style: &style,
+                    viewport: self.viewport,
+                };
+                let inherit = || parent_style.and_then(|p| p.get(name)).cloned();
+                let unset = || if is_inherited(name) { inherit() } else { None };
+
+                // A value that's invalid after `var()` is substituted is
+                // `unset`.
+                let specified = cascaded
+                    .get(name)
+                    .and_then(|c| c.substitute(name, style.custom_properties()));
+                let value = match specified {
+                    Some(value) => match keyword(&value).as_deref() {
+                        Some("inherit") => inherit(),
+                        Some("initial") => None,
+                        Some("unset") => unset(),
+                        _ => compute_value(name, &value, &context).or_else(unset),
+                    },
+                    None => unset(),
+                };
+                value.or_else(|| {
+                    let initial = initial_value(name).unwrap_or_default();
+                    compute_value(name, &initial, &context)
+                })
+            };
+            if let Some(value) = value {
+                style.set(name, value);
+            }
+            if name == "font-size" {
+                let root_font_size = parent_style.map_or(style.font_size(), |p| p.root_font_size());
+                style.set_root_font_size(root_font_size);
+            }
+        }
+
+        style
+    }
+
+    /// Returns the winning declared value of each property of `node`.
+    /// https://drafts.csswg.org/css-cascade-4/#cascaded
+    fn cascade(
+        &self,
+        node: &Rc<RefCell<Node>>,
+        ancestors: Option<&BloomFilter>,
+    ) -> BTreeMap<String, Cascaded> {
+        let element = match node.borrow().get_element() {
+            Some(element) => element,
+            None => return BTreeMap::new(),
+        };
+
+        // A selector list is as specific as the most specific selector in it
+        // that matches. The candidates are in the order of the rules.
+        let mut matched: Vec<(usize, Specificity)> = Vec::new();
+        for candidate in self.rule_hash.candidates(node) {
+            let selector = &self.rules[candidate.rule].1.selectors[candidate.selector];
+            if !selector.matches_with_filter(node, ancestors) {
+                continue;
+            }
+            let specificity = selector.specificity();
+            match matched.last_mut() {
+                Some((rule, max)) if *rule == candidate.rule => *max = (*max).max(specificity),
+                _ => matched.push((candidate.rule, specificity)),
+            }
+        }
+
+        let mut declarations: Vec<(Priority, Declaration)> = Vec::new();
+        for (rule, specificity) in matched {
+            let (origin, rule) = &self.rules[rule];
+            for declaration in &rule.declarations {
+                let priority = Priority::new(*origin, declaration.important, false, specificity);
+                declarations.push((priority, declaration.clone()));
+            }
+        }
+        for declaration in element.style() {
+            let priority = Priority::new(
+                Origin::Author,
+                declaration.important,
+                true,
+                Specificity::default(),
+            );
+            declarations.push((priority, declaration));
+        }
+
+        // The sort is stable, so that later declarations win among the ones
+        // with the same priority.
+        declarations.sort_by_key(|(priority, _)| *priority);
+
+        let mut cascaded = BTreeMap::new();
+        for (_, declaration) in declarations {
+            let Declaration { name, value, .. } = declaration;
+            // An invalid declaration is ignored, so that an earlier one
+            // applies instead.
+            if !is_supported(&name, &value) {
+                continue;
+            }
+            if contains_var(&value) && !is_custom_property(&name) {
+                let shorthand = longhand_names(&name).map(|_| name.clone());
+                for longhand in longhand_names(&name).unwrap_or_else(|| vec![name.clone()]) {
+                    let value = value.clone();
+                    let shorthand = shorthand.clone();
+                    cascaded.insert(longhand, Cascaded { shorthand, value });
+                }
+            } else {
+                for (longhand, value) in expand_shorthand(&name, &value) {
+                    cascaded.insert(
+                        longhand,
+                        Cascaded {
+                            shorthand: None,
+                            value,
+                        },
+                    );
+                }
+            }
+        }
+        cascaded
+    }
+}
+
+/// Returns whether `name: value` is a valid declaration of a property that
+/// the style engine knows. A custom property can have any value, and a value
+/// with `var()` isn't checked until it's substituted.
+/// https://drafts.csswg.org/css-conditional-3/#support-definition
+pub fn is_supported(name: &str, value: &[ComponentValue]) -> bool {
+    if is_custom_property(name) {
+        return true;
+    }
+    if contains_var(value) {
+        return match longhand_names(name) {
+            Some(_) => true,
+            None => is_longhand(name),
+        };
+    }
+    let longhands = expand_shorthand(name, value);
+    !longhands.is_empty() && longhands.iter().all(|(n, v)| is_valid(n, v))
+}
+
+const SIDES: [&str; 4] = ["top", "right", "bottom", "left"];
+
+/// Returns the names of the longhands of the shorthand `name`, or `None` if
+/// it isn't a shorthand.
+fn longhand_names(name: &str) -> Option<Vec<String>> {
+    let longhands = match name {
+        "margin" | "padding" => SIDES.iter().map(|s| format!("{}-{}", name, s)).collect(),
+        "border-width" | "border-style" | "border-color" => {
+            let property = &name["border-".len()..];
+            SIDES
+                .iter()
+                .map(|s| format!("border-{}-{}", s, property))
+                .collect()
+        }
+        "border" | "border-top" | "border-right" | "border-bottom" | "border-left" => {
+            let sides: Vec<&str> = match name.strip_prefix("border-") {
+                Some(side) => vec![side],
+                None => SIDES.to_vec(),
+            };
+            sides
+                .iter()
+                .flat_map(|s| ["width", "style", "color"].map(|p| format!("border-{}-{}", s, p)))
+                .collect()
+        }
+        _ => return None,
+    };
+    Some(longhands)
+}
+
+/// Returns the longhand properties that the declaration `name: value` sets.
+/// A longhand is returned as it is, and an invalid shorthand sets nothing.
+/// https://drafts.csswg.org/css-cascade-4/#shorthand-property
+fn expand_shorthand(name: &str, value: &[ComponentValue]) -> Vec<(String, Vec<ComponentValue>)> {
+    let longhands = match longhand_names(name) {
+        Some(longhands) => longhands,
+        None => return vec![(name.to_string(), value.to_vec())],
+    };
+
+    // `inherit`, `initial` and `unset` go to every longhand.
+    if is_css_wide_keyword(value) {
+        return longhands.into_iter().map(|l| (l, value.to_vec())).collect();
+    }
+
+    let parts: Vec<Vec<ComponentValue>> = value
+        .iter()
+        .filter(|v| !v.is_whitespace())
+        .map(|v| vec![v.clone()])
+        .collect();
+    let values = match name {
+        "margin" | "padding" | "border-width" | "border-style" | "border-color" => {
+            match expand_sides(parts) {
+                Some(values) => values,
+                None => return Vec::new(),
+            }
+        }
+        _ => match split_border(parts) {
+            // The same width, style and color go to every side.
+            Some(values) => values
+                .iter()
+                .cycle()
+                .take(longhands.len())
+                .cloned()
+                .collect(),
+            None => return Vec::new(),
+        },
+    };
+
+    longhands.into_iter().zip(values).collect()
+}
+
+/// Expands 1 to 4 values to the top, right, bottom and left values.
+fn expand_sides(parts: Vec<Vec<ComponentValue>>) -> Option<Vec<Vec<ComponentValue>>> {
+    let (top, right, bottom, left) = match parts.as_slice() {
+        [a] => (a, a, a, a),
+        [a, b] => (a, b, a, b),
+        [a, b, c] => (a, b, c, b),
+        [a, b, c, d] => (a, b, c, d),
+        _ => return None,
+    };
+    Some(vec![
+        top.clone(),
+        right.clone(),
+        bottom.clone(),
+        left.clone(),
+    ])
+}
+
+/// Splits the value of `border` into the width, the style and the color, in
+/// any order. The ones that are left out get their initial values.
+fn split_border(parts: Vec<Vec<ComponentValue>>) -> Option<Vec<Vec<ComponentValue>>> {
+    let mut width = None;
+    let mut style = None;
+    let mut color = None;
+
+    for part in parts {
+        let is_style = keyword(&part).is_some_and(|k| BORDER_STYLES.contains(&k.as_str()));
+        let is_width = matches!(
+            part.as_slice(),
+            [ComponentValue::Token(CssToken::Dimension { .. })]
+                | [ComponentValue::Token(CssToken::Number { .. })]
+        ) || matches!(
+            part.as_slice(),
+            [ComponentValue::Function { name, .. }] if name.eq_ignore_ascii_case("calc")
+        ) || matches!(
+            keyword(&part).as_deref(),
+            Some("thin") | Some("medium") | Some("thick")
+        );
+
+        let slot = if is_style {
+            &mut style
+        } else if is_width {
+            &mut width
+        } else {
+            &mut color
+        };
+        if slot.is_some() {
+            return None;
+        }
+        *slot = Some(part);
+    }
+
+    Some(vec![
+        width.or_else(|| initial_value("border-top-width"))?,
+        style.or_else(|| initial_value("border-top-style"))?,
+        color.or_else(|| initial_value("border-top-color"))?,
+    ])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::renderer::css::parser::parse_component_values;
+    use crate::renderer::css::style::{Display, Value};
+    use crate::renderer::css::value::{Color, LengthPercentage};
+    use crate::renderer::dom::query::get_element_by_id;
+    use crate::renderer::html::parser::HtmlParser;
+    use crate::renderer::html::token::HtmlTokenizer;
+
+    /// Parses `html`, computes the styles, and returns the style of the
+    /// element whose ID is `id`.
+    fn style(html: &str, id: &str) -> ComputedStyle {
+        let window = HtmlParser::new(HtmlTokenizer::new(html.to_string())).construct_tree();
+        let window = window.borrow();
+        let document = window.document();
+        let mut engine = StyleEngine::new(window.style_sheets());
+        engine.set_viewport(Viewport {
+            width: 1000.0,
+            height: 500.0,
+            ..Viewport::default()
+        });
+        engine.style_document(&document);
+
+        let element = get_element_by_id(&document, id).expect("no element");
+        let style = element.borrow().computed_style();
+        style.expect("no computed style")
+    }
+
+    fn px(style: &ComputedStyle, name: &str) -> f64 {
+        style.length(name).expect("not a length").px
+    }
+
+    const RED: Color = Color::rgb(255, 0, 0);
+
+    #[test]
+    fn test_user_agent_style_sheet() {
+        let html = "<head id=head></head><h1 id=h1>a<span id=span>b</span></h1><b id=b>c</b>";
+        assert_eq!(style(html, "head").display(), Display::None);
+
+        let h1 = style(html, "h1");
+        assert_eq!(h1.display(), Display::Block);
+        assert_eq!(h1.font_weight(), 700);
+        assert_eq!(h1.font_size(), 32.0);
+        assert_eq!(px(&h1, "margin-top"), 32.0 * 0.67);
+
+        let span = style(html, "span");
+        assert_eq!(span.display(), Display::Inline);
+        assert_eq!(span.font_weight(), 700);
+
+        assert_eq!(style(html, "b").font_weight(), 700);
+        assert_eq!(style("<p hidden id=p>", "p").display(), Display::None);
+    }
+
+    #[test]
+    fn test_specificity_and_order() {
+        let html = "<style>
+            #p { color: red }
+            p.a { color: green }
+            p { color: blue; display: inline }
+            p { display: none }
+            </style><p id=p class=a>";
+        let p = style(html, "p");
+        assert_eq!(p.color("color"), RED);
+        assert_eq!(p.display(), Display::None);
+    }
+
+    #[test]
+    fn test_selector_matching() {
+        let html = "<style>
+            .c p, #x:has(+ * > i) { color: red }
+            div > :is(p, span):nth-child(2n) { margin-top: 1px }
+            </style>
+            <div class=c><section><p id=p></p><i id=x></i></section></div>
+            <div id=d><span></span><p id=q></p><i><i></i></i></div>";
+        assert_eq!(style(html, "p").color("color"), RED);
+        assert_eq!(px(&style(html, "p"), "margin-top"), 16.0);
+        // The ancestors of `#p` no longer count once its subtree is styled.
+        assert_eq!(style(html, "q").color("color"), Color::BLACK);
+        assert_eq!(px(&style(html, "q"), "margin-top"), 1.0);
+        assert_eq!(style(html, "x").color("color"), Color::BLACK);
+    }
+
+    #[test]
+    fn test_important_and_inline_style() {
+        let html = "<style>
+            #p { color: red; display: inline-block !important }
+            p { margin-left: 1px !important }
+            </style><p id=p style='color: green; display: none; margin-left: 2px'>";
+        let p = style(html, "p");
+        assert_eq!(p.color("color"), Color::rgb(0, 128, 0));
+        assert_eq!(p.display(), Display::InlineBlock);
+        assert_eq!(px(&p, "margin-left"), 1.0);
+
+        // Important declarations in the user-agent style sheet win over
+        // important inline ones.
+        assert!(
+            Priority::new(Origin::UserAgent, true, false, Specificity::default())
+                > Priority::new(Origin::Author, true, true, Specificity(1, 0, 0))
+        );
+    }
+
+    #[test]
+    fn test_inheritance() {
+        let html = "<style>
+            div { color: red; margin-top: 5px; padding-left: 3px }
+            .inherit { margin-top: inherit; color: initial }
+            .unset { color: unset; padding-left: unset }
+            </style><div><p id=p></p><p id=inherit class=inherit></p>\
+            <p id=unset class=unset></p></div>";
+        let p = style(html, "p");
+        assert_eq!(p.color("color"), RED);
+        assert_eq!(px(&p, "margin-top"), 16.0);
+
+        let inherit = style(html, "inherit");
+        assert_eq!(px(&inherit, "margin-top"), 5.0);
+        assert_eq!(inherit.color("color"), Color::BLACK);
+
+        let unset = style(html, "unset");
+        assert_eq!(unset.color("color"), RED);
+        assert_eq!(px(&unset, "padding-left"), 0.0);
+    }
+
+    #[test]
+    fn test_shorthands() {
+        let html = "<style>
+            p { margin: 1px 2px; padding: 1px 2px 3px; border: solid 2px red }
+            p { border-left: none }
+            </style><p id=p>";
+        let p = style(html, "p");
+        for (name, expected) in [
+            ("margin-top", 1.0),
+            ("margin-right", 2.0),
+            ("margin-bottom", 1.0),
+            ("margin-left", 2.0),
+            ("padding-left", 2.0),
+            ("padding-bottom", 3.0),
+            ("border-top-width", 2.0),
+            // The width is zero without a border style.
+            ("border-left-width", 0.0),
+        ] {
+            assert_eq!(px(&p, name), expected, "{}", name);
+        }
+        assert_eq!(p.keyword("border-top-style"), Some("solid"));
+        assert_eq!(p.keyword("border-left-style"), Some("none"));
+        assert_eq!(p.color("border-right-color"), RED);
+        assert_eq!(p.color("border-left-color"), Color::BLACK);
+
+        assert!(
+            expand_shorthand("margin", &parse_component_values("1px 2px 3px 4px 5px")).is_empty()
+        );
+        assert!(expand_shorthand("border", &parse_component_values("solid dotted")).is_empty());
+    }
+
+    #[test]
+    fn test_computed_lengths() {
+        let html = "<style>
+            html { font-size: 10px }
+            div { font-size: 2em; width: 50%; height: 10vh }
+            p { font-size: 150%; margin: 1rem 1em 10vw 12pt; line-height: 1.5 }
+            span { font-size: larger; line-height: 50%; padding-left: calc(100% - 2em) }
+            </style><div id=div><p id=p><span id=span></span></p></div>";
+        let div = style(html, "div");
+        assert_eq!(div.font_size(), 20.0);
+        assert_eq!(
+            div.length("width"),
+            Some(LengthPercentage {
+                px: 0.0,
+                percent: 50.0
+            })
+        );
+        assert_eq!(px(&div, "height"), 50.0);
+
+        let p = style(html, "p");
+        assert_eq!(p.font_size(), 30.0);
+        assert_eq!(px(&p, "margin-top"), 10.0);
+        assert_eq!(px(&p, "margin-right"), 30.0);
+        assert_eq!(px(&p, "margin-bottom"), 100.0);
+        assert_eq!(px(&p, "margin-left"), 16.0);
+        assert_eq!(p.line_height(), 45.0);
+        assert_eq!(p.length("width"), None);
+        assert_eq!(p.keyword("width"), Some("auto"));
+
+        // A line-height number is inherited as a number, and a percentage
+        // as a length.
+        let span = style(html, "span");
+        assert_eq!(span.font_size(), 36.0);
+        assert_eq!(
+            span.get("line-height"),
+            Some(&Value::Length(LengthPercentage::px(18.0)))
+        );
+        assert_eq!(
+            span.length("padding-left"),
+            Some(LengthPercentage {
+                px: -72.0,
+                percent: 100.0
+            })
+        );
+        assert_eq!(style("<p id=p>", "p").line_height(), 16.0 * 1.2);
+    }
+
+    #[test]
+    fn test_computed_colors() {
+        let html = "<style>
+            div { color: #00f; border: solid 1px; background-color: rgb(0 0 0 / 50%) }
+            p { color: hsl(0, 100%, 50%); background-color: currentcolor }
+            p { border-top-color: inherit; color: nonsense; margin-top: -1px; padding-top: -1px }
+            </style><div id=div><p id=p></p></div>";
+        let div = style(html, "div");
+        assert_eq!(div.color("color"), Color::rgb(0, 0, 255));
+        assert_eq!(div.color("border-top-color"), Color::rgb(0, 0, 255));
+        assert_eq!(
+            div.color("background-color"),
+            Color {
+                r: 0,
+                g: 0,
+                b: 0,
+                a: 128
+            }
+        );
+
+        // Invalid declarations are ignored, so that earlier ones apply.
+        let p = style(html, "p");
+        assert_eq!(p.color("color"), RED);
+        assert_eq!(p.color("background-color"), RED);
+        assert_eq!(p.color("border-top-color"), Color::rgb(0, 0, 255));
+        assert_eq!(p.color("border-left-color"), RED);
+        assert_eq!(px(&p, "margin-top"), -1.0);
+        assert_eq!(px(&p, "padding-top"), 0.0);
+
+        assert_eq!(
+            style("<body><a id=a href=x>", "a").color("color"),
+            Color::rgb(0, 0, 255)
+        );
+    }
+
+    #[test]
+    fn test_conditional_rules() {
+        let html = "<style>
+            p { color: red }
+            @media (max-width: 600px) { p { color: green } }
+            @media print { p { color: blue } }
+            @supports (display: block) { @media (orientation: landscape) { p { margin-top: 1px } } }
+            @supports (display: grid) { p { margin-left: 1px } }
+            </style><p id=p>";
+        let window = HtmlParser::new(HtmlTokenizer::new(html.to_string())).construct_tree();
+        let window = window.borrow();
+        let document = window.document();
+        let p = get_element_by_id(&document, "p").expect("no element");
+        let computed = || p.borrow().computed_style().expect("no computed style");
+
+        let mut engine = StyleEngine::new(window.style_sheets());
+        engine.style_document(&document);
+        assert_eq!(computed().color("color"), RED);
+        assert_eq!(px(&computed(), "margin-top"), 1.0);
+        assert_eq!(px(&computed(), "margin-left"), 0.0);
+
+        // Resizing the window re-evaluates the media queries.
+        engine.set_viewport(Viewport {
+            width: 400.0,
+            height: 800.0,
+            ..Viewport::default()
+        });
+        engine.style_document(&document);
+        assert_eq!(computed().color("color"), Color::rgb(0, 128, 0));
+        assert_eq!(px(&computed(), "margin-top"), 16.0);
+    }
+
+    #[test]
+    fn test_custom_properties() {
+        let html = "<style>
+            :root { --main: #f00; --gap: 4px; --Case: 1px }
+            div { --double: calc(var(--gap) * 2); color: blue }
+            p { color: var(--main); margin: var(--double) var(--missing, 1px); margin-top: 3px }
+            p { padding-left: var(--Case, 9px); padding-right: var(--case, 9px) }
+            .invalid { color: var(--gap); margin-left: var(--main); border: var(--missing) }
+            .cycle { --a: var(--b); --b: var(--a); --c: var(--a, 5px); --gap: var(--gap, 1px) }
+            .cycle { padding-top: var(--c); padding-left: var(--gap, 6px) }
+            .initial { --main: initial; color: var(--main, green) }
+            </style><div><p id=p></p><p id=invalid class=invalid></p>\
+            <p id=cycle class=cycle></p><p id=initial class=initial></p></div>";
+
+        let p = style(html, "p");
+        assert_eq!(p.color("color"), RED);
+        assert_eq!(px(&p, "margin-top"), 3.0);
+        assert_eq!(px(&p, "margin-right"), 1.0);
+        assert_eq!(px(&p, "margin-bottom"), 8.0);
+        assert_eq!(px(&p, "padding-left"), 1.0);
+        assert_eq!(px(&p, "padding-right"), 9.0);
+        assert_eq!(
+            p.custom_property("--double"),
+            Some(parse_component_values("calc(4px * 2)").as_slice())
+        );
+
+        // A value that's invalid after substitution is `unset`, so `color`
+        // is inherited and `margin-left` is initial.
+        let invalid = style(html, "invalid");
+        assert_eq!(invalid.color("color"), Color::rgb(0, 0, 255));
+        assert_eq!(px(&invalid, "margin-left"), 0.0);
+        assert_eq!(invalid.keyword("border-top-style"), Some("none"));
+
+        let cycle = style(html, "cycle");
+        assert_eq!(cycle.custom_property("--a"), None);
+        assert_eq!(cycle.custom_property("--b"), None);
+        assert_eq!(px(&cycle, "padding-top"), 5.0);
+        // A custom property that refers to itself is a cycle too, rather
+        // than referring to the inherited value.
+        assert_eq!(cycle.custom_property("--gap"), None);
+        assert_eq!(px(&cycle, "padding-left"), 6.0);
+
+        let initial = style(html, "initial");
+        assert_eq!(initial.custom_property("--main"), None);
+        assert_eq!(initial.color("color"), Color::rgb(0, 128, 0));
+
+        assert!(is_supported("--x", &parse_component_values("{ anything }")));
+        assert!(is_supported("margin", &parse_component_values("var(--x)")));
+        assert!(!is_supported(
+            "unknown",
+            &parse_component_values("var(--x)")
+        ));
+    }
+}
@@ -0,0 +1,129 @@
+//! The `not`, `and` and `or` conditions shared by `@media` and `@supports`.
+//! https://drafts.csswg.org/mediaqueries-4/#mq-syntax
+//! https://drafts.csswg.org/css-conditional-3/#at-supports
+
+use crate::renderer::css::parser::{BlockKind, ComponentValue};
+use crate::renderer::css::style::keyword;
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+
+/// A condition on features of type `F`, like `(min-width: 600px)` or
+/// `(display: grid)`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Condition<F> {
+    Feature(F),
+    Not(Box<Condition<F>>),
+    And(Vec<Condition<F>>),
+    Or(Vec<Condition<F>>),
+    /// Something in parentheses that isn't understood, which is neither
+    /// true nor false. A future level of CSS may define it.
+    /// https://drafts.csswg.org/mediaqueries-4/#typedef-general-enclosed
+    Unknown,
+}
+
+impl<F> Condition<F> {
+    /// Evaluates the condition with three-valued logic, where `None` is
+    /// unknown. `evaluate_feature` returns `None` for unknown features.
+    /// https://drafts.csswg.org/mediaqueries-4/#evaluating
+    pub fn evaluate(&self, evaluate_feature: &impl Fn(&F) -> Option<bool>) -> Option<bool> {
+        match self {
+            Condition::Feature(feature) => evaluate_feature(feature),
+            Condition::Not(condition) => condition.evaluate(evaluate_feature).map(|b| !b),
+            Condition::And(conditions) => {
+                let results: Vec<Option<bool>> = conditions
+                    .iter()
+                    .map(|c| c.evaluate(evaluate_feature))
+                    .collect();
+                if results.contains(&Some(false)) {
+                    Some(false)
+                } else if results.contains(&None) {
+                    None
+                } else {
+                    Some(true)
+                }
+            }
+            Condition::Or(conditions) => {
+                let results: Vec<Option<bool>> = conditions
+                    .iter()
+                    .map(|c| c.evaluate(evaluate_feature))
+                    .collect();
+                if results.contains(&Some(true)) {
+                    Some(true)
+                } else if results.contains(&None) {
+                    None
+                } else {
+                    Some(false)
+                }
+            }
+            Condition::Unknown => None,
+        }
+    }
+}
+
+/// Parses a condition from `values` without whitespace. `parse_feature` is
+/// given each value in parentheses that isn't a nested condition. `or` is
+/// not allowed after the media type of a media query.
+pub fn parse_condition<F>(
+    values: &[&ComponentValue],
+    allow_or: bool,
+    parse_feature: &impl Fn(&ComponentValue) -> Option<F>,
+) -> Option<Condition<F>> {
+    let (first, rest) = values.split_first()?;
+
+    if keyword(core::slice::from_ref(*first)).as_deref() == Some("not") {
+        return match rest {
+            [value] => Some(Condition::Not(Box::new(parse_in_parens(
+                value,
+                parse_feature,
+            )?))),
+            _ => None,
+        };
+    }
+
+    let mut conditions = Vec::from([parse_in_parens(first, parse_feature)?]);
+    let mut operator = None;
+    for pair in rest.chunks(2) {
+        let [combinator, value] = pair else {
+            return None;
+        };
+        let combinator = keyword(core::slice::from_ref(*combinator))?;
+        if combinator != "and" && !(combinator == "or" && allow_or) {
+            return None;
+        }
+        // `and` and `or` can't be mixed without parentheses.
+        if operator.get_or_insert(combinator.clone()) != &combinator {
+            return None;
+        }
+        conditions.push(parse_in_parens(value, parse_feature)?);
+    }
+
+    Some(match operator.as_deref() {
+        None => conditions.remove(0),
+        Some("and") => Condition::And(conditions),
+        _ => Condition::Or(conditions),
+    })
+}
+
+/// https://drafts.csswg.org/mediaqueries-4/#typedef-media-in-parens
+fn parse_in_parens<F>(
+    value: &ComponentValue,
+    parse_feature: &impl Fn(&ComponentValue) -> Option<F>,
+) -> Option<Condition<F>> {
+    match value {
+        ComponentValue::Block {
+            kind: BlockKind::Parenthesis,
+            contents,
+        } => {
+            let contents: Vec<&ComponentValue> =
+                contents.iter().filter(|v| !v.is_whitespace()).collect();
+            if let Some(condition) = parse_condition(&contents, true, parse_feature) {
+                return Some(condition);
+            }
+            Some(parse_feature(value).map_or(Condition::Unknown, Condition::Feature))
+        }
+        ComponentValue::Function { .. } => {
+            Some(parse_feature(value).map_or(Condition::Unknown, Condition::Feature))
+        }
+        _ => None,
+    }
+}
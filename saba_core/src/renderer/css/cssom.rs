@@ -0,0 +1,132 @@
+//! The object model of parsed style sheets.
+//! https://drafts.csswg.org/cssom/
+
+use crate::renderer::css::media::MediaQueryList;
+use crate::renderer::css::parser::ComponentValue;
+use crate::renderer::css::supports::SupportsCondition;
+use crate::renderer::dom::selector::Selector;
+use crate::url::Url;
+use alloc::string::String;
+use alloc::vec::Vec;
+
+/// https://drafts.csswg.org/cssom/#css-style-sheet
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct StyleSheet {
+    pub rules: Vec<CssRule>,
+    /// The URL of an external style sheet, which URLs in it are resolved
+    /// against. `None` for `<style>`.
+    /// https://drafts.csswg.org/cssom/#concept-css-style-sheet-location
+    pub href: Option<Url>,
+    /// The rules apply only when this matches. It comes from the `media`
+    /// attribute of `<link>` and `<style>`.
+    /// https://drafts.csswg.org/cssom/#concept-css-style-sheet-media
+    pub media: MediaQueryList,
+    pub state: LoadState,
+    /// Whether the document mustn't be rendered while the sheet is loading,
+    /// which is the case for `<link>` before `<body>`.
+    /// https://html.spec.whatwg.org/multipage/dom.html#render-blocking
+    pub render_blocking: bool,
+}
+
+impl StyleSheet {
+    pub fn new(rules: Vec<CssRule>) -> Self {
+        Self {
+            rules,
+            ..Self::default()
+        }
+    }
+
+    /// Creates an empty style sheet that's fetched from `href` later, with
+    /// `load_style_sheets()`.
+    pub fn external(href: Url, media: MediaQueryList, render_blocking: bool) -> Self {
+        Self {
+            rules: Vec::new(),
+            href: Some(href),
+            media,
+            state: LoadState::Loading,
+            render_blocking,
+        }
+    }
+}
+
+/// Whether the rules of an external style sheet have arrived.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub enum LoadState {
+    #[default]
+    Loaded,
+    Loading,
+    /// The fetch failed, so the style sheet has no rules.
+    Failed,
+}
+
+/// https://drafts.csswg.org/css-syntax-3/#css-rule
+#[derive(Debug, Clone, PartialEq)]
+pub enum CssRule {
+    Qualified(QualifiedRule),
+    Import(ImportRule),
+    Media(MediaRule),
+    Supports(SupportsRule),
+    /// An at-rule that isn't parsed any further.
+    At(AtRule),
+}
+
+/// A rule like `p, .note { color: red }`.
+/// https://drafts.csswg.org/css-syntax-3/#qualified-rule
+#[derive(Debug, Clone, PartialEq)]
+pub struct QualifiedRule {
+    pub selectors: Vec<Selector>,
+    pub declarations: Vec<Declaration>,
+}
+
+/// `@import url("a.css") screen;`
+/// https://drafts.csswg.org/cssom/#the-cssimportrule-interface
+#[derive(Debug, Clone, PartialEq)]
+pub struct ImportRule {
+    /// The URL as written, not yet resolved.
+    pub href: String,
+    pub media: MediaQueryList,
+    /// The imported style sheet, which is `None` until
+    /// `load_style_sheets()` fetches it.
+    pub style_sheet: Option<StyleSheet>,
+}
+
+/// `@media screen and (width >= 600px) { ... }`
+/// https://drafts.csswg.org/cssom/#the-cssmediarule-interface
+#[derive(Debug, Clone, PartialEq)]
+pub struct MediaRule {
+    pub media: MediaQueryList,
+    pub rules: Vec<CssRule>,
+}
+
+/// `@supports (display: grid) { ... }`
+/// https://drafts.csswg.org/css-conditional-3/#the-csssupportsrule-interface
+#[derive(Debug, Clone, PartialEq)]
+pub struct SupportsRule {
+    pub condition: SupportsCondition,
+    pub rules: Vec<CssRule>,
+}
+
+/// A rule like `@font-face { ... }`. The prelude and the block are kept
+/// unparsed, as their grammar depends on the rule.
+/// https://drafts.csswg.org/css-syntax-3/#at-rule
+#[derive(Debug, Clone, PartialEq)]
+pub struct AtRule {
+    /// The name without the `@`, lowercased.
+    pub name: String,
+    pub prelude: Vec<ComponentValue>,
+    /// The contents of the `{}` block, or `None` for a rule that ended with
+    /// `;`.
+    pub block: Option<Vec<ComponentValue>>,
+}
+
+/// A declaration like `color: red !important`.
+/// https://drafts.csswg.org/css-syntax-3/#declaration
+#[derive(Debug, Clone, PartialEq)]
+pub struct Declaration {
+    /// The property name, lowercased unless it's a custom property like
+    /// `--main-color`.
+    pub name: String,
+    /// The value without the surrounding whitespace and `!important`.
+    pub value: Vec<ComponentValue>,
+    pub important: bool,
+}
@@ -0,0 +1,337 @@
+//! Fetching the external style sheets of `<link rel=stylesheet>` and
+//! `@import`.
+//! https://html.spec.whatwg.org/multipage/semantics.html#fetching-and-processing-a-resource-from-a-link-element
+//! https://drafts.csswg.org/css-cascade-4/#at-import
+
+use crate::http::Fetcher;
+use crate::renderer::css::cssom::{CssRule, LoadState, StyleSheet};
+use crate::renderer::css::parser::parse_stylesheet;
+use crate::renderer::dom::node::Window;
+use crate::url::Url;
+use alloc::string::String;
+use alloc::vec::Vec;
+
+/// Fetches every style sheet of `window` that's still loading, and then the
+/// style sheets that they and `<style>` elements import. A style sheet that
+/// can't be fetched ends up empty in the `Failed` state, so it no longer
+/// blocks rendering.
+pub fn load_style_sheets(window: &mut Window, fetcher: &impl Fetcher) {
+    let base_url = window.base_url();
+    for style_sheet in window.style_sheets_mut() {
+        if style_sheet.state == LoadState::Loading {
+            if let Some(href) = style_sheet.href.clone() {
+                let fetched = fetch_style_sheet(href, fetcher);
+                style_sheet.rules = fetched.rules;
+                style_sheet.state = fetched.state;
+            }
+        }
+
+        let base = style_sheet.href.clone().or(base_url.clone());
+        let mut ancestors: Vec<String> = style_sheet.href.iter().map(|h| h.url()).collect();
+        load_imports(
+            &mut style_sheet.rules,
+            base.as_ref(),
+            &mut ancestors,
+            fetcher,
+        );
+    }
+}
+
+/// Fetches the style sheet at `href`. Only a 2xx response counts as loaded.
+/// https://html.spec.whatwg.org/multipage/semantics.html#fetching-and-processing-a-resource-from-a-link-element
+fn fetch_style_sheet(href: Url, fetcher: &impl Fetcher) -> StyleSheet {
+    let mut style_sheet = match fetcher.fetch(&href) {
+        Ok(response) if (200..300).contains(&response.status_code()) => {
+            parse_stylesheet(&response.body())
+        }
+        _ => StyleSheet {
+            state: LoadState::Failed,
+            ..StyleSheet::default()
+        },
+    };
+    style_sheet.href = Some(href);
+    style_sheet
+}
+
+/// Fetches the style sheets of the `@import` rules in `rules`, whose URLs are
+/// relative to `base`. `ancestors` are the URLs of the style sheets that
+/// import the one with `rules`, directly or indirectly. Importing one of
+/// them again would never end, so such an import is left failed.
+fn load_imports(
+    rules: &mut [CssRule],
+    base: Option<&Url>,
+    ancestors: &mut Vec<String>,
+    fetcher: &impl Fetcher,
+) {
+    for rule in rules {
+        let import = match rule {
+            CssRule::Import(import) if import.style_sheet.is_none() => import,
+            _ => continue,
+        };
+        let href = match base.map(|base| base.join(&import.href)) {
+            Some(Ok(href)) => href,
+            _ => continue,
+        };
+
+        if ancestors.contains(&href.url()) {
+            import.style_sheet = Some(StyleSheet {
+                href: Some(href),
+                state: LoadState::Failed,
+                ..StyleSheet::default()
+            });
+            continue;
+        }
+
+        let mut style_sheet = fetch_style_sheet(href.clone(), fetcher);
+        ancestors.push(href.url());
+        load_imports(&mut style_sheet.rules, Some(&href), ancestors, fetcher);
+        ancestors.pop();
+        import.style_sheet = Some(style_sheet);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::Error;
+    use crate::http::HttpResponse;
+    use crate::renderer::css::cascade::StyleEngine;
+    use crate::renderer::css::media::Viewport;
+    use crate::renderer::css::style::Value;
+    use crate::renderer::css::value::Color;
+    use crate::renderer::dom::node::ElementKind;
+    use crate::renderer::dom::query::descendant_elements;
+    use crate::renderer::html::parser::HtmlParser;
+    use crate::renderer::html::token::HtmlTokenizer;
+    use alloc::format;
+    use alloc::rc::Rc;
+    use alloc::string::ToString;
+    use core::cell::RefCell;
+
+    /// Serves style sheets from a list of paths, and counts the requests.
+    struct MockFetcher {
+        files: Vec<(&'static str, &'static str)>,
+        requests: RefCell<Vec<String>>,
+    }
+
+    impl MockFetcher {
+        fn new(files: &[(&'static str, &'static str)]) -> Self {
+            Self {
+                files: files.to_vec(),
+                requests: RefCell::new(Vec::new()),
+            }
+        }
+    }
+
+    impl Fetcher for MockFetcher {
+        fn fetch(&self, url: &Url) -> Result<HttpResponse, Error> {
+            self.requests.borrow_mut().push(url.path());
+            match self.files.iter().find(|(path, _)| *path == url.path()) {
+                Some((_, css)) => HttpResponse::new(format!("HTTP/1.1 200 OK\n\n{}", css)),
+                None if url.path() == "error.css" => {
+                    Err(Error::Network("connection refused".to_string()))
+                }
+                None => {
+                    HttpResponse::new("HTTP/1.1 404 Not Found\n\nbody { color: red }".to_string())
+                }
+            }
+        }
+    }
+
+    fn parse(html: &str) -> Rc<RefCell<Window>> {
+        let mut parser = HtmlParser::new(HtmlTokenizer::new(html.to_string()));
+        parser.set_url(
+            Url::new("http://example.com/dir/index.html".to_string())
+                .parse()
+                .expect("failed to parse the URL"),
+        );
+        parser.construct_tree()
+    }
+
+    /// Returns the computed `color` of the first `<p>` in `window`.
+    fn color(window: &Rc<RefCell<Window>>, viewport: Viewport) -> Value {
+        let mut engine = StyleEngine::new(window.borrow().style_sheets());
+        engine.set_viewport(viewport);
+        let document = window.borrow().document();
+        engine.style_document(&document);
+        let p = descendant_elements(&document)
+            .find(|n| n.borrow().element_kind() == Some(ElementKind::P))
+            .expect("no p element");
+        let style = p.borrow().computed_style().expect("no computed style");
+        style.get("color").cloned().expect("no color")
+    }
+
+    fn rgb(r: u8, g: u8, b: u8) -> Value {
+        Value::Color(Color::rgb(r, g, b))
+    }
+
+    #[test]
+    fn test_link_order() {
+        let window = parse(
+            "<link rel=stylesheet href=a.css>\
+             <style>p { color: rgb(0, 0, 2) }</style>\
+             <link rel='Alternate Stylesheet' href=alt.css>\
+             <link rel=icon href=a.png>\
+             <link rel=STYLESHEET href='/b.css'>\
+             <p>",
+        );
+        let fetcher = MockFetcher::new(&[
+            ("dir/a.css", "p { color: rgb(0, 0, 1) }"),
+            ("b.css", "p { color: rgb(0, 0, 3) }"),
+        ]);
+
+        assert_eq!(window.borrow().style_sheets().len(), 3);
+        assert!(window.borrow().is_render_blocked());
+        load_style_sheets(&mut window.borrow_mut(), &fetcher);
+        assert!(!window.borrow().is_render_blocked());
+        assert_eq!(*fetcher.requests.borrow(), ["dir/a.css", "b.css"]);
+
+        // The last style sheet in tree order wins.
+        assert_eq!(color(&window, Viewport::default()), rgb(0, 0, 3));
+    }
+
+    #[test]
+    fn test_imports() {
+        let window = parse(
+            "<style>@import 'a.css'; p { color: rgb(0, 0, 9) }</style>\
+             <link rel=stylesheet href=css/b.css>\
+             <p>",
+        );
+        let fetcher = MockFetcher::new(&[
+            ("dir/a.css", "p { color: rgb(0, 0, 1) }"),
+            // Relative to the importing style sheet.
+            (
+                "dir/css/b.css",
+                "@import url(c.css); p { color: rgb(0, 0, 2) }",
+            ),
+            (
+                "dir/css/c.css",
+                "p { color: rgb(0, 0, 3) } p { background-color: red }",
+            ),
+        ]);
+        load_style_sheets(&mut window.borrow_mut(), &fetcher);
+
+        // Imported rules come before the rest of the importing style sheet.
+        assert_eq!(color(&window, Viewport::default()), rgb(0, 0, 2));
+        assert_eq!(
+            *fetcher.requests.borrow(),
+            ["dir/a.css", "dir/css/b.css", "dir/css/c.css"]
+        );
+    }
+
+    #[test]
+    fn test_import_cycles() {
+        let window = parse("<link rel=stylesheet href=a.css><p>");
+        let fetcher = MockFetcher::new(&[
+            ("dir/a.css", "@import 'b.css'; p { color: rgb(0, 0, 1) }"),
+            (
+                "dir/b.css",
+                "@import 'a.css'; @import 'b.css'; p { color: rgb(0, 0, 2) }",
+            ),
+        ]);
+        load_style_sheets(&mut window.borrow_mut(), &fetcher);
+
+        assert_eq!(*fetcher.requests.borrow(), ["dir/a.css", "dir/b.css"]);
+        assert_eq!(color(&window, Viewport::default()), rgb(0, 0, 1));
+
+        let window = window.borrow();
+        let CssRule::Import(import) = &window.style_sheets()[0].rules[0] else {
+            panic!("expected @import");
+        };
+        let b = import
+            .style_sheet
+            .as_ref()
+            .expect("no imported style sheet");
+        assert_eq!(b.state, LoadState::Loaded);
+        for rule in &b.rules[..2] {
+            let CssRule::Import(import) = rule else {
+                panic!("expected @import");
+            };
+            assert_eq!(
+                import
+                    .style_sheet
+                    .as_ref()
+                    .expect("no imported style sheet")
+                    .state,
+                LoadState::Failed
+            );
+        }
+    }
+
+    #[test]
+    fn test_failed_loads() {
+        let window = parse(
+            "<style>p { color: rgb(0, 0, 1) }</style>\
+             <link rel=stylesheet href=missing.css>\
+             <link rel=stylesheet href=error.css>\
+             <p>",
+        );
+        load_style_sheets(&mut window.borrow_mut(), &MockFetcher::new(&[]));
+
+        let states: Vec<LoadState> = window
+            .borrow()
+            .style_sheets()
+            .iter()
+            .map(|s| s.state)
+            .collect();
+        assert_eq!(
+            states,
+            [LoadState::Loaded, LoadState::Failed, LoadState::Failed]
+        );
+        assert!(!window.borrow().is_render_blocked());
+        // The body of an error response isn't used.
+        assert_eq!(color(&window, Viewport::default()), rgb(0, 0, 1));
+    }
+
+    #[test]
+    fn test_render_blocking() {
+        let blocking = |window: &Rc<RefCell<Window>>| -> Vec<bool> {
+            window
+                .borrow()
+                .style_sheets()
+                .iter()
+                .map(|s| s.render_blocking)
+                .collect()
+        };
+        let window = parse(
+            "<head><link rel=stylesheet href=a.css></head>\
+             <body><link rel=stylesheet href=b.css><p>",
+        );
+        assert_eq!(blocking(&window), [true, false]);
+
+        // The doctype and comments before the html element don't hide the
+        // body.
+        let with_doctype = parse(
+            "<!DOCTYPE html><!--a--><head><link rel=stylesheet href=a.css></head>\
+             <body><link rel=stylesheet href=b.css><p>",
+        );
+        assert_eq!(blocking(&with_doctype), [true, false]);
+
+        // A link in the body doesn't block rendering on its own.
+        window.borrow_mut().style_sheets_mut()[0].state = LoadState::Loaded;
+        assert!(!window.borrow().is_render_blocked());
+    }
+
+    #[test]
+    fn test_media() {
+        let window = parse(
+            "<link rel=stylesheet href=a.css media='(min-width: 600px)'>\
+             <style media=print>p { color: rgb(0, 0, 2) }</style>\
+             <style>@import 'b.css' (max-width: 400px);</style>\
+             <p>",
+        );
+        let fetcher = MockFetcher::new(&[
+            ("dir/a.css", "p { color: rgb(0, 0, 1) }"),
+            ("dir/b.css", "p { color: rgb(0, 0, 3) }"),
+        ]);
+        load_style_sheets(&mut window.borrow_mut(), &fetcher);
+
+        let viewport = |width| Viewport {
+            width,
+            ..Viewport::default()
+        };
+        assert_eq!(color(&window, viewport(800.0)), rgb(0, 0, 1));
+        assert_eq!(color(&window, viewport(500.0)), rgb(0, 0, 0));
+        assert_eq!(color(&window, viewport(300.0)), rgb(0, 0, 3));
+    }
+}
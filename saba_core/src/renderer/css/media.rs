@@ -0,0 +1,509 @@
+//! Media queries, which decide whether the rules in `@media` apply.
+//! https://drafts.csswg.org/mediaqueries-4/
+
+use crate::renderer::css::condition::{parse_condition, Condition};
+use crate::renderer::css::parser::{trim_whitespace, ComponentValue};
+use crate::renderer::css::style::keyword;
+use crate::renderer::css::token::CssToken;
+use crate::renderer::css::value::{LengthContext, MEDIUM_FONT_SIZE};
+use alloc::string::String;
+use alloc::vec::Vec;
+
+/// https://drafts.csswg.org/mediaqueries-5/#prefers-color-scheme
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub enum ColorScheme {
+    #[default]
+    Light,
+    Dark,
+}
+
+/// The viewport and the device that media queries are evaluated against,
+/// supplied by the embedder. Its size is also what `vw` and `vh` are
+/// relative to.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Viewport {
+    /// The width in CSS pixels.
+    pub width: f64,
+    /// The height in CSS pixels.
+    pub height: f64,
+    /// Device pixels per CSS pixel.
+    pub resolution: f64,
+    pub color_scheme: ColorScheme,
+}
+
+impl Default for Viewport {
+    fn default() -> Self {
+        Self {
+            width: 800.0,
+            height: 600.0,
+            resolution: 1.0,
+            color_scheme: ColorScheme::Light,
+        }
+    }
+}
+
+/// A comma-separated list of media queries like `screen and (width >= 600px),
+/// print`, which matches if any of them matches.
+/// https://drafts.csswg.org/mediaqueries-4/#media-query-list
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MediaQueryList {
+    queries: Vec<MediaQuery>,
+}
+
+/// https://drafts.csswg.org/mediaqueries-4/#media-query
+#[derive(Debug, Clone, PartialEq)]
+struct MediaQuery {
+    negated: bool,
+    /// The lowercased media type. `None` is `all`.
+    media_type: Option<String>,
+    condition: Option<Condition<MediaFeature>>,
+}
+
+impl MediaQuery {
+    /// An invalid media query becomes `not all`, which never matches.
+    fn not_all() -> Self {
+        Self {
+            negated: true,
+            media_type: None,
+            condition: None,
+        }
+    }
+}
+
+/// A comparison in a media feature. `(min-width: 600px)` is
+/// `width >= 600px`.
+/// https://drafts.csswg.org/mediaqueries-4/#mq-range-context
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum Comparison {
+    Lt,
+    Le,
+    Eq,
+    Ge,
+    Gt,
+}
+
+impl Comparison {
+    /// Returns the comparison with the sides swapped, so that `600px < width`
+    /// is `width > 600px`.
+    fn flip(self) -> Self {
+        match self {
+            Comparison::Lt => Comparison::Gt,
+            Comparison::Le => Comparison::Ge,
+            Comparison::Eq => Comparison::Eq,
+            Comparison::Ge => Comparison::Le,
+            Comparison::Gt => Comparison::Lt,
+        }
+    }
+
+    fn compare(self, actual: f64, expected: f64) -> bool {
+        match self {
+            Comparison::Lt => actual < expected,
+            Comparison::Le => actual <= expected,
+            Comparison::Eq => actual == expected,
+            Comparison::Ge => actual >= expected,
+            Comparison::Gt => actual > expected,
+        }
+    }
+}
+
+/// A value in a media feature.
+/// https://drafts.csswg.org/mediaqueries-4/#values
+#[derive(Debug, Clone, PartialEq)]
+enum MediaValue {
+    Number(f64),
+    /// A length and its unit, which are resolved with the viewport.
+    Length(f64, String),
+    /// `16/9`
+    Ratio(f64),
+    /// In device pixels per CSS pixel.
+    Resolution(f64),
+    /// Lowercased.
+    Keyword(String),
+}
+
+/// https://drafts.csswg.org/mediaqueries-4/#media-feature
+#[derive(Debug, Clone, PartialEq)]
+enum MediaFeature {
+    /// `(color)`, which is true unless the value is zero or `none`.
+    Boolean(String),
+    /// `(orientation: landscape)`, `(min-width: 600px)` or
+    /// `(400px < width <= 800px)`.
+    Range {
+        name: String,
+        comparisons: Vec<(Comparison, MediaValue)>,
+    },
+}
+
+/// The value of a media feature for the current viewport.
+enum Actual {
+    Length(f64),
+    Ratio(f64),
+    Resolution(f64),
+    Integer(f64),
+    Keyword(&'static str),
+}
+
+impl MediaQueryList {
+    /// Parses the prelude of `@media` or the `media` attribute of `<link>`.
+    /// An empty list matches everything.
+    /// https://drafts.csswg.org/mediaqueries-4/#mq-list
+    pub fn parse(values: &[ComponentValue]) -> Self {
+        let values = trim_whitespace(values);
+        if values.is_empty() {
+            return Self::default();
+        }
+        let queries = values
+            .split(|v| *v == ComponentValue::Token(CssToken::Comma))
+            .map(|query| parse_media_query(query).unwrap_or_else(MediaQuery::not_all))
+            .collect();
+        Self { queries }
+    }
+
+    /// Returns whether the rules under the list apply to `viewport`.
+    /// https://drafts.csswg.org/mediaqueries-4/#mq-list
+    pub fn matches(&self, viewport: &Viewport) -> bool {
+        if self.queries.is_empty() {
+            return true;
+        }
+        self.queries.iter().any(|query| {
+            let type_matches = match query.media_type.as_deref() {
+                None | Some("all") | Some("screen") => true,
+                Some(_) => false,
+            };
+            // An unknown condition is false, and so is its negation.
+            let matches = type_matches
+                && query.condition.as_ref().map_or(Some(true), |c| {
+                    c.evaluate(&|feature| evaluate_feature(feature, viewport))
+                }) == Some(true);
+            matches != query.negated
+        })
+    }
+}
+
+/// https://drafts.csswg.org/mediaqueries-4/#typedef-media-query
+fn parse_media_query(values: &[ComponentValue]) -> Option<MediaQuery> {
+    let values: Vec<&ComponentValue> = values.iter().filter(|v| !v.is_whitespace()).collect();
+    let ident = |i: usize| {
+        values
+            .get(i)
+            .and_then(|v| keyword(core::slice::from_ref(*v)))
+    };
+
+    let (negated, type_index) = match ident(0).as_deref() {
+        Some("not") if ident(1).is_some() => (true, 1),
+        Some("only") => (false, 1),
+        Some(media_type) if media_type != "not" => (false, 0),
+        // `not (color)` is a condition.
+        _ => {
+            return Some(MediaQuery {
+                negated: false,
+                media_type: None,
+                condition: Some(parse_condition(&values, true, &parse_feature)?),
+            })
+        }
+    };
+
+    let media_type = ident(type_index)?;
+    // These are reserved, so they can't be media types.
+    if matches!(media_type.as_str(), "not" | "and" | "or" | "only" | "layer") {
+        return None;
+    }
+
+    let condition = match values.get(type_index + 1..)? {
+        [] => None,
+        [and, rest @ ..] if keyword(core::slice::from_ref(*and)).as_deref() == Some("and") => {
+            Some(parse_condition(rest, false, &parse_feature)?)
+        }
+        _ => return None,
+    };
+
+    Some(MediaQuery {
+        negated,
+        media_type: Some(media_type),
+        condition,
+    })
+}
+
+/// Parses a media feature in parentheses.
+/// https://drafts.csswg.org/mediaqueries-4/#typedef-media-feature
+fn parse_feature(value: &ComponentValue) -> Option<MediaFeature> {
+    let ComponentValue::Block { contents, .. } = value else {
+        return None;
+    };
+    let values: Vec<&ComponentValue> = contents.iter().filter(|v| !v.is_whitespace()).collect();
+    let name_of = |values: &[&ComponentValue]| match values {
+        [ComponentValue::Token(CssToken::Ident(name))] => Some(name.to_ascii_lowercase()),
+        _ => None,
+    };
+
+    // `(color)`
+    if let Some(name) = name_of(&values) {
+        return Some(MediaFeature::Boolean(name));
+    }
+
+    // `(min-width: 600px)`
+    if let [name, ComponentValue::Token(CssToken::Colon), rest @ ..] = values.as_slice() {
+        let name = name_of(core::slice::from_ref(name))?;
+        let value = parse_value(rest)?;
+        let (comparison, name) = if let Some(name) = name.strip_prefix("min-") {
+            (Comparison::Ge, name)
+        } else if let Some(name) = name.strip_prefix("max-") {
+            (Comparison::Le, name)
+        } else {
+            (Comparison::Eq, name.as_str())
+        };
+        return Some(MediaFeature::Range {
+            name: name.into(),
+            comparisons: Vec::from([(comparison, value)]),
+        });
+    }
+
+    // `(width >= 600px)`, `(600px <= width)` or `(400px < width < 800px)`
+    let mut segments: Vec<&[&ComponentValue]> = Vec::new();
+    let mut comparisons = Vec::new();
+    let mut start = 0;
+    let mut i = 0;
+    while i < values.len() {
+        let comparison = match values[i] {
+            ComponentValue::Token(CssToken::Delim(c @ ('<' | '>' | '='))) => *c,
+            _ => {
+                i += 1;
+                continue;
+            }
+        };
+        segments.push(&values[start..i]);
+        let or_equal = comparison != '='
+            && values.get(i + 1) == Some(&&ComponentValue::Token(CssToken::Delim('=')));
+        comparisons.push(match (comparison, or_equal) {
+            ('<', false) => Comparison::Lt,
+            ('<', true) => Comparison::Le,
+            ('>', false) => Comparison::Gt,
+            ('>', true) => Comparison::Ge,
+            _ => Comparison::Eq,
+        });
+        i += if or_equal { 2 } else { 1 };
+        start = i;
+    }
+    segments.push(&values[start..]);
+
+    match (segments.as_slice(), comparisons.as_slice()) {
+        ([a, b], [comparison]) => {
+            if let Some(name) = name_of(a) {
+                Some(MediaFeature::Range {
+                    name,
+                    comparisons: Vec::from([(*comparison, parse_value(b)?)]),
+                })
+            } else {
+                Some(MediaFeature::Range {
+                    name: name_of(b)?,
+                    comparisons: Vec::from([(comparison.flip(), parse_value(a)?)]),
+                })
+            }
+        }
+        ([a, name, b], [first, second]) => {
+            // Both comparisons must go the same way.
+            let ascending = matches!(first, Comparison::Lt | Comparison::Le);
+            if ascending != matches!(second, Comparison::Lt | Comparison::Le)
+                || *first == Comparison::Eq
+                || *second == Comparison::Eq
+            {
+                return None;
+            }
+            Some(MediaFeature::Range {
+                name: name_of(name)?,
+                comparisons: Vec::from([
+                    (first.flip(), parse_value(a)?),
+                    (*second, parse_value(b)?),
+                ]),
+            })
+        }
+        _ => None,
+    }
+}
+
+/// https://drafts.csswg.org/mediaqueries-4/#values
+fn parse_value(values: &[&ComponentValue]) -> Option<MediaValue> {
+    match values {
+        [ComponentValue::Token(CssToken::Number { value, .. })] => Some(MediaValue::Number(*value)),
+        [ComponentValue::Token(CssToken::Ident(keyword))] => {
+            Some(MediaValue::Keyword(keyword.to_ascii_lowercase()))
+        }
+        [ComponentValue::Token(CssToken::Dimension { value, unit, .. })] => {
+            match unit.to_ascii_lowercase().as_str() {
+                "dppx" | "x" => Some(MediaValue::Resolution(*value)),
+                "dpi" => Some(MediaValue::Resolution(value / 96.0)),
+                "dpcm" => Some(MediaValue::Resolution(value * 2.54 / 96.0)),
+                unit => {
+                    // Check that it's a length.
+                    LengthContext::default().to_px(*value, unit)?;
+                    Some(MediaValue::Length(*value, unit.into()))
+                }
+            }
+        }
+        // https://drafts.csswg.org/mediaqueries-4/#typedef-ratio
+        [ComponentValue::Token(CssToken::Number { value: a, .. }), ComponentValue::Token(CssToken::Delim('/')), ComponentValue::Token(CssToken::Number { value: b, .. })] => {
+            (*b != 0.0).then_some(MediaValue::Ratio(a / b))
+        }
+        _ => None,
+    }
+}
+
+/// Returns the value of the media feature `name`, or `None` for an unknown
+/// feature.
+/// https://drafts.csswg.org/mediaqueries-5/#media-descriptor-table
+fn actual_value(name: &str, viewport: &Viewport) -> Option<Actual> {
+    Some(match name {
+        "width" => Actual::Length(viewport.width),
+        "height" => Actual::Length(viewport.height),
+        "aspect-ratio" => Actual::Ratio(viewport.width / viewport.height),
+        "orientation" if viewport.height >= viewport.width => Actual::Keyword("portrait"),
+        "orientation" => Actual::Keyword("landscape"),
+        "resolution" => Actual::Resolution(viewport.resolution),
+        "prefers-color-scheme" => Actual::Keyword(match viewport.color_scheme {
+            ColorScheme::Light => "light",
+            ColorScheme::Dark => "dark",
+        }),
+        // Bits per color component.
+        "color" => Actual::Integer(8.0),
+        "monochrome" | "grid" | "color-index" => Actual::Integer(0.0),
+        "hover" | "any-hover" => Actual::Keyword("hover"),
+        "pointer" | "any-pointer" => Actual::Keyword("fine"),
+        _ => return None,
+    })
+}
+
+fn evaluate_feature(feature: &MediaFeature, viewport: &Viewport) -> Option<bool> {
+    match feature {
+        MediaFeature::Boolean(name) => Some(match actual_value(name, viewport)? {
+            Actual::Length(n) | Actual::Ratio(n) | Actual::Resolution(n) | Actual::Integer(n) => {
+                n != 0.0
+            }
+            Actual::Keyword(keyword) => keyword != "none",
+        }),
+        MediaFeature::Range { name, comparisons } => {
+            let actual = actual_value(name, viewport)?;
+            let mut result = true;
+            for (comparison, expected) in comparisons {
+                let (actual, expected) = match (&actual, expected) {
+                    (Actual::Keyword(actual), MediaValue::Keyword(expected)) => {
+                        // Keywords can't be compared with `<` or `>`.
+                        if *comparison != Comparison::Eq {
+                            return None;
+                        }
+                        result &= actual == expected;
+                        continue;
+                    }
+                    (Actual::Length(actual), MediaValue::Length(value, unit)) => {
+                        // Relative lengths in media queries use the initial
+                        // font size.
+                        let context = LengthContext {
+                            font_size: MEDIUM_FONT_SIZE,
+                            root_font_size: MEDIUM_FONT_SIZE,
+                            viewport: *viewport,
+                        };
+                        (*actual, context.to_px(*value, unit)?)
+                    }
+                    (Actual::Length(actual), MediaValue::Number(n)) if *n == 0.0 => (*actual, 0.0),
+                    (Actual::Ratio(actual), MediaValue::Ratio(n) | MediaValue::Number(n))
+                    | (Actual::Resolution(actual), MediaValue::Resolution(n))
+                    | (Actual::Integer(actual), MediaValue::Number(n)) => (*actual, *n),
+                    _ => return None,
+                };
+                result &= comparison.compare(actual, expected);
+            }
+            Some(result)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::renderer::css::parser::parse_component_values;
+
+    fn matches(query: &str, viewport: &Viewport) -> bool {
+        MediaQueryList::parse(&parse_component_values(query)).matches(viewport)
+    }
+
+    const PHONE: Viewport = Viewport {
+        width: 400.0,
+        height: 800.0,
+        resolution: 3.0,
+        color_scheme: ColorScheme::Dark,
+    };
+
+    #[test]
+    fn test_media_types() {
+        let desktop = Viewport::default();
+        assert!(matches("", &desktop));
+        assert!(matches("all", &desktop));
+        assert!(matches("SCREEN", &desktop));
+        assert!(matches("only screen", &desktop));
+        assert!(!matches("print", &desktop));
+        assert!(matches("not print", &desktop));
+        assert!(matches("print, screen", &desktop));
+        assert!(!matches("not screen", &desktop));
+        assert!(!matches("unknown", &desktop));
+    }
+
+    #[test]
+    fn test_media_features() {
+        let desktop = Viewport::default();
+        for query in [
+            "(min-width: 600px)",
+            "screen and (max-width: 50em)",
+            "(width > 799px) and (height = 600px)",
+            "(600px <= width <= 800px)",
+            "(orientation: landscape)",
+            "(aspect-ratio: 4/3)",
+            "(min-aspect-ratio: 1)",
+            "(resolution: 96dpi)",
+            "(prefers-color-scheme: light)",
+            "(color)",
+            "not (monochrome)",
+            "(max-width: 100px) or (hover: hover)",
+            "(not (grid)) and (min-width: 50vw)",
+        ] {
+            assert!(matches(query, &desktop), "{}", query);
+            assert!(!matches(&alloc::format!("not all and {}", query), &desktop));
+        }
+
+        for query in [
+            "(min-width: 600px)",
+            "(width > 400px)",
+            "(orientation: landscape)",
+            "(resolution < 2x)",
+            "(prefers-color-scheme: light)",
+            "(monochrome)",
+        ] {
+            assert!(!matches(query, &PHONE), "{}", query);
+        }
+        assert!(matches(
+            "(max-width: 599.98px) and (orientation: portrait)",
+            &PHONE
+        ));
+        assert!(matches("(min-resolution: 2dppx)", &PHONE));
+    }
+
+    #[test]
+    fn test_invalid_and_unknown() {
+        let desktop = Viewport::default();
+        // An invalid query is `not all`, but the others in the list still
+        // count.
+        assert!(!matches("screen and", &desktop));
+        assert!(!matches(
+            "(width: 1px) and (height: 1px) or (color)",
+            &desktop
+        ));
+        assert!(matches("screen and, screen", &desktop));
+        assert!(!matches("screen and (color) or (grid)", &desktop));
+        assert!(!matches("(100px < width > 200px)", &desktop));
+
+        // An unknown feature is neither true nor false.
+        assert!(!matches("(unknown)", &desktop));
+        assert!(!matches("not (unknown)", &desktop));
+        assert!(matches("(unknown) or (color)", &desktop));
+        assert!(!matches("(orientation > landscape)", &desktop));
+        assert!(!matches("(foo(1))", &desktop));
+    }
+}
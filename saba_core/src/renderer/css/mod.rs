@@ -0,0 +1,12 @@
+pub mod cascade;
+pub mod condition;
+pub mod cssom;
+pub mod loader;
+pub mod media;
+pub mod parser;
+pub mod rule_hash;
+pub mod style;
+pub mod supports;
+pub mod token;
+pub mod value;
+pub mod variables;
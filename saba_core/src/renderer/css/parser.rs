@@ -0,0 +1,567 @@
+//! Turns CSS tokens into rules and declarations.
+//! https://drafts.csswg.org/css-syntax-3/#parsing
+//!
+//! Parsing happens in two steps. The tokens are first grouped into component
+//! values, so that functions and blocks are single values whose contents are
+//! nested in them. Rules and declarations are then read from the component
+//! values. Invalid rules and declarations are dropped, and parsing goes on
+//! after them.
+
+use crate::renderer::css::cssom::{
+    AtRule, CssRule, Declaration, ImportRule, MediaRule, QualifiedRule, StyleSheet, SupportsRule,
+};
+use crate::renderer::css::media::MediaQueryList;
+use crate::renderer::css::supports::SupportsCondition;
+use crate::renderer::css::token::{CssToken, CssTokenizer};
+use crate::renderer::dom::selector::parse_selector_list_from_values;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::iter::Peekable;
+use core::slice::Iter;
+
+/// https://drafts.csswg.org/css-syntax-3/#component-value
+#[derive(Debug, Clone, PartialEq)]
+pub enum ComponentValue {
+    /// A token that isn't a function or a block.
+    Token(CssToken),
+    /// `name(arguments)`
+    Function {
+        name: String,
+        arguments: Vec<ComponentValue>,
+    },
+    /// `(...)`, `[...]` or `{...}`
+    Block {
+        kind: BlockKind,
+        contents: Vec<ComponentValue>,
+    },
+}
+
+/// The brackets around a block.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum BlockKind {
+    Parenthesis,
+    SquareBracket,
+    Curly,
+}
+
+impl ComponentValue {
+    pub fn is_whitespace(&self) -> bool {
+        *self == ComponentValue::Token(CssToken::Whitespace)
+    }
+}
+
+/// Parses `css` as a `<style>` element's text or a linked style sheet.
+/// https://drafts.csswg.org/css-syntax-3/#parse-a-css-stylesheet
+pub fn parse_stylesheet(css: &str) -> StyleSheet {
+    StyleSheet::new(consume_rule_list(
+        &parse_component_values(css),
+        /* top_level */ true,
+    ))
+}
+
+/// https://drafts.csswg.org/css-syntax-3/#parse-list-of-component-values
+pub fn parse_component_values(css: &str) -> Vec<ComponentValue> {
+    let mut tokens = CssTokenizer::new(css.to_string());
+    consume_component_values(&mut tokens, None)
+}
+
+/// Consumes component values until `closing`, which is consumed too, or the
+/// end of the input. An unclosed function or block ends at the end of the
+/// input.
+fn consume_component_values(
+    tokens: &mut impl Iterator<Item = CssToken>,
+    closing: Option<CssToken>,
+) -> Vec<ComponentValue> {
+    let mut values = Vec::new();
+    while let Some(token) = tokens.next() {
+        if Some(&token) == closing.as_ref() {
+            break;
+        }
+        values.push(consume_component_value(token, tokens));
+    }
+    values
+}
+
+/// https://drafts.csswg.org/css-syntax-3/#consume-component-value
+fn consume_component_value(
+    token: CssToken,
+    tokens: &mut impl Iterator<Item = CssToken>,
+) -> ComponentValue {
+    let (kind, closing) = match token {
+        CssToken::Function(name) => {
+            return ComponentValue::Function {
+                name,
+                arguments: consume_component_values(tokens, Some(CssToken::CloseParenthesis)),
+            }
+        }
+        CssToken::OpenParenthesis => (BlockKind::Parenthesis, CssToken::CloseParenthesis),
+        CssToken::OpenSquareBracket => (BlockKind::SquareBracket, CssToken::CloseSquareBracket),
+        CssToken::OpenCurly => (BlockKind::Curly, CssToken::CloseCurly),
+        token => return ComponentValue::Token(token),
+    };
+
+    ComponentValue::Block {
+        kind,
+        contents: consume_component_values(tokens, Some(closing)),
+    }
+}
+
+/// Parses the rules in the block of an at-rule like `@media`.
+/// https://drafts.csswg.org/css-syntax-3/#consume-list-of-rules
+pub fn parse_rule_list(values: &[ComponentValue]) -> Vec<CssRule> {
+    consume_rule_list(values, /* top_level */ false)
+}
+
+/// `<!--` and `-->` are ignored at the top level of a style sheet, for the
+/// old trick of hiding `<style>` contents from browsers without CSS.
+/// `@import` is only allowed at the top level before any other rule except
+/// `@charset`.
+fn consume_rule_list(values: &[ComponentValue], top_level: bool) -> Vec<CssRule> {
+    let mut rules = Vec::new();
+    let mut values = values.iter().peekable();
+    let mut imports_allowed = top_level;
+
+    while let Some(value) = values.next() {
+        match value {
+            ComponentValue::Token(CssToken::Whitespace) => {}
+            ComponentValue::Token(CssToken::Cdo) | ComponentValue::Token(CssToken::Cdc)
+                if top_level => {}
+            ComponentValue::Token(CssToken::AtKeyword(name)) => {
+                let rule = match parse_at_rule(consume_at_rule(name, &mut values)) {
+                    Some(rule) => rule,
+                    None => continue,
+                };
+                match rule {
+                    CssRule::Import(_) if !imports_allowed => continue,
+                    CssRule::Import(_) => {}
+                    CssRule::At(ref rule) if rule.name == "charset" => {}
+                    _ => imports_allowed = false,
+                }
+                rules.push(rule);
+            }
+            _ => {
+                imports_allowed = false;
+                if let Some(rule) = consume_qualified_rule(value, &mut values) {
+                    rules.push(CssRule::Qualified(rule));
+                }
+            }
+        }
+    }
+
+    rules
+}
+
+/// https://drafts.csswg.org/css-syntax-3/#consume-at-rule
+fn consume_at_rule(name: &str, values: &mut Peekable<Iter<ComponentValue>>) -> AtRule {
+    let mut prelude = Vec::new();
+    let mut block = None;
+
+    for value in values.by_ref() {
+        match value {
+            ComponentValue::Token(CssToken::Semicolon) => break,
+            ComponentValue::Block {
+                kind: BlockKind::Curly,
+                contents,
+            } => {
+                block = Some(contents.clone());
+                break;
+            }
+            _ => prelude.push(value.clone()),
+        }
+    }
+
+    AtRule {
+        name: name.to_ascii_lowercase(),
+        prelude: trim_whitespace(&prelude).to_vec(),
+        block,
+    }
+}
+
+/// Parses `@import` and the conditional at-rules, whose blocks contain
+/// rules. Returns `None` for an invalid one, which is dropped.
+/// https://drafts.csswg.org/css-conditional-3/#processing
+fn parse_at_rule(rule: AtRule) -> Option<CssRule> {
+    if rule.name == "import" {
+        return parse_import_rule(&rule).map(CssRule::Import);
+    }
+    let block = match (rule.name.as_str(), &rule.block) {
+        ("media" | "supports", Some(block)) => block,
+        ("media" | "supports", None) => return None,
+        _ => return Some(CssRule::At(rule)),
+    };
+    let rules = parse_rule_list(block);
+    Some(if rule.name == "media" {
+        CssRule::Media(MediaRule {
+            media: MediaQueryList::parse(&rule.prelude),
+            rules,
+        })
+    } else {
+        CssRule::Supports(SupportsRule {
+            condition: SupportsCondition::parse(&rule.prelude)?,
+            rules,
+        })
+    })
+}
+
+/// Parses `@import "a.css";`, `@import url(a.css) print;` and so on.
+/// https://drafts.csswg.org/css-cascade-4/#at-import
+fn parse_import_rule(rule: &AtRule) -> Option<ImportRule> {
+    if rule.block.is_some() {
+        return None;
+    }
+    let (href, media) = rule.prelude.split_first()?;
+    let href = match href {
+        ComponentValue::Token(CssToken::String(href))
+        | ComponentValue::Token(CssToken::Url(href)) => href.clone(),
+        ComponentValue::Function { name, arguments } if name.eq_ignore_ascii_case("url") => {
+            match trim_whitespace(arguments) {
+                [ComponentValue::Token(CssToken::String(href))] => href.clone(),
+                _ => return None,
+            }
+        }
+        _ => return None,
+    };
+    Some(ImportRule {
+        href,
+        media: MediaQueryList::parse(media),
+        style_sheet: None,
+    })
+}
+
+/// Consumes a rule that starts with `first`. Returns `None` if the input ends
+/// before the block, or the selectors are invalid, which drops the rule.
+/// https://drafts.csswg.org/css-syntax-3/#consume-qualified-rule
+fn consume_qualified_rule(
+    first: &ComponentValue,
+    values: &mut Peekable<Iter<ComponentValue>>,
+) -> Option<QualifiedRule> {
+    let mut prelude = Vec::new();
+    let mut value = Some(first);
+
+    while let Some(v) = value {
+        if let ComponentValue::Block {
+            kind: BlockKind::Curly,
+            contents,
+        } = v
+        {
+            let selectors = parse_selector_list_from_values(&prelude).ok()?;
+            return Some(QualifiedRule {
+                selectors,
+                declarations: parse_declaration_list(contents),
+            });
+        }
+
+        prelude.push(v.clone());
+        value = values.next();
+    }
+
+    None
+}
+
+/// Parses the contents of a `{}` block or a `style` attribute. Invalid
+/// declarations are skipped up to the next `;`, and at-rules up to the next
+/// `;` or the end of their `{}` block.
+/// https://drafts.csswg.org/css-syntax-3/#consume-a-list-of-declarations
+pub fn parse_declaration_list(values: &[ComponentValue]) -> Vec<Declaration> {
+    let is_semicolon = |v: &ComponentValue| *v == ComponentValue::Token(CssToken::Semicolon);
+
+    let mut declarations = Vec::new();
+    let mut rest = values;
+    while let Some(first) = rest.first() {
+        let end = match first {
+            v if v.is_whitespace() || is_semicolon(v) => 1,
+            ComponentValue::Token(CssToken::AtKeyword(_)) => {
+                let is_end = |v: &ComponentValue| {
+                    is_semicolon(v)
+                        || matches!(
+                            v,
+                            ComponentValue::Block {
+                                kind: BlockKind::Curly,
+                                ..
+                            }
+                        )
+                };
+                rest.iter().position(is_end).map_or(rest.len(), |i| i + 1)
+            }
+            _ => {
+                let end = rest.iter().position(is_semicolon).unwrap_or(rest.len());
+                declarations.extend(parse_declaration(&rest[..end]));
+                (end + 1).min(rest.len())
+            }
+        };
+        rest = &rest[end..];
+    }
+    declarations
+}
+
+/// https://drafts.csswg.org/css-syntax-3/#consume-declaration
+fn parse_declaration(values: &[ComponentValue]) -> Option<Declaration> {
+    let values = trim_whitespace(values);
+    let (name, rest) = match values.split_first()? {
+        (ComponentValue::Token(CssToken::Ident(name)), rest) => (name, rest),
+        _ => return None,
+    };
+    let rest = match trim_whitespace(rest).split_first() {
+        Some((ComponentValue::Token(CssToken::Colon), rest)) => rest,
+        _ => return None,
+    };
+
+    let value = trim_whitespace(rest);
+    let (value, important) = match strip_important(value) {
+        Some(value) => (value, true),
+        None => (value, false),
+    };
+
+    Some(Declaration {
+        name: if name.starts_with("--") {
+            name.clone()
+        } else {
+            name.to_ascii_lowercase()
+        },
+        value: value.to_vec(),
+        important,
+    })
+}
+
+/// Returns the value before a trailing `!important`, or `None` if there is
+/// none. Whitespace can go between `!` and `important`.
+fn strip_important(value: &[ComponentValue]) -> Option<&[ComponentValue]> {
+    let (last, rest) = value.split_last()?;
+    match last {
+        ComponentValue::Token(CssToken::Ident(s)) if s.eq_ignore_ascii_case("important") => {}
+        _ => return None,
+    }
+
+    match trim_whitespace(rest).split_last()? {
+        (ComponentValue::Token(CssToken::Delim('!')), rest) => Some(trim_whitespace(rest)),
+        _ => None,
+    }
+}
+
+/// Returns `values` without the whitespace at the start and the end.
+pub fn trim_whitespace(values: &[ComponentValue]) -> &[ComponentValue] {
+    let start = values
+        .iter()
+        .position(|v| !v.is_whitespace())
+        .unwrap_or(values.len());
+    let end = values
+        .iter()
+        .rposition(|v| !v.is_whitespace())
+        .map_or(start, |i| i + 1);
+    &values[start..end]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::renderer::dom::selector::parse_selector_list;
+    use alloc::vec;
+
+    fn ident(s: &str) -> ComponentValue {
+        ComponentValue::Token(CssToken::Ident(s.to_string()))
+    }
+
+    fn qualified_rules(sheet: &StyleSheet) -> Vec<QualifiedRule> {
+        sheet
+            .rules
+            .iter()
+            .filter_map(|r| match r {
+                CssRule::Qualified(rule) => Some(rule.clone()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_component_values() {
+        assert_eq!(
+            parse_component_values("a(b [c) d"),
+            vec![ComponentValue::Function {
+                name: "a".to_string(),
+                arguments: vec![
+                    ident("b"),
+                    ComponentValue::Token(CssToken::Whitespace),
+                    ComponentValue::Block {
+                        kind: BlockKind::SquareBracket,
+                        contents: vec![
+                            ident("c"),
+                            ComponentValue::Token(CssToken::CloseParenthesis),
+                            ComponentValue::Token(CssToken::Whitespace),
+                            ident("d"),
+                        ],
+                    },
+                ],
+            }]
+        );
+    }
+
+    #[test]
+    fn test_stylesheet() {
+        let sheet = parse_stylesheet(
+            "<!-- p, .a > b { COLOR: red; margin : 0 auto !IMPORTANT ; --X: { 1 } } -->",
+        );
+        let rules = qualified_rules(&sheet);
+        assert_eq!(rules.len(), 1);
+        assert_eq!(
+            rules[0].selectors,
+            parse_selector_list("p, .a > b").expect("failed to parse")
+        );
+        assert_eq!(
+            rules[0].declarations,
+            vec![
+                Declaration {
+                    name: "color".to_string(),
+                    value: vec![ident("red")],
+                    important: false,
+                },
+                Declaration {
+                    name: "margin".to_string(),
+                    value: vec![
+                        ComponentValue::Token(CssToken::Number {
+                            value: 0.0,
+                            is_integer: true
+                        }),
+                        ComponentValue::Token(CssToken::Whitespace),
+                        ident("auto"),
+                    ],
+                    important: true,
+                },
+                Declaration {
+                    name: "--X".to_string(),
+                    value: vec![ComponentValue::Block {
+                        kind: BlockKind::Curly,
+                        contents: vec![
+                            ComponentValue::Token(CssToken::Whitespace),
+                            ComponentValue::Token(CssToken::Number {
+                                value: 1.0,
+                                is_integer: true
+                            }),
+                            ComponentValue::Token(CssToken::Whitespace),
+                        ],
+                    }],
+                    important: false,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_at_rules() {
+        let sheet =
+            parse_stylesheet("@import 'a.css'; @font-face { src: x } @MEDIA screen { p {} } a {}");
+        assert_eq!(sheet.rules.len(), 4);
+        assert_eq!(
+            sheet.rules[0],
+            CssRule::Import(ImportRule {
+                href: "a.css".to_string(),
+                media: MediaQueryList::default(),
+                style_sheet: None,
+            })
+        );
+
+        let block = match sheet.rules[1] {
+            CssRule::At(ref rule) => {
+                assert_eq!(rule.name, "font-face");
+                rule.block.clone().expect("no block")
+            }
+            _ => panic!("not an at-rule"),
+        };
+        assert_eq!(parse_declaration_list(&block).len(), 1);
+
+        match sheet.rules[2] {
+            CssRule::Media(ref rule) => {
+                assert_eq!(
+                    rule.media,
+                    MediaQueryList::parse(&parse_component_values("screen"))
+                );
+                assert_eq!(rule.rules.len(), 1);
+                assert!(matches!(rule.rules[0], CssRule::Qualified(_)));
+            }
+            _ => panic!("not a media rule"),
+        };
+    }
+
+    #[test]
+    fn test_import_rules() {
+        let sheet = parse_stylesheet(
+            "@charset 'utf-8'; @import url(a.css); @import url('b.css') print, screen;
+            @import \"c.css\" { } @import 1; @import 'd.css';
+            p {} @import 'e.css'; @media screen { @import 'f.css'; }",
+        );
+        let hrefs: Vec<String> = sheet
+            .rules
+            .iter()
+            .filter_map(|r| match r {
+                CssRule::Import(rule) => Some(rule.href.clone()),
+                _ => None,
+            })
+            .collect();
+        // `@import` after other rules, or in a block, is ignored.
+        assert_eq!(hrefs, vec!["a.css", "b.css", "d.css"]);
+        match sheet.rules[2] {
+            CssRule::Import(ref rule) => assert_eq!(
+                rule.media,
+                MediaQueryList::parse(&parse_component_values("print, screen"))
+            ),
+            _ => panic!("not an import rule"),
+        }
+        match sheet.rules[5] {
+            CssRule::Media(ref rule) => assert!(rule.rules.is_empty()),
+            _ => panic!("not a media rule"),
+        }
+    }
+
+    #[test]
+    fn test_conditional_rules() {
+        // `@supports` with an invalid condition is dropped, and so is a
+        // conditional rule without a block.
+        let sheet = parse_stylesheet(
+            "@supports (color: red) { @media print { a {} } b {} }
+            @supports color: red { c {} }
+            @media screen;
+            d {}",
+        );
+        assert_eq!(sheet.rules.len(), 2);
+        let rules = match sheet.rules[0] {
+            CssRule::Supports(ref rule) => {
+                assert!(rule.condition.matches());
+                &rule.rules
+            }
+            _ => panic!("not a supports rule"),
+        };
+        assert_eq!(rules.len(), 2);
+        assert!(matches!(rules[0], CssRule::Media(_)));
+        assert!(matches!(sheet.rules[1], CssRule::Qualified(_)));
+    }
+
+    #[test]
+    fn test_error_recovery() {
+        // A rule with an invalid selector is dropped as a whole, and so is an
+        // unterminated rule at the end.
+        let sheet = parse_stylesheet("p!x { color: red } q { color: blue } r");
+        let rules = qualified_rules(&sheet);
+        assert_eq!(rules.len(), 1);
+        assert_eq!(
+            rules[0].selectors,
+            parse_selector_list("q").expect("failed to parse")
+        );
+
+        // Invalid declarations are dropped up to the next `;`, and at-rules
+        // up to the end of their block.
+        let declarations = parse_declaration_list(&parse_component_values(
+            "color; : red; 1px: x; width: 1px; @foo { a; b } height:2px; @bar; x: 1 ! important",
+        ));
+        let names: Vec<(String, bool)> = declarations
+            .iter()
+            .map(|d| (d.name.clone(), d.important))
+            .collect();
+        assert_eq!(
+            names,
+            [
+                ("width".to_string(), false),
+                ("height".to_string(), false),
+                ("x".to_string(), true),
+            ]
+        );
+    }
+}
@@ -0,0 +1,130 @@
+//! An index of the selectors of style rules by the ID, class or type that an
+//! element must have to match them, so that styling an element only tries
+//! the selectors that it might match.
+
+use crate::renderer::css::cssom::QualifiedRule;
+use crate::renderer::dom::node::Node;
+use crate::renderer::dom::selector::SubjectKey;
+use alloc::collections::BTreeMap;
+use alloc::rc::Rc;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::cell::RefCell;
+
+/// `selectors[selector]` of the `rule`th rule.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Candidate {
+    pub rule: usize,
+    pub selector: usize,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct RuleHash {
+    ids: BTreeMap<String, Vec<Candidate>>,
+    classes: BTreeMap<String, Vec<Candidate>>,
+    types: BTreeMap<String, Vec<Candidate>>,
+    /// The selectors that any element might match, like `*` and `[hidden]`.
+    universal: Vec<Candidate>,
+}
+
+impl RuleHash {
+    pub fn new<'a>(rules: impl IntoIterator<Item = &'a QualifiedRule>) -> Self {
+        let mut rule_hash = Self::default();
+        for (rule_index, rule) in rules.into_iter().enumerate() {
+            for (selector_index, selector) in rule.selectors.iter().enumerate() {
+                let candidate = Candidate {
+                    rule: rule_index,
+                    selector: selector_index,
+                };
+                let (map, key) = match selector.subject_key() {
+                    SubjectKey::Id(id) => (&mut rule_hash.ids, id),
+                    SubjectKey::Class(class) => (&mut rule_hash.classes, class),
+                    SubjectKey::Type(name) => (&mut rule_hash.types, name),
+                    SubjectKey::Universal => {
+                        rule_hash.universal.push(candidate);
+                        continue;
+                    }
+                };
+                map.entry(key.to_string()).or_default().push(candidate);
+            }
+        }
+        rule_hash
+    }
+
+    /// Returns the selectors that the element `node` might match, in the
+    /// order of the rules. Each selector is in only one list, so none is
+    /// repeated.
+    pub fn candidates(&self, node: &Rc<RefCell<Node>>) -> Vec<Candidate> {
+        let element = match node.borrow().get_element() {
+            Some(element) => element,
+            None => return Vec::new(),
+        };
+
+        let mut candidates = self.universal.clone();
+        let mut extend = |list: Option<&Vec<Candidate>>| {
+            candidates.extend(list.into_iter().flatten());
+        };
+        if let Some(id) = element.get_attribute("id") {
+            extend(self.ids.get(&id));
+        }
+        for class in element.class_list().iter() {
+            extend(self.classes.get(class));
+        }
+        extend(self.types.get(&element.tag_name().to_ascii_lowercase()));
+
+        candidates.sort_unstable();
+        candidates
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::renderer::css::cssom::CssRule;
+    use crate::renderer::css::parser::parse_stylesheet;
+    use crate::renderer::dom::query::descendant_elements;
+    use crate::renderer::html::parser::HtmlParser;
+    use crate::renderer::html::token::HtmlTokenizer;
+    use alloc::vec;
+
+    #[test]
+    fn test_candidates() {
+        let style_sheet = parse_stylesheet(
+            "div.a, #b { }
+             p { }
+             .c .a { }
+             * { }
+             .a:hover, DIV > span#x, [title] { }",
+        );
+        let rules: Vec<QualifiedRule> = style_sheet
+            .rules
+            .into_iter()
+            .filter_map(|r| match r {
+                CssRule::Qualified(rule) => Some(rule),
+                _ => None,
+            })
+            .collect();
+        let rule_hash = RuleHash::new(&rules);
+
+        // The candidates for the last element in `html`.
+        let candidates = |html: &str| {
+            let window = HtmlParser::new(HtmlTokenizer::new(html.to_string())).construct_tree();
+            let node = descendant_elements(&window.borrow().document())
+                .last()
+                .expect("no element");
+            rule_hash
+                .candidates(&node)
+                .iter()
+                .map(|c| (c.rule, c.selector))
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(candidates("<span>"), vec![(3, 0), (4, 2)]);
+        assert_eq!(candidates("<p>"), vec![(1, 0), (3, 0), (4, 2)]);
+        assert_eq!(
+            candidates("<span class='a c' id=b>"),
+            vec![(0, 0), (0, 1), (2, 0), (3, 0), (4, 0), (4, 2)]
+        );
+        assert_eq!(candidates("<span id=x>"), vec![(3, 0), (4, 1), (4, 2)]);
+    }
+}
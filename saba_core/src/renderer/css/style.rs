@@ -0,0 +1,539 @@
+//! The properties that the style engine knows, and the computed style of an
+//! element.
+//! https://drafts.csswg.org/css-cascade-4/#computed
+
+use crate::renderer::css::media::Viewport;
+use crate::renderer::css::parser::{parse_component_values, trim_whitespace, ComponentValue};
+use crate::renderer::css::token::CssToken;
+use crate::renderer::css::value::{
+    parse_length_percentage, parse_number, Color, LengthContext, LengthPercentage, MEDIUM_FONT_SIZE,
+};
+use alloc::collections::BTreeMap;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+
+/// https://drafts.csswg.org/css-backgrounds-3/#typedef-line-style
+pub const BORDER_STYLES: [&str; 10] = [
+    "none", "hidden", "dotted", "dashed", "solid", "double", "groove", "ridge", "inset", "outset",
+];
+
+/// The values that a property accepts, which decides how it's computed.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum Kind {
+    Color,
+    /// One of the keywords.
+    Keyword(&'static [&'static str]),
+    /// `thin`, `medium`, `thick` or a non-negative length.
+    BorderWidth,
+    /// Kept as it is.
+    FontFamily,
+    FontSize,
+    FontWeight,
+    LineHeight,
+    /// `auto` or a length-percentage, like `margin-top`.
+    LengthPercentageOrAuto,
+    /// `auto` or a non-negative length-percentage, like `width`.
+    SizeOrAuto,
+    /// A non-negative length-percentage, like `padding-top`.
+    Size,
+}
+
+/// A longhand property, whether it's inherited, its initial value and the
+/// values it accepts.
+struct Property {
+    name: &'static str,
+    inherited: bool,
+    initial: &'static str,
+    kind: Kind,
+}
+
+const fn property(
+    name: &'static str,
+    inherited: bool,
+    initial: &'static str,
+    kind: Kind,
+) -> Property {
+    Property {
+        name,
+        inherited,
+        initial,
+        kind,
+    }
+}
+
+const DISPLAYS: Kind = Kind::Keyword(&["block", "inline", "inline-block", "list-item", "none"]);
+const LINE_STYLES: Kind = Kind::Keyword(&BORDER_STYLES);
+
+/// Sorted by name.
+const PROPERTIES: [Property; 34] = [
+    property("background-color", false, "transparent", Kind::Color),
+    property("border-bottom-color", false, "currentcolor", Kind::Color),
+    property("border-bottom-style", false, "none", LINE_STYLES),
+    property("border-bottom-width", false, "medium", Kind::BorderWidth),
+    property("border-left-color", false, "currentcolor", Kind::Color),
+    property("border-left-style", false, "none", LINE_STYLES),
+    property("border-left-width", false, "medium", Kind::BorderWidth),
+    property("border-right-color", false, "currentcolor", Kind::Color),
+    property("border-right-style", false, "none", LINE_STYLES),
+    property("border-right-width", false, "medium", Kind::BorderWidth),
+    property("border-top-color", false, "currentcolor", Kind::Color),
+    property("border-top-style", false, "none", LINE_STYLES),
+    property("border-top-width", false, "medium", Kind::BorderWidth),
+    property("color", true, "black", Kind::Color),
+    property("display", false, "inline", DISPLAYS),
+    property("font-family", true, "serif", Kind::FontFamily),
+    property("font-size", true, "medium", Kind::FontSize),
+    property(
+        "font-style",
+        true,
+        "normal",
+        Kind::Keyword(&["normal", "italic", "oblique"]),
+    ),
+    property("font-weight", true, "normal", Kind::FontWeight),
+    property("height", false, "auto", Kind::SizeOrAuto),
+    property("line-height", true, "normal", Kind::LineHeight),
+    property("margin-bottom", false, "0", Kind::LengthPercentageOrAuto),
+    property("margin-left", false, "0", Kind::LengthPercentageOrAuto),
+    property("margin-right", false, "0", Kind::LengthPercentageOrAuto),
+    property("margin-top", false, "0", Kind::LengthPercentageOrAuto),
+    property("padding-bottom", false, "0", Kind::Size),
+    property("padding-left", false, "0", Kind::Size),
+    property("padding-right", false, "0", Kind::Size),
+    property("padding-top", false, "0", Kind::Size),
+    property(
+        "text-align",
+        true,
+        "start",
+        Kind::Keyword(&["start", "end", "left", "right", "center", "justify"]),
+    ),
+    property(
+        "text-decoration",
+        false,
+        "none",
+        Kind::Keyword(&["none", "underline", "overline", "line-through"]),
+    ),
+    property(
+        "visibility",
+        true,
+        "visible",
+        Kind::Keyword(&["visible", "hidden", "collapse"]),
+    ),
+    property(
+        "white-space",
+        true,
+        "normal",
+        Kind::Keyword(&[
+            "normal",
+            "pre",
+            "nowrap",
+            "pre-wrap",
+            "pre-line",
+            "break-spaces",
+        ]),
+    ),
+    property("width", false, "auto", Kind::SizeOrAuto),
+];
+
+fn find_property(name: &str) -> Option<&'static Property> {
+    PROPERTIES.iter().find(|p| p.name == name)
+}
+
+/// Returns the names of the longhand properties that the style engine knows.
+pub fn property_names() -> impl Iterator<Item = &'static str> {
+    PROPERTIES.iter().map(|p| p.name)
+}
+
+/// Returns whether `name` is a longhand property that the style engine
+/// knows.
+pub fn is_longhand(name: &str) -> bool {
+    find_property(name).is_some()
+}
+
+/// https://drafts.csswg.org/css-cascade-4/#inherited-property
+pub fn is_inherited(name: &str) -> bool {
+    find_property(name).is_some_and(|p| p.inherited)
+}
+
+/// https://drafts.csswg.org/css-cascade-4/#initial-value
+pub fn initial_value(name: &str) -> Option<Vec<ComponentValue>> {
+    find_property(name).map(|p| parse_component_values(p.initial))
+}
+
+/// Returns the lowercased keyword if `value` is a single identifier.
+pub fn keyword(value: &[ComponentValue]) -> Option<String> {
+    match value {
+        [ComponentValue::Token(CssToken::Ident(s))] => Some(s.to_ascii_lowercase()),
+        _ => None,
+    }
+}
+
+/// https://drafts.csswg.org/css-cascade-4/#defaulting-keywords
+pub fn is_css_wide_keyword(value: &[ComponentValue]) -> bool {
+    matches!(
+        keyword(value).as_deref(),
+        Some("inherit") | Some("initial") | Some("unset")
+    )
+}
+
+/// Returns whether `name` is a known longhand and `value` is a valid value
+/// of it. Invalid declarations are ignored by the cascade.
+/// https://drafts.csswg.org/css-syntax-3/#css-parse-something-according-to-a-css-grammar
+pub fn is_valid(name: &str, value: &[ComponentValue]) -> bool {
+    if find_property(name).is_none() {
+        return false;
+    }
+    if is_css_wide_keyword(value) {
+        return true;
+    }
+    let style = ComputedStyle::default();
+    let context = ComputeContext {
+        parent: None,
+        style: &style,
+        viewport: Viewport::default(),
+    };
+    compute_value(name, value, &context).is_some()
+}
+
+/// A computed value.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    /// A lowercased keyword, like `block` or `auto`.
+    Keyword(String),
+    Number(f64),
+    /// A length in pixels, maybe with a percentage that's resolved in
+    /// layout.
+    Length(LengthPercentage),
+    Color(Color),
+    /// A value that the style engine doesn't interpret, like a list of font
+    /// families.
+    Other(Vec<ComponentValue>),
+}
+
+/// What a specified value is computed against.
+#[derive(Debug, Copy, Clone)]
+pub struct ComputeContext<'a> {
+    /// The computed style of the parent element. The root element has none.
+    pub parent: Option<&'a ComputedStyle>,
+    /// The values of the element that are already computed. Other values
+    /// depend on `font-size`, `color` and the border styles, so those must be
+    /// computed first.
+    pub style: &'a ComputedStyle,
+    pub viewport: Viewport,
+}
+
+impl ComputeContext<'_> {
+    fn parent_font_size(&self) -> f64 {
+        self.parent.map_or(MEDIUM_FONT_SIZE, |p| p.font_size())
+    }
+
+    /// `em` is relative to the element's own font size, except in
+    /// `font-size` itself where it's relative to the parent's.
+    fn lengths(&self, name: &str) -> LengthContext {
+        if name == "font-size" {
+            return LengthContext {
+                font_size: self.parent_font_size(),
+                root_font_size: self.parent.map_or(MEDIUM_FONT_SIZE, |p| p.root_font_size()),
+                viewport: self.viewport,
+            };
+        }
+        LengthContext {
+            font_size: self.style.font_size(),
+            root_font_size: self
+                .parent
+                .map_or(self.style.font_size(), |p| p.root_font_size()),
+            viewport: self.viewport,
+        }
+    }
+}
+
+/// Turns a specified value into a computed value, which is what children
+/// inherit, or returns `None` if `value` isn't valid for `name`. Lengths
+/// become pixels and colors become RGBA.
+/// https://drafts.csswg.org/css-cascade-4/#computed
+pub fn compute_value(
+    name: &str,
+    value: &[ComponentValue],
+    context: &ComputeContext,
+) -> Option<Value> {
+    let value = trim_whitespace(value);
+    let kind = find_property(name)?.kind;
+    let lengths = context.lengths(name);
+    // A negative `calc()` is valid where negative values aren't, and is
+    // clamped when it's used.
+    // https://drafts.csswg.org/css-values-4/#calc-range
+    let is_calc = matches!(value, [ComponentValue::Function { .. }]);
+    let non_negative = |length: LengthPercentage| {
+        (is_calc || (length.px >= 0.0 && length.percent >= 0.0)).then_some(Value::Length(length))
+    };
+
+    match kind {
+        Kind::Color => {
+            // https://drafts.csswg.org/css-color-4/#resolving-other-colors
+            let current_color = if name == "color" {
+                context.parent.map_or(Color::BLACK, |p| p.color("color"))
+            } else {
+                context.style.color("color")
+            };
+            Color::parse(value, current_color).map(Value::Color)
+        }
+        Kind::Keyword(keywords) => {
+            let keyword = keyword(value)?;
+            keywords
+                .contains(&keyword.as_str())
+                .then_some(Value::Keyword(keyword))
+        }
+        Kind::BorderWidth => {
+            // https://drafts.csswg.org/css-backgrounds-3/#border-width
+            let width = match keyword(value).as_deref() {
+                Some("thin") => LengthPercentage::px(1.0),
+                Some("medium") => LengthPercentage::px(3.0),
+                Some("thick") => LengthPercentage::px(5.0),
+                _ => parse_length_percentage(value, &lengths)
+                    .filter(|l| l.percent == 0.0 && (is_calc || l.px >= 0.0))?,
+            };
+            // The width is zero when there's no border.
+            let style_name = name.replace("-width", "-style");
+            match context.style.get(&style_name) {
+                Some(Value::Keyword(style)) if style == "none" || style == "hidden" => {
+                    Some(Value::Length(LengthPercentage::default()))
+                }
+                _ => Some(Value::Length(LengthPercentage::px(width.px.max(0.0)))),
+            }
+        }
+        Kind::FontFamily => (!value.is_empty()).then(|| Value::Other(value.to_vec())),
+        Kind::FontSize => {
+            // https://drafts.csswg.org/css-fonts-4/#font-size-prop
+            let parent_size = context.parent_font_size();
+            let size = match keyword(value).as_deref() {
+                Some("xx-small") => 9.0,
+                Some("x-small") => 10.0,
+                Some("small") => 13.0,
+                Some("medium") => MEDIUM_FONT_SIZE,
+                Some("large") => 18.0,
+                Some("x-large") => 24.0,
+                Some("xx-large") => 32.0,
+                Some("xxx-large") => 48.0,
+                Some("smaller") => parent_size / 1.2,
+                Some("larger") => parent_size * 1.2,
+                _ => {
+                    let length = parse_length_percentage(value, &lengths)?;
+                    length.resolve(parent_size)
+                }
+            };
+            let size = if is_calc { size.max(0.0) } else { size };
+            (size >= 0.0).then_some(Value::Length(LengthPercentage::px(size)))
+        }
+        Kind::FontWeight => {
+            let parent_weight = context.parent.map_or(400, |p| p.font_weight());
+            font_weight(value, parent_weight).map(|w| Value::Number(w as f64))
+        }
+        Kind::LineHeight => {
+            // https://drafts.csswg.org/css-inline-3/#line-height-property
+            // A number is inherited as a number, so that it's relative to the
+            // font size of each element, but a percentage isn't.
+            if keyword(value).as_deref() == Some("normal") {
+                return Some(Value::Keyword("normal".to_string()));
+            }
+            if let Some(number) = parse_number(value) {
+                return (number >= 0.0).then_some(Value::Number(number));
+            }
+            let length = parse_length_percentage(value, &lengths)?;
+            let px = length.resolve(lengths.font_size);
+            let px = if is_calc { px.max(0.0) } else { px };
+            (px >= 0.0).then_some(Value::Length(LengthPercentage::px(px)))
+        }
+        Kind::LengthPercentageOrAuto | Kind::SizeOrAuto
+            if keyword(value).as_deref() == Some("auto") =>
+        {
+            Some(Value::Keyword("auto".to_string()))
+        }
+        Kind::LengthPercentageOrAuto => parse_length_percentage(value, &lengths).map(Value::Length),
+        Kind::SizeOrAuto | Kind::Size => non_negative(parse_length_percentage(value, &lengths)?),
+    }
+}
+
+/// https://drafts.csswg.org/css-fonts-4/#relative-weights
+fn font_weight(value: &[ComponentValue], parent_weight: u16) -> Option<u16> {
+    if let Some(weight) = parse_number(value) {
+        return (1.0..=1000.0).contains(&weight).then_some(weight as u16);
+    }
+
+    let weight = match keyword(value)?.as_str() {
+        "normal" => 400,
+        "bold" => 700,
+        "bolder" => match parent_weight {
+            0..=349 => 400,
+            350..=549 => 700,
+            550..=899 => 900,
+            _ => parent_weight,
+        },
+        "lighter" => match parent_weight {
+            0..=99 => parent_weight,
+            100..=549 => 100,
+            550..=749 => 400,
+            _ => 700,
+        },
+        _ => return None,
+    };
+    Some(weight)
+}
+
+/// The computed values of the properties of an element.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ComputedStyle {
+    properties: BTreeMap<String, Value>,
+    /// The custom properties with `var()` substituted, which are all
+    /// inherited.
+    /// https://drafts.csswg.org/css-variables-1/#defining-variables
+    custom_properties: BTreeMap<String, Vec<ComponentValue>>,
+    /// The font size of the root element, which `rem` is relative to.
+    root_font_size: f64,
+}
+
+impl ComputedStyle {
+    /// Returns the style of an anonymous box inside a box with the style
+    /// `parent`. It inherits the inherited properties and has the initial
+    /// value of the others.
+    /// https://drafts.csswg.org/css-display-3/#anonymous
+    pub fn anonymous(parent: &ComputedStyle) -> Self {
+        let mut style = Self {
+            custom_properties: parent.custom_properties.clone(),
+            root_font_size: parent.root_font_size,
+            ..Self::default()
+        };
+        let (inherited, rest): (Vec<&str>, Vec<&str>) =
+            property_names().partition(|n| is_inherited(n));
+        for name in inherited {
+            if let Some(value) = parent.get(name) {
+                style.set(name, value.clone());
+            }
+        }
+        // The names are sorted, so each border style is computed before the
+        // border width that depends on it.
+        for name in rest {
+            let context = ComputeContext {
+                parent: Some(parent),
+                style: &style,
+                viewport: Viewport::default(),
+            };
+            let value = initial_value(name).and_then(|v| compute_value(name, &v, &context));
+            if let Some(value) = value {
+                style.set(name, value);
+            }
+        }
+        style
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Value> {
+        self.properties.get(name)
+    }
+
+    pub fn set(&mut self, name: &str, value: Value) {
+        self.properties.insert(name.to_string(), value);
+    }
+
+    /// Returns the value of the custom property `name`, like `--main-color`.
+    pub fn custom_property(&self, name: &str) -> Option<&[ComponentValue]> {
+        self.custom_properties.get(name).map(|v| v.as_slice())
+    }
+
+    pub fn custom_properties(&self) -> &BTreeMap<String, Vec<ComponentValue>> {
+        &self.custom_properties
+    }
+
+    pub fn set_custom_properties(&mut self, properties: BTreeMap<String, Vec<ComponentValue>>) {
+        self.custom_properties = properties;
+    }
+
+    pub fn root_font_size(&self) -> f64 {
+        self.root_font_size
+    }
+
+    pub fn set_root_font_size(&mut self, size: f64) {
+        self.root_font_size = size;
+    }
+
+    /// Returns the value of `name` if it's a keyword.
+    pub fn keyword(&self, name: &str) -> Option<&str> {
+        match self.get(name) {
+            Some(Value::Keyword(keyword)) => Some(keyword),
+            _ => None,
+        }
+    }
+
+    /// Returns the value of `name` if it's a length, or `None` if it's
+    /// `auto`.
+    pub fn length(&self, name: &str) -> Option<LengthPercentage> {
+        match self.get(name) {
+            Some(Value::Length(length)) => Some(*length),
+            _ => None,
+        }
+    }
+
+    /// Returns the value of a color property like `color` or
+    /// `background-color`.
+    pub fn color(&self, name: &str) -> Color {
+        match self.get(name) {
+            Some(Value::Color(color)) => *color,
+            _ if name == "color" => Color::BLACK,
+            _ => Color::TRANSPARENT,
+        }
+    }
+
+    /// https://drafts.csswg.org/css-display-3/#the-display-properties
+    pub fn display(&self) -> Display {
+        self.keyword("display")
+            .and_then(Display::from_keyword)
+            .unwrap_or(Display::Inline)
+    }
+
+    /// The font size in pixels.
+    /// https://drafts.csswg.org/css-fonts-4/#font-size-prop
+    pub fn font_size(&self) -> f64 {
+        self.length("font-size").map_or(MEDIUM_FONT_SIZE, |l| l.px)
+    }
+
+    /// The weight from 1 to 1000, where 400 is normal and 700 is bold.
+    /// https://drafts.csswg.org/css-fonts-4/#font-weight-prop
+    pub fn font_weight(&self) -> u16 {
+        match self.get("font-weight") {
+            Some(Value::Number(weight)) => *weight as u16,
+            _ => 400,
+        }
+    }
+
+    /// The height of a line box in pixels. `normal` is 1.2 times the font
+    /// size.
+    /// https://drafts.csswg.org/css-inline-3/#line-height-property
+    pub fn line_height(&self) -> f64 {
+        match self.get("line-height") {
+            Some(Value::Number(n)) => n * self.font_size(),
+            Some(Value::Length(length)) => length.px,
+            _ => self.font_size() * 1.2,
+        }
+    }
+}
+
+/// https://drafts.csswg.org/css-display-3/#the-display-properties
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Display {
+    Block,
+    Inline,
+    InlineBlock,
+    ListItem,
+    /// The element and its descendants aren't rendered.
+    None,
+}
+
+impl Display {
+    pub fn from_keyword(keyword: &str) -> Option<Self> {
+        match keyword {
+            "block" => Some(Display::Block),
+            "inline" => Some(Display::Inline),
+            "inline-block" => Some(Display::InlineBlock),
+            "list-item" => Some(Display::ListItem),
+            "none" => Some(Display::None),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,116 @@
+//! Feature queries, which decide whether the rules in `@supports` apply.
+//! https://drafts.csswg.org/css-conditional-3/#at-supports
+
+use crate::renderer::css::cascade::is_supported;
+use crate::renderer::css::condition::{parse_condition, Condition};
+use crate::renderer::css::parser::{parse_declaration_list, BlockKind, ComponentValue};
+use crate::renderer::dom::selector::parse_selector_list_from_values;
+use alloc::string::String;
+use alloc::vec::Vec;
+
+/// https://drafts.csswg.org/css-conditional-3/#typedef-supports-feature
+#[derive(Debug, Clone, PartialEq)]
+pub enum SupportsFeature {
+    /// `(display: grid)`
+    Declaration {
+        name: String,
+        value: Vec<ComponentValue>,
+    },
+    /// `selector(a > b)`
+    /// https://drafts.csswg.org/css-conditional-4/#typedef-supports-selector-fn
+    Selector(Vec<ComponentValue>),
+    /// Anything else in parentheses, which is false rather than unknown
+    /// like in media queries.
+    /// https://drafts.csswg.org/css-conditional-3/#typedef-general-enclosed
+    Unknown,
+}
+
+/// A condition like `(display: grid) and (not selector(:has(a)))`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SupportsCondition {
+    condition: Condition<SupportsFeature>,
+}
+
+impl SupportsCondition {
+    /// Parses the prelude of `@supports`, or returns `None` if it's invalid,
+    /// which drops the rule.
+    /// https://drafts.csswg.org/css-conditional-3/#typedef-supports-condition
+    pub fn parse(values: &[ComponentValue]) -> Option<Self> {
+        let values: Vec<&ComponentValue> = values.iter().filter(|v| !v.is_whitespace()).collect();
+        Some(Self {
+            condition: parse_condition(&values, true, &parse_feature)?,
+        })
+    }
+
+    /// Returns whether the style engine supports the condition.
+    /// https://drafts.csswg.org/css-conditional-3/#support-definition
+    pub fn matches(&self) -> bool {
+        self.condition.evaluate(&|feature| {
+            Some(match feature {
+                SupportsFeature::Declaration { name, value } => is_supported(name, value),
+                SupportsFeature::Selector(selector) => {
+                    parse_selector_list_from_values(selector).is_ok()
+                }
+                SupportsFeature::Unknown => false,
+            })
+        }) == Some(true)
+    }
+}
+
+fn parse_feature(value: &ComponentValue) -> Option<SupportsFeature> {
+    match value {
+        ComponentValue::Block {
+            kind: BlockKind::Parenthesis,
+            contents,
+        } => match parse_declaration_list(contents).as_slice() {
+            [declaration] => Some(SupportsFeature::Declaration {
+                name: declaration.name.clone(),
+                value: declaration.value.clone(),
+            }),
+            _ => Some(SupportsFeature::Unknown),
+        },
+        ComponentValue::Function { name, arguments } if name.eq_ignore_ascii_case("selector") => {
+            Some(SupportsFeature::Selector(arguments.clone()))
+        }
+        ComponentValue::Function { .. } => Some(SupportsFeature::Unknown),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::renderer::css::parser::parse_component_values;
+
+    fn matches(condition: &str) -> Option<bool> {
+        SupportsCondition::parse(&parse_component_values(condition)).map(|c| c.matches())
+    }
+
+    #[test]
+    fn test_supports() {
+        assert_eq!(matches("(color: red)"), Some(true));
+        assert_eq!(matches("(COLOR: rgb(1 2 3))"), Some(true));
+        assert_eq!(matches("(margin: 1px auto)"), Some(true));
+        assert_eq!(matches("(color: 1px)"), Some(false));
+        assert_eq!(matches("(display: grid)"), Some(false));
+        assert_eq!(matches("(float: left)"), Some(false));
+        assert_eq!(matches("not (display: grid)"), Some(true));
+        assert_eq!(matches("(display: grid) or (display: block)"), Some(true));
+        assert_eq!(
+            matches("(color: red) and ((width: 1px) or (x: y))"),
+            Some(true)
+        );
+        assert_eq!(matches("selector(div > p.a)"), Some(true));
+        assert_eq!(matches("selector(p!)"), Some(false));
+
+        // Unknown features are false.
+        assert_eq!(matches("unknown(1)"), Some(false));
+        assert_eq!(matches("not unknown(1)"), Some(true));
+        assert_eq!(matches("(color)"), Some(false));
+
+        // An invalid condition drops the rule.
+        assert_eq!(matches("color: red"), None);
+        assert_eq!(matches("(color: red) and (width: 1px) or (x: y)"), None);
+        assert_eq!(matches(""), None);
+    }
+}
@@ -0,0 +1,664 @@
+use alloc::string::String;
+use alloc::vec::Vec;
+
+/// Splits a style sheet into tokens.
+/// https://www.w3.org/TR/css-syntax-3/#tokenization
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CssTokenizer {
+    /// The preprocessed input.
+    input: Vec<char>,
+    /// The position of the character that will be consumed next.
+    pos: usize,
+}
+
+impl CssTokenizer {
+    pub fn new(css: String) -> Self {
+        // https://www.w3.org/TR/css-syntax-3/#input-preprocessing
+        // CR LF, a lone CR and FF become LF, and NUL becomes U+FFFD.
+        let mut input = Vec::with_capacity(css.len());
+        let mut chars = css.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '\r' => {
+                    if chars.peek() == Some(&'\n') {
+                        chars.next();
+                    }
+                    input.push('\n');
+                }
+                '\x0C' => input.push('\n'),
+                '\0' => input.push('\u{FFFD}'),
+                _ => input.push(c),
+            }
+        }
+
+        Self { input, pos: 0 }
+    }
+
+    /// Returns the character `n` characters after the next one without
+    /// consuming anything, or `None` at the end of the input.
+    fn peek(&self, n: usize) -> Option<char> {
+        self.input.get(self.pos + n).copied()
+    }
+
+    fn consume(&mut self) -> Option<char> {
+        let c = self.peek(0);
+        if c.is_some() {
+            self.pos += 1;
+        }
+        c
+    }
+
+    /// https://www.w3.org/TR/css-syntax-3/#check-if-three-code-points-would-start-an-ident-sequence
+    fn starts_ident_sequence(&self) -> bool {
+        match self.peek(0) {
+            Some('-') => {
+                self.peek(1).is_some_and(|c| is_ident_start(c) || c == '-')
+                    || is_valid_escape(self.peek(1), self.peek(2))
+            }
+            Some('\\') => is_valid_escape(self.peek(0), self.peek(1)),
+            Some(c) => is_ident_start(c),
+            None => false,
+        }
+    }
+
+    /// https://www.w3.org/TR/css-syntax-3/#starts-with-a-number
+    fn starts_number(&self) -> bool {
+        let is_digit = |c: Option<char>| c.is_some_and(|c| c.is_ascii_digit());
+        match self.peek(0) {
+            Some('+') | Some('-') => {
+                is_digit(self.peek(1)) || (self.peek(1) == Some('.') && is_digit(self.peek(2)))
+            }
+            Some('.') => is_digit(self.peek(1)),
+            c => is_digit(c),
+        }
+    }
+
+    /// https://www.w3.org/TR/css-syntax-3/#consume-comments
+    fn consume_comments(&mut self) {
+        while self.peek(0) == Some('/') && self.peek(1) == Some('*') {
+            self.pos += 2;
+            loop {
+                match self.consume() {
+                    Some('*') if self.peek(0) == Some('/') => {
+                        self.pos += 1;
+                        break;
+                    }
+                    Some(_) => {}
+                    // An unterminated comment runs to the end of the input.
+                    None => return,
+                }
+            }
+        }
+    }
+
+    /// Consumes the character after a backslash.
+    /// https://www.w3.org/TR/css-syntax-3/#consume-an-escaped-code-point
+    fn consume_escaped_code_point(&mut self) -> char {
+        let c = match self.consume() {
+            Some(c) => c,
+            None => return '\u{FFFD}',
+        };
+        if !c.is_ascii_hexdigit() {
+            return c;
+        }
+
+        let mut code = c.to_digit(16).unwrap_or(0);
+        for _ in 0..5 {
+            match self.peek(0).and_then(|c| c.to_digit(16)) {
+                Some(digit) => {
+                    code = code * 16 + digit;
+                    self.pos += 1;
+                }
+                None => break,
+            }
+        }
+        if self.peek(0).is_some_and(is_whitespace) {
+            self.pos += 1;
+        }
+
+        // Surrogates and code points over U+10FFFF aren't chars.
+        match char::from_u32(code) {
+            Some(c) if code != 0 => c,
+            _ => '\u{FFFD}',
+        }
+    }
+
+    /// https://www.w3.org/TR/css-syntax-3/#consume-name
+    fn consume_ident_sequence(&mut self) -> String {
+        let mut s = String::new();
+        loop {
+            match self.peek(0) {
+                Some(c) if is_ident(c) => {
+                    s.push(c);
+                    self.pos += 1;
+                }
+                Some('\\') if is_valid_escape(self.peek(0), self.peek(1)) => {
+                    self.pos += 1;
+                    s.push(self.consume_escaped_code_point());
+                }
+                _ => return s,
+            }
+        }
+    }
+
+    /// Returns the value of the number and whether it's an integer.
+    /// https://www.w3.org/TR/css-syntax-3/#consume-number
+    fn consume_number(&mut self) -> (f64, bool) {
+        let mut repr = String::new();
+        let mut is_integer = true;
+
+        if let Some(c @ ('+' | '-')) = self.peek(0) {
+            repr.push(c);
+            self.pos += 1;
+        }
+        self.consume_digits(&mut repr);
+
+        if self.peek(0) == Some('.') && self.peek(1).is_some_and(|c| c.is_ascii_digit()) {
+            repr.push('.');
+            self.pos += 1;
+            self.consume_digits(&mut repr);
+            is_integer = false;
+        }
+
+        if let Some(e @ ('e' | 'E')) = self.peek(0) {
+            let sign = match self.peek(1) {
+                Some(c @ ('+' | '-')) => Some(c),
+                _ => None,
+            };
+            let digit = self.peek(if sign.is_some() { 2 } else { 1 });
+            if digit.is_some_and(|c| c.is_ascii_digit()) {
+                repr.push(e);
+                self.pos += 1;
+                if let Some(sign) = sign {
+                    repr.push(sign);
+                    self.pos += 1;
+                }
+                self.consume_digits(&mut repr);
+                is_integer = false;
+            }
+        }
+
+        (repr.parse().unwrap_or(0.0), is_integer)
+    }
+
+    fn consume_digits(&mut self, repr: &mut String) {
+        while let Some(c) = self.peek(0).filter(|c| c.is_ascii_digit()) {
+            repr.push(c);
+            self.pos += 1;
+        }
+    }
+
+    /// https://www.w3.org/TR/css-syntax-3/#consume-numeric-token
+    fn consume_numeric_token(&mut self) -> CssToken {
+        let (value, is_integer) = self.consume_number();
+
+        if self.starts_ident_sequence() {
+            return CssToken::Dimension {
+                value,
+                unit: self.consume_ident_sequence(),
+                is_integer,
+            };
+        }
+
+        if self.peek(0) == Some('%') {
+            self.pos += 1;
+            return CssToken::Percentage(value);
+        }
+
+        CssToken::Number { value, is_integer }
+    }
+
+    /// https://www.w3.org/TR/css-syntax-3/#consume-ident-like-token
+    fn consume_ident_like_token(&mut self) -> CssToken {
+        let name = self.consume_ident_sequence();
+        if self.peek(0) != Some('(') {
+            return CssToken::Ident(name);
+        }
+        self.pos += 1;
+
+        if !name.eq_ignore_ascii_case("url") {
+            return CssToken::Function(name);
+        }
+
+        // A quoted URL is a function whose argument is a string token.
+        while self.peek(0).is_some_and(is_whitespace) && self.peek(1).is_some_and(is_whitespace) {
+            self.pos += 1;
+        }
+        let is_quote = |c: Option<char>| matches!(c, Some('"') | Some('\''));
+        if is_quote(self.peek(0))
+            || (self.peek(0).is_some_and(is_whitespace) && is_quote(self.peek(1)))
+        {
+            return CssToken::Function(name);
+        }
+
+        self.consume_url_token()
+    }
+
+    /// https://www.w3.org/TR/css-syntax-3/#consume-url-token
+    fn consume_url_token(&mut self) -> CssToken {
+        let mut url = String::new();
+        while self.peek(0).is_some_and(is_whitespace) {
+            self.pos += 1;
+        }
+
+        loop {
+            match self.consume() {
+                Some(')') | None => return CssToken::Url(url),
+                Some(c) if is_whitespace(c) => {
+                    while self.peek(0).is_some_and(is_whitespace) {
+                        self.pos += 1;
+                    }
+                    match self.peek(0) {
+                        Some(')') | None => {
+                            self.consume();
+                            return CssToken::Url(url);
+                        }
+                        Some(_) => {
+                            self.consume_bad_url_remnants();
+                            return CssToken::BadUrl;
+                        }
+                    }
+                }
+                Some('"') | Some('\'') | Some('(') => {
+                    self.consume_bad_url_remnants();
+                    return CssToken::BadUrl;
+                }
+                Some(c) if is_non_printable(c) => {
+                    self.consume_bad_url_remnants();
+                    return CssToken::BadUrl;
+                }
+                Some('\\') => {
+                    if is_valid_escape(Some('\\'), self.peek(0)) {
+                        url.push(self.consume_escaped_code_point());
+                    } else {
+                        self.consume_bad_url_remnants();
+                        return CssToken::BadUrl;
+                    }
+                }
+                Some(c) => url.push(c),
+            }
+        }
+    }
+
+    /// https://www.w3.org/TR/css-syntax-3/#consume-remnants-of-bad-url
+    fn consume_bad_url_remnants(&mut self) {
+        loop {
+            match self.consume() {
+                Some(')') | None => return,
+                Some('\\') if is_valid_escape(Some('\\'), self.peek(0)) => {
+                    self.consume_escaped_code_point();
+                }
+                Some(_) => {}
+            }
+        }
+    }
+
+    /// Consumes a string whose opening quote `ending` was consumed.
+    /// https://www.w3.org/TR/css-syntax-3/#consume-string-token
+    fn consume_string_token(&mut self, ending: char) -> CssToken {
+        let mut s = String::new();
+        loop {
+            match self.consume() {
+                // An unterminated string ends at the end of the input.
+                None => return CssToken::String(s),
+                Some(c) if c == ending => return CssToken::String(s),
+                Some('\n') => {
+                    // The newline isn't part of the bad string.
+                    self.pos -= 1;
+                    return CssToken::BadString;
+                }
+                Some('\\') => match self.peek(0) {
+                    None => {}
+                    // An escaped newline continues the string.
+                    Some('\n') => self.pos += 1,
+                    Some(_) => s.push(self.consume_escaped_code_point()),
+                },
+                Some(c) => s.push(c),
+            }
+        }
+    }
+
+    /// https://www.w3.org/TR/css-syntax-3/#consume-token
+    fn consume_token(&mut self) -> Option<CssToken> {
+        self.consume_comments();
+
+        let c = self.peek(0)?;
+        if is_whitespace(c) {
+            while self.peek(0).is_some_and(is_whitespace) {
+                self.pos += 1;
+            }
+            return Some(CssToken::Whitespace);
+        }
+        if c.is_ascii_digit() {
+            return Some(self.consume_numeric_token());
+        }
+        if is_ident_start(c) {
+            return Some(self.consume_ident_like_token());
+        }
+
+        let token = match c {
+            '+' | '.' if self.starts_number() => self.consume_numeric_token(),
+            '-' if self.starts_number() => self.consume_numeric_token(),
+            '-' if self.peek(1) == Some('-') && self.peek(2) == Some('>') => {
+                self.pos += 3;
+                CssToken::Cdc
+            }
+            '-' | '\\' if self.starts_ident_sequence() => self.consume_ident_like_token(),
+            _ => {
+                self.pos += 1;
+                match c {
+                    '"' | '\'' => self.consume_string_token(c),
+                    '#' if self.peek(0).is_some_and(is_ident)
+                        || is_valid_escape(self.peek(0), self.peek(1)) =>
+                    {
+                        let is_id = self.starts_ident_sequence();
+                        CssToken::Hash {
+                            value: self.consume_ident_sequence(),
+                            is_id,
+                        }
+                    }
+                    '<' if self.peek(0) == Some('!')
+                        && self.peek(1) == Some('-')
+                        && self.peek(2) == Some('-') =>
+                    {
+                        self.pos += 3;
+                        CssToken::Cdo
+                    }
+                    '@' if self.starts_ident_sequence() => {
+                        CssToken::AtKeyword(self.consume_ident_sequence())
+                    }
+                    '(' => CssToken::OpenParenthesis,
+                    ')' => CssToken::CloseParenthesis,
+                    '[' => CssToken::OpenSquareBracket,
+                    ']' => CssToken::CloseSquareBracket,
+                    '{' => CssToken::OpenCurly,
+                    '}' => CssToken::CloseCurly,
+                    ',' => CssToken::Comma,
+                    ':' => CssToken::Colon,
+                    ';' => CssToken::Semicolon,
+                    _ => CssToken::Delim(c),
+                }
+            }
+        };
+        Some(token)
+    }
+}
+
+impl Iterator for CssTokenizer {
+    type Item = CssToken;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.consume_token()
+    }
+}
+
+/// https://www.w3.org/TR/css-syntax-3/#tokenization
+#[derive(Debug, Clone, PartialEq)]
+pub enum CssToken {
+    Ident(String),
+    /// An identifier followed by `(`, like `rgb(`. The name doesn't include
+    /// the parenthesis.
+    Function(String),
+    /// `@` followed by an identifier, like `@media`. The name doesn't include
+    /// the `@`.
+    AtKeyword(String),
+    /// `#` followed by a name. `is_id` is true if the name would also be a
+    /// valid identifier, so that the token can be an ID selector.
+    Hash {
+        value: String,
+        is_id: bool,
+    },
+    String(String),
+    /// A string that has an unescaped newline in it.
+    BadString,
+    /// An unquoted `url(...)`. A quoted one is a `Function("url")`.
+    Url(String),
+    BadUrl,
+    Delim(char),
+    Number {
+        value: f64,
+        is_integer: bool,
+    },
+    Percentage(f64),
+    Dimension {
+        value: f64,
+        unit: String,
+        is_integer: bool,
+    },
+    Whitespace,
+    /// `<!--`
+    Cdo,
+    /// `-->`
+    Cdc,
+    Colon,
+    Semicolon,
+    Comma,
+    OpenSquareBracket,
+    CloseSquareBracket,
+    OpenParenthesis,
+    CloseParenthesis,
+    OpenCurly,
+    CloseCurly,
+}
+
+/// https://www.w3.org/TR/css-syntax-3/#whitespace
+fn is_whitespace(c: char) -> bool {
+    matches!(c, '\n' | '\t' | ' ')
+}
+
+/// https://www.w3.org/TR/css-syntax-3/#ident-start-code-point
+fn is_ident_start(c: char) -> bool {
+    c.is_ascii_alphabetic() || !c.is_ascii() || c == '_'
+}
+
+/// https://www.w3.org/TR/css-syntax-3/#ident-code-point
+fn is_ident(c: char) -> bool {
+    is_ident_start(c) || c.is_ascii_digit() || c == '-'
+}
+
+/// https://www.w3.org/TR/css-syntax-3/#non-printable-code-point
+fn is_non_printable(c: char) -> bool {
+    matches!(c, '\0'..='\x08' | '\x0B' | '\x0E'..='\x1F' | '\x7F')
+}
+
+/// https://www.w3.org/TR/css-syntax-3/#starts-with-a-valid-escape
+fn is_valid_escape(first: Option<char>, second: Option<char>) -> bool {
+    first == Some('\\') && second != Some('\n')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::string::ToString;
+    use alloc::vec;
+
+    fn tokenize(css: &str) -> Vec<CssToken> {
+        CssTokenizer::new(css.to_string()).collect()
+    }
+
+    fn ident(s: &str) -> CssToken {
+        CssToken::Ident(s.to_string())
+    }
+
+    #[test]
+    fn test_empty() {
+        assert!(tokenize("").is_empty());
+    }
+
+    #[test]
+    fn test_rule() {
+        assert_eq!(
+            tokenize("p#main .a{color:red;}"),
+            vec![
+                ident("p"),
+                CssToken::Hash {
+                    value: "main".to_string(),
+                    is_id: true
+                },
+                CssToken::Whitespace,
+                CssToken::Delim('.'),
+                ident("a"),
+                CssToken::OpenCurly,
+                ident("color"),
+                CssToken::Colon,
+                ident("red"),
+                CssToken::Semicolon,
+                CssToken::CloseCurly,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_comments() {
+        assert_eq!(
+            tokenize("a/* b */c /* d"),
+            vec![ident("a"), ident("c"), CssToken::Whitespace]
+        );
+    }
+
+    #[test]
+    fn test_numbers() {
+        assert_eq!(
+            tokenize("12 +.5 -3e2 10px 50% 1.5em -x"),
+            vec![
+                CssToken::Number {
+                    value: 12.0,
+                    is_integer: true
+                },
+                CssToken::Whitespace,
+                CssToken::Number {
+                    value: 0.5,
+                    is_integer: false
+                },
+                CssToken::Whitespace,
+                CssToken::Number {
+                    value: -300.0,
+                    is_integer: false
+                },
+                CssToken::Whitespace,
+                CssToken::Dimension {
+                    value: 10.0,
+                    unit: "px".to_string(),
+                    is_integer: true
+                },
+                CssToken::Whitespace,
+                CssToken::Percentage(50.0),
+                CssToken::Whitespace,
+                CssToken::Dimension {
+                    value: 1.5,
+                    unit: "em".to_string(),
+                    is_integer: false
+                },
+                CssToken::Whitespace,
+                ident("-x"),
+            ]
+        );
+        assert_eq!(
+            tokenize("1e"),
+            vec![CssToken::Dimension {
+                value: 1.0,
+                unit: "e".to_string(),
+                is_integer: true
+            }]
+        );
+    }
+
+    #[test]
+    fn test_strings() {
+        assert_eq!(
+            tokenize("\"a\\\"b\" 'c\\\nd' 'e\nf"),
+            vec![
+                CssToken::String("a\"b".to_string()),
+                CssToken::Whitespace,
+                CssToken::String("cd".to_string()),
+                CssToken::Whitespace,
+                CssToken::BadString,
+                CssToken::Whitespace,
+                ident("f"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_urls() {
+        assert_eq!(
+            tokenize("url( a.png ) url(\"b.png\") URL(c d)"),
+            vec![
+                CssToken::Url("a.png".to_string()),
+                CssToken::Whitespace,
+                CssToken::Function("url".to_string()),
+                CssToken::String("b.png".to_string()),
+                CssToken::CloseParenthesis,
+                CssToken::Whitespace,
+                CssToken::BadUrl,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_escapes() {
+        assert_eq!(
+            tokenize("\\41 b \\{ #\\31 x #1x"),
+            vec![
+                ident("Ab"),
+                CssToken::Whitespace,
+                ident("{"),
+                CssToken::Whitespace,
+                CssToken::Hash {
+                    value: "1x".to_string(),
+                    is_id: true
+                },
+                CssToken::Whitespace,
+                CssToken::Hash {
+                    value: "1x".to_string(),
+                    is_id: false
+                },
+            ]
+        );
+        assert_eq!(tokenize("\\0"), vec![ident("\u{FFFD}")]);
+    }
+
+    #[test]
+    fn test_at_keywords_and_delimiters() {
+        assert_eq!(
+            tokenize("<!--@media -->rgb(@ 1,2)[a]"),
+            vec![
+                CssToken::Cdo,
+                CssToken::AtKeyword("media".to_string()),
+                CssToken::Whitespace,
+                CssToken::Cdc,
+                CssToken::Function("rgb".to_string()),
+                CssToken::Delim('@'),
+                CssToken::Whitespace,
+                CssToken::Number {
+                    value: 1.0,
+                    is_integer: true
+                },
+                CssToken::Comma,
+                CssToken::Number {
+                    value: 2.0,
+                    is_integer: true
+                },
+                CssToken::CloseParenthesis,
+                CssToken::OpenSquareBracket,
+                ident("a"),
+                CssToken::CloseSquareBracket,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_preprocessing() {
+        assert_eq!(
+            tokenize("'a\r\nb'\x0C\0"),
+            vec![
+                CssToken::BadString,
+                CssToken::Whitespace,
+                ident("b"),
+                CssToken::BadString,
+                CssToken::Whitespace,
+                ident("\u{FFFD}"),
+            ]
+        );
+    }
+}
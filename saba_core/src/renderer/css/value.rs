@@ -0,0 +1,725 @@
+//! Typed CSS values: lengths, percentages, numbers and colors.
+//! https://drafts.csswg.org/css-values-4/
+//! https://drafts.csswg.org/css-color-4/
+
+use crate::renderer::css::media::Viewport;
+use crate::renderer::css::parser::{trim_whitespace, BlockKind, ComponentValue};
+use crate::renderer::css::token::CssToken;
+use alloc::vec::Vec;
+
+/// The font size of `medium`, which is the initial font size.
+pub const MEDIUM_FONT_SIZE: f64 = 16.0;
+
+/// What relative lengths are resolved against.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct LengthContext {
+    /// The font size that `em` is relative to.
+    pub font_size: f64,
+    /// The font size of the root element, which `rem` is relative to.
+    pub root_font_size: f64,
+    pub viewport: Viewport,
+}
+
+impl Default for LengthContext {
+    fn default() -> Self {
+        Self {
+            font_size: MEDIUM_FONT_SIZE,
+            root_font_size: MEDIUM_FONT_SIZE,
+            viewport: Viewport::default(),
+        }
+    }
+}
+
+impl LengthContext {
+    /// Converts a length in `unit` to pixels, or returns `None` for an
+    /// unknown unit.
+    /// https://drafts.csswg.org/css-values-4/#lengths
+    pub fn to_px(&self, value: f64, unit: &str) -> Option<f64> {
+        let px = match unit.to_ascii_lowercase().as_str() {
+            "px" => 1.0,
+            "in" => 96.0,
+            "cm" => 96.0 / 2.54,
+            "mm" => 96.0 / 25.4,
+            "q" => 96.0 / 101.6,
+            "pt" => 96.0 / 72.0,
+            "pc" => 16.0,
+            "em" => self.font_size,
+            "rem" => self.root_font_size,
+            // Without font metrics, `ex` and `ch` are half an `em`.
+            "ex" | "ch" => self.font_size / 2.0,
+            "vw" => self.viewport.width / 100.0,
+            "vh" => self.viewport.height / 100.0,
+            "vmin" => self.viewport.width.min(self.viewport.height) / 100.0,
+            "vmax" => self.viewport.width.max(self.viewport.height) / 100.0,
+            _ => return None,
+        };
+        Some(value * px)
+    }
+}
+
+/// An absolute length plus a percentage of something that isn't known until
+/// layout, like the width of the containing block. `calc(50% - 10px)` is
+/// `{ px: -10.0, percent: 50.0 }`.
+/// https://drafts.csswg.org/css-values-4/#mixed-percentages
+#[derive(Debug, Copy, Clone, Default, PartialEq)]
+pub struct LengthPercentage {
+    pub px: f64,
+    pub percent: f64,
+}
+
+impl LengthPercentage {
+    pub fn px(px: f64) -> Self {
+        Self { px, percent: 0.0 }
+    }
+
+    /// Returns the length in pixels when percentages are of `base`.
+    pub fn resolve(&self, base: f64) -> f64 {
+        self.px + base * self.percent / 100.0
+    }
+}
+
+/// The result of a numeric value or a `calc()` expression.
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum Numeric {
+    Number(f64),
+    LengthPercentage(LengthPercentage),
+}
+
+/// Parses a length, a percentage or a `calc()` that results in one of them.
+/// A `0` without a unit is a length too.
+pub fn parse_length_percentage(
+    value: &[ComponentValue],
+    context: &LengthContext,
+) -> Option<LengthPercentage> {
+    match value {
+        [ComponentValue::Token(CssToken::Number { value, .. })] if *value == 0.0 => {
+            Some(LengthPercentage::default())
+        }
+        [value] => match evaluate(value, context)? {
+            Numeric::LengthPercentage(length) => Some(length),
+            Numeric::Number(_) => None,
+        },
+        _ => None,
+    }
+}
+
+/// Parses a number or a `calc()` that results in one.
+pub fn parse_number(value: &[ComponentValue]) -> Option<f64> {
+    match value {
+        [value] => match evaluate(value, &LengthContext::default())? {
+            Numeric::Number(n) => Some(n),
+            Numeric::LengthPercentage(_) => None,
+        },
+        _ => None,
+    }
+}
+
+fn evaluate(value: &ComponentValue, context: &LengthContext) -> Option<Numeric> {
+    match value {
+        ComponentValue::Token(CssToken::Number { value, .. }) => Some(Numeric::Number(*value)),
+        ComponentValue::Token(CssToken::Percentage(percent)) => {
+            Some(Numeric::LengthPercentage(LengthPercentage {
+                px: 0.0,
+                percent: *percent,
+            }))
+        }
+        ComponentValue::Token(CssToken::Dimension { value, unit, .. }) => Some(
+            Numeric::LengthPercentage(LengthPercentage::px(context.to_px(*value, unit)?)),
+        ),
+        ComponentValue::Function { name, arguments } if name.eq_ignore_ascii_case("calc") => {
+            evaluate_calc(arguments, context)
+        }
+        _ => None,
+    }
+}
+
+/// Evaluates the contents of `calc()`. `*` and `/` need a number on one side,
+/// and `+` and `-` need the same type on both sides.
+/// https://drafts.csswg.org/css-values-4/#calc-syntax
+fn evaluate_calc(arguments: &[ComponentValue], context: &LengthContext) -> Option<Numeric> {
+    let items: Vec<&ComponentValue> = arguments.iter().filter(|v| !v.is_whitespace()).collect();
+    let mut parser = CalcParser {
+        items: &items,
+        pos: 0,
+        context,
+    };
+    let result = parser.sum()?;
+    if parser.pos != items.len() {
+        return None;
+    }
+    Some(result)
+}
+
+struct CalcParser<'a> {
+    items: &'a [&'a ComponentValue],
+    pos: usize,
+    context: &'a LengthContext,
+}
+
+impl CalcParser<'_> {
+    fn operator(&self) -> Option<char> {
+        match self.items.get(self.pos)? {
+            ComponentValue::Token(CssToken::Delim(c)) => Some(*c),
+            _ => None,
+        }
+    }
+
+    /// https://drafts.csswg.org/css-values-4/#typedef-calc-sum
+    fn sum(&mut self) -> Option<Numeric> {
+        let mut result = self.product()?;
+        while let Some(op @ ('+' | '-')) = self.operator() {
+            self.pos += 1;
+            let rhs = self.product()?;
+            let sign = if op == '+' { 1.0 } else { -1.0 };
+            result = match (result, rhs) {
+                (Numeric::Number(a), Numeric::Number(b)) => Numeric::Number(a + sign * b),
+                (Numeric::LengthPercentage(a), Numeric::LengthPercentage(b)) => {
+                    Numeric::LengthPercentage(LengthPercentage {
+                        px: a.px + sign * b.px,
+                        percent: a.percent + sign * b.percent,
+                    })
+                }
+                _ => return None,
+            };
+        }
+        Some(result)
+    }
+
+    /// https://drafts.csswg.org/css-values-4/#typedef-calc-product
+    fn product(&mut self) -> Option<Numeric> {
+        let mut result = self.value()?;
+        while let Some(op @ ('*' | '/')) = self.operator() {
+            self.pos += 1;
+            let rhs = self.value()?;
+            result = match (op, result, rhs) {
+                ('*', Numeric::Number(a), Numeric::Number(b)) => Numeric::Number(a * b),
+                ('*', Numeric::LengthPercentage(l), Numeric::Number(n))
+                | ('*', Numeric::Number(n), Numeric::LengthPercentage(l)) => {
+                    Numeric::LengthPercentage(LengthPercentage {
+                        px: l.px * n,
+                        percent: l.percent * n,
+                    })
+                }
+                ('/', _, Numeric::Number(n)) if n == 0.0 => return None,
+                ('/', Numeric::Number(a), Numeric::Number(b)) => Numeric::Number(a / b),
+                ('/', Numeric::LengthPercentage(l), Numeric::Number(n)) => {
+                    Numeric::LengthPercentage(LengthPercentage {
+                        px: l.px / n,
+                        percent: l.percent / n,
+                    })
+                }
+                _ => return None,
+            };
+        }
+        Some(result)
+    }
+
+    /// https://drafts.csswg.org/css-values-4/#typedef-calc-value
+    fn value(&mut self) -> Option<Numeric> {
+        let value = self.items.get(self.pos)?;
+        self.pos += 1;
+        match value {
+            ComponentValue::Block {
+                kind: BlockKind::Parenthesis,
+                contents,
+            } => evaluate_calc(contents, self.context),
+            _ => evaluate(value, self.context),
+        }
+    }
+}
+
+/// An sRGB color with an alpha channel.
+/// https://drafts.csswg.org/css-color-4/#color-type
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct Color {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+    pub a: u8,
+}
+
+impl Color {
+    pub const BLACK: Color = Color::rgb(0, 0, 0);
+    pub const TRANSPARENT: Color = Color {
+        r: 0,
+        g: 0,
+        b: 0,
+        a: 0,
+    };
+
+    pub const fn rgb(r: u8, g: u8, b: u8) -> Self {
+        Self { r, g, b, a: 255 }
+    }
+
+    const fn from_code(code: u32) -> Self {
+        Self::rgb((code >> 16) as u8, (code >> 8) as u8, code as u8)
+    }
+
+    /// Parses a color. `currentcolor` becomes `current_color`.
+    /// https://drafts.csswg.org/css-color-4/#typedef-color
+    pub fn parse(value: &[ComponentValue], current_color: Color) -> Option<Self> {
+        match value {
+            [ComponentValue::Token(CssToken::Ident(name))] => {
+                let name = name.to_ascii_lowercase();
+                match name.as_str() {
+                    "currentcolor" => Some(current_color),
+                    "transparent" => Some(Color::TRANSPARENT),
+                    _ => NAMED_COLORS
+                        .iter()
+                        .find(|(n, _)| *n == name)
+                        .map(|(_, code)| Color::from_code(*code)),
+                }
+            }
+            [ComponentValue::Token(CssToken::Hash { value, .. })] => Color::parse_hex(value),
+            [ComponentValue::Function { name, arguments }] => {
+                match name.to_ascii_lowercase().as_str() {
+                    "rgb" | "rgba" => Color::parse_rgb(arguments),
+                    "hsl" | "hsla" => Color::parse_hsl(arguments),
+                    _ => None,
+                }
+            }
+            _ => None,
+        }
+    }
+
+    /// https://drafts.csswg.org/css-color-4/#hex-notation
+    fn parse_hex(hex: &str) -> Option<Self> {
+        let digits: Vec<u8> = hex
+            .chars()
+            .map(|c| c.to_digit(16).map(|d| d as u8))
+            .collect::<Option<_>>()?;
+        match *digits.as_slice() {
+            [r, g, b] => Some(Color::rgb(r * 17, g * 17, b * 17)),
+            [r, g, b, a] => Some(Color {
+                r: r * 17,
+                g: g * 17,
+                b: b * 17,
+                a: a * 17,
+            }),
+            [r1, r2, g1, g2, b1, b2] => Some(Color::rgb(r1 * 16 + r2, g1 * 16 + g2, b1 * 16 + b2)),
+            [r1, r2, g1, g2, b1, b2, a1, a2] => Some(Color {
+                r: r1 * 16 + r2,
+                g: g1 * 16 + g2,
+                b: b1 * 16 + b2,
+                a: a1 * 16 + a2,
+            }),
+            _ => None,
+        }
+    }
+
+    /// Parses the arguments of `rgb()`: three numbers or three percentages,
+    /// and an optional alpha. Both `rgb(1, 2, 3, 0.5)` and
+    /// `rgb(1 2 3 / 50%)` are allowed.
+    /// https://drafts.csswg.org/css-color-4/#rgb-functions
+    fn parse_rgb(arguments: &[ComponentValue]) -> Option<Self> {
+        let (channels, alpha) = split_color_arguments(arguments)?;
+        let channel = |v: &ComponentValue| match v {
+            ComponentValue::Token(CssToken::Number { value, .. }) => Some(*value),
+            ComponentValue::Token(CssToken::Percentage(p)) => Some(p * 255.0 / 100.0),
+            _ => None,
+        };
+        Some(Color {
+            r: clamp_channel(channel(channels[0])?),
+            g: clamp_channel(channel(channels[1])?),
+            b: clamp_channel(channel(channels[2])?),
+            a: parse_alpha(alpha)?,
+        })
+    }
+
+    /// https://drafts.csswg.org/css-color-4/#the-hsl-notation
+    fn parse_hsl(arguments: &[ComponentValue]) -> Option<Self> {
+        let (channels, alpha) = split_color_arguments(arguments)?;
+        let hue = match channels[0] {
+            ComponentValue::Token(CssToken::Number { value, .. }) => *value,
+            ComponentValue::Token(CssToken::Dimension { value, unit, .. }) => {
+                match unit.to_ascii_lowercase().as_str() {
+                    "deg" => *value,
+                    "grad" => value * 0.9,
+                    "rad" => value.to_degrees(),
+                    "turn" => value * 360.0,
+                    _ => return None,
+                }
+            }
+            _ => return None,
+        };
+        let percentage = |v: &ComponentValue| match v {
+            ComponentValue::Token(CssToken::Percentage(p))
+            | ComponentValue::Token(CssToken::Number { value: p, .. }) => {
+                Some(p.clamp(0.0, 100.0) / 100.0)
+            }
+            _ => None,
+        };
+        let saturation = percentage(channels[1])?;
+        let lightness = percentage(channels[2])?;
+
+        // https://drafts.csswg.org/css-color-4/#hsl-to-rgb
+        let hue = modulo(hue, 360.0);
+        let f = |n: f64| {
+            let k = modulo(n + hue / 30.0, 12.0);
+            let a = saturation * lightness.min(1.0 - lightness);
+            lightness - a * (k - 3.0).min(9.0 - k).clamp(-1.0, 1.0)
+        };
+        Some(Color {
+            r: clamp_channel(f(0.0) * 255.0),
+            g: clamp_channel(f(8.0) * 255.0),
+            b: clamp_channel(f(4.0) * 255.0),
+            a: parse_alpha(alpha)?,
+        })
+    }
+}
+
+/// Splits the arguments of a color function into the three channels and
+/// the alpha, if any.
+fn split_color_arguments(
+    arguments: &[ComponentValue],
+) -> Option<(Vec<&ComponentValue>, Option<&ComponentValue>)> {
+    let arguments = trim_whitespace(arguments);
+    let is_comma = |v: &&ComponentValue| **v == ComponentValue::Token(CssToken::Comma);
+
+    let mut values: Vec<&ComponentValue> = Vec::new();
+    let mut alpha = None;
+    if arguments.iter().any(|v| is_comma(&v)) {
+        // The legacy syntax with commas.
+        for part in arguments.split(|v| is_comma(&v)) {
+            match trim_whitespace(part) {
+                [value] => values.push(value),
+                _ => return None,
+            }
+        }
+        if values.len() == 4 {
+            alpha = values.pop();
+        }
+    } else {
+        let mut parts = arguments.iter().filter(|v| !v.is_whitespace());
+        for value in parts.by_ref() {
+            if *value == ComponentValue::Token(CssToken::Delim('/')) {
+                alpha = Some(parts.next()?);
+                break;
+            }
+            values.push(value);
+        }
+        if parts.next().is_some() {
+            return None;
+        }
+    }
+
+    if values.len() != 3 {
+        return None;
+    }
+    Some((values, alpha))
+}
+
+fn parse_alpha(alpha: Option<&ComponentValue>) -> Option<u8> {
+    let alpha = match alpha {
+        None => 1.0,
+        Some(ComponentValue::Token(CssToken::Number { value, .. })) => *value,
+        Some(ComponentValue::Token(CssToken::Percentage(p))) => p / 100.0,
+        Some(_) => return None,
+    };
+    Some(clamp_channel(alpha * 255.0))
+}
+
+/// `f64::rem_euclid()` needs `std`.
+fn modulo(a: f64, b: f64) -> f64 {
+    let r = a % b;
+    if r < 0.0 {
+        r + b
+    } else {
+        r
+    }
+}
+
+fn clamp_channel(value: f64) -> u8 {
+    // `as` saturates, but doesn't round.
+    (value + 0.5).clamp(0.0, 255.0) as u8
+}
+
+/// https://drafts.csswg.org/css-color-4/#named-colors
+const NAMED_COLORS: [(&str, u32); 148] = [
+    ("aliceblue", 0xf0f8ff),
+    ("antiquewhite", 0xfaebd7),
+    ("aqua", 0x00ffff),
+    ("aquamarine", 0x7fffd4),
+    ("azure", 0xf0ffff),
+    ("beige", 0xf5f5dc),
+    ("bisque", 0xffe4c4),
+    ("black", 0x000000),
+    ("blanchedalmond", 0xffebcd),
+    ("blue", 0x0000ff),
+    ("blueviolet", 0x8a2be2),
+    ("brown", 0xa52a2a),
+    ("burlywood", 0xdeb887),
+    ("cadetblue", 0x5f9ea0),
+    ("chartreuse", 0x7fff00),
+    ("chocolate", 0xd2691e),
+    ("coral", 0xff7f50),
+    ("cornflowerblue", 0x6495ed),
+    ("cornsilk", 0xfff8dc),
+    ("crimson", 0xdc143c),
+    ("cyan", 0x00ffff),
+    ("darkblue", 0x00008b),
+    ("darkcyan", 0x008b8b),
+    ("darkgoldenrod", 0xb8860b),
+    ("darkgray", 0xa9a9a9),
+    ("darkgreen", 0x006400),
+    ("darkgrey", 0xa9a9a9),
+    ("darkkhaki", 0xbdb76b),
+    ("darkmagenta", 0x8b008b),
+    ("darkolivegreen", 0x556b2f),
+    ("darkorange", 0xff8c00),
+    ("darkorchid", 0x9932cc),
+    ("darkred", 0x8b0000),
+    ("darksalmon", 0xe9967a),
+    ("darkseagreen", 0x8fbc8f),
+    ("darkslateblue", 0x483d8b),
+    ("darkslategray", 0x2f4f4f),
+    ("darkslategrey", 0x2f4f4f),
+    ("darkturquoise", 0x00ced1),
+    ("darkviolet", 0x9400d3),
+    ("deeppink", 0xff1493),
+    ("deepskyblue", 0x00bfff),
+    ("dimgray", 0x696969),
+    ("dimgrey", 0x696969),
+    ("dodgerblue", 0x1e90ff),
+    ("firebrick", 0xb22222),
+    ("floralwhite", 0xfffaf0),
+    ("forestgreen", 0x228b22),
+    ("fuchsia", 0xff00ff),
+    ("gainsboro", 0xdcdcdc),
+    ("ghostwhite", 0xf8f8ff),
+    ("gold", 0xffd700),
+    ("goldenrod", 0xdaa520),
+    ("gray", 0x808080),
+    ("green", 0x008000),
+    ("greenyellow", 0xadff2f),
+    ("grey", 0x808080),
+    ("honeydew", 0xf0fff0),
+    ("hotpink", 0xff69b4),
+    ("indianred", 0xcd5c5c),
+    ("indigo", 0x4b0082),
+    ("ivory", 0xfffff0),
+    ("khaki", 0xf0e68c),
+    ("lavender", 0xe6e6fa),
+    ("lavenderblush", 0xfff0f5),
+    ("lawngreen", 0x7cfc00),
+    ("lemonchiffon", 0xfffacd),
+    ("lightblue", 0xadd8e6),
+    ("lightcoral", 0xf08080),
+    ("lightcyan", 0xe0ffff),
+    ("lightgoldenrodyellow", 0xfafad2),
+    ("lightgray", 0xd3d3d3),
+    ("lightgreen", 0x90ee90),
+    ("lightgrey", 0xd3d3d3),
+    ("lightpink", 0xffb6c1),
+    ("lightsalmon", 0xffa07a),
+    ("lightseagreen", 0x20b2aa),
+    ("lightskyblue", 0x87cefa),
+    ("lightslategray", 0x778899),
+    ("lightslategrey", 0x778899),
+    ("lightsteelblue", 0xb0c4de),
+    ("lightyellow", 0xffffe0),
+    ("lime", 0x00ff00),
+    ("limegreen", 0x32cd32),
+    ("linen", 0xfaf0e6),
+    ("magenta", 0xff00ff),
+    ("maroon", 0x800000),
+    ("mediumaquamarine", 0x66cdaa),
+    ("mediumblue", 0x0000cd),
+    ("mediumorchid", 0xba55d3),
+    ("mediumpurple", 0x9370db),
+    ("mediumseagreen", 0x3cb371),
+    ("mediumslateblue", 0x7b68ee),
+    ("mediumspringgreen", 0x00fa9a),
+    ("mediumturquoise", 0x48d1cc),
+    ("mediumvioletred", 0xc71585),
+    ("midnightblue", 0x191970),
+    ("mintcream", 0xf5fffa),
+    ("mistyrose", 0xffe4e1),
+    ("moccasin", 0xffe4b5),
+    ("navajowhite", 0xffdead),
+    ("navy", 0x000080),
+    ("oldlace", 0xfdf5e6),
+    ("olive", 0x808000),
+    ("olivedrab", 0x6b8e23),
+    ("orange", 0xffa500),
+    ("orangered", 0xff4500),
+    ("orchid", 0xda70d6),
+    ("palegoldenrod", 0xeee8aa),
+    ("palegreen", 0x98fb98),
+    ("paleturquoise", 0xafeeee),
+    ("palevioletred", 0xdb7093),
+    ("papayawhip", 0xffefd5),
+    ("peachpuff", 0xffdab9),
+    ("peru", 0xcd853f),
+    ("pink", 0xffc0cb),
+    ("plum", 0xdda0dd),
+    ("powderblue", 0xb0e0e6),
+    ("purple", 0x800080),
+    ("rebeccapurple", 0x663399),
+    ("red", 0xff0000),
+    ("rosybrown", 0xbc8f8f),
+    ("royalblue", 0x4169e1),
+    ("saddlebrown", 0x8b4513),
+    ("salmon", 0xfa8072),
+    ("sandybrown", 0xf4a460),
+    ("seagreen", 0x2e8b57),
+    ("seashell", 0xfff5ee),
+    ("sienna", 0xa0522d),
+    ("silver", 0xc0c0c0),
+    ("skyblue", 0x87ceeb),
+    ("slateblue", 0x6a5acd),
+    ("slategray", 0x708090),
+    ("slategrey", 0x708090),
+    ("snow", 0xfffafa),
+    ("springgreen", 0x00ff7f),
+    ("steelblue", 0x4682b4),
+    ("tan", 0xd2b48c),
+    ("teal", 0x008080),
+    ("thistle", 0xd8bfd8),
+    ("tomato", 0xff6347),
+    ("turquoise", 0x40e0d0),
+    ("violet", 0xee82ee),
+    ("wheat", 0xf5deb3),
+    ("white", 0xffffff),
+    ("whitesmoke", 0xf5f5f5),
+    ("yellow", 0xffff00),
+    ("yellowgreen", 0x9acd32),
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::renderer::css::media::Viewport;
+    use crate::renderer::css::parser::parse_component_values;
+
+    fn length(css: &str) -> Option<LengthPercentage> {
+        let context = LengthContext {
+            font_size: 20.0,
+            root_font_size: 10.0,
+            viewport: Viewport {
+                width: 1000.0,
+                height: 500.0,
+                ..Viewport::default()
+            },
+        };
+        parse_length_percentage(&parse_component_values(css), &context)
+    }
+
+    fn color(css: &str) -> Option<Color> {
+        Color::parse(&parse_component_values(css), Color::rgb(1, 2, 3))
+    }
+
+    #[test]
+    fn test_lengths() {
+        assert_eq!(length("12px"), Some(LengthPercentage::px(12.0)));
+        assert_eq!(length("2em"), Some(LengthPercentage::px(40.0)));
+        assert_eq!(length("2rem"), Some(LengthPercentage::px(20.0)));
+        assert_eq!(length("10vw"), Some(LengthPercentage::px(100.0)));
+        assert_eq!(length("10VH"), Some(LengthPercentage::px(50.0)));
+        assert_eq!(length("12pt"), Some(LengthPercentage::px(16.0)));
+        assert_eq!(length("1in"), Some(LengthPercentage::px(96.0)));
+        assert_eq!(
+            length("50%"),
+            Some(LengthPercentage {
+                px: 0.0,
+                percent: 50.0
+            })
+        );
+        assert_eq!(length("0"), Some(LengthPercentage::default()));
+        assert_eq!(length("1"), None);
+        assert_eq!(length("1xx"), None);
+        assert_eq!(length("auto"), None);
+        assert_eq!(length("1px 2px"), None);
+        assert_eq!(length("50%").map(|l| l.resolve(300.0)), Some(150.0));
+    }
+
+    #[test]
+    fn test_calc() {
+        assert_eq!(
+            length("calc(50% - 2em)"),
+            Some(LengthPercentage {
+                px: -40.0,
+                percent: 50.0
+            })
+        );
+        assert_eq!(
+            length("calc((1px + 2px) * 2 + 10px / 2)"),
+            Some(LengthPercentage::px(11.0))
+        );
+        assert_eq!(
+            length("calc(2 * calc(1rem + 1px))"),
+            Some(LengthPercentage::px(22.0))
+        );
+        assert_eq!(length("calc(1px + 2)"), None);
+        assert_eq!(length("calc(1px * 2px)"), None);
+        assert_eq!(length("calc(1px / 0)"), None);
+        assert_eq!(length("calc(1px 2px)"), None);
+        assert_eq!(
+            parse_number(&parse_component_values("calc(1 + 2 * 3)")),
+            Some(7.0)
+        );
+    }
+
+    #[test]
+    fn test_colors() {
+        assert_eq!(color("red"), Some(Color::rgb(255, 0, 0)));
+        assert_eq!(color("RebeccaPurple"), Some(Color::rgb(0x66, 0x33, 0x99)));
+        assert_eq!(color("currentColor"), Some(Color::rgb(1, 2, 3)));
+        assert_eq!(color("transparent"), Some(Color::TRANSPARENT));
+        assert_eq!(color("#f80"), Some(Color::rgb(255, 136, 0)));
+        assert_eq!(
+            color("#ff880080"),
+            Some(Color {
+                r: 255,
+                g: 136,
+                b: 0,
+                a: 128
+            })
+        );
+        assert_eq!(color("#12345"), None);
+        assert_eq!(color("nocolor"), None);
+    }
+
+    #[test]
+    fn test_color_functions() {
+        assert_eq!(color("rgb(255, 0, 128)"), Some(Color::rgb(255, 0, 128)));
+        assert_eq!(color("rgb(100% 50% 0%)"), Some(Color::rgb(255, 128, 0)));
+        assert_eq!(
+            color("rgba(0, 0, 0, 0.5)"),
+            Some(Color {
+                r: 0,
+                g: 0,
+                b: 0,
+                a: 128
+            })
+        );
+        assert_eq!(
+            color("rgb(0 0 0 / 25%)"),
+            Some(Color {
+                r: 0,
+                g: 0,
+                b: 0,
+                a: 64
+            })
+        );
+        assert_eq!(color("rgb(300, -1, 0)"), Some(Color::rgb(255, 0, 0)));
+        assert_eq!(color("rgb(1, 2)"), None);
+        assert_eq!(color("rgb(1 2 3 4)"), None);
+
+        assert_eq!(color("hsl(0, 100%, 50%)"), Some(Color::rgb(255, 0, 0)));
+        assert_eq!(color("hsl(120deg 100% 25%)"), Some(Color::rgb(0, 128, 0)));
+        assert_eq!(
+            color("hsl(0.5turn 100% 50%)"),
+            Some(Color::rgb(0, 255, 255))
+        );
+        assert_eq!(
+            color("hsla(0, 0%, 100%, 0)"),
+            Some(Color {
+                r: 255,
+                g: 255,
+                b: 255,
+                a: 0
+            })
+        );
+    }
+}
@@ -0,0 +1,228 @@
+//! Custom properties like `--main-color` and their substitution with
+//! `var()`.
+//! https://drafts.csswg.org/css-variables-1/
+
+use crate::renderer::css::parser::{trim_whitespace, ComponentValue};
+use crate::renderer::css::style::keyword;
+use crate::renderer::css::token::CssToken;
+use alloc::collections::{BTreeMap, BTreeSet};
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+
+/// https://drafts.csswg.org/css-variables-1/#custom-property
+pub fn is_custom_property(name: &str) -> bool {
+    name.starts_with("--")
+}
+
+/// Returns whether `value` has a `var()` anywhere in it.
+pub fn contains_var(value: &[ComponentValue]) -> bool {
+    value.iter().any(|v| match v {
+        ComponentValue::Function { name, .. } if name.eq_ignore_ascii_case("var") => true,
+        ComponentValue::Function {
+            arguments: values, ..
+        }
+        | ComponentValue::Block {
+            contents: values, ..
+        } => contains_var(values),
+        ComponentValue::Token(_) => false,
+    })
+}
+
+/// Replaces each `var(--name, fallback)` in `value` with the value that
+/// `lookup` returns for `--name`, or with the fallback if there's none.
+/// Returns `None` if a `var()` has neither, which makes the whole value
+/// invalid at computed-value time.
+/// https://drafts.csswg.org/css-variables-1/#substitute-a-var
+pub fn substitute_var(
+    value: &[ComponentValue],
+    lookup: &mut impl FnMut(&str) -> Option<Vec<ComponentValue>>,
+) -> Option<Vec<ComponentValue>> {
+    let mut substituted = Vec::new();
+    for v in value {
+        match v {
+            ComponentValue::Function { name, arguments } if name.eq_ignore_ascii_case("var") => {
+                let (name, fallback) = parse_var(arguments)?;
+                match lookup(&name) {
+                    Some(value) => substituted.extend(value),
+                    None => substituted.extend(substitute_var(fallback?, lookup)?),
+                }
+            }
+            ComponentValue::Function { name, arguments } => {
+                substituted.push(ComponentValue::Function {
+                    name: name.clone(),
+                    arguments: substitute_var(arguments, lookup)?,
+                })
+            }
+            ComponentValue::Block { kind, contents } => substituted.push(ComponentValue::Block {
+                kind: *kind,
+                contents: substitute_var(contents, lookup)?,
+            }),
+            ComponentValue::Token(_) => substituted.push(v.clone()),
+        }
+    }
+    Some(substituted)
+}
+
+/// Splits the arguments of `var()` into the custom property name and the
+/// fallback after the first comma, if any.
+/// https://drafts.csswg.org/css-variables-1/#funcdef-var
+fn parse_var(arguments: &[ComponentValue]) -> Option<(String, Option<&[ComponentValue]>)> {
+    let arguments = trim_whitespace(arguments);
+    let (name, rest) = match arguments.split_first()? {
+        (ComponentValue::Token(CssToken::Ident(name)), rest) if is_custom_property(name) => {
+            (name, trim_whitespace(rest))
+        }
+        _ => return None,
+    };
+    match rest.split_first() {
+        None => Some((name.to_string(), None)),
+        Some((ComponentValue::Token(CssToken::Comma), fallback)) => {
+            Some((name.to_string(), Some(trim_whitespace(fallback))))
+        }
+        Some(_) => None,
+    }
+}
+
+/// Computes the custom properties of an element from the ones declared on
+/// it and the ones it inherits from its parent. All custom properties are
+/// inherited. A custom property that's `initial`, or is invalid at
+/// computed-value time, isn't in the result.
+/// https://drafts.csswg.org/css-variables-1/#defining-variables
+pub fn compute_custom_properties(
+    specified: &BTreeMap<String, Vec<ComponentValue>>,
+    inherited: Option<&BTreeMap<String, Vec<ComponentValue>>>,
+) -> BTreeMap<String, Vec<ComponentValue>> {
+    let mut resolver = Resolver {
+        specified,
+        inherited,
+        resolved: BTreeMap::new(),
+        stack: Vec::new(),
+        in_cycle: BTreeSet::new(),
+    };
+
+    let mut computed = inherited.cloned().unwrap_or_default();
+    for name in specified.keys() {
+        match resolver.resolve(name) {
+            Some(value) => computed.insert(name.clone(), value),
+            None => computed.remove(name),
+        };
+    }
+    computed
+}
+
+/// Substitutes `var()` in the declared custom properties of an element,
+/// following references to each other depth-first.
+struct Resolver<'a> {
+    specified: &'a BTreeMap<String, Vec<ComponentValue>>,
+    inherited: Option<&'a BTreeMap<String, Vec<ComponentValue>>>,
+    resolved: BTreeMap<String, Option<Vec<ComponentValue>>>,
+    /// The custom properties being resolved, each one referenced by the one
+    /// before it.
+    stack: Vec<String>,
+    in_cycle: BTreeSet<String>,
+}
+
+impl Resolver<'_> {
+    fn resolve(&mut self, name: &str) -> Option<Vec<ComponentValue>> {
+        if let Some(value) = self.resolved.get(name) {
+            return value.clone();
+        }
+
+        // The custom properties in a cycle are all invalid at computed-value
+        // time.
+        // https://drafts.csswg.org/css-variables-1/#cycles
+        if let Some(start) = self.stack.iter().position(|n| n == name) {
+            self.in_cycle.extend(self.stack[start..].iter().cloned());
+            return None;
+        }
+
+        let inherited = self.inherited.and_then(|i| i.get(name)).cloned();
+        let specified = match self.specified.get(name) {
+            Some(value) => value.clone(),
+            None => return inherited,
+        };
+        let value = match keyword(&specified).as_deref() {
+            Some("initial") => None,
+            Some("inherit") | Some("unset") => inherited,
+            _ => {
+                self.stack.push(name.to_string());
+                let value = substitute_var(&specified, &mut |n| self.resolve(n));
+                self.stack.pop();
+                value.filter(|_| !self.in_cycle.contains(name))
+            }
+        };
+
+        self.resolved.insert(name.to_string(), value.clone());
+        value
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::renderer::css::parser::parse_component_values;
+
+    fn properties(declarations: &[(&str, &str)]) -> BTreeMap<String, Vec<ComponentValue>> {
+        declarations
+            .iter()
+            .map(|(name, value)| (name.to_string(), parse_component_values(value)))
+            .collect()
+    }
+
+    #[test]
+    fn test_substitute_var() {
+        let variables = properties(&[("--a", "1px"), ("--b", "red blue")]);
+        let substitute = |css: &str| {
+            substitute_var(&parse_component_values(css), &mut |name| {
+                variables.get(name).cloned()
+            })
+        };
+
+        assert_eq!(substitute("var(--a)"), Some(parse_component_values("1px")));
+        assert_eq!(
+            substitute("calc(var(--a) + 2px) var(--b)"),
+            Some(parse_component_values("calc(1px + 2px) red blue"))
+        );
+        assert_eq!(
+            substitute("var(--x, var(--y, 3px 4px))"),
+            Some(parse_component_values("3px 4px"))
+        );
+        assert_eq!(substitute("var(--x,)"), Some(Vec::new()));
+        assert_eq!(substitute("var(--x)"), None);
+        assert_eq!(substitute("var(x)"), None);
+        assert_eq!(substitute("var(--a --b)"), None);
+
+        assert!(contains_var(&parse_component_values("1px (a VAR(--x))")));
+        assert!(!contains_var(&parse_component_values("1px variable(--x)")));
+    }
+
+    #[test]
+    fn test_compute_custom_properties() {
+        let inherited = properties(&[("--color", "red"), ("--size", "1px"), ("--gone", "x")]);
+        let specified = properties(&[
+            ("--size", "var(--color) 2px"),
+            ("--gone", "initial"),
+            ("--chain", "var(--size) var(--missing, 3px)"),
+            ("--a", "var(--b)"),
+            ("--b", "var(--c, 1px) var(--a)"),
+            ("--c", "var(--b)"),
+            ("--uses-cycle", "var(--a, fallback)"),
+            ("--broken", "var(--a)"),
+        ]);
+        let computed = compute_custom_properties(&specified, Some(&inherited));
+
+        let get = |name: &str| computed.get(name).cloned();
+        assert_eq!(get("--color"), Some(parse_component_values("red")));
+        assert_eq!(get("--size"), Some(parse_component_values("red 2px")));
+        assert_eq!(get("--chain"), Some(parse_component_values("red 2px 3px")));
+        assert_eq!(get("--gone"), None);
+        assert_eq!(get("--a"), None);
+        assert_eq!(get("--b"), None);
+        assert_eq!(get("--c"), None);
+        assert_eq!(
+            get("--uses-cycle"),
+            Some(parse_component_values("fallback"))
+        );
+        assert_eq!(get("--broken"), None);
+    }
+}
@@ -0,0 +1,102 @@
+//! A counting bloom filter of the types, IDs and classes of the ancestors of
+//! an element, which rules out most descendant selectors without walking up
+//! the tree.
+//! https://en.wikipedia.org/wiki/Bloom_filter#Counting_Bloom_filters
+
+use alloc::vec;
+use alloc::vec::Vec;
+
+/// The number of bits of a hash that index the counters.
+const KEY_SIZE: u32 = 12;
+const KEY_MASK: u32 = (1 << KEY_SIZE) - 1;
+
+/// Each hash sets two counters, so that removing a hash on the way back up
+/// the tree undoes inserting it. A counter that overflows stays at the
+/// maximum, which can only cause false positives.
+#[derive(Debug, Clone)]
+pub struct BloomFilter {
+    counters: Vec<u8>,
+}
+
+impl BloomFilter {
+    pub fn new() -> Self {
+        Self {
+            counters: vec![0; 1 << KEY_SIZE],
+        }
+    }
+
+    pub fn insert(&mut self, hash: u32) {
+        for index in indexes(hash) {
+            self.counters[index] = self.counters[index].saturating_add(1);
+        }
+    }
+
+    /// Removes a hash that was inserted before.
+    pub fn remove(&mut self, hash: u32) {
+        for index in indexes(hash) {
+            let counter = &mut self.counters[index];
+            if *counter != u8::MAX {
+                *counter -= 1;
+            }
+        }
+    }
+
+    /// Returns false if `hash` was certainly not inserted, and true if it
+    /// probably was.
+    pub fn might_contain(&self, hash: u32) -> bool {
+        indexes(hash).iter().all(|&index| self.counters[index] != 0)
+    }
+}
+
+impl Default for BloomFilter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn indexes(hash: u32) -> [usize; 2] {
+    [
+        (hash & KEY_MASK) as usize,
+        ((hash >> KEY_SIZE) & KEY_MASK) as usize,
+    ]
+}
+
+/// Hashes `name` with FNV-1a. `kind` tells types, IDs and classes apart.
+/// ASCII case is ignored, since type selectors are case-insensitive, and a
+/// hash that's equal more often only makes the filter less precise.
+/// http://www.isthe.com/chongo/tech/comp/fnv/
+pub fn hash(kind: u8, name: &str) -> u32 {
+    let mut hash: u32 = 0x811c_9dc5;
+    for byte in core::iter::once(kind).chain(name.bytes()) {
+        hash ^= u32::from(byte.to_ascii_lowercase());
+        hash = hash.wrapping_mul(0x0100_0193);
+    }
+    hash
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bloom_filter() {
+        let mut filter = BloomFilter::new();
+        let div = hash(b't', "div");
+        let a = hash(b'.', "a");
+        assert!(!filter.might_contain(div));
+
+        filter.insert(div);
+        filter.insert(a);
+        filter.insert(div);
+        assert!(filter.might_contain(div));
+        assert!(filter.might_contain(a));
+        assert!(!filter.might_contain(hash(b'#', "a")));
+        assert_eq!(hash(b't', "DIV"), div);
+
+        filter.remove(div);
+        assert!(filter.might_contain(div));
+        filter.remove(div);
+        assert!(!filter.might_contain(div));
+        assert!(filter.might_contain(a));
+    }
+}
@@ -0,0 +1,6 @@
+pub mod bloom;
+pub mod mutation;
+pub mod node;
+pub mod query;
+pub mod selector;
+pub mod token_list;
@@ -0,0 +1,474 @@
+//! Change records for DOM mutations, modeled after `MutationObserver`.
+//! https://dom.spec.whatwg.org/#mutation-observers
+//!
+//! There is no event loop to deliver records to callbacks, so observers
+//! collect them until the embedder calls `MutationObserver::take_records()`.
+
+use crate::error::Error;
+use crate::renderer::dom::node::Node;
+use alloc::rc::{Rc, Weak};
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::cell::RefCell;
+use core::mem;
+
+/// Collects the records of the mutations of the nodes that it observes.
+/// https://dom.spec.whatwg.org/#interface-mutationobserver
+#[derive(Debug, Default)]
+pub struct MutationObserver {
+    records: Vec<MutationRecord>,
+    /// The nodes that have a registration of this observer, for
+    /// `disconnect()`.
+    targets: Vec<Weak<RefCell<Node>>>,
+}
+
+impl MutationObserver {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the records queued so far and empties the queue.
+    /// https://dom.spec.whatwg.org/#dom-mutationobserver-takerecords
+    pub fn take_records(&mut self) -> Vec<MutationRecord> {
+        mem::take(&mut self.records)
+    }
+}
+
+/// Which mutations an observer is interested in.
+/// https://dom.spec.whatwg.org/#dictdef-mutationobserverinit
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MutationObserverInit {
+    pub child_list: bool,
+    pub attributes: bool,
+    pub character_data: bool,
+    /// Observe the descendants of the target as well.
+    pub subtree: bool,
+    pub attribute_old_value: bool,
+    pub character_data_old_value: bool,
+    /// Only observe the attributes with these names, when set.
+    pub attribute_filter: Option<Vec<String>>,
+}
+
+/// https://dom.spec.whatwg.org/#registered-observer
+#[derive(Debug, Clone)]
+pub struct RegisteredObserver {
+    observer: Weak<RefCell<MutationObserver>>,
+    options: MutationObserverInit,
+}
+
+/// https://dom.spec.whatwg.org/#dom-mutationrecord-type
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum MutationType {
+    ChildList,
+    Attributes,
+    CharacterData,
+}
+
+/// https://dom.spec.whatwg.org/#interface-mutationrecord
+#[derive(Debug, Clone)]
+pub struct MutationRecord {
+    pub kind: MutationType,
+    /// The parent for `ChildList`, and the changed node otherwise.
+    pub target: Rc<RefCell<Node>>,
+    pub added_nodes: Vec<Rc<RefCell<Node>>>,
+    pub removed_nodes: Vec<Rc<RefCell<Node>>>,
+    pub previous_sibling: Option<Rc<RefCell<Node>>>,
+    pub next_sibling: Option<Rc<RefCell<Node>>>,
+    pub attribute_name: Option<String>,
+    /// Only set when the observer asked for old values.
+    pub old_value: Option<String>,
+}
+
+impl MutationRecord {
+    fn new(kind: MutationType, target: Rc<RefCell<Node>>) -> Self {
+        Self {
+            kind,
+            target,
+            added_nodes: Vec::new(),
+            removed_nodes: Vec::new(),
+            previous_sibling: None,
+            next_sibling: None,
+            attribute_name: None,
+            old_value: None,
+        }
+    }
+
+    pub(crate) fn child_list(
+        target: Rc<RefCell<Node>>,
+        added_nodes: Vec<Rc<RefCell<Node>>>,
+        removed_nodes: Vec<Rc<RefCell<Node>>>,
+        previous_sibling: Option<Rc<RefCell<Node>>>,
+        next_sibling: Option<Rc<RefCell<Node>>>,
+    ) -> Self {
+        Self {
+            added_nodes,
+            removed_nodes,
+            previous_sibling,
+            next_sibling,
+            ..Self::new(MutationType::ChildList, target)
+        }
+    }
+
+    pub(crate) fn attributes(
+        target: Rc<RefCell<Node>>,
+        attribute_name: &str,
+        old_value: Option<String>,
+    ) -> Self {
+        Self {
+            attribute_name: Some(String::from(attribute_name)),
+            old_value,
+            ..Self::new(MutationType::Attributes, target)
+        }
+    }
+
+    pub(crate) fn character_data(target: Rc<RefCell<Node>>, old_value: String) -> Self {
+        Self {
+            old_value: Some(old_value),
+            ..Self::new(MutationType::CharacterData, target)
+        }
+    }
+}
+
+/// Registers `observer` on `target`, replacing the options of an existing
+/// registration by the same observer. Returns an error if `options` doesn't
+/// observe any kind of mutation.
+/// https://dom.spec.whatwg.org/#dom-mutationobserver-observe
+pub fn observe(
+    observer: &Rc<RefCell<MutationObserver>>,
+    target: &Rc<RefCell<Node>>,
+    mut options: MutationObserverInit,
+) -> Result<(), Error> {
+    if options.attribute_old_value || options.attribute_filter.is_some() {
+        options.attributes = true;
+    }
+    if options.character_data_old_value {
+        options.character_data = true;
+    }
+    if !options.child_list && !options.attributes && !options.character_data {
+        return Err(Error::UnexpectedInput(String::from(
+            "childList, attributes or characterData must be observed",
+        )));
+    }
+
+    let mut t = target.borrow_mut();
+    let registered = t.registered_observers_mut();
+    match registered
+        .iter_mut()
+        .find(|r| Weak::ptr_eq(&r.observer, &Rc::downgrade(observer)))
+    {
+        Some(r) => r.options = options,
+        None => {
+            registered.push(RegisteredObserver {
+                observer: Rc::downgrade(observer),
+                options,
+            });
+            observer.borrow_mut().targets.push(Rc::downgrade(target));
+        }
+    }
+
+    Ok(())
+}
+
+/// Stops `observer` from observing any node, and drops its pending records.
+/// https://dom.spec.whatwg.org/#dom-mutationobserver-disconnect
+pub fn disconnect(observer: &Rc<RefCell<MutationObserver>>) {
+    let targets = mem::take(&mut observer.borrow_mut().targets);
+    for target in targets {
+        if let Some(t) = target.upgrade() {
+            t.borrow_mut()
+                .registered_observers_mut()
+                .retain(|r| !Weak::ptr_eq(&r.observer, &Rc::downgrade(observer)));
+        }
+    }
+    observer.borrow_mut().records.clear();
+}
+
+/// Queues `record` to the observers of its target and of the target's
+/// ancestors that observe the subtree.
+/// https://dom.spec.whatwg.org/#queue-a-mutation-record
+pub(crate) fn queue_record(record: MutationRecord) {
+    // Each interested observer gets one record, with the old value if any of
+    // its registrations asked for it.
+    let mut interested: Vec<(Rc<RefCell<MutationObserver>>, bool)> = Vec::new();
+
+    let mut node = Some(record.target.clone());
+    while let Some(n) = node {
+        let is_target = Rc::ptr_eq(&n, &record.target);
+        for registered in n.borrow().registered_observers() {
+            let options = &registered.options;
+            if !is_target && !options.subtree {
+                continue;
+            }
+
+            let wants_old_value = match record.kind {
+                MutationType::ChildList if options.child_list => false,
+                MutationType::Attributes if options.attributes => {
+                    if let (Some(filter), Some(name)) =
+                        (&options.attribute_filter, &record.attribute_name)
+                    {
+                        if !filter.contains(name) {
+                            continue;
+                        }
+                    }
+                    options.attribute_old_value
+                }
+                MutationType::CharacterData if options.character_data => {
+                    options.character_data_old_value
+                }
+                _ => continue,
+            };
+
+            let observer = match registered.observer.upgrade() {
+                Some(o) => o,
+                None => continue,
+            };
+            match interested
+                .iter_mut()
+                .find(|(o, _)| Rc::ptr_eq(o, &observer))
+            {
+                Some((_, old_value)) => *old_value |= wants_old_value,
+                None => interested.push((observer, wants_old_value)),
+            }
+        }
+        node = n.borrow().parent().upgrade();
+    }
+
+    for (observer, wants_old_value) in interested {
+        let mut r = record.clone();
+        if !wants_old_value {
+            r.old_value = None;
+        }
+        observer.borrow_mut().records.push(r);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::renderer::dom::node::{
+        append_child, detach, insert_before, remove_attribute, replace_child, set_attribute,
+        set_data, set_inner_html, Element, NodeKind,
+    };
+    use alloc::string::ToString;
+    use alloc::vec;
+
+    fn element(tag_name: &str) -> Rc<RefCell<Node>> {
+        Rc::new(RefCell::new(Node::new(NodeKind::Element(Element::new(
+            tag_name,
+            Vec::new(),
+        )))))
+    }
+
+    fn text(s: &str) -> Rc<RefCell<Node>> {
+        Rc::new(RefCell::new(Node::new(NodeKind::Text(s.to_string()))))
+    }
+
+    fn observer() -> Rc<RefCell<MutationObserver>> {
+        Rc::new(RefCell::new(MutationObserver::new()))
+    }
+
+    fn same(a: &Option<Rc<RefCell<Node>>>, b: &Rc<RefCell<Node>>) -> bool {
+        a.as_ref().is_some_and(|a| Rc::ptr_eq(a, b))
+    }
+
+    #[test]
+    fn test_child_list() {
+        let parent = element("div");
+        let a = element("a");
+        let b = element("b");
+        let i = element("i");
+        let o = observer();
+        observe(
+            &o,
+            &parent,
+            MutationObserverInit {
+                child_list: true,
+                ..Default::default()
+            },
+        )
+        .expect("failed to observe");
+
+        append_child(&parent, a.clone()).expect("failed to append");
+        append_child(&parent, b.clone()).expect("failed to append");
+        insert_before(&parent, i.clone(), Some(&b)).expect("failed to insert");
+        let records = o.borrow_mut().take_records();
+        assert_eq!(records.len(), 3);
+        assert!(records.iter().all(|r| r.kind == MutationType::ChildList));
+        assert!(Rc::ptr_eq(&records[2].target, &parent));
+        assert!(Rc::ptr_eq(&records[2].added_nodes[0], &i));
+        assert!(same(&records[2].previous_sibling, &a));
+        assert!(same(&records[2].next_sibling, &b));
+        assert!(o.borrow_mut().take_records().is_empty());
+
+        detach(&i);
+        let records = o.borrow_mut().take_records();
+        assert_eq!(records.len(), 1);
+        assert!(Rc::ptr_eq(&records[0].removed_nodes[0], &i));
+        assert!(records[0].added_nodes.is_empty());
+
+        replace_child(&parent, i.clone(), &a).expect("failed to replace");
+        let records = o.borrow_mut().take_records();
+        assert_eq!(records.len(), 1);
+        assert!(Rc::ptr_eq(&records[0].added_nodes[0], &i));
+        assert!(Rc::ptr_eq(&records[0].removed_nodes[0], &a));
+        assert!(records[0].previous_sibling.is_none());
+        assert!(same(&records[0].next_sibling, &b));
+        assert!(a.borrow().parent().upgrade().is_none());
+    }
+
+    #[test]
+    fn test_subtree() {
+        let parent = element("div");
+        let child = element("p");
+        append_child(&parent, child.clone()).expect("failed to append");
+
+        let shallow = observer();
+        let deep = observer();
+        let options = MutationObserverInit {
+            child_list: true,
+            attributes: true,
+            ..Default::default()
+        };
+        observe(&shallow, &parent, options.clone()).expect("failed to observe");
+        observe(
+            &deep,
+            &parent,
+            MutationObserverInit {
+                subtree: true,
+                ..options.clone()
+            },
+        )
+        .expect("failed to observe");
+        // A second registration on a descendant doesn't duplicate records.
+        observe(&deep, &child, options).expect("failed to observe");
+
+        append_child(&child, text("x")).expect("failed to append");
+        set_attribute(&child, "id", "a");
+        assert!(shallow.borrow_mut().take_records().is_empty());
+        assert_eq!(deep.borrow_mut().take_records().len(), 2);
+
+        disconnect(&deep);
+        set_attribute(&child, "id", "b");
+        assert!(deep.borrow_mut().take_records().is_empty());
+    }
+
+    #[test]
+    fn test_attributes() {
+        let node = element("div");
+        let o = observer();
+        observe(
+            &o,
+            &node,
+            MutationObserverInit {
+                attribute_old_value: true,
+                attribute_filter: Some(vec!["id".to_string(), "class".to_string()]),
+                ..Default::default()
+            },
+        )
+        .expect("failed to observe");
+
+        set_attribute(&node, "ID", "a");
+        set_attribute(&node, "id", "b");
+        set_attribute(&node, "title", "c");
+        remove_attribute(&node, "id");
+        remove_attribute(&node, "class");
+
+        let records = o.borrow_mut().take_records();
+        let changes: Vec<(Option<String>, Option<String>)> = records
+            .iter()
+            .map(|r| (r.attribute_name.clone(), r.old_value.clone()))
+            .collect();
+        assert_eq!(
+            changes,
+            [
+                (Some("id".to_string()), None),
+                (Some("id".to_string()), Some("a".to_string())),
+                (Some("id".to_string()), Some("b".to_string())),
+            ]
+        );
+        let element = node.borrow().get_element().expect("not an element");
+        assert_eq!(element.get_attribute("id"), None);
+        assert_eq!(element.get_attribute("title"), Some("c".to_string()));
+    }
+
+    #[test]
+    fn test_character_data() {
+        let parent = element("p");
+        let t = text("a");
+        append_child(&parent, t.clone()).expect("failed to append");
+
+        let with_old_value = observer();
+        let without_old_value = observer();
+        observe(
+            &with_old_value,
+            &parent,
+            MutationObserverInit {
+                character_data_old_value: true,
+                subtree: true,
+                ..Default::default()
+            },
+        )
+        .expect("failed to observe");
+        observe(
+            &without_old_value,
+            &t,
+            MutationObserverInit {
+                character_data: true,
+                ..Default::default()
+            },
+        )
+        .expect("failed to observe");
+
+        set_data(&t, "b");
+        assert_eq!(t.borrow().kind(), NodeKind::Text("b".to_string()));
+
+        let records = with_old_value.borrow_mut().take_records();
+        assert_eq!(records[0].kind, MutationType::CharacterData);
+        assert!(Rc::ptr_eq(&records[0].target, &t));
+        assert_eq!(records[0].old_value, Some("a".to_string()));
+
+        let records = without_old_value.borrow_mut().take_records();
+        assert_eq!(records[0].old_value, None);
+    }
+
+    #[test]
+    fn test_observe_error() {
+        assert!(observe(&observer(), &element("div"), Default::default()).is_err());
+    }
+
+    #[test]
+    fn test_inner_html() {
+        let parent = element("div");
+        let a = element("a");
+        let b = element("b");
+        append_child(&parent, a.clone()).expect("failed to append");
+        append_child(&parent, b.clone()).expect("failed to append");
+
+        let o = observer();
+        observe(
+            &o,
+            &parent,
+            MutationObserverInit {
+                child_list: true,
+                ..Default::default()
+            },
+        )
+        .expect("failed to observe");
+
+        // Setting the markup replaces all the children at once.
+        set_inner_html(&parent, "<i></i>c");
+        let records = o.borrow_mut().take_records();
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].added_nodes.len(), 2);
+        assert_eq!(records[0].removed_nodes.len(), 2);
+        assert!(Rc::ptr_eq(&records[0].removed_nodes[0], &a));
+        assert!(records[0].previous_sibling.is_none());
+        assert!(records[0].next_sibling.is_none());
+
+        set_inner_html(&element("div"), "");
+        set_inner_html(&parent, "");
+        assert_eq!(o.borrow_mut().take_records().len(), 1);
+        set_inner_html(&parent, "");
+        assert!(o.borrow_mut().take_records().is_empty());
+    }
+}
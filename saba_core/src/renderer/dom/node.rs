@@ -0,0 +1,1409 @@
+use crate::error::Error;
+use crate::renderer::css::cssom::{Declaration, LoadState, StyleSheet};
+use crate::renderer::css::parser::{parse_component_values, parse_declaration_list};
+use crate::renderer::css::style::ComputedStyle;
+use crate::renderer::dom::mutation::{queue_record, MutationRecord, RegisteredObserver};
+use crate::renderer::dom::query::descendant_elements;
+use crate::renderer::dom::token_list::DomTokenList;
+use crate::renderer::html::attribute::{parse_non_negative_integer, Attribute};
+use crate::renderer::html::parser::HtmlParser;
+use crate::renderer::html::serializer;
+use crate::renderer::html::token::HtmlTokenizer;
+use crate::url::Url;
+use alloc::rc::{Rc, Weak};
+use alloc::string::{String, ToString};
+use alloc::vec;
+use alloc::vec::Vec;
+use core::cell::RefCell;
+
+#[derive(Debug, Clone)]
+pub struct Window {
+    document: Rc<RefCell<Node>>,
+    quirks_mode: QuirksMode,
+    url: Option<Url>,
+    style_sheets: Vec<StyleSheet>,
+    hovered: Option<Rc<RefCell<Node>>>,
+    focused: Option<Rc<RefCell<Node>>>,
+}
+
+impl Window {
+    pub fn new() -> Self {
+        Self {
+            document: Rc::new(RefCell::new(Node::new(NodeKind::Document))),
+            quirks_mode: QuirksMode::NoQuirks,
+            url: None,
+            style_sheets: Vec::new(),
+            hovered: None,
+            focused: None,
+        }
+    }
+
+    pub fn document(&self) -> Rc<RefCell<Node>> {
+        self.document.clone()
+    }
+
+    /// The mode of the document, which is decided by its DOCTYPE.
+    pub fn quirks_mode(&self) -> QuirksMode {
+        self.quirks_mode
+    }
+
+    pub fn set_quirks_mode(&mut self, quirks_mode: QuirksMode) {
+        self.quirks_mode = quirks_mode;
+    }
+
+    /// The URL that the document was loaded from.
+    pub fn url(&self) -> Option<Url> {
+        self.url.clone()
+    }
+
+    pub fn set_url(&mut self, url: Url) {
+        self.url = Some(url);
+    }
+
+    /// The style sheets of the document in tree order, which the HTML parser
+    /// adds as it finishes `<style>` elements and sees `<link>` elements.
+    /// https://drafts.csswg.org/cssom/#documentorshadowroot-document-or-shadow-root-css-style-sheets
+    pub fn style_sheets(&self) -> &[StyleSheet] {
+        &self.style_sheets
+    }
+
+    pub fn style_sheets_mut(&mut self) -> &mut [StyleSheet] {
+        &mut self.style_sheets
+    }
+
+    /// Returns whether a style sheet in `<head>` is still loading, in which
+    /// case the document shouldn't be painted yet.
+    /// https://html.spec.whatwg.org/multipage/dom.html#render-blocking-mechanism
+    pub fn is_render_blocked(&self) -> bool {
+        self.style_sheets
+            .iter()
+            .any(|s| s.render_blocking && s.state == LoadState::Loading)
+    }
+
+    pub fn add_style_sheet(&mut self, style_sheet: StyleSheet) {
+        self.style_sheets.push(style_sheet);
+    }
+
+    /// Sets the element under the pointer. It and its ancestors match
+    /// `:hover`. Style the document again afterwards.
+    /// https://drafts.csswg.org/selectors-4/#the-hover-pseudo
+    pub fn set_hovered(&mut self, node: Option<Rc<RefCell<Node>>>) {
+        for (node, hover) in [(self.hovered.take(), false), (node.clone(), true)] {
+            let mut current = node;
+            while let Some(n) = current {
+                n.borrow_mut().state.hover = hover;
+                current = n.borrow().parent().upgrade();
+            }
+        }
+        self.hovered = node;
+    }
+
+    /// Sets the element that has focus, which matches `:focus`. Style the
+    /// document again afterwards.
+    /// https://drafts.csswg.org/selectors-4/#the-focus-pseudo
+    pub fn set_focused(&mut self, node: Option<Rc<RefCell<Node>>>) {
+        if let Some(n) = self.focused.take() {
+            n.borrow_mut().state.focus = false;
+        }
+        if let Some(n) = &node {
+            n.borrow_mut().state.focus = true;
+        }
+        self.focused = node;
+    }
+
+    /// Returns the URL that relative URLs in the document are resolved
+    /// against: the `href` of the first `<base>` element, or the document's
+    /// URL.
+    /// https://html.spec.whatwg.org/multipage/urls-and-fetching.html#document-base-url
+    pub fn base_url(&self) -> Option<Url> {
+        let url = self.url.clone()?;
+        let base_href = descendant_elements(&self.document).find_map(|n| {
+            let element = n.borrow().get_element()?;
+            if element.kind() != ElementKind::Base {
+                return None;
+            }
+            element.get_attribute("href")
+        });
+
+        match base_href {
+            Some(href) => url.join(&href).ok().or(Some(url)),
+            None => Some(url),
+        }
+    }
+}
+
+impl Default for Window {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// https://dom.spec.whatwg.org/#concept-document-mode
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum QuirksMode {
+    NoQuirks,
+    LimitedQuirks,
+    Quirks,
+}
+
+#[derive(Debug, Clone)]
+pub struct Node {
+    pub kind: NodeKind,
+    parent: Weak<RefCell<Node>>,
+    first_child: Option<Rc<RefCell<Node>>>,
+    last_child: Weak<RefCell<Node>>,
+    previous_sibling: Weak<RefCell<Node>>,
+    next_sibling: Option<Rc<RefCell<Node>>>,
+    /// https://dom.spec.whatwg.org/#registered-observer-list
+    registered_observers: Vec<RegisteredObserver>,
+    /// https://html.spec.whatwg.org/multipage/scripting.html#template-contents
+    template_contents: Option<Rc<RefCell<Node>>>,
+    /// Set by `StyleEngine::style_document()`.
+    computed_style: Option<ComputedStyle>,
+    /// Set by `Window::set_hovered()` and `Window::set_focused()`.
+    state: ElementState,
+}
+
+/// The user interaction states that pseudo-classes like `:hover` match.
+/// https://drafts.csswg.org/selectors-4/#useraction-pseudos
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct ElementState {
+    pub hover: bool,
+    pub focus: bool,
+}
+
+impl PartialEq for Node {
+    fn eq(&self, other: &Self) -> bool {
+        self.kind == other.kind
+    }
+}
+
+impl Node {
+    /// Creates a node. A template element gets an empty document fragment as
+    /// its contents.
+    pub fn new(kind: NodeKind) -> Self {
+        let template_contents = match kind {
+            NodeKind::Element(ref e) if e.kind() == ElementKind::Template => {
+                Some(Rc::new(RefCell::new(Node::new(NodeKind::DocumentFragment))))
+            }
+            _ => None,
+        };
+
+        Self {
+            kind,
+            parent: Weak::new(),
+            first_child: None,
+            last_child: Weak::new(),
+            previous_sibling: Weak::new(),
+            next_sibling: None,
+            registered_observers: Vec::new(),
+            template_contents,
+            computed_style: None,
+            state: ElementState::default(),
+        }
+    }
+
+    pub fn kind(&self) -> NodeKind {
+        self.kind.clone()
+    }
+
+    pub fn get_element(&self) -> Option<Element> {
+        match self.kind {
+            NodeKind::Element(ref e) => Some(e.clone()),
+            _ => None,
+        }
+    }
+
+    pub fn element_kind(&self) -> Option<ElementKind> {
+        match self.kind {
+            NodeKind::Element(ref e) => Some(e.kind()),
+            _ => None,
+        }
+    }
+
+    pub fn set_parent(&mut self, parent: Weak<RefCell<Node>>) {
+        self.parent = parent;
+    }
+
+    pub fn parent(&self) -> Weak<RefCell<Node>> {
+        self.parent.clone()
+    }
+
+    pub fn set_first_child(&mut self, first_child: Option<Rc<RefCell<Node>>>) {
+        self.first_child = first_child;
+    }
+
+    pub fn first_child(&self) -> Option<Rc<RefCell<Node>>> {
+        self.first_child.as_ref().cloned()
+    }
+
+    pub fn set_last_child(&mut self, last_child: Weak<RefCell<Node>>) {
+        self.last_child = last_child;
+    }
+
+    pub fn last_child(&self) -> Weak<RefCell<Node>> {
+        self.last_child.clone()
+    }
+
+    pub fn set_previous_sibling(&mut self, previous_sibling: Weak<RefCell<Node>>) {
+        self.previous_sibling = previous_sibling;
+    }
+
+    pub fn previous_sibling(&self) -> Weak<RefCell<Node>> {
+        self.previous_sibling.clone()
+    }
+
+    pub fn set_next_sibling(&mut self, next_sibling: Option<Rc<RefCell<Node>>>) {
+        self.next_sibling = next_sibling;
+    }
+
+    pub fn next_sibling(&self) -> Option<Rc<RefCell<Node>>> {
+        self.next_sibling.as_ref().cloned()
+    }
+
+    /// Returns the children of this node in tree order.
+    pub fn children(&self) -> Vec<Rc<RefCell<Node>>> {
+        let mut children = Vec::new();
+        let mut child = self.first_child();
+        while let Some(c) = child {
+            child = c.borrow().next_sibling();
+            children.push(c);
+        }
+        children
+    }
+
+    /// The document fragment that holds the contents of a template element.
+    /// The contents aren't children of the template.
+    pub fn template_contents(&self) -> Option<Rc<RefCell<Node>>> {
+        self.template_contents.clone()
+    }
+
+    /// The style of an element, once the style engine has computed it.
+    pub fn computed_style(&self) -> Option<ComputedStyle> {
+        self.computed_style.clone()
+    }
+
+    pub fn set_computed_style(&mut self, style: ComputedStyle) {
+        self.computed_style = Some(style);
+    }
+
+    pub fn state(&self) -> ElementState {
+        self.state
+    }
+
+    pub(crate) fn registered_observers(&self) -> Vec<RegisteredObserver> {
+        self.registered_observers.clone()
+    }
+
+    pub(crate) fn registered_observers_mut(&mut self) -> &mut Vec<RegisteredObserver> {
+        &mut self.registered_observers
+    }
+}
+
+/// Appends `child` as the last child of `parent`.
+/// https://dom.spec.whatwg.org/#concept-node-append
+pub fn append_child(parent: &Rc<RefCell<Node>>, child: Rc<RefCell<Node>>) -> Result<(), Error> {
+    insert_before(parent, child, None)
+}
+
+/// Inserts `child` into `parent` just before `reference`, or at the end when
+/// `reference` is `None`. `child` is detached from its current parent first.
+/// Returns an error if the tree would be invalid, or if `reference` isn't a
+/// child of `parent`.
+/// https://dom.spec.whatwg.org/#concept-node-pre-insert
+pub fn insert_before(
+    parent: &Rc<RefCell<Node>>,
+    child: Rc<RefCell<Node>>,
+    reference: Option<&Rc<RefCell<Node>>>,
+) -> Result<(), Error> {
+    ensure_validity(parent, &child, reference, false)?;
+
+    // Inserting a node before itself inserts it before its next sibling.
+    let reference = match reference {
+        Some(r) if Rc::ptr_eq(r, &child) => child.borrow().next_sibling(),
+        r => r.cloned(),
+    };
+    detach(&child);
+    let previous = insert(parent, child.clone(), reference.as_ref());
+    queue_record(MutationRecord::child_list(
+        parent.clone(),
+        vec![child],
+        Vec::new(),
+        previous,
+        reference,
+    ));
+    Ok(())
+}
+
+/// Removes `node` from its parent, if any. The node keeps its own children.
+/// https://dom.spec.whatwg.org/#concept-node-remove
+pub fn detach(node: &Rc<RefCell<Node>>) {
+    let parent = match node.borrow().parent().upgrade() {
+        Some(p) => p,
+        None => return,
+    };
+
+    let previous = node.borrow().previous_sibling().upgrade();
+    let next = node.borrow().next_sibling();
+    remove(node);
+    queue_record(MutationRecord::child_list(
+        parent,
+        Vec::new(),
+        vec![node.clone()],
+        previous,
+        next,
+    ));
+}
+
+/// Replaces `old_child` of `parent` with `new_child`, which is detached from
+/// its current parent first. Returns an error if the tree would be invalid,
+/// or if `old_child` isn't a child of `parent`.
+/// https://dom.spec.whatwg.org/#concept-node-replace
+pub fn replace_child(
+    parent: &Rc<RefCell<Node>>,
+    new_child: Rc<RefCell<Node>>,
+    old_child: &Rc<RefCell<Node>>,
+) -> Result<(), Error> {
+    ensure_validity(parent, &new_child, Some(old_child), true)?;
+    if Rc::ptr_eq(&new_child, old_child) {
+        return Ok(());
+    }
+
+    detach(&new_child);
+    let previous = old_child.borrow().previous_sibling().upgrade();
+    let next = old_child.borrow().next_sibling();
+    remove(old_child);
+    insert(parent, new_child.clone(), next.as_ref());
+    queue_record(MutationRecord::child_list(
+        parent.clone(),
+        vec![new_child],
+        vec![old_child.clone()],
+        previous,
+        next,
+    ));
+    Ok(())
+}
+
+/// Replaces all the children of `parent` with `nodes`, which are detached
+/// from their current parents first, and queues a single record of it. Like
+/// in the spec, the nodes aren't checked to fit in `parent`.
+/// https://dom.spec.whatwg.org/#concept-node-replace-all
+fn replace_all(parent: &Rc<RefCell<Node>>, nodes: Vec<Rc<RefCell<Node>>>) {
+    let removed = parent.borrow().children();
+    for child in &removed {
+        remove(child);
+    }
+    for node in &nodes {
+        detach(node);
+        insert(parent, node.clone(), None);
+    }
+    if !nodes.is_empty() || !removed.is_empty() {
+        queue_record(MutationRecord::child_list(
+            parent.clone(),
+            nodes,
+            removed,
+            None,
+            None,
+        ));
+    }
+}
+
+/// Returns an error if inserting `node` into `parent` before `child`, or in
+/// place of `child` when `replacing`, would make an invalid tree.
+///
+/// The host of a template's contents isn't known, so a template can be
+/// inserted into its own contents. That doesn't make a cycle of parents.
+/// https://dom.spec.whatwg.org/#concept-node-ensure-pre-insertion-validity
+/// https://dom.spec.whatwg.org/#concept-node-replace
+fn ensure_validity(
+    parent: &Rc<RefCell<Node>>,
+    node: &Rc<RefCell<Node>>,
+    child: Option<&Rc<RefCell<Node>>>,
+    replacing: bool,
+) -> Result<(), Error> {
+    let hierarchy_request = |message: &str| Err(Error::HierarchyRequest(String::from(message)));
+
+    let is_document = parent.borrow().kind == NodeKind::Document;
+    if !matches!(
+        parent.borrow().kind,
+        NodeKind::Document | NodeKind::DocumentFragment | NodeKind::Element(_)
+    ) {
+        return hierarchy_request("the parent can't have children");
+    }
+
+    let mut ancestor = Some(parent.clone());
+    while let Some(a) = ancestor {
+        if Rc::ptr_eq(&a, node) {
+            return hierarchy_request("the node is an inclusive ancestor of the parent");
+        }
+        ancestor = a.borrow().parent().upgrade();
+    }
+
+    if let Some(child) = child {
+        match child.borrow().parent().upgrade() {
+            Some(ref p) if Rc::ptr_eq(p, parent) => {}
+            _ => {
+                return Err(Error::NotFound(String::from(
+                    "the child isn't a child of the parent",
+                )))
+            }
+        }
+    }
+
+    match node.borrow().kind {
+        NodeKind::Document => return hierarchy_request("a document can't be inserted"),
+        NodeKind::Text(_) if is_document => {
+            return hierarchy_request("a document can't have text children")
+        }
+        NodeKind::DocumentType(_) if !is_document => {
+            return hierarchy_request("only a document can have a doctype child")
+        }
+        _ => {}
+    }
+    if !is_document {
+        return Ok(());
+    }
+
+    // A document has at most one doctype and one element, in that order.
+    let others: Vec<Rc<RefCell<Node>>> = parent
+        .borrow()
+        .children()
+        .into_iter()
+        .filter(|c| !(replacing && child.is_some_and(|child| Rc::ptr_eq(c, child))))
+        .collect();
+    let is_element = |n: &Rc<RefCell<Node>>| matches!(n.borrow().kind, NodeKind::Element(_));
+    let is_doctype = |n: &Rc<RefCell<Node>>| matches!(n.borrow().kind, NodeKind::DocumentType(_));
+    // The index of `child` among the children, or the number of children.
+    let children = parent.borrow().children();
+    let position = child
+        .and_then(|child| children.iter().position(|c| Rc::ptr_eq(c, child)))
+        .unwrap_or(children.len());
+    let following = &children[(position + 1).min(children.len())..];
+    let preceding = &children[..position];
+
+    let elements = match node.borrow().kind {
+        NodeKind::DocumentFragment => {
+            let fragment_children = node.borrow().children();
+            if fragment_children
+                .iter()
+                .any(|c| matches!(c.borrow().kind, NodeKind::Text(_)))
+            {
+                return hierarchy_request("a document can't have text children");
+            }
+            fragment_children.iter().filter(|c| is_element(c)).count()
+        }
+        NodeKind::Element(_) => 1,
+        NodeKind::DocumentType(_) => {
+            let has_element = match child {
+                Some(_) => preceding.iter().any(is_element),
+                None => others.iter().any(is_element),
+            };
+            if others.iter().any(is_doctype) || has_element {
+                return hierarchy_request(
+                    "a document can have only one doctype, before the element",
+                );
+            }
+            return Ok(());
+        }
+        _ => return Ok(()),
+    };
+    // When replacing, `child` itself goes away and can't be the doctype
+    // that the element is before.
+    let is_before_doctype =
+        (!replacing && child.is_some_and(is_doctype)) || following.iter().any(is_doctype);
+    match elements {
+        0 => Ok(()),
+        1 if !others.iter().any(is_element) && !is_before_doctype => Ok(()),
+        _ => hierarchy_request("a document can have only one element, after the doctype"),
+    }
+}
+
+/// Links `child`, which must have no parent, into `parent` before
+/// `reference`, and returns its new previous sibling.
+fn insert(
+    parent: &Rc<RefCell<Node>>,
+    child: Rc<RefCell<Node>>,
+    reference: Option<&Rc<RefCell<Node>>>,
+) -> Option<Rc<RefCell<Node>>> {
+    let previous = match reference {
+        Some(r) => r.borrow().previous_sibling().upgrade(),
+        None => parent.borrow().last_child().upgrade(),
+    };
+
+    {
+        let mut c = child.borrow_mut();
+        c.set_parent(Rc::downgrade(parent));
+        c.set_next_sibling(reference.cloned());
+        c.set_previous_sibling(match previous {
+            Some(ref p) => Rc::downgrade(p),
+            None => Weak::new(),
+        });
+    }
+
+    match previous {
+        Some(ref p) => p.borrow_mut().set_next_sibling(Some(child.clone())),
+        None => parent.borrow_mut().set_first_child(Some(child.clone())),
+    }
+
+    match reference {
+        Some(r) => r.borrow_mut().set_previous_sibling(Rc::downgrade(&child)),
+        None => parent.borrow_mut().set_last_child(Rc::downgrade(&child)),
+    }
+
+    previous
+}
+
+/// Unlinks `node`, which must have a parent, from its parent and siblings.
+fn remove(node: &Rc<RefCell<Node>>) {
+    let parent = match node.borrow().parent().upgrade() {
+        Some(p) => p,
+        None => return,
+    };
+
+    let previous = node.borrow().previous_sibling().upgrade();
+    let next = node.borrow().next_sibling();
+
+    match previous {
+        Some(ref p) => p.borrow_mut().set_next_sibling(next.clone()),
+        None => parent.borrow_mut().set_first_child(next.clone()),
+    }
+
+    match next {
+        Some(ref n) => n.borrow_mut().set_previous_sibling(match previous {
+            Some(ref p) => Rc::downgrade(p),
+            None => Weak::new(),
+        }),
+        None => parent.borrow_mut().set_last_child(match previous {
+            Some(ref p) => Rc::downgrade(p),
+            None => Weak::new(),
+        }),
+    }
+
+    let mut n = node.borrow_mut();
+    n.set_parent(Weak::new());
+    n.set_previous_sibling(Weak::new());
+    n.set_next_sibling(None);
+}
+
+/// Sets the attribute `name` of the element `node` to `value`. `name` is
+/// lowercased, as `node` is an HTML element.
+/// https://dom.spec.whatwg.org/#dom-element-setattribute
+pub fn set_attribute(node: &Rc<RefCell<Node>>, name: &str, value: &str) {
+    let name = name.to_ascii_lowercase();
+    let old_value = match node.borrow_mut().kind {
+        NodeKind::Element(ref mut e) => e.set_attribute(&name, value),
+        _ => return,
+    };
+    queue_record(MutationRecord::attributes(node.clone(), &name, old_value));
+}
+
+/// Removes the attribute `name` of the element `node`, if it has one.
+/// https://dom.spec.whatwg.org/#dom-element-removeattribute
+pub fn remove_attribute(node: &Rc<RefCell<Node>>, name: &str) {
+    let name = name.to_ascii_lowercase();
+    let old_value = match node.borrow_mut().kind {
+        NodeKind::Element(ref mut e) => e.remove_attribute(&name),
+        _ => return,
+    };
+    if old_value.is_some() {
+        queue_record(MutationRecord::attributes(node.clone(), &name, old_value));
+    }
+}
+
+/// Replaces the data of the text or comment `node` with `data`.
+/// https://dom.spec.whatwg.org/#concept-cd-replace
+pub fn set_data(node: &Rc<RefCell<Node>>, data: &str) {
+    let old_value = match node.borrow_mut().kind {
+        NodeKind::Text(ref mut s) | NodeKind::Comment(ref mut s) => {
+            core::mem::replace(s, String::from(data))
+        }
+        _ => return,
+    };
+    queue_record(MutationRecord::character_data(node.clone(), old_value));
+}
+
+/// Returns the markup of the children of `node`.
+/// https://html.spec.whatwg.org/multipage/dynamic-markup-insertion.html#dom-element-innerhtml
+pub fn inner_html(node: &Rc<RefCell<Node>>) -> String {
+    serializer::serialize_children(node)
+}
+
+/// Replaces the children of `node` with the nodes parsed from `html` in the
+/// context of `node`.
+/// https://html.spec.whatwg.org/multipage/dynamic-markup-insertion.html#dom-element-innerhtml
+pub fn set_inner_html(node: &Rc<RefCell<Node>>, html: &str) {
+    let t = HtmlTokenizer::new(html.to_string());
+    let children = HtmlParser::new_fragment(t, node.clone()).construct_fragment();
+
+    // A template's markup goes into its contents.
+    let target = node.borrow().template_contents();
+    let target = target.as_ref().unwrap_or(node);
+
+    replace_all(target, children);
+}
+
+/// Returns the markup of `node` and its descendants.
+/// https://html.spec.whatwg.org/multipage/dynamic-markup-insertion.html#dom-element-outerhtml
+pub fn outer_html(node: &Rc<RefCell<Node>>) -> String {
+    serializer::serialize(node)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum NodeKind {
+    /// https://dom.spec.whatwg.org/#interface-document
+    Document,
+    /// https://dom.spec.whatwg.org/#interface-documentfragment
+    DocumentFragment,
+    /// https://dom.spec.whatwg.org/#interface-documenttype
+    DocumentType(DocumentType),
+    /// https://dom.spec.whatwg.org/#interface-element
+    Element(Element),
+    /// https://dom.spec.whatwg.org/#interface-text
+    Text(String),
+    /// https://dom.spec.whatwg.org/#interface-comment
+    Comment(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DocumentType {
+    name: String,
+    public_id: String,
+    system_id: String,
+}
+
+impl DocumentType {
+    pub fn new(name: String, public_id: String, system_id: String) -> Self {
+        Self {
+            name,
+            public_id,
+            system_id,
+        }
+    }
+
+    pub fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    pub fn public_id(&self) -> String {
+        self.public_id.clone()
+    }
+
+    pub fn system_id(&self) -> String {
+        self.system_id.clone()
+    }
+}
+
+/// https://infra.spec.whatwg.org/#namespaces
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Namespace {
+    Html,
+    MathMl,
+    Svg,
+}
+
+impl Namespace {
+    pub fn url(&self) -> &'static str {
+        match self {
+            Namespace::Html => "http://www.w3.org/1999/xhtml",
+            Namespace::MathMl => "http://www.w3.org/1998/Math/MathML",
+            Namespace::Svg => "http://www.w3.org/2000/svg",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Element {
+    kind: ElementKind,
+    namespace: Namespace,
+    tag_name: String,
+    attributes: Vec<Attribute>,
+}
+
+impl Element {
+    /// Creates an element in the HTML namespace.
+    pub fn new(tag_name: &str, attributes: Vec<Attribute>) -> Self {
+        Self::new_in_namespace(tag_name, attributes, Namespace::Html)
+    }
+
+    /// Creates an element in `namespace`. Elements that aren't in the HTML
+    /// namespace are always `ElementKind::Unknown`, so that `<svg><title>`
+    /// isn't mistaken for an HTML `<title>`.
+    pub fn new_in_namespace(
+        tag_name: &str,
+        attributes: Vec<Attribute>,
+        namespace: Namespace,
+    ) -> Self {
+        let kind = match namespace {
+            Namespace::Html => ElementKind::from(tag_name),
+            _ => ElementKind::Unknown,
+        };
+        Self {
+            kind,
+            namespace,
+            tag_name: String::from(tag_name),
+            attributes,
+        }
+    }
+
+    pub fn kind(&self) -> ElementKind {
+        self.kind
+    }
+
+    pub fn namespace(&self) -> Namespace {
+        self.namespace
+    }
+
+    pub fn is_html(&self) -> bool {
+        self.namespace == Namespace::Html
+    }
+
+    pub fn tag_name(&self) -> String {
+        self.tag_name.clone()
+    }
+
+    pub fn attributes(&self) -> Vec<Attribute> {
+        self.attributes.clone()
+    }
+
+    pub fn get_attribute(&self, name: &str) -> Option<String> {
+        self.attributes
+            .iter()
+            .find(|a| a.name() == name)
+            .map(|a| a.value())
+    }
+
+    /// Sets the attribute `name` to `value`, and returns its old value.
+    pub fn set_attribute(&mut self, name: &str, value: &str) -> Option<String> {
+        match self.attributes.iter_mut().find(|a| a.name() == name) {
+            Some(a) => {
+                let old_value = a.value();
+                a.set_value(value);
+                Some(old_value)
+            }
+            None => {
+                self.attributes.push(Attribute::with_value(name, value));
+                None
+            }
+        }
+    }
+
+    /// Removes the attribute `name`, and returns its value.
+    pub fn remove_attribute(&mut self, name: &str) -> Option<String> {
+        let index = self.attributes.iter().position(|a| a.name() == name)?;
+        Some(self.attributes.remove(index).value())
+    }
+
+    pub fn has_attribute(&self, name: &str) -> bool {
+        self.attributes.iter().any(|a| a.name() == name)
+    }
+
+    /// The `id` attribute, or an empty string without one.
+    /// https://dom.spec.whatwg.org/#dom-element-id
+    pub fn id(&self) -> String {
+        self.get_attribute("id").unwrap_or_default()
+    }
+
+    /// The tokens in the `class` attribute.
+    /// https://dom.spec.whatwg.org/#dom-element-classlist
+    pub fn class_list(&self) -> DomTokenList {
+        DomTokenList::new(&self.get_attribute("class").unwrap_or_default())
+    }
+
+    /// The declarations in the `style` attribute.
+    /// https://drafts.csswg.org/cssom/#dom-elementcssinlinestyle-style
+    pub fn style(&self) -> Vec<Declaration> {
+        match self.get_attribute("style") {
+            Some(style) => parse_declaration_list(&parse_component_values(&style)),
+            None => Vec::new(),
+        }
+    }
+
+    /// The `href` attribute resolved against `base_url`, which is usually
+    /// `Window::base_url()`. Returns `None` if there is no `href` or it can't
+    /// be resolved.
+    /// https://html.spec.whatwg.org/multipage/links.html#dom-hyperlink-href
+    pub fn href(&self, base_url: &Url) -> Option<Url> {
+        base_url.join(&self.get_attribute("href")?).ok()
+    }
+
+    /// The `src` attribute resolved against `base_url`.
+    /// https://html.spec.whatwg.org/multipage/embedded-content.html#dom-img-src
+    pub fn src(&self, base_url: &Url) -> Option<Url> {
+        base_url.join(&self.get_attribute("src")?).ok()
+    }
+
+    /// https://html.spec.whatwg.org/multipage/form-control-infrastructure.html#attr-fe-disabled
+    pub fn disabled(&self) -> bool {
+        self.has_attribute("disabled")
+    }
+
+    /// https://html.spec.whatwg.org/multipage/input.html#attr-input-checked
+    pub fn checked(&self) -> bool {
+        self.has_attribute("checked")
+    }
+
+    /// https://html.spec.whatwg.org/multipage/interaction.html#the-hidden-attribute
+    pub fn hidden(&self) -> bool {
+        self.has_attribute("hidden")
+    }
+
+    /// The `width` attribute, or `None` if it's missing or not a valid
+    /// non-negative integer.
+    /// https://html.spec.whatwg.org/multipage/embedded-content-other.html#attr-dim-width
+    pub fn width(&self) -> Option<u32> {
+        parse_non_negative_integer(&self.get_attribute("width")?)
+    }
+
+    /// https://html.spec.whatwg.org/multipage/embedded-content-other.html#attr-dim-height
+    pub fn height(&self) -> Option<u32> {
+        parse_non_negative_integer(&self.get_attribute("height")?)
+    }
+
+    /// Adds `attribute` unless an attribute with the same name already exists.
+    pub fn add_attribute_if_missing(&mut self, attribute: Attribute) {
+        if self.get_attribute(&attribute.name()).is_none() {
+            self.attributes.push(attribute);
+        }
+    }
+}
+
+/// HTML elements known to the parser. Tags that aren't listed here are parsed
+/// as `ElementKind::Unknown` and keep their name in `Element::tag_name`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ElementKind {
+    A,
+    Abbr,
+    Address,
+    Applet,
+    Area,
+    Article,
+    Aside,
+    Audio,
+    B,
+    Base,
+    Basefont,
+    Bdi,
+    Bdo,
+    Bgsound,
+    Big,
+    Blockquote,
+    Body,
+    Br,
+    Button,
+    Canvas,
+    Caption,
+    Center,
+    Cite,
+    Code,
+    Col,
+    Colgroup,
+    Data,
+    Datalist,
+    Dd,
+    Del,
+    Details,
+    Dfn,
+    Dialog,
+    Dir,
+    Div,
+    Dl,
+    Dt,
+    Em,
+    Embed,
+    Fieldset,
+    Figcaption,
+    Figure,
+    Font,
+    Footer,
+    Form,
+    Frame,
+    Frameset,
+    H1,
+    H2,
+    H3,
+    H4,
+    H5,
+    H6,
+    Head,
+    Header,
+    Hgroup,
+    Hr,
+    Html,
+    I,
+    Iframe,
+    Image,
+    Img,
+    Input,
+    Ins,
+    Kbd,
+    Keygen,
+    Label,
+    Legend,
+    Li,
+    Link,
+    Listing,
+    Main,
+    Map,
+    Mark,
+    Marquee,
+    Menu,
+    Meta,
+    Meter,
+    Nav,
+    Nobr,
+    Noembed,
+    Noframes,
+    Noscript,
+    Object,
+    Ol,
+    Optgroup,
+    Option,
+    Output,
+    P,
+    Param,
+    Picture,
+    Plaintext,
+    Pre,
+    Progress,
+    Q,
+    Rb,
+    Rp,
+    Rt,
+    Rtc,
+    Ruby,
+    S,
+    Samp,
+    Script,
+    Search,
+    Section,
+    Select,
+    Slot,
+    Small,
+    Source,
+    Span,
+    Strike,
+    Strong,
+    Style,
+    Sub,
+    Summary,
+    Sup,
+    Table,
+    Tbody,
+    Td,
+    Template,
+    Textarea,
+    Tfoot,
+    Th,
+    Thead,
+    Time,
+    Title,
+    Tr,
+    Track,
+    Tt,
+    U,
+    Ul,
+    Var,
+    Video,
+    Wbr,
+    Xmp,
+    Unknown,
+}
+
+impl From<&str> for ElementKind {
+    fn from(tag_name: &str) -> Self {
+        match tag_name {
+            "a" => ElementKind::A,
+            "abbr" => ElementKind::Abbr,
+            "address" => ElementKind::Address,
+            "applet" => ElementKind::Applet,
+            "area" => ElementKind::Area,
+            "article" => ElementKind::Article,
+            "aside" => ElementKind::Aside,
+            "audio" => ElementKind::Audio,
+            "b" => ElementKind::B,
+            "base" => ElementKind::Base,
+            "basefont" => ElementKind::Basefont,
+            "bdi" => ElementKind::Bdi,
+            "bdo" => ElementKind::Bdo,
+            "bgsound" => ElementKind::Bgsound,
+            "big" => ElementKind::Big,
+            "blockquote" => ElementKind::Blockquote,
+            "body" => ElementKind::Body,
+            "br" => ElementKind::Br,
+            "button" => ElementKind::Button,
+            "canvas" => ElementKind::Canvas,
+            "caption" => ElementKind::Caption,
+            "center" => ElementKind::Center,
+            "cite" => ElementKind::Cite,
+            "code" => ElementKind::Code,
+            "col" => ElementKind::Col,
+            "colgroup" => ElementKind::Colgroup,
+            "data" => ElementKind::Data,
+            "datalist" => ElementKind::Datalist,
+            "dd" => ElementKind::Dd,
+            "del" => ElementKind::Del,
+            "details" => ElementKind::Details,
+            "dfn" => ElementKind::Dfn,
+            "dialog" => ElementKind::Dialog,
+            "dir" => ElementKind::Dir,
+            "div" => ElementKind::Div,
+            "dl" => ElementKind::Dl,
+            "dt" => ElementKind::Dt,
+            "em" => ElementKind::Em,
+            "embed" => ElementKind::Embed,
+            "fieldset" => ElementKind::Fieldset,
+            "figcaption" => ElementKind::Figcaption,
+            "figure" => ElementKind::Figure,
+            "font" => ElementKind::Font,
+            "footer" => ElementKind::Footer,
+            "form" => ElementKind::Form,
+            "frame" => ElementKind::Frame,
+            "frameset" => ElementKind::Frameset,
+            "h1" => ElementKind::H1,
+            "h2" => ElementKind::H2,
+            "h3" => ElementKind::H3,
+            "h4" => ElementKind::H4,
+            "h5" => ElementKind::H5,
+            "h6" => ElementKind::H6,
+            "head" => ElementKind::Head,
+            "header" => ElementKind::Header,
+            "hgroup" => ElementKind::Hgroup,
+            "hr" => ElementKind::Hr,
+            "html" => ElementKind::Html,
+            "i" => ElementKind::I,
+            "iframe" => ElementKind::Iframe,
+            "image" => ElementKind::Image,
+            "img" => ElementKind::Img,
+            "input" => ElementKind::Input,
+            "ins" => ElementKind::Ins,
+            "kbd" => ElementKind::Kbd,
+            "keygen" => ElementKind::Keygen,
+            "label" => ElementKind::Label,
+            "legend" => ElementKind::Legend,
+            "li" => ElementKind::Li,
+            "link" => ElementKind::Link,
+            "listing" => ElementKind::Listing,
+            "main" => ElementKind::Main,
+            "map" => ElementKind::Map,
+            "mark" => ElementKind::Mark,
+            "marquee" => ElementKind::Marquee,
+            "menu" => ElementKind::Menu,
+            "meta" => ElementKind::Meta,
+            "meter" => ElementKind::Meter,
+            "nav" => ElementKind::Nav,
+            "nobr" => ElementKind::Nobr,
+            "noembed" => ElementKind::Noembed,
+            "noframes" => ElementKind::Noframes,
+            "noscript" => ElementKind::Noscript,
+            "object" => ElementKind::Object,
+            "ol" => ElementKind::Ol,
+            "optgroup" => ElementKind::Optgroup,
+            "option" => ElementKind::Option,
+            "output" => ElementKind::Output,
+            "p" => ElementKind::P,
+            "param" => ElementKind::Param,
+            "picture" => ElementKind::Picture,
+            "plaintext" => ElementKind::Plaintext,
+            "pre" => ElementKind::Pre,
+            "progress" => ElementKind::Progress,
+            "q" => ElementKind::Q,
+            "rb" => ElementKind::Rb,
+            "rp" => ElementKind::Rp,
+            "rt" => ElementKind::Rt,
+            "rtc" => ElementKind::Rtc,
+            "ruby" => ElementKind::Ruby,
+            "s" => ElementKind::S,
+            "samp" => ElementKind::Samp,
+            "script" => ElementKind::Script,
+            "search" => ElementKind::Search,
+            "section" => ElementKind::Section,
+            "select" => ElementKind::Select,
+            "slot" => ElementKind::Slot,
+            "small" => ElementKind::Small,
+            "source" => ElementKind::Source,
+            "span" => ElementKind::Span,
+            "strike" => ElementKind::Strike,
+            "strong" => ElementKind::Strong,
+            "style" => ElementKind::Style,
+            "sub" => ElementKind::Sub,
+            "summary" => ElementKind::Summary,
+            "sup" => ElementKind::Sup,
+            "table" => ElementKind::Table,
+            "tbody" => ElementKind::Tbody,
+            "td" => ElementKind::Td,
+            "template" => ElementKind::Template,
+            "textarea" => ElementKind::Textarea,
+            "tfoot" => ElementKind::Tfoot,
+            "th" => ElementKind::Th,
+            "thead" => ElementKind::Thead,
+            "time" => ElementKind::Time,
+            "title" => ElementKind::Title,
+            "tr" => ElementKind::Tr,
+            "track" => ElementKind::Track,
+            "tt" => ElementKind::Tt,
+            "u" => ElementKind::U,
+            "ul" => ElementKind::Ul,
+            "var" => ElementKind::Var,
+            "video" => ElementKind::Video,
+            "wbr" => ElementKind::Wbr,
+            "xmp" => ElementKind::Xmp,
+            _ => ElementKind::Unknown,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn element(tag_name: &str) -> Rc<RefCell<Node>> {
+        Rc::new(RefCell::new(Node::new(NodeKind::Element(Element::new(
+            tag_name,
+            Vec::new(),
+        )))))
+    }
+
+    fn tag_names(children: Vec<Rc<RefCell<Node>>>) -> Vec<String> {
+        children
+            .iter()
+            .map(|c| c.borrow().get_element().expect("not an element").tag_name())
+            .collect()
+    }
+
+    #[test]
+    fn test_element_kind() {
+        assert_eq!(ElementKind::from("body"), ElementKind::Body);
+        assert_eq!(ElementKind::from("h1"), ElementKind::H1);
+        assert_eq!(ElementKind::from("foo"), ElementKind::Unknown);
+        assert_eq!(
+            Element::new("foo", Vec::new()).tag_name(),
+            String::from("foo")
+        );
+    }
+
+    #[test]
+    fn test_append_child() {
+        let parent = element("div");
+        let a = element("a");
+        let b = element("b");
+        append_child(&parent, a.clone()).expect("failed to append");
+        append_child(&parent, b.clone()).expect("failed to append");
+
+        assert_eq!(tag_names(parent.borrow().children()), ["a", "b"]);
+        assert!(Rc::ptr_eq(
+            &a.borrow().parent().upgrade().expect("no parent"),
+            &parent
+        ));
+        assert!(Rc::ptr_eq(
+            &b.borrow().previous_sibling().upgrade().expect("no sibling"),
+            &a
+        ));
+        assert!(Rc::ptr_eq(
+            &parent.borrow().last_child().upgrade().expect("no child"),
+            &b
+        ));
+    }
+
+    #[test]
+    fn test_insert_before() {
+        let parent = element("div");
+        let a = element("a");
+        let b = element("b");
+        let i = element("i");
+        append_child(&parent, a.clone()).expect("failed to append");
+        append_child(&parent, b.clone()).expect("failed to append");
+        insert_before(&parent, i.clone(), Some(&b)).expect("failed to insert");
+
+        assert_eq!(tag_names(parent.borrow().children()), ["a", "i", "b"]);
+        assert!(Rc::ptr_eq(
+            &b.borrow().previous_sibling().upgrade().expect("no sibling"),
+            &i
+        ));
+
+        insert_before(&parent, b.clone(), Some(&a)).expect("failed to insert");
+        assert_eq!(tag_names(parent.borrow().children()), ["b", "a", "i"]);
+        assert!(Rc::ptr_eq(
+            &parent.borrow().last_child().upgrade().expect("no child"),
+            &i
+        ));
+    }
+
+    #[test]
+    fn test_detach() {
+        let parent = element("div");
+        let a = element("a");
+        let b = element("b");
+        let i = element("i");
+        append_child(&parent, a.clone()).expect("failed to append");
+        append_child(&parent, b.clone()).expect("failed to append");
+        append_child(&parent, i.clone()).expect("failed to append");
+
+        detach(&b);
+        assert_eq!(tag_names(parent.borrow().children()), ["a", "i"]);
+        assert!(b.borrow().parent().upgrade().is_none());
+
+        detach(&i);
+        assert_eq!(tag_names(parent.borrow().children()), ["a"]);
+        assert!(Rc::ptr_eq(
+            &parent.borrow().last_child().upgrade().expect("no child"),
+            &a
+        ));
+
+        detach(&a);
+        assert!(parent.borrow().first_child().is_none());
+        assert!(parent.borrow().last_child().upgrade().is_none());
+    }
+
+    #[test]
+    fn test_insertion_validity() {
+        let parent = element("div");
+        let a = element("a");
+        let b = element("b");
+        append_child(&parent, a.clone()).expect("failed to append");
+        append_child(&parent, b.clone()).expect("failed to append");
+
+        // Inserting a node before itself leaves it where it is.
+        insert_before(&parent, a.clone(), Some(&a)).expect("failed to insert");
+        assert_eq!(tag_names(parent.borrow().children()), ["a", "b"]);
+        insert_before(&parent, b.clone(), Some(&b)).expect("failed to insert");
+        assert_eq!(tag_names(parent.borrow().children()), ["a", "b"]);
+
+        // A node can't be inserted into itself or its descendants.
+        let child = element("i");
+        append_child(&a, child.clone()).expect("failed to append");
+        assert!(matches!(
+            append_child(&child, parent.clone()),
+            Err(Error::HierarchyRequest(_))
+        ));
+        assert!(matches!(
+            replace_child(&a, a.clone(), &child),
+            Err(Error::HierarchyRequest(_))
+        ));
+        assert!(child.borrow().first_child().is_none());
+
+        // The reference must be a child of the parent.
+        let other = element("p");
+        assert!(matches!(
+            insert_before(&parent, other.clone(), Some(&child)),
+            Err(Error::NotFound(_))
+        ));
+        assert!(matches!(
+            replace_child(&parent, other.clone(), &child),
+            Err(Error::NotFound(_))
+        ));
+        assert_eq!(tag_names(parent.borrow().children()), ["a", "b"]);
+        assert!(other.borrow().parent().upgrade().is_none());
+
+        // A document has at most one doctype and one element, and no text.
+        let document = Rc::new(RefCell::new(Node::new(NodeKind::Document)));
+        let doctype = || {
+            Rc::new(RefCell::new(Node::new(NodeKind::DocumentType(
+                DocumentType::new(String::from("html"), String::new(), String::new()),
+            ))))
+        };
+        let html = element("html");
+        append_child(&document, html.clone()).expect("failed to append");
+        assert!(append_child(&document, element("body")).is_err());
+        assert!(append_child(&document, doctype()).is_err());
+        insert_before(&document, doctype(), Some(&html)).expect("failed to insert");
+        assert!(insert_before(&document, doctype(), Some(&html)).is_err());
+        assert!(append_child(
+            &document,
+            Rc::new(RefCell::new(Node::new(NodeKind::Text(String::from("a")))))
+        )
+        .is_err());
+        replace_child(&document, element("body"), &html).expect("failed to replace");
+        assert!(append_child(&element("div"), doctype()).is_err());
+    }
+
+    #[test]
+    fn test_inner_html() {
+        let div = element("div");
+        set_inner_html(&div, "<p>a<b>b</b></p>c<!--d-->");
+        assert_eq!(div.borrow().children().len(), 3);
+        assert_eq!(inner_html(&div), "<p>a<b>b</b></p>c<!--d-->");
+        assert_eq!(outer_html(&div), "<div><p>a<b>b</b></p>c<!--d--></div>");
+
+        set_inner_html(&div, "x &amp; y");
+        assert_eq!(inner_html(&div), "x &amp; y");
+        assert_eq!(div.borrow().children().len(), 1);
+    }
+
+    #[test]
+    fn test_typed_attributes() {
+        let mut attributes = Vec::new();
+        for (name, value) in [
+            ("id", "main"),
+            ("class", " a b\ta "),
+            ("href", "../c.html"),
+            ("width", " 120px"),
+            ("height", "-1"),
+            ("disabled", ""),
+        ] {
+            attributes.push(Attribute::with_value(name, value));
+        }
+        let e = Element::new("a", attributes);
+
+        assert_eq!(e.id(), "main");
+        assert_eq!(e.class_list().value(), "a b");
+        assert!(e.disabled());
+        assert!(!e.checked());
+        assert_eq!(e.width(), Some(120));
+        assert_eq!(e.height(), None);
+
+        let base = Url::new("http://example.com/x/y/z.html".to_string())
+            .parse()
+            .expect("failed to parse");
+        assert_eq!(
+            e.href(&base).map(|u| u.url()),
+            Some("http://example.com/x/c.html".to_string())
+        );
+        assert!(e.src(&base).is_none());
+        assert_eq!(Element::new("p", Vec::new()).id(), "");
+    }
+
+    #[test]
+    fn test_style_attribute() {
+        let e = Element::new(
+            "p",
+            [Attribute::with_value(
+                "style",
+                "color: red; ; margin:0!important",
+            )]
+            .to_vec(),
+        );
+        let style: Vec<(String, bool)> = e
+            .style()
+            .into_iter()
+            .map(|d| (d.name, d.important))
+            .collect();
+        assert_eq!(
+            style,
+            [("color".to_string(), false), ("margin".to_string(), true)]
+        );
+        assert!(Element::new("p", Vec::new()).style().is_empty());
+    }
+
+    #[test]
+    fn test_base_url() {
+        let mut window = Window::new();
+        assert!(window.base_url().is_none());
+
+        let url = Url::new("http://example.com/a/b.html".to_string())
+            .parse()
+            .expect("failed to parse");
+        window.set_url(url);
+        assert_eq!(
+            window.base_url().map(|u| u.url()),
+            Some("http://example.com/a/b.html".to_string())
+        );
+
+        let html = element("html");
+        append_child(&window.document(), html.clone()).expect("failed to append");
+        set_inner_html(&html, "<head><base target=_blank><base href=/c/></head>");
+        assert_eq!(
+            window.base_url().map(|u| u.url()),
+            Some("http://example.com/c/".to_string())
+        );
+    }
+}
@@ -0,0 +1,256 @@
+//! Traversal and query API over a DOM tree.
+//! https://dom.spec.whatwg.org/#traversal
+
+use crate::error::Error;
+use crate::renderer::dom::node::Node;
+use crate::renderer::dom::selector::{parse_selector_list, Selector};
+use crate::renderer::dom::token_list::DomTokenList;
+use alloc::rc::Rc;
+use alloc::vec::Vec;
+use core::cell::RefCell;
+
+/// Iterates over the descendants of `root` in tree order, that is, a
+/// depth-first pre-order traversal. `root` itself isn't included.
+/// https://dom.spec.whatwg.org/#concept-tree-order
+#[derive(Debug, Clone)]
+pub struct TreeWalker {
+    root: Rc<RefCell<Node>>,
+    next: Option<Rc<RefCell<Node>>>,
+}
+
+impl TreeWalker {
+    pub fn new(root: Rc<RefCell<Node>>) -> Self {
+        let next = root.borrow().first_child();
+        Self { root, next }
+    }
+
+    /// Returns the node after the descendants of `node`, staying inside
+    /// `root`.
+    fn following(&self, node: &Rc<RefCell<Node>>) -> Option<Rc<RefCell<Node>>> {
+        let mut node = node.clone();
+        loop {
+            if Rc::ptr_eq(&node, &self.root) {
+                return None;
+            }
+            if let Some(sibling) = node.borrow().next_sibling() {
+                return Some(sibling);
+            }
+            let parent = node.borrow().parent().upgrade()?;
+            node = parent;
+        }
+    }
+}
+
+impl Iterator for TreeWalker {
+    type Item = Rc<RefCell<Node>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let node = self.next.take()?;
+        let first_child = node.borrow().first_child();
+        self.next = match first_child {
+            Some(child) => Some(child),
+            None => self.following(&node),
+        };
+        Some(node)
+    }
+}
+
+/// Returns the element descendants of `root` in tree order.
+pub fn descendant_elements(root: &Rc<RefCell<Node>>) -> impl Iterator<Item = Rc<RefCell<Node>>> {
+    TreeWalker::new(root.clone()).filter(|n| n.borrow().get_element().is_some())
+}
+
+/// Returns the first element under `root` whose ID is `id`.
+/// https://dom.spec.whatwg.org/#dom-nonelementparentnode-getelementbyid
+pub fn get_element_by_id(root: &Rc<RefCell<Node>>, id: &str) -> Option<Rc<RefCell<Node>>> {
+    if id.is_empty() {
+        return None;
+    }
+    descendant_elements(root).find(|n| {
+        n.borrow()
+            .get_element()
+            .and_then(|e| e.get_attribute("id"))
+            .is_some_and(|i| i == id)
+    })
+}
+
+/// Returns the elements under `root` whose tag name is `tag_name`, or all
+/// elements for `"*"`.
+/// https://dom.spec.whatwg.org/#concept-getelementsbytagname
+pub fn get_elements_by_tag_name(
+    root: &Rc<RefCell<Node>>,
+    tag_name: &str,
+) -> Vec<Rc<RefCell<Node>>> {
+    descendant_elements(root)
+        .filter(|n| {
+            tag_name == "*"
+                || n.borrow()
+                    .get_element()
+                    .is_some_and(|e| e.tag_name().eq_ignore_ascii_case(tag_name))
+        })
+        .collect()
+}
+
+/// Returns the elements under `root` that have all the classes in the
+/// whitespace-separated `class_names`.
+/// https://dom.spec.whatwg.org/#concept-getelementsbyclassname
+pub fn get_elements_by_class_name(
+    root: &Rc<RefCell<Node>>,
+    class_names: &str,
+) -> Vec<Rc<RefCell<Node>>> {
+    let wanted = DomTokenList::new(class_names);
+    if wanted.is_empty() {
+        return Vec::new();
+    }
+
+    descendant_elements(root)
+        .filter(|n| {
+            let classes = match n.borrow().get_element() {
+                Some(e) => e.class_list(),
+                None => return false,
+            };
+            wanted.iter().all(|w| classes.contains(w))
+        })
+        .collect()
+}
+
+/// Returns the first element under `root` that matches any of `selectors`,
+/// or an error if `selectors` can't be parsed.
+/// https://dom.spec.whatwg.org/#dom-parentnode-queryselector
+pub fn query_selector(
+    root: &Rc<RefCell<Node>>,
+    selectors: &str,
+) -> Result<Option<Rc<RefCell<Node>>>, Error> {
+    let selectors = parse_selector_list(selectors)?;
+    Ok(descendant_elements(root).find(|n| matches_any(&selectors, n)))
+}
+
+/// Returns all elements under `root` that match any of `selectors`, in tree
+/// order, or an error if `selectors` can't be parsed.
+/// https://dom.spec.whatwg.org/#dom-parentnode-queryselectorall
+pub fn query_selector_all(
+    root: &Rc<RefCell<Node>>,
+    selectors: &str,
+) -> Result<Vec<Rc<RefCell<Node>>>, Error> {
+    let selectors = parse_selector_list(selectors)?;
+    Ok(descendant_elements(root)
+        .filter(|n| matches_any(&selectors, n))
+        .collect())
+}
+
+fn matches_any(selectors: &[Selector], node: &Rc<RefCell<Node>>) -> bool {
+    selectors.iter().any(|s| s.matches(node))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::renderer::html::parser::HtmlParser;
+    use crate::renderer::html::token::HtmlTokenizer;
+    use alloc::string::{String, ToString};
+
+    fn parse(html: &str) -> Rc<RefCell<Node>> {
+        let t = HtmlTokenizer::new(html.to_string());
+        let window = HtmlParser::new(t).construct_tree();
+        let document = window.borrow().document();
+        document
+    }
+
+    fn ids(nodes: &[Rc<RefCell<Node>>]) -> Vec<String> {
+        nodes
+            .iter()
+            .map(|n| {
+                n.borrow()
+                    .get_element()
+                    .and_then(|e| e.get_attribute("id"))
+                    .unwrap_or_default()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_tree_walker() {
+        let document = parse("<p>a<b>b</b></p><i></i>");
+        let body = get_elements_by_tag_name(&document, "body")[0].clone();
+        let tag_names: Vec<String> = TreeWalker::new(body)
+            .map(|n| match n.borrow().get_element() {
+                Some(e) => e.tag_name(),
+                None => "#text".to_string(),
+            })
+            .collect();
+        assert_eq!(tag_names, ["p", "#text", "b", "#text", "i"]);
+    }
+
+    #[test]
+    fn test_get_element_by_id() {
+        let document = parse("<div id=a><p id=b></p><p id=b class=second></p></div>");
+        let b = get_element_by_id(&document, "b").expect("no element");
+        assert!(b
+            .borrow()
+            .get_element()
+            .expect("not an element")
+            .get_attribute("class")
+            .is_none());
+        assert!(get_element_by_id(&document, "c").is_none());
+        assert!(get_element_by_id(&document, "").is_none());
+
+        let a = get_element_by_id(&document, "a").expect("no element");
+        assert!(get_element_by_id(&a, "a").is_none());
+    }
+
+    #[test]
+    fn test_get_elements_by_tag_name() {
+        let document = parse("<p id=a></p><div><P id=b></P></div>");
+        assert_eq!(ids(&get_elements_by_tag_name(&document, "p")), ["a", "b"]);
+        assert_eq!(ids(&get_elements_by_tag_name(&document, "P")), ["a", "b"]);
+        assert_eq!(get_elements_by_tag_name(&document, "*").len(), 6);
+    }
+
+    #[test]
+    fn test_get_elements_by_class_name() {
+        let document =
+            parse("<p id=a class='x y'></p><p id=b class=x></p><p id=c class='y\tx z'></p>");
+        assert_eq!(
+            ids(&get_elements_by_class_name(&document, "x")),
+            ["a", "b", "c"]
+        );
+        assert_eq!(
+            ids(&get_elements_by_class_name(&document, " y x ")),
+            ["a", "c"]
+        );
+        assert!(get_elements_by_class_name(&document, " ").is_empty());
+    }
+
+    #[test]
+    fn test_query_selector() {
+        let document = parse(
+            "<div id=a class=box><p id=b>x</p><span id=c></span><p id=d></p></div>\
+             <ul><li id=e><a id=f href=x></a></li></ul>",
+        );
+
+        let b = query_selector(&document, "div p").expect("failed to parse");
+        assert_eq!(ids(&[b.expect("no element")]), ["b"]);
+        assert!(query_selector(&document, "ol")
+            .expect("failed to parse")
+            .is_none());
+
+        let all = |s: &str| ids(&query_selector_all(&document, s).expect("failed to parse"));
+        assert_eq!(all("p"), ["b", "d"]);
+        assert_eq!(all(".box > p:last-child"), ["d"]);
+        assert_eq!(all("p + span, a"), ["c", "f"]);
+        assert_eq!(all("p ~ p"), ["d"]);
+        assert_eq!(all("body a[href]"), ["f"]);
+        assert_eq!(all("[href=y]"), Vec::<String>::new());
+        assert_eq!(all("div > :empty"), ["c", "d"]);
+        assert_eq!(all("#e :only-child"), ["f"]);
+        assert_eq!(all(":root > body > *"), ["a", ""]);
+
+        let div = get_element_by_id(&document, "a").expect("no element");
+        assert_eq!(
+            ids(&query_selector_all(&div, "body p").expect("failed to parse")),
+            ["b", "d"]
+        );
+
+        assert!(query_selector(&document, "p >").is_err());
+    }
+}
@@ -0,0 +1,1156 @@
+//! Selectors used by `query_selector()`, `query_selector_all()` and style
+//! rules.
+//! https://drafts.csswg.org/selectors-4/
+
+use crate::error::Error;
+use crate::renderer::css::parser::{parse_component_values, BlockKind, ComponentValue};
+use crate::renderer::css::token::CssToken;
+use crate::renderer::dom::bloom::{hash, BloomFilter};
+use crate::renderer::dom::node::{Node, NodeKind};
+use crate::renderer::dom::query::descendant_elements;
+use alloc::format;
+use alloc::rc::Rc;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::cell::RefCell;
+
+/// https://drafts.csswg.org/selectors-4/#complex
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Selector {
+    compounds: Vec<CompoundSelector>,
+    /// `combinators[i]` combines `compounds[i]` and `compounds[i + 1]`.
+    combinators: Vec<Combinator>,
+    /// The hashes of the types, IDs and classes that ancestors of a matching
+    /// element must have.
+    ancestor_hashes: Vec<u32>,
+}
+
+/// A selector in `:has()` that may start with a combinator, like `> img`.
+/// The combinator relates the selector to the element that `:has()` is on.
+/// https://drafts.csswg.org/selectors-4/#relative
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RelativeSelector {
+    combinator: Combinator,
+    selector: Selector,
+}
+
+/// The ID, class-like and type selector counts, compared in that order.
+/// https://drafts.csswg.org/selectors-4/#specificity-rules
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Specificity(pub u32, pub u32, pub u32);
+
+/// https://drafts.csswg.org/selectors-4/#compound
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CompoundSelector {
+    simple_selectors: Vec<SimpleSelector>,
+}
+
+/// https://drafts.csswg.org/selectors-4/#simple
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SimpleSelector {
+    /// `*`
+    Universal,
+    /// `div`
+    Type(String),
+    /// `#id`
+    Id(String),
+    /// `.class`
+    Class(String),
+    /// `[name]` when `value` is `None`, `[name=value]`, `[name^=value]` and
+    /// so on otherwise.
+    Attribute {
+        name: String,
+        value: Option<(AttributeOperator, String)>,
+    },
+    /// `:first-child`
+    PseudoClass(PseudoClass),
+}
+
+/// https://drafts.csswg.org/selectors-4/#attribute-representation
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum AttributeOperator {
+    /// `=`
+    Equals,
+    /// `~=`, one of the whitespace-separated words.
+    Includes,
+    /// `|=`, the value or the value followed by `-`, like `en` in `en-US`.
+    DashMatch,
+    /// `^=`
+    Prefix,
+    /// `$=`
+    Suffix,
+    /// `*=`
+    Substring,
+}
+
+/// https://drafts.csswg.org/selectors-4/#pseudo-classes
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PseudoClass {
+    Root,
+    Empty,
+    FirstChild,
+    LastChild,
+    OnlyChild,
+    /// `:nth-child(2n+1)`
+    NthChild(Nth),
+    /// `:nth-last-child(2n+1)`, which counts from the last child.
+    NthLastChild(Nth),
+    /// https://drafts.csswg.org/selectors-4/#the-hover-pseudo
+    Hover,
+    /// https://drafts.csswg.org/selectors-4/#the-focus-pseudo
+    Focus,
+    /// `:not(a, b)`, which matches elements that match none of the
+    /// selectors.
+    Not(Vec<Selector>),
+    /// `:is(a, b)`, which matches elements that match any of the selectors.
+    Is(Vec<Selector>),
+    /// `:where(a, b)`, which is like `:is()` without specificity.
+    Where(Vec<Selector>),
+    /// `:has(> a, b)`, which matches elements that one of the relative
+    /// selectors finds an element from.
+    Has(Vec<RelativeSelector>),
+}
+
+/// The `An+B` of `:nth-child()`, which matches the elements whose index,
+/// counting from 1, is `A*n + B` for some `n >= 0`.
+/// https://drafts.csswg.org/css-syntax-3/#anb-microsyntax
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Nth {
+    pub a: i32,
+    pub b: i32,
+}
+
+/// https://drafts.csswg.org/selectors-4/#combinators
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Combinator {
+    /// `a b`
+    Descendant,
+    /// `a > b`
+    Child,
+    /// `a + b`
+    NextSibling,
+    /// `a ~ b`
+    SubsequentSibling,
+}
+
+/// Parses a comma-separated list of selectors such as `"div > p, a.link"`.
+/// https://drafts.csswg.org/selectors-4/#parse-a-selector
+pub fn parse_selector_list(input: &str) -> Result<Vec<Selector>, Error> {
+    parse_selector_list_from_values(&parse_component_values(input))
+}
+
+/// Parses a selector list that has been split into component values, like
+/// the prelude of a style rule.
+pub fn parse_selector_list_from_values(values: &[ComponentValue]) -> Result<Vec<Selector>, Error> {
+    split_commas(values)
+        .map(|values| SelectorParser { values, pos: 0 }.consume_selector())
+        .collect()
+}
+
+/// Parses the selector list of `:is()` and `:where()`, leaving out the
+/// selectors that are invalid instead of failing.
+/// https://drafts.csswg.org/selectors-4/#typedef-forgiving-selector-list
+fn parse_forgiving_selector_list(values: &[ComponentValue]) -> Vec<Selector> {
+    split_commas(values)
+        .filter_map(|values| SelectorParser { values, pos: 0 }.consume_selector().ok())
+        .collect()
+}
+
+/// https://drafts.csswg.org/selectors-4/#typedef-relative-selector-list
+fn parse_relative_selector_list(values: &[ComponentValue]) -> Result<Vec<RelativeSelector>, Error> {
+    split_commas(values)
+        .map(|values| SelectorParser { values, pos: 0 }.consume_relative_selector())
+        .collect()
+}
+
+fn split_commas(values: &[ComponentValue]) -> impl Iterator<Item = &[ComponentValue]> {
+    values.split(|v| *v == ComponentValue::Token(CssToken::Comma))
+}
+
+struct SelectorParser<'a> {
+    values: &'a [ComponentValue],
+    pos: usize,
+}
+
+impl<'a> SelectorParser<'a> {
+    fn peek(&self) -> Option<&'a ComponentValue> {
+        self.values.get(self.pos)
+    }
+
+    fn peek_token(&self) -> Option<&'a CssToken> {
+        match self.peek() {
+            Some(ComponentValue::Token(token)) => Some(token),
+            _ => None,
+        }
+    }
+
+    fn skip_whitespace(&mut self) -> bool {
+        let mut skipped = false;
+        while self.peek().is_some_and(|v| v.is_whitespace()) {
+            self.pos += 1;
+            skipped = true;
+        }
+        skipped
+    }
+
+    fn unexpected(&self, context: &str) -> Error {
+        Error::UnexpectedInput(match self.peek() {
+            Some(v) => format!("unexpected {:?} in {}", v, context),
+            None => format!("unexpected end of input in {}", context),
+        })
+    }
+
+    /// Consumes a selector up to the end of the input.
+    fn consume_selector(&mut self) -> Result<Selector, Error> {
+        self.skip_whitespace();
+
+        let mut compounds = Vec::new();
+        let mut combinators = Vec::new();
+        compounds.push(self.consume_compound_selector()?);
+
+        loop {
+            let whitespace = self.skip_whitespace();
+            let combinator = match self.peek() {
+                None => break,
+                Some(ComponentValue::Token(CssToken::Delim('>'))) => Combinator::Child,
+                Some(ComponentValue::Token(CssToken::Delim('+'))) => Combinator::NextSibling,
+                Some(ComponentValue::Token(CssToken::Delim('~'))) => Combinator::SubsequentSibling,
+                Some(_) if whitespace => Combinator::Descendant,
+                Some(_) => return Err(self.unexpected("selector")),
+            };
+            if combinator != Combinator::Descendant {
+                self.pos += 1;
+                self.skip_whitespace();
+            }
+
+            combinators.push(combinator);
+            compounds.push(self.consume_compound_selector()?);
+        }
+
+        Ok(Selector::new(compounds, combinators))
+    }
+
+    /// Consumes a selector that may start with `>`, `+` or `~`. Without one,
+    /// the combinator is the descendant combinator.
+    fn consume_relative_selector(&mut self) -> Result<RelativeSelector, Error> {
+        self.skip_whitespace();
+        let combinator = match self.peek_token() {
+            Some(CssToken::Delim('>')) => Combinator::Child,
+            Some(CssToken::Delim('+')) => Combinator::NextSibling,
+            Some(CssToken::Delim('~')) => Combinator::SubsequentSibling,
+            _ => Combinator::Descendant,
+        };
+        if combinator != Combinator::Descendant {
+            self.pos += 1;
+        }
+        Ok(RelativeSelector {
+            combinator,
+            selector: self.consume_selector()?,
+        })
+    }
+
+    fn consume_compound_selector(&mut self) -> Result<CompoundSelector, Error> {
+        let mut simple_selectors = Vec::new();
+
+        match self.peek_token() {
+            Some(CssToken::Delim('*')) => {
+                self.pos += 1;
+                simple_selectors.push(SimpleSelector::Universal);
+            }
+            Some(CssToken::Ident(name)) => {
+                simple_selectors.push(SimpleSelector::Type(name.to_ascii_lowercase()));
+                self.pos += 1;
+            }
+            _ => {}
+        }
+
+        loop {
+            let simple_selector = match self.peek() {
+                Some(ComponentValue::Token(CssToken::Hash { value, is_id: true })) => {
+                    self.pos += 1;
+                    SimpleSelector::Id(value.clone())
+                }
+                Some(ComponentValue::Token(CssToken::Delim('.'))) => {
+                    self.pos += 1;
+                    SimpleSelector::Class(self.consume_ident("class selector")?)
+                }
+                Some(ComponentValue::Block {
+                    kind: BlockKind::SquareBracket,
+                    contents,
+                }) => {
+                    self.pos += 1;
+                    let mut parser = SelectorParser {
+                        values: contents,
+                        pos: 0,
+                    };
+                    parser.consume_attribute_selector()?
+                }
+                Some(ComponentValue::Token(CssToken::Colon)) => {
+                    self.pos += 1;
+                    self.consume_pseudo_class()?
+                }
+                _ => break,
+            };
+            simple_selectors.push(simple_selector);
+        }
+
+        if simple_selectors.is_empty() {
+            return Err(self.unexpected("selector"));
+        }
+
+        Ok(CompoundSelector { simple_selectors })
+    }
+
+    /// Consumes the contents of an attribute selector, which are inside `[]`.
+    fn consume_attribute_selector(&mut self) -> Result<SimpleSelector, Error> {
+        self.skip_whitespace();
+        let name = self
+            .consume_ident("attribute selector")?
+            .to_ascii_lowercase();
+        self.skip_whitespace();
+
+        let operator = match self.peek_token() {
+            None if self.peek().is_none() => {
+                return Ok(SimpleSelector::Attribute { name, value: None })
+            }
+            Some(CssToken::Delim('=')) => AttributeOperator::Equals,
+            Some(CssToken::Delim(c)) => {
+                let operator = match c {
+                    '~' => AttributeOperator::Includes,
+                    '|' => AttributeOperator::DashMatch,
+                    '^' => AttributeOperator::Prefix,
+                    '$' => AttributeOperator::Suffix,
+                    '*' => AttributeOperator::Substring,
+                    _ => return Err(self.unexpected("attribute selector")),
+                };
+                self.pos += 1;
+                if self.peek_token() != Some(&CssToken::Delim('=')) {
+                    return Err(self.unexpected("attribute selector"));
+                }
+                operator
+            }
+            _ => return Err(self.unexpected("attribute selector")),
+        };
+        self.pos += 1;
+        self.skip_whitespace();
+
+        let value = match self.peek_token() {
+            Some(CssToken::Ident(s)) | Some(CssToken::String(s)) => s.clone(),
+            _ => return Err(self.unexpected("attribute selector")),
+        };
+        self.pos += 1;
+        self.skip_whitespace();
+
+        match self.peek() {
+            None => Ok(SimpleSelector::Attribute {
+                name,
+                value: Some((operator, value)),
+            }),
+            Some(_) => Err(self.unexpected("attribute selector")),
+        }
+    }
+
+    /// Consumes a pseudo-class after `:`, which is a name like `hover` or a
+    /// function like `nth-child(2n)`.
+    fn consume_pseudo_class(&mut self) -> Result<SimpleSelector, Error> {
+        let unsupported =
+            |name: &str| Error::UnexpectedInput(format!("unsupported pseudo-class :{}", name));
+
+        let pseudo_class = match self.peek() {
+            Some(ComponentValue::Token(CssToken::Ident(name))) => {
+                match name.to_ascii_lowercase().as_str() {
+                    "root" => PseudoClass::Root,
+                    "empty" => PseudoClass::Empty,
+                    "first-child" => PseudoClass::FirstChild,
+                    "last-child" => PseudoClass::LastChild,
+                    "only-child" => PseudoClass::OnlyChild,
+                    "hover" => PseudoClass::Hover,
+                    "focus" => PseudoClass::Focus,
+                    name => return Err(unsupported(name)),
+                }
+            }
+            Some(ComponentValue::Function { name, arguments }) => {
+                let name = name.to_ascii_lowercase();
+                let nth = || {
+                    Nth::parse(arguments).ok_or_else(|| {
+                        Error::UnexpectedInput(format!("invalid argument of :{}()", name))
+                    })
+                };
+                match name.as_str() {
+                    "nth-child" => PseudoClass::NthChild(nth()?),
+                    "nth-last-child" => PseudoClass::NthLastChild(nth()?),
+                    "not" => PseudoClass::Not(parse_selector_list_from_values(arguments)?),
+                    "is" => PseudoClass::Is(parse_forgiving_selector_list(arguments)),
+                    "where" => PseudoClass::Where(parse_forgiving_selector_list(arguments)),
+                    "has" => PseudoClass::Has(parse_relative_selector_list(arguments)?),
+                    _ => return Err(unsupported(&name)),
+                }
+            }
+            _ => return Err(self.unexpected("pseudo-class")),
+        };
+        self.pos += 1;
+        Ok(SimpleSelector::PseudoClass(pseudo_class))
+    }
+
+    fn consume_ident(&mut self, context: &str) -> Result<String, Error> {
+        match self.peek_token() {
+            Some(CssToken::Ident(s)) => {
+                let s = s.clone();
+                self.pos += 1;
+                Ok(s)
+            }
+            _ => Err(self.unexpected(context)),
+        }
+    }
+}
+
+impl core::ops::Add for Specificity {
+    type Output = Self;
+
+    fn add(self, other: Self) -> Self {
+        Specificity(self.0 + other.0, self.1 + other.1, self.2 + other.2)
+    }
+}
+
+/// The simple selector in the last compound of a selector that `RuleHash`
+/// files the selector under, since only elements with that ID, class or
+/// type can match it.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum SubjectKey<'a> {
+    Id(&'a str),
+    Class(&'a str),
+    Type(&'a str),
+    Universal,
+}
+
+/// The kinds of names that `element_hashes()` hashes.
+const TYPE_HASH: u8 = b'*';
+const ID_HASH: u8 = b'#';
+const CLASS_HASH: u8 = b'.';
+
+impl Selector {
+    fn new(compounds: Vec<CompoundSelector>, combinators: Vec<Combinator>) -> Self {
+        // Every compound to the left of a descendant or child combinator
+        // matches an ancestor. The ones that only sibling combinators lead
+        // to match siblings.
+        let mut ancestor_hashes = Vec::new();
+        let mut is_ancestor = false;
+        for (i, combinator) in combinators.iter().enumerate().rev() {
+            if matches!(combinator, Combinator::Descendant | Combinator::Child) {
+                is_ancestor = true;
+            }
+            if is_ancestor {
+                ancestor_hashes.extend(compounds[i].hashes());
+            }
+        }
+        ancestor_hashes.sort_unstable();
+        ancestor_hashes.dedup();
+
+        Self {
+            compounds,
+            combinators,
+            ancestor_hashes,
+        }
+    }
+
+    /// Attribute selectors and pseudo-classes count as classes, and the
+    /// universal selector doesn't count. `:is()`, `:not()` and `:has()`
+    /// count as their most specific argument, and `:where()` doesn't count.
+    pub fn specificity(&self) -> Specificity {
+        self.compounds
+            .iter()
+            .flat_map(|c| &c.simple_selectors)
+            .fold(Specificity::default(), |specificity, simple_selector| {
+                specificity + simple_selector.specificity()
+            })
+    }
+
+    /// Returns true if the element `node` matches this selector. The
+    /// selector is matched from right to left, starting with `node`.
+    /// https://drafts.csswg.org/selectors-4/#match-a-complex-selector-against-an-element
+    pub fn matches(&self, node: &Rc<RefCell<Node>>) -> bool {
+        self.matches_with_filter(node, None)
+    }
+
+    /// Like `matches()`, but first rules out the element if `filter` shows
+    /// that no ancestor has a type, ID or class that the selector needs.
+    /// `filter` must hold `element_hashes()` of every ancestor of `node`.
+    pub fn matches_with_filter(
+        &self,
+        node: &Rc<RefCell<Node>>,
+        filter: Option<&BloomFilter>,
+    ) -> bool {
+        if let Some(filter) = filter {
+            if !self
+                .ancestor_hashes
+                .iter()
+                .all(|h| filter.might_contain(*h))
+            {
+                return false;
+            }
+        }
+        self.matches_from(self.compounds.len() - 1, node, None)
+    }
+
+    pub fn subject_key(&self) -> SubjectKey<'_> {
+        let mut key = SubjectKey::Universal;
+        for simple_selector in &self.compounds[self.compounds.len() - 1].simple_selectors {
+            match simple_selector {
+                SimpleSelector::Id(id) => return SubjectKey::Id(id),
+                SimpleSelector::Class(class) if !matches!(key, SubjectKey::Class(_)) => {
+                    key = SubjectKey::Class(class)
+                }
+                SimpleSelector::Type(name) if key == SubjectKey::Universal => {
+                    key = SubjectKey::Type(name)
+                }
+                _ => {}
+            }
+        }
+        key
+    }
+
+    /// Matches `compounds[..=index]`, where `compounds[index]` must match
+    /// `node`. For a relative selector, `anchor` is the element of `:has()`
+    /// and the combinator that `compounds[0]` must be related to it with.
+    fn matches_from(
+        &self,
+        index: usize,
+        node: &Rc<RefCell<Node>>,
+        anchor: Option<(&Rc<RefCell<Node>>, Combinator)>,
+    ) -> bool {
+        if !self.compounds[index].matches(node) {
+            return false;
+        }
+        if index == 0 {
+            return match anchor {
+                Some((anchor, combinator)) => is_related(anchor, combinator, node),
+                None => true,
+            };
+        }
+
+        match self.combinators[index - 1] {
+            Combinator::Descendant => {
+                let mut ancestor = parent_element(node);
+                while let Some(a) = ancestor {
+                    if self.matches_from(index - 1, &a, anchor) {
+                        return true;
+                    }
+                    ancestor = parent_element(&a);
+                }
+                false
+            }
+            Combinator::Child => match parent_element(node) {
+                Some(p) => self.matches_from(index - 1, &p, anchor),
+                None => false,
+            },
+            Combinator::NextSibling => match previous_element_sibling(node) {
+                Some(s) => self.matches_from(index - 1, &s, anchor),
+                None => false,
+            },
+            Combinator::SubsequentSibling => {
+                let mut sibling = previous_element_sibling(node);
+                while let Some(s) = sibling {
+                    if self.matches_from(index - 1, &s, anchor) {
+                        return true;
+                    }
+                    sibling = previous_element_sibling(&s);
+                }
+                false
+            }
+        }
+    }
+}
+
+impl RelativeSelector {
+    /// Returns true if an element related to `anchor` by the combinator
+    /// matches the selector.
+    /// https://drafts.csswg.org/selectors-4/#relational
+    fn matches(&self, anchor: &Rc<RefCell<Node>>) -> bool {
+        let last = self.selector.compounds.len() - 1;
+        let anchor_combinator = Some((anchor, self.combinator));
+        match self.combinator {
+            Combinator::Descendant | Combinator::Child => descendant_elements(anchor)
+                .any(|n| self.selector.matches_from(last, &n, anchor_combinator)),
+            Combinator::NextSibling | Combinator::SubsequentSibling => {
+                let mut sibling = next_element_sibling(anchor);
+                while let Some(s) = sibling {
+                    if self.selector.matches_from(last, &s, anchor_combinator)
+                        || descendant_elements(&s)
+                            .any(|n| self.selector.matches_from(last, &n, anchor_combinator))
+                    {
+                        return true;
+                    }
+                    sibling = next_element_sibling(&s);
+                }
+                false
+            }
+        }
+    }
+}
+
+impl CompoundSelector {
+    fn matches(&self, node: &Rc<RefCell<Node>>) -> bool {
+        self.simple_selectors.iter().all(|s| s.matches(node))
+    }
+
+    fn hashes(&self) -> impl Iterator<Item = u32> + '_ {
+        self.simple_selectors.iter().filter_map(|s| match s {
+            SimpleSelector::Type(name) => Some(hash(TYPE_HASH, name)),
+            SimpleSelector::Id(id) => Some(hash(ID_HASH, id)),
+            SimpleSelector::Class(class) => Some(hash(CLASS_HASH, class)),
+            _ => None,
+        })
+    }
+}
+
+impl SimpleSelector {
+    fn specificity(&self) -> Specificity {
+        let max = |selectors: &mut dyn Iterator<Item = &Selector>| {
+            selectors.map(|s| s.specificity()).max().unwrap_or_default()
+        };
+        match self {
+            SimpleSelector::Universal => Specificity(0, 0, 0),
+            SimpleSelector::Type(_) => Specificity(0, 0, 1),
+            SimpleSelector::Id(_) => Specificity(1, 0, 0),
+            SimpleSelector::PseudoClass(PseudoClass::Is(selectors))
+            | SimpleSelector::PseudoClass(PseudoClass::Not(selectors)) => {
+                max(&mut selectors.iter())
+            }
+            SimpleSelector::PseudoClass(PseudoClass::Has(selectors)) => {
+                max(&mut selectors.iter().map(|s| &s.selector))
+            }
+            SimpleSelector::PseudoClass(PseudoClass::Where(_)) => Specificity(0, 0, 0),
+            SimpleSelector::Class(_)
+            | SimpleSelector::Attribute { .. }
+            | SimpleSelector::PseudoClass(_) => Specificity(0, 1, 0),
+        }
+    }
+
+    fn matches(&self, node: &Rc<RefCell<Node>>) -> bool {
+        let n = node.borrow();
+        let element = match n.kind {
+            NodeKind::Element(ref e) => e,
+            _ => return false,
+        };
+
+        match self {
+            SimpleSelector::Universal => true,
+            SimpleSelector::Type(name) => element.tag_name().eq_ignore_ascii_case(name),
+            SimpleSelector::Id(id) => element.id() == *id,
+            SimpleSelector::Class(class) => element.class_list().contains(class),
+            SimpleSelector::Attribute { name, value } => match element.get_attribute(name) {
+                Some(v) => value
+                    .as_ref()
+                    .map_or(true, |(operator, value)| operator.matches(&v, value)),
+                None => false,
+            },
+            SimpleSelector::PseudoClass(pseudo_class) => pseudo_class.matches(node),
+        }
+    }
+}
+
+impl AttributeOperator {
+    /// Returns true if the attribute value `actual` matches `expected`.
+    /// Only `=` and `|=` can match an empty `expected`.
+    fn matches(&self, actual: &str, expected: &str) -> bool {
+        match self {
+            AttributeOperator::Equals => actual == expected,
+            AttributeOperator::Includes => {
+                !expected.is_empty()
+                    && !expected.contains(is_whitespace)
+                    && actual.split(is_whitespace).any(|word| word == expected)
+            }
+            AttributeOperator::DashMatch => {
+                actual == expected
+                    || actual
+                        .strip_prefix(expected)
+                        .is_some_and(|rest| rest.starts_with('-'))
+            }
+            AttributeOperator::Prefix => !expected.is_empty() && actual.starts_with(expected),
+            AttributeOperator::Suffix => !expected.is_empty() && actual.ends_with(expected),
+            AttributeOperator::Substring => !expected.is_empty() && actual.contains(expected),
+        }
+    }
+}
+
+impl PseudoClass {
+    fn matches(&self, node: &Rc<RefCell<Node>>) -> bool {
+        match self {
+            PseudoClass::Root => match node.borrow().parent().upgrade() {
+                Some(p) => p.borrow().kind == NodeKind::Document,
+                None => false,
+            },
+            // Comments don't count as contents.
+            PseudoClass::Empty => node
+                .borrow()
+                .children()
+                .iter()
+                .all(|c| match c.borrow().kind {
+                    NodeKind::Element(_) => false,
+                    NodeKind::Text(ref s) => s.is_empty(),
+                    _ => true,
+                }),
+            PseudoClass::FirstChild => previous_element_sibling(node).is_none(),
+            PseudoClass::LastChild => next_element_sibling(node).is_none(),
+            PseudoClass::OnlyChild => {
+                previous_element_sibling(node).is_none() && next_element_sibling(node).is_none()
+            }
+            PseudoClass::NthChild(nth) => {
+                nth.matches(count_siblings(node, previous_element_sibling) + 1)
+            }
+            PseudoClass::NthLastChild(nth) => {
+                nth.matches(count_siblings(node, next_element_sibling) + 1)
+            }
+            PseudoClass::Hover => node.borrow().state().hover,
+            PseudoClass::Focus => node.borrow().state().focus,
+            PseudoClass::Not(selectors) => !selectors.iter().any(|s| s.matches(node)),
+            PseudoClass::Is(selectors) | PseudoClass::Where(selectors) => {
+                selectors.iter().any(|s| s.matches(node))
+            }
+            PseudoClass::Has(selectors) => selectors.iter().any(|s| s.matches(node)),
+        }
+    }
+}
+
+impl Nth {
+    /// Parses the argument of `:nth-child()`, like `odd`, `3`, `-n+3` or
+    /// `2n - 1`.
+    /// https://drafts.csswg.org/css-syntax-3/#anb-production
+    fn parse(values: &[ComponentValue]) -> Option<Self> {
+        let tokens: Vec<&CssToken> = values
+            .iter()
+            .filter(|v| !v.is_whitespace())
+            .map(|v| match v {
+                ComponentValue::Token(token) => Some(token),
+                _ => None,
+            })
+            .collect::<Option<_>>()?;
+
+        match tokens.as_slice() {
+            [CssToken::Ident(s)] if s.eq_ignore_ascii_case("odd") => {
+                return Some(Self { a: 2, b: 1 })
+            }
+            [CssToken::Ident(s)] if s.eq_ignore_ascii_case("even") => {
+                return Some(Self { a: 2, b: 0 })
+            }
+            [CssToken::Number {
+                value,
+                is_integer: true,
+            }] => {
+                return Some(Self {
+                    a: 0,
+                    b: *value as i32,
+                })
+            }
+            _ => {}
+        }
+
+        // The tokenizer keeps what follows `n` in the same token when it can
+        // be part of a name, like `-1` in `2n-1`.
+        let (a, rest, tokens) = match tokens.as_slice() {
+            [CssToken::Dimension {
+                value,
+                unit,
+                is_integer: true,
+            }, tokens @ ..] => (*value as i32, unit.as_str(), tokens),
+            [CssToken::Delim('+'), CssToken::Ident(ident), tokens @ ..] => {
+                (1, ident.as_str(), tokens)
+            }
+            [CssToken::Ident(ident), tokens @ ..] => match ident.strip_prefix('-') {
+                Some(ident) => (-1, ident, tokens),
+                None => (1, ident.as_str(), tokens),
+            },
+            _ => return None,
+        };
+        let rest = rest.to_ascii_lowercase();
+        let rest = rest.strip_prefix('n')?;
+
+        let integer = |token: &CssToken| match token {
+            CssToken::Number {
+                value,
+                is_integer: true,
+            } => Some(*value as i32),
+            _ => None,
+        };
+        let b = match (rest, tokens) {
+            ("", []) => 0,
+            // `2n+1` and `2n -1`
+            ("", [number]) => integer(number)?,
+            // `2n + 1` and `2n - 1`
+            ("", [CssToken::Delim(sign @ ('+' | '-')), number]) => {
+                let b = integer(number).filter(|b| *b >= 0)?;
+                if *sign == '-' {
+                    -b
+                } else {
+                    b
+                }
+            }
+            // `2n- 1`
+            ("-", [number]) => -integer(number).filter(|b| *b >= 0)?,
+            // `2n-1`
+            (rest, []) => {
+                let digits = rest.strip_prefix('-')?;
+                if digits.is_empty() || !digits.bytes().all(|b| b.is_ascii_digit()) {
+                    return None;
+                }
+                -digits.parse::<i32>().ok()?
+            }
+            _ => return None,
+        };
+        Some(Self { a, b })
+    }
+
+    /// Returns true if `index`, counting from 1, is `a*n + b` for some
+    /// `n >= 0`.
+    pub fn matches(&self, index: i32) -> bool {
+        if self.a == 0 {
+            return index == self.b;
+        }
+        // Large values saturate when they're parsed, so `index - b` may not
+        // fit in an `i32`.
+        let diff = i64::from(index) - i64::from(self.b);
+        let a = i64::from(self.a);
+        diff % a == 0 && diff / a >= 0
+    }
+}
+
+/// Returns the hashes of the type, ID and classes of the element `node`. The
+/// style engine keeps them in a bloom filter for the ancestors of the
+/// element that it styles, for `Selector::matches_with_filter()`.
+pub fn element_hashes(node: &Rc<RefCell<Node>>) -> Vec<u32> {
+    let node = node.borrow();
+    let element = match node.kind {
+        NodeKind::Element(ref e) => e,
+        _ => return Vec::new(),
+    };
+    let mut hashes = Vec::from([hash(TYPE_HASH, &element.tag_name())]);
+    if let Some(id) = element.get_attribute("id") {
+        hashes.push(hash(ID_HASH, &id));
+    }
+    hashes.extend(element.class_list().iter().map(|c| hash(CLASS_HASH, c)));
+    hashes
+}
+
+/// A function that moves from an element to a related one, like
+/// `parent_element()`.
+type Step = fn(&Rc<RefCell<Node>>) -> Option<Rc<RefCell<Node>>>;
+
+/// Returns true if `node` is related to `anchor` by `combinator`, as in
+/// `anchor > node` for the child combinator.
+fn is_related(
+    anchor: &Rc<RefCell<Node>>,
+    combinator: Combinator,
+    node: &Rc<RefCell<Node>>,
+) -> bool {
+    let (next, repeat): (Step, bool) = match combinator {
+        Combinator::Descendant => (parent_element, true),
+        Combinator::Child => (parent_element, false),
+        Combinator::NextSibling => (previous_element_sibling, false),
+        Combinator::SubsequentSibling => (previous_element_sibling, true),
+    };
+    let mut current = next(node);
+    while let Some(c) = current {
+        if Rc::ptr_eq(&c, anchor) {
+            return true;
+        }
+        if !repeat {
+            return false;
+        }
+        current = next(&c);
+    }
+    false
+}
+
+fn count_siblings(node: &Rc<RefCell<Node>>, next: Step) -> i32 {
+    let mut count = 0;
+    let mut sibling = next(node);
+    while let Some(s) = sibling {
+        count += 1;
+        sibling = next(&s);
+    }
+    count
+}
+
+/// https://infra.spec.whatwg.org/#ascii-whitespace
+fn is_whitespace(c: char) -> bool {
+    matches!(c, '\t' | '\n' | '\x0c' | '\r' | ' ')
+}
+
+fn parent_element(node: &Rc<RefCell<Node>>) -> Option<Rc<RefCell<Node>>> {
+    let parent = node.borrow().parent().upgrade()?;
+    let is_element = matches!(parent.borrow().kind, NodeKind::Element(_));
+    if is_element {
+        Some(parent)
+    } else {
+        None
+    }
+}
+
+fn previous_element_sibling(node: &Rc<RefCell<Node>>) -> Option<Rc<RefCell<Node>>> {
+    let mut sibling = node.borrow().previous_sibling().upgrade();
+    while let Some(s) = sibling {
+        if matches!(s.borrow().kind, NodeKind::Element(_)) {
+            return Some(s);
+        }
+        sibling = s.borrow().previous_sibling().upgrade();
+    }
+    None
+}
+
+fn next_element_sibling(node: &Rc<RefCell<Node>>) -> Option<Rc<RefCell<Node>>> {
+    let mut sibling = node.borrow().next_sibling();
+    while let Some(s) = sibling {
+        if matches!(s.borrow().kind, NodeKind::Element(_)) {
+            return Some(s);
+        }
+        sibling = s.borrow().next_sibling();
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::renderer::html::parser::HtmlParser;
+    use crate::renderer::html::token::HtmlTokenizer;
+    use alloc::string::ToString;
+    use alloc::vec;
+
+    #[test]
+    fn test_parse() {
+        let selectors =
+            parse_selector_list("DIV#a.b > p[x='1'] + *, :first-child").expect("failed to parse");
+        assert_eq!(
+            selectors,
+            vec![
+                Selector::new(
+                    vec![
+                        CompoundSelector {
+                            simple_selectors: vec![
+                                SimpleSelector::Type("div".to_string()),
+                                SimpleSelector::Id("a".to_string()),
+                                SimpleSelector::Class("b".to_string()),
+                            ],
+                        },
+                        CompoundSelector {
+                            simple_selectors: vec![
+                                SimpleSelector::Type("p".to_string()),
+                                SimpleSelector::Attribute {
+                                    name: "x".to_string(),
+                                    value: Some((AttributeOperator::Equals, "1".to_string())),
+                                },
+                            ],
+                        },
+                        CompoundSelector {
+                            simple_selectors: vec![SimpleSelector::Universal],
+                        },
+                    ],
+                    vec![Combinator::Child, Combinator::NextSibling],
+                ),
+                Selector::new(
+                    vec![CompoundSelector {
+                        simple_selectors: vec![SimpleSelector::PseudoClass(
+                            PseudoClass::FirstChild
+                        )],
+                    }],
+                    Vec::new(),
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_escape() {
+        let selectors = parse_selector_list(".a\\:b #\\31 x").expect("failed to parse");
+        assert_eq!(
+            selectors[0].compounds[0].simple_selectors,
+            vec![SimpleSelector::Class("a:b".to_string())]
+        );
+        assert_eq!(
+            selectors[0].compounds[1].simple_selectors,
+            vec![SimpleSelector::Id("1x".to_string())]
+        );
+    }
+
+    #[test]
+    fn test_specificity() {
+        let specificity =
+            |s: &str| parse_selector_list(s).expect("failed to parse")[0].specificity();
+        assert_eq!(specificity("*"), Specificity(0, 0, 0));
+        assert_eq!(specificity("ul li > a"), Specificity(0, 0, 3));
+        assert_eq!(specificity("#a.b[c]:first-child p"), Specificity(1, 3, 1));
+        assert!(specificity("#a") > specificity(".a.b.c.d.e.f.g.h.i.j.k"));
+        assert_eq!(specificity(":is(#a, p) .b"), Specificity(1, 1, 0));
+        assert_eq!(specificity(":not(.a.b, p)"), Specificity(0, 2, 0));
+        assert_eq!(specificity(":where(#a) :has(> p)"), Specificity(0, 0, 1));
+        assert_eq!(specificity("li:nth-child(2n)"), Specificity(0, 1, 1));
+    }
+
+    #[test]
+    fn test_parse_nth() {
+        let nth = |s: &str| Nth::parse(&parse_component_values(s)).map(|n| (n.a, n.b));
+        assert_eq!(nth("odd"), Some((2, 1)));
+        assert_eq!(nth(" EVEN "), Some((2, 0)));
+        assert_eq!(nth("3"), Some((0, 3)));
+        assert_eq!(nth("-2"), Some((0, -2)));
+        assert_eq!(nth("n"), Some((1, 0)));
+        assert_eq!(nth("+n+2"), Some((1, 2)));
+        assert_eq!(nth("-n+3"), Some((-1, 3)));
+        assert_eq!(nth("2n+1"), Some((2, 1)));
+        assert_eq!(nth("2n-1"), Some((2, -1)));
+        assert_eq!(nth("2N - 1"), Some((2, -1)));
+        assert_eq!(nth("2n- 1"), Some((2, -1)));
+        assert_eq!(nth("-3n + 10"), Some((-3, 10)));
+        assert_eq!(nth("-n-5"), Some((-1, -5)));
+
+        assert_eq!(nth(""), None);
+        assert_eq!(nth("2"), Some((0, 2)));
+        assert_eq!(nth("1.5n"), None);
+        assert_eq!(nth("2m"), None);
+        assert_eq!(nth("2n - -1"), None);
+        assert_eq!(nth("2n-a"), None);
+        assert_eq!(nth("n 1 1"), None);
+
+        // Large values saturate without overflowing when matching.
+        let large = Nth::parse(&parse_component_values("2n -3000000000")).expect("no nth");
+        assert_eq!((large.a, large.b), (2, i32::MIN));
+        assert!(large.matches(2));
+        assert!(!large.matches(1));
+        let large = Nth::parse(&parse_component_values("-n+3000000000")).expect("no nth");
+        assert!(large.matches(3));
+        assert!(!Nth { a: -1, b: i32::MIN }.matches(1));
+    }
+
+    /// Returns the IDs of the elements in `html` that match `selector`.
+    fn matching(html: &str, selector: &str) -> Vec<String> {
+        let window = HtmlParser::new(HtmlTokenizer::new(html.to_string())).construct_tree();
+        let document = window.borrow().document();
+        let selectors = parse_selector_list(selector).expect("failed to parse");
+        descendant_elements(&document)
+            .filter(|n| selectors.iter().any(|s| s.matches(n)))
+            .filter_map(|n| n.borrow().get_element()?.get_attribute("id"))
+            .collect()
+    }
+
+    #[test]
+    fn test_matches() {
+        let html = "<ul id=ul>\
+            <li id=1 class=a><a id=a1 href=/x.png title='en-US'></a></li>\
+            <li id=2 lang=en><p id=p></p></li>\
+            <li id=3 class='a b'></li>\
+            <li id=4 data-x='one two'></li>\
+            </ul>";
+
+        assert_eq!(matching(html, "li:nth-child(odd)"), ["1", "3"]);
+        assert_eq!(matching(html, "li:nth-child(-n+2)"), ["1", "2"]);
+        assert_eq!(matching(html, "li:nth-last-child(1)"), ["4"]);
+        assert_eq!(matching(html, "li:first-child, li:last-child"), ["1", "4"]);
+        assert_eq!(matching(html, "li + li ~ li"), ["3", "4"]);
+        assert_eq!(matching(html, "ul > .a a"), ["a1"]);
+
+        assert_eq!(matching(html, "[href^='/x']"), ["a1"]);
+        assert_eq!(matching(html, "[href$=png]"), ["a1"]);
+        assert_eq!(matching(html, "[href*='x.']"), ["a1"]);
+        assert_eq!(matching(html, "[href^='']"), Vec::<String>::new());
+        assert_eq!(matching(html, "[data-x~=two]"), ["4"]);
+        assert_eq!(matching(html, "[data-x~='one two']"), Vec::<String>::new());
+        assert_eq!(matching(html, "[lang|=en], [title|=en]"), ["a1", "2"]);
+        assert_eq!(matching(html, "[class~=b]"), ["3"]);
+
+        assert_eq!(matching(html, "li:not(.a, [lang])"), ["4"]);
+        // `#2` isn't an ID selector, which `:is()` forgives.
+        assert_eq!(matching(html, "li:is(.b, #2)"), ["3"]);
+        assert_eq!(matching(html, "li:where(.b, :nth-child(-1))"), ["3"]);
+        assert_eq!(matching(html, "li:has(a, p)"), ["1", "2"]);
+        assert_eq!(matching(html, "ul:has(> li > p)"), ["ul"]);
+        assert_eq!(matching(html, "ul:has(> p)"), Vec::<String>::new());
+        assert_eq!(matching(html, "li:has(+ .a)"), ["2"]);
+        assert_eq!(matching(html, "li:has(~ [data-x] )"), ["1", "2", "3"]);
+        assert_eq!(matching(html, ":has(.b) li:not(:has(*))"), ["3", "4"]);
+    }
+
+    #[test]
+    fn test_matches_state() {
+        let window = HtmlParser::new(HtmlTokenizer::new(
+            "<div id=d><a id=a></a></div><input id=i>".to_string(),
+        ))
+        .construct_tree();
+        let document = window.borrow().document();
+        let element = |id: &str| {
+            descendant_elements(&document)
+                .find(|n| n.borrow().get_element().is_some_and(|e| e.id() == id))
+                .expect("no element")
+        };
+        let hover = &parse_selector_list("div:hover").expect("failed to parse")[0];
+        let focus = &parse_selector_list(":focus").expect("failed to parse")[0];
+
+        window.borrow_mut().set_hovered(Some(element("a")));
+        window.borrow_mut().set_focused(Some(element("i")));
+        assert!(hover.matches(&element("d")));
+        assert!(focus.matches(&element("i")));
+
+        window.borrow_mut().set_hovered(Some(element("i")));
+        window.borrow_mut().set_focused(None);
+        assert!(!hover.matches(&element("d")));
+        assert!(!focus.matches(&element("i")));
+    }
+
+    #[test]
+    fn test_matches_with_filter() {
+        let window = HtmlParser::new(HtmlTokenizer::new(
+            "<div class=a><p id=p></p></div>".to_string(),
+        ))
+        .construct_tree();
+        let document = window.borrow().document();
+        let p = descendant_elements(&document).last().expect("no element");
+        let selector = |s: &str| parse_selector_list(s).expect("failed to parse").remove(0);
+
+        let mut ancestors = BloomFilter::new();
+        let mut ancestor = parent_element(&p);
+        while let Some(a) = ancestor {
+            for hash in element_hashes(&a) {
+                ancestors.insert(hash);
+            }
+            ancestor = parent_element(&a);
+        }
+
+        for s in ["div.a > p", "body p#p", ":is(.a) p", "p"] {
+            assert!(
+                selector(s).matches_with_filter(&p, Some(&ancestors)),
+                "{}",
+                s
+            );
+        }
+        for s in ["span p", ".b p", "#p p", "div + p"] {
+            assert!(
+                !selector(s).matches_with_filter(&p, Some(&ancestors)),
+                "{}",
+                s
+            );
+        }
+        // A sibling isn't an ancestor, so the filter can't rule it out.
+        assert_eq!(selector("span + p").ancestor_hashes, Vec::new());
+        assert_eq!(selector("div p + span ~ a").ancestor_hashes.len(), 1);
+        assert_eq!(selector("div > p span + a").ancestor_hashes.len(), 2);
+    }
+
+    #[test]
+    fn test_parse_error() {
+        assert!(parse_selector_list("").is_err());
+        assert!(parse_selector_list("a,").is_err());
+        assert!(parse_selector_list("a >").is_err());
+        assert!(parse_selector_list("[a b]").is_err());
+        assert!(parse_selector_list("a:hover-ish").is_err());
+        assert!(parse_selector_list("a!").is_err());
+        assert!(parse_selector_list("[a^b]").is_err());
+        assert!(parse_selector_list("[a~ =b]").is_err());
+        assert!(parse_selector_list("[a=]").is_err());
+        assert!(parse_selector_list(":nth-child(x)").is_err());
+        assert!(parse_selector_list(":not()").is_err());
+        assert!(parse_selector_list(":not(a!)").is_err());
+        assert!(parse_selector_list(":has(> )").is_err());
+        assert!(parse_selector_list(":is(a!, b)").is_ok());
+        assert!(parse_selector_list(":where()").is_ok());
+        assert!(parse_selector_list(":unknown(a)").is_err());
+    }
+}
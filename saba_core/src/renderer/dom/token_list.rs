@@ -0,0 +1,131 @@
+//! https://dom.spec.whatwg.org/#interface-domtokenlist
+
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+
+/// An ordered set of the whitespace-separated tokens in an attribute such as
+/// `class`. This is a copy of the attribute value; write `value()` back to
+/// the attribute with `set_attribute()` to apply changes.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DomTokenList {
+    tokens: Vec<String>,
+}
+
+impl DomTokenList {
+    /// https://dom.spec.whatwg.org/#concept-ordered-set-parser
+    pub fn new(value: &str) -> Self {
+        let mut list = Self::default();
+        for token in value.split(is_whitespace).filter(|t| !t.is_empty()) {
+            if !list.contains(token) {
+                list.tokens.push(token.to_string());
+            }
+        }
+        list
+    }
+
+    pub fn len(&self) -> usize {
+        self.tokens.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tokens.is_empty()
+    }
+
+    pub fn item(&self, index: usize) -> Option<String> {
+        self.tokens.get(index).cloned()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &str> {
+        self.tokens.iter().map(|t| t.as_str())
+    }
+
+    pub fn contains(&self, token: &str) -> bool {
+        self.tokens.iter().any(|t| t == token)
+    }
+
+    /// Adds `token` at the end unless it's already in the list.
+    pub fn add(&mut self, token: &str) {
+        if !self.contains(token) {
+            self.tokens.push(token.to_string());
+        }
+    }
+
+    pub fn remove(&mut self, token: &str) {
+        self.tokens.retain(|t| t != token);
+    }
+
+    /// Removes `token` if it's in the list, and adds it otherwise. Returns
+    /// whether `token` is in the list afterwards.
+    /// https://dom.spec.whatwg.org/#dom-domtokenlist-toggle
+    pub fn toggle(&mut self, token: &str) -> bool {
+        if self.contains(token) {
+            self.remove(token);
+            false
+        } else {
+            self.tokens.push(token.to_string());
+            true
+        }
+    }
+
+    /// Replaces `token` with `new_token` in place. Returns false if `token`
+    /// isn't in the list.
+    /// https://dom.spec.whatwg.org/#dom-domtokenlist-replace
+    pub fn replace(&mut self, token: &str, new_token: &str) -> bool {
+        let index = match self.tokens.iter().position(|t| t == token) {
+            Some(i) => i,
+            None => return false,
+        };
+
+        if self.contains(new_token) {
+            self.remove(token);
+        } else {
+            self.tokens[index] = new_token.to_string();
+        }
+        true
+    }
+
+    /// Returns the tokens joined by a space, which is the attribute value.
+    /// https://dom.spec.whatwg.org/#concept-ordered-set-serializer
+    pub fn value(&self) -> String {
+        self.tokens.join(" ")
+    }
+}
+
+/// https://infra.spec.whatwg.org/#ascii-whitespace
+fn is_whitespace(c: char) -> bool {
+    matches!(c, '\t' | '\n' | '\x0C' | '\r' | ' ')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_new() {
+        let list = DomTokenList::new("  a\tb a\nc ");
+        assert_eq!(list.len(), 3);
+        assert_eq!(list.iter().collect::<Vec<&str>>(), ["a", "b", "c"]);
+        assert_eq!(list.item(1), Some("b".to_string()));
+        assert_eq!(list.item(3), None);
+        assert_eq!(list.value(), "a b c");
+        assert!(DomTokenList::new(" ").is_empty());
+    }
+
+    #[test]
+    fn test_modify() {
+        let mut list = DomTokenList::new("a b c");
+        list.add("a");
+        list.add("d");
+        list.remove("b");
+        assert_eq!(list.value(), "a c d");
+
+        assert!(!list.toggle("c"));
+        assert!(list.toggle("e"));
+        assert_eq!(list.value(), "a d e");
+
+        assert!(list.replace("d", "x"));
+        assert!(list.replace("a", "e"));
+        assert!(!list.replace("z", "y"));
+        assert_eq!(list.value(), "x e");
+    }
+}
@@ -14,6 +14,13 @@ impl Attribute {
         }
     }
 
+    pub fn with_value(name: &str, value: &str) -> Self {
+        Self {
+            name: String::from(name),
+            value: String::from(value),
+        }
+    }
+
     pub fn add_char(&mut self, c: char, is_name: bool) {
         if is_name {
             self.name.push(c);
@@ -29,4 +36,66 @@ impl Attribute {
     pub fn value(&self) -> String {
         self.value.clone()
     }
+
+    pub fn set_value(&mut self, value: &str) {
+        self.value = String::from(value);
+    }
+}
+
+/// Parses `input` as an integer, ignoring leading whitespace and anything
+/// after the digits.
+/// https://html.spec.whatwg.org/multipage/common-microsyntaxes.html#rules-for-parsing-integers
+pub fn parse_integer(input: &str) -> Option<i64> {
+    let input = input.trim_start_matches(|c: char| matches!(c, '\t' | '\n' | '\x0C' | '\r' | ' '));
+    let (negative, input) = match input.strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, input.strip_prefix('+').unwrap_or(input)),
+    };
+
+    let digits = input
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(input.len());
+    if digits == 0 {
+        return None;
+    }
+
+    let value: i64 = input[..digits].parse().ok()?;
+    Some(if negative { -value } else { value })
+}
+
+/// Parses `input` as a non-negative integer that fits in the range that
+/// reflected `unsigned long` attributes accept.
+/// https://html.spec.whatwg.org/multipage/common-microsyntaxes.html#rules-for-parsing-non-negative-integers
+/// https://html.spec.whatwg.org/multipage/common-dom-interfaces.html#reflecting-content-attributes-in-idl-attributes
+pub fn parse_non_negative_integer(input: &str) -> Option<u32> {
+    match parse_integer(input)? {
+        value @ 0..=2147483647 => Some(value as u32),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_integer() {
+        assert_eq!(parse_integer("42"), Some(42));
+        assert_eq!(parse_integer(" \n-7px"), Some(-7));
+        assert_eq!(parse_integer("+3.5"), Some(3));
+        assert_eq!(parse_integer("0012"), Some(12));
+        assert_eq!(parse_integer(""), None);
+        assert_eq!(parse_integer("px"), None);
+        assert_eq!(parse_integer("- 1"), None);
+        assert_eq!(parse_integer("99999999999999999999"), None);
+    }
+
+    #[test]
+    fn test_parse_non_negative_integer() {
+        assert_eq!(parse_non_negative_integer("100"), Some(100));
+        assert_eq!(parse_non_negative_integer("100%"), Some(100));
+        assert_eq!(parse_non_negative_integer("-0"), Some(0));
+        assert_eq!(parse_non_negative_integer("-1"), None);
+        assert_eq!(parse_non_negative_integer("2147483648"), None);
+    }
 }
@@ -0,0 +1,137 @@
+//! https://encoding.spec.whatwg.org/#legacy-multi-byte-japanese-encodings
+
+use crate::renderer::html::encoding::jis0208::JIS0208;
+use crate::renderer::html::encoding::jis0212::JIS0212;
+use alloc::string::String;
+
+fn lookup(table: &[u16], pointer: usize) -> Option<char> {
+    match table.get(pointer) {
+        Some(0) | None => None,
+        Some(code) => char::from_u32(*code as u32),
+    }
+}
+
+/// Decodes `bytes` as Shift_JIS and returns the number of bytes consumed.
+/// Unless `last` is true, a lead byte at the end is left for the next call.
+/// https://encoding.spec.whatwg.org/#shift_jis-decoder
+pub fn decode_shift_jis(bytes: &[u8], last: bool, output: &mut String) -> usize {
+    let mut i = 0;
+    while i < bytes.len() {
+        let lead = bytes[i];
+        match lead {
+            0x00..=0x80 => {
+                output.push(lead as char);
+                i += 1;
+            }
+            0xA1..=0xDF => {
+                output.push(char::from_u32(0xFF61 + (lead - 0xA1) as u32).unwrap_or('\u{FFFD}'));
+                i += 1;
+            }
+            0x81..=0x9F | 0xE0..=0xFC => {
+                let trail = match bytes.get(i + 1) {
+                    Some(trail) => *trail,
+                    None if last => {
+                        output.push('\u{FFFD}');
+                        return bytes.len();
+                    }
+                    None => return i,
+                };
+
+                let offset = if trail < 0x7F { 0x40 } else { 0x41 };
+                let lead_offset = if lead < 0xA0 { 0x81 } else { 0xC1 };
+                let pointer = match trail {
+                    0x40..=0x7E | 0x80..=0xFC => {
+                        Some((lead - lead_offset) as usize * 188 + (trail - offset) as usize)
+                    }
+                    _ => None,
+                };
+
+                let c = match pointer {
+                    // The user-defined area maps to the Private Use Area.
+                    Some(p @ 8836..=10715) => char::from_u32(0xE000 + (p - 8836) as u32),
+                    Some(p) => lookup(&JIS0208, p),
+                    None => None,
+                };
+
+                match c {
+                    Some(c) => {
+                        output.push(c);
+                        i += 2;
+                    }
+                    None => {
+                        output.push('\u{FFFD}');
+                        // An ASCII trail byte is decoded again by itself.
+                        i += if trail.is_ascii() { 1 } else { 2 };
+                    }
+                }
+            }
+            _ => {
+                output.push('\u{FFFD}');
+                i += 1;
+            }
+        }
+    }
+    i
+}
+
+/// Decodes `bytes` as EUC-JP and returns the number of bytes consumed. Unless
+/// `last` is true, an incomplete sequence at the end is left for the next
+/// call.
+/// https://encoding.spec.whatwg.org/#euc-jp-decoder
+pub fn decode_euc_jp(bytes: &[u8], last: bool, output: &mut String) -> usize {
+    let mut i = 0;
+    while i < bytes.len() {
+        let lead = bytes[i];
+        let len = match lead {
+            0x00..=0x7F => 1,
+            // A JIS X 0212 character is prefixed with 0x8F.
+            0x8F => match bytes.get(i + 1) {
+                Some(b) if !(0xA1..=0xFE).contains(b) => 2,
+                _ => 3,
+            },
+            0x8E | 0xA1..=0xFE => 2,
+            _ => {
+                output.push('\u{FFFD}');
+                i += 1;
+                continue;
+            }
+        };
+
+        if i + len > bytes.len() {
+            if last {
+                output.push('\u{FFFD}');
+                return bytes.len();
+            }
+            return i;
+        }
+
+        let sequence = &bytes[i..i + len];
+        let trail = sequence[len - 1];
+        let c = match *sequence {
+            [c] => Some(c as char),
+            [0x8E, trail @ 0xA1..=0xDF] => char::from_u32(0xFF61 + (trail - 0xA1) as u32),
+            [0x8F, lead @ 0xA1..=0xFE, trail @ 0xA1..=0xFE] => {
+                lookup(&JIS0212, pointer(lead, trail))
+            }
+            [lead @ 0xA1..=0xFE, trail @ 0xA1..=0xFE] => lookup(&JIS0208, pointer(lead, trail)),
+            _ => None,
+        };
+
+        match c {
+            Some(c) => {
+                output.push(c);
+                i += len;
+            }
+            None => {
+                output.push('\u{FFFD}');
+                // An ASCII trail byte is decoded again by itself.
+                i += if trail.is_ascii() { len - 1 } else { len };
+            }
+        }
+    }
+    i
+}
+
+fn pointer(lead: u8, trail: u8) -> usize {
+    (lead - 0xA1) as usize * 94 + (trail - 0xA1) as usize
+}
@@ -0,0 +1,427 @@
+//! https://encoding.spec.whatwg.org/
+
+pub mod japanese;
+pub mod jis0208;
+pub mod jis0212;
+pub mod single_byte;
+pub mod sniff;
+
+use alloc::{string::String, vec::Vec};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Encoding {
+    Utf8,
+    Utf16Be,
+    Utf16Le,
+    Windows1252,
+    Iso8859_2,
+    Iso8859_3,
+    Iso8859_4,
+    Iso8859_5,
+    Iso8859_6,
+    Iso8859_7,
+    Iso8859_8,
+    Iso8859_10,
+    Iso8859_13,
+    Iso8859_14,
+    Iso8859_15,
+    Iso8859_16,
+    ShiftJis,
+    EucJp,
+}
+
+impl Encoding {
+    /// Returns the encoding that `label` refers to, or `None` for a label of
+    /// an encoding that isn't supported.
+    /// https://encoding.spec.whatwg.org/#concept-encoding-get
+    pub fn for_label(label: &str) -> Option<Self> {
+        let label = label
+            .trim_matches(|c| matches!(c, '\t' | '\n' | '\x0C' | '\r' | ' '))
+            .to_ascii_lowercase();
+
+        let encoding = match label.as_str() {
+            "unicode-1-1-utf-8" | "unicode11utf8" | "unicode20utf8" | "utf-8" | "utf8"
+            | "x-unicode20utf8" => Encoding::Utf8,
+            "unicodefffe" | "utf-16be" => Encoding::Utf16Be,
+            "csunicode" | "iso-10646-ucs-2" | "ucs-2" | "unicode" | "unicodefeff" | "utf-16"
+            | "utf-16le" => Encoding::Utf16Le,
+            "ansi_x3.4-1968" | "ascii" | "cp1252" | "cp819" | "csisolatin1" | "ibm819"
+            | "iso-8859-1" | "iso-ir-100" | "iso8859-1" | "iso88591" | "iso_8859-1"
+            | "iso_8859-1:1987" | "l1" | "latin1" | "us-ascii" | "windows-1252" | "x-cp1252" => {
+                Encoding::Windows1252
+            }
+            "csisolatin2" | "iso-8859-2" | "iso-ir-101" | "iso8859-2" | "iso88592"
+            | "iso_8859-2" | "iso_8859-2:1987" | "l2" | "latin2" => Encoding::Iso8859_2,
+            "csisolatin3" | "iso-8859-3" | "iso-ir-109" | "iso8859-3" | "iso88593"
+            | "iso_8859-3" | "iso_8859-3:1988" | "l3" | "latin3" => Encoding::Iso8859_3,
+            "csisolatin4" | "iso-8859-4" | "iso-ir-110" | "iso8859-4" | "iso88594"
+            | "iso_8859-4" | "iso_8859-4:1988" | "l4" | "latin4" => Encoding::Iso8859_4,
+            "csisolatincyrillic" | "cyrillic" | "iso-8859-5" | "iso-ir-144" | "iso8859-5"
+            | "iso88595" | "iso_8859-5" | "iso_8859-5:1988" => Encoding::Iso8859_5,
+            "arabic" | "asmo-708" | "csiso88596e" | "csiso88596i" | "csisolatinarabic"
+            | "ecma-114" | "iso-8859-6" | "iso-8859-6-e" | "iso-8859-6-i" | "iso-ir-127"
+            | "iso8859-6" | "iso88596" | "iso_8859-6" | "iso_8859-6:1987" => Encoding::Iso8859_6,
+            "csisolatingreek" | "ecma-118" | "elot_928" | "greek" | "greek8" | "iso-8859-7"
+            | "iso-ir-126" | "iso8859-7" | "iso88597" | "iso_8859-7" | "iso_8859-7:1987"
+            | "sun_eu_greek" => Encoding::Iso8859_7,
+            // ISO-8859-8-I is decoded in the same way as ISO-8859-8.
+            "csiso88598e" | "csisolatinhebrew" | "hebrew" | "iso-8859-8" | "iso-8859-8-e"
+            | "iso-ir-138" | "iso8859-8" | "iso88598" | "iso_8859-8" | "iso_8859-8:1988"
+            | "visual" | "csiso88598i" | "iso-8859-8-i" | "logical" => Encoding::Iso8859_8,
+            "csisolatin6" | "iso-8859-10" | "iso-ir-157" | "iso8859-10" | "iso885910" | "l6"
+            | "latin6" => Encoding::Iso8859_10,
+            "iso-8859-13" | "iso8859-13" | "iso885913" => Encoding::Iso8859_13,
+            "iso-8859-14" | "iso8859-14" | "iso885914" => Encoding::Iso8859_14,
+            "csisolatin9" | "iso-8859-15" | "iso8859-15" | "iso885915" | "iso_8859-15" | "l9" => {
+                Encoding::Iso8859_15
+            }
+            "iso-8859-16" => Encoding::Iso8859_16,
+            "csshiftjis" | "ms932" | "ms_kanji" | "shift-jis" | "shift_jis" | "sjis"
+            | "windows-31j" | "x-sjis" => Encoding::ShiftJis,
+            "cseucpkdfmtjapanese" | "euc-jp" | "x-euc-jp" => Encoding::EucJp,
+            _ => return None,
+        };
+        Some(encoding)
+    }
+
+    /// https://encoding.spec.whatwg.org/#names-and-labels
+    pub fn name(&self) -> &'static str {
+        match self {
+            Encoding::Utf8 => "UTF-8",
+            Encoding::Utf16Be => "UTF-16BE",
+            Encoding::Utf16Le => "UTF-16LE",
+            Encoding::Windows1252 => "windows-1252",
+            Encoding::Iso8859_2 => "ISO-8859-2",
+            Encoding::Iso8859_3 => "ISO-8859-3",
+            Encoding::Iso8859_4 => "ISO-8859-4",
+            Encoding::Iso8859_5 => "ISO-8859-5",
+            Encoding::Iso8859_6 => "ISO-8859-6",
+            Encoding::Iso8859_7 => "ISO-8859-7",
+            Encoding::Iso8859_8 => "ISO-8859-8",
+            Encoding::Iso8859_10 => "ISO-8859-10",
+            Encoding::Iso8859_13 => "ISO-8859-13",
+            Encoding::Iso8859_14 => "ISO-8859-14",
+            Encoding::Iso8859_15 => "ISO-8859-15",
+            Encoding::Iso8859_16 => "ISO-8859-16",
+            Encoding::ShiftJis => "Shift_JIS",
+            Encoding::EucJp => "EUC-JP",
+        }
+    }
+
+    fn is_utf16(&self) -> bool {
+        matches!(self, Encoding::Utf16Be | Encoding::Utf16Le)
+    }
+
+    /// Decodes the whole of `bytes`. A byte order mark overrides the encoding.
+    /// https://encoding.spec.whatwg.org/#decode
+    pub fn decode(&self, bytes: &[u8]) -> String {
+        Decoder::new(*self).decode(bytes, /* last */ true)
+    }
+}
+
+/// Decodes input that arrives in chunks. A sequence of bytes that's split
+/// between chunks is kept until the next chunk completes it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Decoder {
+    encoding: Encoding,
+    /// The bytes at the end of the previous chunk that don't form a complete
+    /// sequence yet.
+    pending: Vec<u8>,
+    /// Whether the input has been checked for a byte order mark.
+    bom_seen: bool,
+}
+
+impl Decoder {
+    pub fn new(encoding: Encoding) -> Self {
+        Self {
+            encoding,
+            pending: Vec::new(),
+            bom_seen: false,
+        }
+    }
+
+    pub fn encoding(&self) -> Encoding {
+        self.encoding
+    }
+
+    /// Decodes the next chunk. `last` is true if no more input follows, and
+    /// then an incomplete sequence at the end is decoded to U+FFFD.
+    pub fn decode(&mut self, bytes: &[u8], last: bool) -> String {
+        self.pending.extend_from_slice(bytes);
+        let input = core::mem::take(&mut self.pending);
+        let mut start = 0;
+
+        if !self.bom_seen {
+            // Wait until it's known whether the input starts with a BOM.
+            if !last && input.len() < 3 && may_be_bom(&input) {
+                self.pending = input;
+                return String::new();
+            }
+
+            if let Some((encoding, len)) = bom(&input) {
+                self.encoding = encoding;
+                start = len;
+            }
+            self.bom_seen = true;
+        }
+
+        let mut output = String::new();
+        let input = &input[start..];
+        let consumed = match self.encoding {
+            Encoding::Utf8 => decode_utf8(input, last, &mut output),
+            Encoding::Utf16Be | Encoding::Utf16Le => {
+                decode_utf16(input, self.encoding, last, &mut output)
+            }
+            Encoding::ShiftJis => japanese::decode_shift_jis(input, last, &mut output),
+            Encoding::EucJp => japanese::decode_euc_jp(input, last, &mut output),
+            _ => {
+                single_byte::decode(single_byte_table(self.encoding), input, &mut output);
+                input.len()
+            }
+        };
+
+        self.pending = input[consumed..].to_vec();
+        output
+    }
+}
+
+/// https://encoding.spec.whatwg.org/#bom-sniff
+pub fn bom(bytes: &[u8]) -> Option<(Encoding, usize)> {
+    match bytes {
+        [0xEF, 0xBB, 0xBF, ..] => Some((Encoding::Utf8, 3)),
+        [0xFE, 0xFF, ..] => Some((Encoding::Utf16Be, 2)),
+        [0xFF, 0xFE, ..] => Some((Encoding::Utf16Le, 2)),
+        _ => None,
+    }
+}
+
+fn may_be_bom(bytes: &[u8]) -> bool {
+    [0xEF, 0xBB, 0xBF].starts_with(bytes)
+        || [0xFE, 0xFF].starts_with(bytes)
+        || [0xFF, 0xFE].starts_with(bytes)
+}
+
+fn single_byte_table(encoding: Encoding) -> &'static [u16; 128] {
+    match encoding {
+        Encoding::Iso8859_2 => &single_byte::ISO_8859_2,
+        Encoding::Iso8859_3 => &single_byte::ISO_8859_3,
+        Encoding::Iso8859_4 => &single_byte::ISO_8859_4,
+        Encoding::Iso8859_5 => &single_byte::ISO_8859_5,
+        Encoding::Iso8859_6 => &single_byte::ISO_8859_6,
+        Encoding::Iso8859_7 => &single_byte::ISO_8859_7,
+        Encoding::Iso8859_8 => &single_byte::ISO_8859_8,
+        Encoding::Iso8859_10 => &single_byte::ISO_8859_10,
+        Encoding::Iso8859_13 => &single_byte::ISO_8859_13,
+        Encoding::Iso8859_14 => &single_byte::ISO_8859_14,
+        Encoding::Iso8859_15 => &single_byte::ISO_8859_15,
+        Encoding::Iso8859_16 => &single_byte::ISO_8859_16,
+        _ => &single_byte::WINDOWS_1252,
+    }
+}
+
+/// Decodes `bytes` as UTF-8 and returns the number of bytes consumed. Each
+/// maximal invalid subsequence is replaced with U+FFFD.
+/// https://encoding.spec.whatwg.org/#utf-8-decoder
+fn decode_utf8(bytes: &[u8], last: bool, output: &mut String) -> usize {
+    let mut rest = bytes;
+    loop {
+        match core::str::from_utf8(rest) {
+            Ok(s) => {
+                output.push_str(s);
+                return bytes.len();
+            }
+            Err(e) => {
+                let (valid, invalid) = rest.split_at(e.valid_up_to());
+                if let Ok(s) = core::str::from_utf8(valid) {
+                    output.push_str(s);
+                }
+
+                match e.error_len() {
+                    Some(len) => {
+                        output.push('\u{FFFD}');
+                        rest = &invalid[len..];
+                    }
+                    None if last => {
+                        output.push('\u{FFFD}');
+                        return bytes.len();
+                    }
+                    // The sequence may be completed by the next chunk.
+                    None => return bytes.len() - invalid.len(),
+                }
+            }
+        }
+    }
+}
+
+/// Decodes `bytes` as UTF-16 and returns the number of bytes consumed.
+/// https://encoding.spec.whatwg.org/#shared-utf-16-decoder
+fn decode_utf16(bytes: &[u8], encoding: Encoding, last: bool, output: &mut String) -> usize {
+    let unit = |i: usize| -> Option<u16> {
+        let pair = [*bytes.get(i)?, *bytes.get(i + 1)?];
+        Some(match encoding {
+            Encoding::Utf16Be => u16::from_be_bytes(pair),
+            _ => u16::from_le_bytes(pair),
+        })
+    };
+
+    let mut i = 0;
+    while i < bytes.len() {
+        let first = match unit(i) {
+            Some(first) => first,
+            None if last => {
+                output.push('\u{FFFD}');
+                return bytes.len();
+            }
+            None => return i,
+        };
+
+        if !(0xD800..=0xDFFF).contains(&first) {
+            output.push(char::from_u32(first as u32).unwrap_or('\u{FFFD}'));
+            i += 2;
+            continue;
+        }
+
+        if first >= 0xDC00 {
+            // A low surrogate without a high surrogate.
+            output.push('\u{FFFD}');
+            i += 2;
+            continue;
+        }
+
+        match unit(i + 2) {
+            Some(second @ 0xDC00..=0xDFFF) => {
+                let code = 0x10000 + ((first as u32 - 0xD800) << 10) + (second as u32 - 0xDC00);
+                output.push(char::from_u32(code).unwrap_or('\u{FFFD}'));
+                i += 4;
+            }
+            None if !last => return i,
+            // The unit after an unpaired high surrogate is decoded by itself.
+            _ => {
+                output.push('\u{FFFD}');
+                i += 2;
+            }
+        }
+    }
+    i
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::string::ToString;
+
+    #[test]
+    fn test_for_label() {
+        assert_eq!(Encoding::for_label("utf-8"), Some(Encoding::Utf8));
+        assert_eq!(
+            Encoding::for_label(" Shift_JIS\n"),
+            Some(Encoding::ShiftJis)
+        );
+        assert_eq!(Encoding::for_label("latin1"), Some(Encoding::Windows1252));
+        assert_eq!(Encoding::for_label("x-euc-jp"), Some(Encoding::EucJp));
+        assert_eq!(Encoding::for_label("utf-16"), Some(Encoding::Utf16Le));
+        assert_eq!(Encoding::for_label("foo"), None);
+    }
+
+    #[test]
+    fn test_decode_utf8() {
+        assert_eq!(
+            Encoding::Utf8.decode(b"\xEF\xBB\xBFa\xE3\x81\x82"),
+            "a\u{3042}"
+        );
+        assert_eq!(
+            Encoding::Utf8.decode(b"a\xFFb\xE3\x81"),
+            "a\u{FFFD}b\u{FFFD}"
+        );
+    }
+
+    #[test]
+    fn test_decode_utf16() {
+        assert_eq!(Encoding::Utf16Le.decode(b"a\x00B0"), "a\u{3042}");
+        assert_eq!(Encoding::Utf16Be.decode(b"\xD8\x3D\xDE\x00"), "\u{1F600}");
+        assert_eq!(
+            Encoding::Utf16Be.decode(b"\xD8\x3D\x00a\x00"),
+            "\u{FFFD}a\u{FFFD}"
+        );
+        // The BOM overrides the encoding.
+        assert_eq!(Encoding::Utf8.decode(b"\xFE\xFF\x00a"), "a");
+    }
+
+    #[test]
+    fn test_decode_single_byte() {
+        assert_eq!(
+            Encoding::Windows1252.decode(b"caf\xE9 \x80\x81"),
+            "caf\u{E9} \u{20AC}\u{81}"
+        );
+        assert_eq!(
+            Encoding::Iso8859_2.decode(b"\xA3\xF3d\xBC"),
+            "\u{141}\u{F3}d\u{17A}"
+        );
+        assert_eq!(
+            Encoding::Iso8859_5.decode(b"\xBC\xD8\xE0"),
+            "\u{41C}\u{438}\u{440}"
+        );
+        assert_eq!(Encoding::Iso8859_15.decode(b"\xA4"), "\u{20AC}");
+    }
+
+    #[test]
+    fn test_decode_shift_jis() {
+        assert_eq!(
+            Encoding::ShiftJis.decode(b"\x93\xfa\x96\x7b\x8c\xea \xb6\xc5 \x81\x60"),
+            "\u{65E5}\u{672C}\u{8A9E} \u{FF76}\u{FF85} \u{FF5E}"
+        );
+        // The index has the Windows mappings of these symbols.
+        assert_eq!(
+            Encoding::ShiftJis.decode(b"\x81\x61\x81\x7C\x81\x91\x81\x92\x81\xCA"),
+            "\u{2225}\u{FF0D}\u{FFE0}\u{FFE1}\u{FFE2}"
+        );
+        assert_eq!(
+            Encoding::ShiftJis.decode(b"\x87\x40\xED\x40"),
+            "\u{2460}\u{7E8A}"
+        );
+        assert_eq!(Encoding::ShiftJis.decode(b"\xF0\x40"), "\u{E000}");
+        // An invalid ASCII trail byte is decoded by itself.
+        assert_eq!(
+            Encoding::ShiftJis.decode(b"\x81\x20a\x81"),
+            "\u{FFFD} a\u{FFFD}"
+        );
+    }
+
+    #[test]
+    fn test_decode_euc_jp() {
+        assert_eq!(
+            Encoding::EucJp.decode(b"\xc6\xfc\xcb\xdc\xb8\xec \x8e\xb6 \x8f\xb0\xa1"),
+            "\u{65E5}\u{672C}\u{8A9E} \u{FF76} \u{4E02}"
+        );
+        assert_eq!(
+            Encoding::EucJp.decode(b"\xA1\xC1\xA1\xDD"),
+            "\u{FF5E}\u{FF0D}"
+        );
+        assert_eq!(
+            Encoding::EucJp.decode(b"\xc6a\x8f\xb0"),
+            "\u{FFFD}a\u{FFFD}"
+        );
+    }
+
+    #[test]
+    fn test_decoder_in_chunks() {
+        let bytes = "\u{FEFF}日本語 text ½".as_bytes();
+        for encoding in [Encoding::Utf8, Encoding::Windows1252] {
+            let mut decoder = Decoder::new(encoding);
+            let mut decoded = String::new();
+            for b in bytes {
+                decoded.push_str(&decoder.decode(&[*b], false));
+            }
+            decoded.push_str(&decoder.decode(&[], true));
+            assert_eq!(decoded, "日本語 text ½".to_string());
+        }
+
+        let sjis = b"\x93\xfa\x96\x7b\x8c\xea";
+        let mut decoder = Decoder::new(Encoding::ShiftJis);
+        let mut decoded = String::new();
+        for b in sjis {
+            decoded.push_str(&decoder.decode(&[*b], false));
+        }
+        assert_eq!(decoded, "日本語");
+    }
+}
@@ -0,0 +1,337 @@
+//! https://html.spec.whatwg.org/multipage/parsing.html#determining-the-character-encoding
+
+use crate::renderer::html::encoding::{bom, Encoding};
+use alloc::{string::String, vec::Vec};
+
+/// The number of bytes that the prescan looks at.
+const PRESCAN_LENGTH: usize = 1024;
+
+/// Determines the encoding of an HTML document from its byte order mark, the
+/// charset parameter of its `Content-Type` header, and `<meta>` elements near
+/// its start, in this order.
+/// https://html.spec.whatwg.org/multipage/parsing.html#encoding-sniffing-algorithm
+pub fn sniff(bytes: &[u8], content_type: Option<&str>) -> Encoding {
+    if let Some((encoding, _)) = bom(bytes) {
+        return encoding;
+    }
+
+    if let Some(encoding) = content_type.and_then(charset_from_content_type) {
+        return encoding;
+    }
+
+    let end = bytes.len().min(PRESCAN_LENGTH);
+    if let Some(encoding) = prescan(&bytes[..end]) {
+        return encoding;
+    }
+
+    // Nothing declares the encoding. A document that has non-ASCII bytes and
+    // is valid UTF-8 is most likely UTF-8; otherwise fall back to the legacy
+    // default.
+    match core::str::from_utf8(bytes) {
+        Ok(s) if !s.is_ascii() => Encoding::Utf8,
+        // The input may end in the middle of a character.
+        Err(e) if e.error_len().is_none() && e.valid_up_to() > 0 => Encoding::Utf8,
+        _ => Encoding::Windows1252,
+    }
+}
+
+/// Returns the encoding in the charset parameter of a MIME type such as
+/// `text/html; charset="Shift_JIS"`.
+pub fn charset_from_content_type(content_type: &str) -> Option<Encoding> {
+    for parameter in content_type.split(';').skip(1) {
+        let (name, value) = match parameter.split_once('=') {
+            Some(pair) => pair,
+            None => continue,
+        };
+
+        if name.trim().eq_ignore_ascii_case("charset") {
+            let value = value.trim().trim_matches('"');
+            return Encoding::for_label(value);
+        }
+    }
+    None
+}
+
+fn is_whitespace(b: u8) -> bool {
+    matches!(b, b'\t' | b'\n' | b'\x0C' | b'\r' | b' ')
+}
+
+fn starts_with_ignore_case(bytes: &[u8], s: &[u8]) -> bool {
+    bytes.len() >= s.len() && bytes[..s.len()].eq_ignore_ascii_case(s)
+}
+
+/// Returns the index of the first occurrence of `s` in `bytes` at or after
+/// `from`.
+fn find(bytes: &[u8], from: usize, s: &[u8]) -> Option<usize> {
+    (from..bytes.len()).find(|i| bytes[*i..].starts_with(s))
+}
+
+/// https://html.spec.whatwg.org/multipage/parsing.html#prescan-a-byte-stream-to-determine-its-encoding
+pub fn prescan(bytes: &[u8]) -> Option<Encoding> {
+    let mut position = 0;
+    while position < bytes.len() {
+        let rest = &bytes[position..];
+
+        if rest.starts_with(b"<!--") {
+            position = find(bytes, position + 2, b"-->")? + 3;
+            continue;
+        }
+
+        if starts_with_ignore_case(rest, b"<meta")
+            && rest
+                .get(5)
+                .map_or(false, |b| is_whitespace(*b) || *b == b'/')
+        {
+            position += 6;
+            if let Some(encoding) = prescan_meta(bytes, &mut position)? {
+                return Some(encoding);
+            }
+            continue;
+        }
+
+        let is_tag = match rest {
+            [b'<', b'/', c, ..] | [b'<', c, ..] => c.is_ascii_alphabetic(),
+            _ => false,
+        };
+        if is_tag {
+            position = (position + 1..bytes.len())
+                .find(|i| is_whitespace(bytes[*i]) || bytes[*i] == b'>')?;
+            while get_attribute(bytes, &mut position)?.is_some() {}
+            position += 1;
+            continue;
+        }
+
+        if rest.starts_with(b"<!") || rest.starts_with(b"</") || rest.starts_with(b"<?") {
+            position = find(bytes, position + 2, b">")? + 1;
+            continue;
+        }
+
+        position += 1;
+    }
+    None
+}
+
+/// Processes the attributes of a `<meta>` element. Returns `None` if the input
+/// ends in the middle of it, and `Some(None)` if it doesn't declare a
+/// supported encoding.
+fn prescan_meta(bytes: &[u8], position: &mut usize) -> Option<Option<Encoding>> {
+    let mut names = Vec::new();
+    let mut got_pragma = false;
+    let mut need_pragma = None;
+    let mut charset = None;
+
+    while let Some((name, value)) = get_attribute(bytes, position)? {
+        if names.contains(&name) {
+            continue;
+        }
+
+        match name.as_str() {
+            "http-equiv" => got_pragma |= value == "content-type",
+            "content" if charset.is_none() => {
+                if let Some(encoding) = charset_from_meta_content(&value) {
+                    charset = Some(encoding);
+                    need_pragma = Some(true);
+                }
+            }
+            "charset" => {
+                charset = Encoding::for_label(&value);
+                need_pragma = Some(false);
+            }
+            _ => {}
+        }
+        names.push(name);
+    }
+
+    let encoding = match (need_pragma, charset) {
+        (Some(true), Some(_)) if !got_pragma => None,
+        (Some(_), Some(encoding)) if encoding.is_utf16() => Some(Encoding::Utf8),
+        (Some(_), charset) => charset,
+        (None, _) => None,
+    };
+    Some(encoding)
+}
+
+/// Returns the next attribute with its name and value lowercased, or
+/// `Some(None)` if there are no more attributes. Returns `None` if the input
+/// ends.
+/// https://html.spec.whatwg.org/multipage/parsing.html#concept-get-attributes-when-sniffing
+fn get_attribute(bytes: &[u8], position: &mut usize) -> Option<Option<(String, String)>> {
+    let mut next = || -> Option<u8> {
+        let b = *bytes.get(*position)?;
+        *position += 1;
+        Some(b.to_ascii_lowercase())
+    };
+
+    let mut b = next()?;
+    while is_whitespace(b) || b == b'/' {
+        b = next()?;
+    }
+    if b == b'>' {
+        *position -= 1;
+        return Some(None);
+    }
+
+    let mut name = Vec::new();
+    let mut value = Vec::new();
+    loop {
+        if b == b'=' && !name.is_empty() {
+            break;
+        }
+        if is_whitespace(b) {
+            while is_whitespace(b) {
+                b = next()?;
+            }
+            if b != b'=' {
+                *position -= 1;
+                return Some(Some((to_string(&name), String::new())));
+            }
+            break;
+        }
+        if b == b'/' || b == b'>' {
+            *position -= 1;
+            return Some(Some((to_string(&name), String::new())));
+        }
+        name.push(b);
+        b = next()?;
+    }
+
+    // `b` is the '=' after the name.
+    b = next()?;
+    while is_whitespace(b) {
+        b = next()?;
+    }
+
+    if b == b'"' || b == b'\'' {
+        let quote = b;
+        loop {
+            b = next()?;
+            if b == quote {
+                return Some(Some((to_string(&name), to_string(&value))));
+            }
+            value.push(b);
+        }
+    }
+
+    if b == b'>' {
+        *position -= 1;
+        return Some(Some((to_string(&name), String::new())));
+    }
+
+    loop {
+        value.push(b);
+        b = next()?;
+        if is_whitespace(b) || b == b'>' {
+            *position -= 1;
+            return Some(Some((to_string(&name), to_string(&value))));
+        }
+    }
+}
+
+fn to_string(bytes: &[u8]) -> String {
+    String::from_utf8_lossy(bytes).into_owned()
+}
+
+/// https://html.spec.whatwg.org/multipage/urls-and-fetching.html#algorithm-for-extracting-a-character-encoding-from-a-meta-element
+pub fn charset_from_meta_content(content: &str) -> Option<Encoding> {
+    let bytes = content.as_bytes();
+    let mut position = 0;
+    loop {
+        position = (position..bytes.len())
+            .find(|i| starts_with_ignore_case(&bytes[*i..], b"charset"))?
+            + "charset".len();
+
+        while bytes.get(position).map_or(false, |b| is_whitespace(*b)) {
+            position += 1;
+        }
+        if bytes.get(position) == Some(&b'=') {
+            position += 1;
+            break;
+        }
+    }
+
+    while bytes.get(position).map_or(false, |b| is_whitespace(*b)) {
+        position += 1;
+    }
+
+    let rest = &content[position..];
+    match rest.chars().next()? {
+        quote @ ('"' | '\'') => {
+            let (label, _) = rest[1..].split_once(quote)?;
+            Encoding::for_label(label)
+        }
+        _ => {
+            let end = rest
+                .find(|c: char| c == ';' || c.is_ascii_whitespace())
+                .unwrap_or(rest.len());
+            Encoding::for_label(&rest[..end])
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sniff_bom_and_content_type() {
+        assert_eq!(
+            sniff(b"\xFF\xFE<\x00", Some("text/html; charset=utf-8")),
+            Encoding::Utf16Le
+        );
+        assert_eq!(
+            sniff(
+                b"<meta charset=utf-8>",
+                Some("text/html; charset=\"Shift_JIS\"")
+            ),
+            Encoding::ShiftJis
+        );
+        assert_eq!(
+            sniff(b"<p>", Some("text/html; charset=unknown")),
+            Encoding::Windows1252
+        );
+    }
+
+    #[test]
+    fn test_prescan() {
+        assert_eq!(prescan(b"<meta charset=\"euc-jp\">"), Some(Encoding::EucJp));
+        assert_eq!(
+            prescan(b"<!DOCTYPE html><html><head><META CHARSET=Shift_JIS>"),
+            Some(Encoding::ShiftJis)
+        );
+        assert_eq!(
+            prescan(
+                b"<meta http-equiv=\"Content-Type\" content=\"text/html; charset=iso-8859-2\">"
+            ),
+            Some(Encoding::Iso8859_2)
+        );
+        // The content attribute needs the http-equiv attribute.
+        assert_eq!(prescan(b"<meta content=\"charset=iso-8859-2\">"), None);
+        assert_eq!(prescan(b"<!-- <meta charset=euc-jp> --><p>"), None);
+        assert_eq!(
+            prescan(b"<div title='<meta charset=euc-jp>'><meta charset=utf-16>"),
+            Some(Encoding::Utf8)
+        );
+        assert_eq!(prescan(b"<meta charset=euc-jp"), None);
+    }
+
+    #[test]
+    fn test_sniff_without_declaration() {
+        assert_eq!(sniff(b"<p>abc</p>", None), Encoding::Windows1252);
+        assert_eq!(sniff("<p>日本語</p>".as_bytes(), None), Encoding::Utf8);
+        assert_eq!(sniff(b"<p>caf\xE9</p>", None), Encoding::Windows1252);
+    }
+
+    #[test]
+    fn test_charset_from_meta_content() {
+        assert_eq!(
+            charset_from_meta_content("text/html; charset = 'euc-jp'"),
+            Some(Encoding::EucJp)
+        );
+        assert_eq!(
+            charset_from_meta_content("charsetcharset=sjis;"),
+            Some(Encoding::ShiftJis)
+        );
+        assert_eq!(charset_from_meta_content("charset=\"utf-8"), None);
+        assert_eq!(charset_from_meta_content("text/html"), None);
+    }
+}
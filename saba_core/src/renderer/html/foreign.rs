@@ -0,0 +1,238 @@
+//! Tables and predicates for SVG and MathML content in HTML documents.
+//! https://html.spec.whatwg.org/multipage/parsing.html#parsing-main-inforeign
+
+use crate::renderer::dom::node::{Element, Namespace};
+use crate::renderer::html::attribute::Attribute;
+use alloc::string::String;
+use alloc::vec::Vec;
+
+/// The tokenizer lowercases tag names, so SVG elements whose names have
+/// capital letters get them back from this table.
+/// https://html.spec.whatwg.org/multipage/parsing.html#adjust-svg-attributes
+const SVG_TAG_NAMES: [&str; 37] = [
+    "altGlyph",
+    "altGlyphDef",
+    "altGlyphItem",
+    "animateColor",
+    "animateMotion",
+    "animateTransform",
+    "clipPath",
+    "feBlend",
+    "feColorMatrix",
+    "feComponentTransfer",
+    "feComposite",
+    "feConvolveMatrix",
+    "feDiffuseLighting",
+    "feDisplacementMap",
+    "feDistantLight",
+    "feDropShadow",
+    "feFlood",
+    "feFuncA",
+    "feFuncB",
+    "feFuncG",
+    "feFuncR",
+    "feGaussianBlur",
+    "feImage",
+    "feMerge",
+    "feMergeNode",
+    "feMorphology",
+    "feOffset",
+    "fePointLight",
+    "feSpecularLighting",
+    "feSpotLight",
+    "feTile",
+    "feTurbulence",
+    "foreignObject",
+    "glyphRef",
+    "linearGradient",
+    "radialGradient",
+    "textPath",
+];
+
+/// https://html.spec.whatwg.org/multipage/parsing.html#adjust-svg-attributes
+const SVG_ATTRIBUTE_NAMES: [&str; 58] = [
+    "attributeName",
+    "attributeType",
+    "baseFrequency",
+    "baseProfile",
+    "calcMode",
+    "clipPathUnits",
+    "diffuseConstant",
+    "edgeMode",
+    "filterUnits",
+    "glyphRef",
+    "gradientTransform",
+    "gradientUnits",
+    "kernelMatrix",
+    "kernelUnitLength",
+    "keyPoints",
+    "keySplines",
+    "keyTimes",
+    "lengthAdjust",
+    "limitingConeAngle",
+    "markerHeight",
+    "markerUnits",
+    "markerWidth",
+    "maskContentUnits",
+    "maskUnits",
+    "numOctaves",
+    "pathLength",
+    "patternContentUnits",
+    "patternTransform",
+    "patternUnits",
+    "pointsAtX",
+    "pointsAtY",
+    "pointsAtZ",
+    "preserveAlpha",
+    "preserveAspectRatio",
+    "primitiveUnits",
+    "refX",
+    "refY",
+    "repeatCount",
+    "repeatDur",
+    "requiredExtensions",
+    "requiredFeatures",
+    "specularConstant",
+    "specularExponent",
+    "spreadMethod",
+    "startOffset",
+    "stdDeviation",
+    "stitchTiles",
+    "surfaceScale",
+    "systemLanguage",
+    "tableValues",
+    "targetX",
+    "targetY",
+    "textLength",
+    "viewBox",
+    "viewTarget",
+    "xChannelSelector",
+    "yChannelSelector",
+    "zoomAndPan",
+];
+
+/// Returns the SVG tag name for the lowercased `tag`.
+pub fn adjust_svg_tag_name(tag: &str) -> String {
+    restore_case(&SVG_TAG_NAMES, tag)
+}
+
+/// Restores the case of the attribute names of an element in `namespace`.
+/// https://html.spec.whatwg.org/multipage/parsing.html#adjust-mathml-attributes
+/// https://html.spec.whatwg.org/multipage/parsing.html#adjust-svg-attributes
+pub fn adjust_attributes(attributes: &[Attribute], namespace: Namespace) -> Vec<Attribute> {
+    attributes
+        .iter()
+        .map(|a| {
+            let name = match namespace {
+                Namespace::MathMl if a.name() == "definitionurl" => String::from("definitionURL"),
+                Namespace::Svg => restore_case(&SVG_ATTRIBUTE_NAMES, &a.name()),
+                _ => a.name(),
+            };
+            Attribute::with_value(&name, &a.value())
+        })
+        .collect()
+}
+
+fn restore_case(names: &[&str], lowercase: &str) -> String {
+    match names.iter().find(|n| n.eq_ignore_ascii_case(lowercase)) {
+        Some(name) => String::from(*name),
+        None => String::from(lowercase),
+    }
+}
+
+/// Start tags that close open SVG and MathML elements and go back to HTML.
+/// `<font>` only does with a `color`, `face` or `size` attribute.
+pub fn breaks_out_of_foreign_content(tag: &str, attributes: &[Attribute]) -> bool {
+    match tag {
+        "b" | "big" | "blockquote" | "body" | "br" | "center" | "code" | "dd" | "div" | "dl"
+        | "dt" | "em" | "embed" | "h1" | "h2" | "h3" | "h4" | "h5" | "h6" | "head" | "hr" | "i"
+        | "img" | "li" | "listing" | "menu" | "meta" | "nobr" | "ol" | "p" | "pre" | "ruby"
+        | "s" | "small" | "span" | "strong" | "strike" | "sub" | "sup" | "table" | "tt" | "u"
+        | "ul" | "var" => true,
+        "font" => attributes
+            .iter()
+            .any(|a| matches!(a.name().as_str(), "color" | "face" | "size")),
+        _ => false,
+    }
+}
+
+/// https://html.spec.whatwg.org/multipage/parsing.html#mathml-text-integration-point
+pub fn is_mathml_text_integration_point(e: &Element) -> bool {
+    e.namespace() == Namespace::MathMl
+        && matches!(e.tag_name().as_str(), "mi" | "mo" | "mn" | "ms" | "mtext")
+}
+
+/// https://html.spec.whatwg.org/multipage/parsing.html#html-integration-point
+pub fn is_html_integration_point(e: &Element) -> bool {
+    match e.namespace() {
+        Namespace::MathMl => {
+            e.tag_name() == "annotation-xml"
+                && e.get_attribute("encoding").is_some_and(|encoding| {
+                    encoding.eq_ignore_ascii_case("text/html")
+                        || encoding.eq_ignore_ascii_case("application/xhtml+xml")
+                })
+        }
+        Namespace::Svg => matches!(e.tag_name().as_str(), "foreignObject" | "desc" | "title"),
+        Namespace::Html => false,
+    }
+}
+
+/// SVG and MathML elements that are in the special category, and bound the
+/// default scope.
+/// https://html.spec.whatwg.org/multipage/parsing.html#special
+pub fn is_special_foreign(e: &Element) -> bool {
+    match e.namespace() {
+        Namespace::MathMl => matches!(
+            e.tag_name().as_str(),
+            "mi" | "mo" | "mn" | "ms" | "mtext" | "annotation-xml"
+        ),
+        Namespace::Svg => matches!(e.tag_name().as_str(), "foreignObject" | "desc" | "title"),
+        Namespace::Html => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_adjust_names() {
+        assert_eq!(adjust_svg_tag_name("foreignobject"), "foreignObject");
+        assert_eq!(adjust_svg_tag_name("path"), "path");
+
+        let attributes = [
+            Attribute::with_value("viewbox", "0 0 1 1"),
+            Attribute::with_value("definitionurl", "x"),
+        ];
+        let svg = adjust_attributes(&attributes, Namespace::Svg);
+        assert_eq!(svg[0].name(), "viewBox");
+        assert_eq!(svg[1].name(), "definitionurl");
+        let mathml = adjust_attributes(&attributes, Namespace::MathMl);
+        assert_eq!(mathml[0].name(), "viewbox");
+        assert_eq!(mathml[1].name(), "definitionURL");
+    }
+
+    #[test]
+    fn test_integration_points() {
+        let annotation = |encoding: &str| {
+            Element::new_in_namespace(
+                "annotation-xml",
+                [Attribute::with_value("encoding", encoding)].to_vec(),
+                Namespace::MathMl,
+            )
+        };
+        assert!(is_html_integration_point(&annotation("Text/HTML")));
+        assert!(!is_html_integration_point(&annotation("image/svg+xml")));
+
+        let title = Element::new_in_namespace("title", Vec::new(), Namespace::Svg);
+        assert!(is_html_integration_point(&title));
+        assert!(!is_html_integration_point(&Element::new(
+            "title",
+            Vec::new()
+        )));
+
+        let mi = Element::new_in_namespace("mi", Vec::new(), Namespace::MathMl);
+        assert!(is_mathml_text_integration_point(&mi));
+        assert!(is_special_foreign(&mi));
+    }
+}
@@ -1,2 +1,8 @@
 pub mod attribute;
+pub mod character_reference;
+pub mod encoding;
+pub mod foreign;
+pub mod parse_error;
+pub mod parser;
+pub mod serializer;
 pub mod token;
@@ -0,0 +1,3197 @@
+use crate::renderer::css::cssom::StyleSheet;
+use crate::renderer::css::media::MediaQueryList;
+use crate::renderer::css::parser::{parse_component_values, parse_stylesheet};
+use crate::renderer::dom::node::{
+    detach, insert_before, DocumentType, Element, ElementKind, Namespace, Node, NodeKind,
+    QuirksMode, Window,
+};
+use crate::renderer::dom::token_list::DomTokenList;
+use crate::renderer::html::attribute::Attribute;
+use crate::renderer::html::foreign::{
+    adjust_attributes, adjust_svg_tag_name, breaks_out_of_foreign_content,
+    is_html_integration_point, is_mathml_text_integration_point, is_special_foreign,
+};
+use crate::renderer::html::parse_error::ParseError;
+use crate::renderer::html::token::{HtmlToken, HtmlTokenizer, State};
+use crate::url::Url;
+use alloc::rc::Rc;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::cell::RefCell;
+
+/// https://html.spec.whatwg.org/multipage/parsing.html#the-insertion-mode
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum InsertionMode {
+    Initial,
+    BeforeHtml,
+    BeforeHead,
+    InHead,
+    InHeadNoscript,
+    AfterHead,
+    InBody,
+    Text,
+    InTable,
+    InTableText,
+    InCaption,
+    InColumnGroup,
+    InTableBody,
+    InRow,
+    InCell,
+    InSelect,
+    InSelectInTable,
+    InTemplate,
+    AfterBody,
+    InFrameset,
+    AfterFrameset,
+    AfterAfterBody,
+    AfterAfterFrameset,
+}
+
+/// https://html.spec.whatwg.org/multipage/parsing.html#the-list-of-active-formatting-elements
+#[derive(Debug, Clone)]
+enum ActiveFormattingElement {
+    Marker,
+    Element(Rc<RefCell<Node>>),
+}
+
+impl ActiveFormattingElement {
+    fn is(&self, node: &Rc<RefCell<Node>>) -> bool {
+        match self {
+            ActiveFormattingElement::Element(ref n) => Rc::ptr_eq(n, node),
+            ActiveFormattingElement::Marker => false,
+        }
+    }
+}
+
+/// https://html.spec.whatwg.org/multipage/parsing.html#has-an-element-in-scope
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum Scope {
+    Default,
+    ListItem,
+    Button,
+    Table,
+    Select,
+}
+
+/// Where the new element goes in the adoption agency algorithm's list of
+/// active formatting elements.
+enum Bookmark {
+    Replace(Rc<RefCell<Node>>),
+    InsertAfter(Rc<RefCell<Node>>),
+}
+
+#[derive(Debug, Clone)]
+pub struct HtmlParser {
+    window: Rc<RefCell<Window>>,
+    mode: InsertionMode,
+    /// https://html.spec.whatwg.org/multipage/parsing.html#original-insertion-mode
+    original_insertion_mode: InsertionMode,
+    /// https://html.spec.whatwg.org/multipage/parsing.html#the-stack-of-open-elements
+    stack_of_open_elements: Vec<Rc<RefCell<Node>>>,
+    /// https://html.spec.whatwg.org/multipage/parsing.html#the-list-of-active-formatting-elements
+    active_formatting_elements: Vec<ActiveFormattingElement>,
+    /// https://html.spec.whatwg.org/multipage/parsing.html#stack-of-template-insertion-modes
+    template_insertion_modes: Vec<InsertionMode>,
+    /// https://html.spec.whatwg.org/multipage/parsing.html#head-element-pointer
+    head_element: Option<Rc<RefCell<Node>>>,
+    /// https://html.spec.whatwg.org/multipage/parsing.html#form-element-pointer
+    form_element: Option<Rc<RefCell<Node>>>,
+    /// https://html.spec.whatwg.org/multipage/parsing.html#frameset-ok-flag
+    frameset_ok: bool,
+    /// https://html.spec.whatwg.org/multipage/parsing.html#foster-parent
+    foster_parenting: bool,
+    /// https://html.spec.whatwg.org/multipage/parsing.html#concept-pending-table-char-tokens
+    pending_table_characters: Vec<char>,
+    /// Set after `<pre>`, `<listing>` and `<textarea>`, whose first newline is
+    /// dropped.
+    ignore_next_line_feed: bool,
+    /// https://html.spec.whatwg.org/multipage/parsing.html#concept-frag-parse-context
+    context: Option<Rc<RefCell<Node>>>,
+    stopped: bool,
+    t: HtmlTokenizer,
+}
+
+impl HtmlParser {
+    pub fn new(t: HtmlTokenizer) -> Self {
+        Self {
+            window: Rc::new(RefCell::new(Window::new())),
+            mode: InsertionMode::Initial,
+            original_insertion_mode: InsertionMode::Initial,
+            stack_of_open_elements: Vec::new(),
+            active_formatting_elements: Vec::new(),
+            template_insertion_modes: Vec::new(),
+            head_element: None,
+            form_element: None,
+            frameset_ok: true,
+            foster_parenting: false,
+            pending_table_characters: Vec::new(),
+            ignore_next_line_feed: false,
+            context: None,
+            stopped: false,
+            t,
+        }
+    }
+
+    /// Creates a parser that parses its input as the contents of `context`,
+    /// as `innerHTML` does. `construct_fragment()` returns the parsed nodes.
+    /// https://html.spec.whatwg.org/multipage/parsing.html#html-fragment-parsing-algorithm
+    pub fn new_fragment(t: HtmlTokenizer, context: Rc<RefCell<Node>>) -> Self {
+        let mut parser = Self::new(t);
+
+        // The scripting flag is always disabled, so noscript is parsed as
+        // normal elements.
+        let state = match context.borrow().element_kind() {
+            Some(ElementKind::Title) | Some(ElementKind::Textarea) => State::Rcdata,
+            Some(ElementKind::Style)
+            | Some(ElementKind::Xmp)
+            | Some(ElementKind::Iframe)
+            | Some(ElementKind::Noembed)
+            | Some(ElementKind::Noframes) => State::Rawtext,
+            Some(ElementKind::Script) => State::ScriptData,
+            Some(ElementKind::Plaintext) => State::Plaintext,
+            _ => State::Data,
+        };
+        parser.t.switch_to(state);
+
+        let root = Rc::new(RefCell::new(Node::new(NodeKind::Element(Element::new(
+            "html",
+            Vec::new(),
+        )))));
+        insert_node(&parser.window.borrow().document(), root.clone(), None);
+        parser.stack_of_open_elements.push(root);
+
+        if context.borrow().element_kind() == Some(ElementKind::Template) {
+            parser
+                .template_insertion_modes
+                .push(InsertionMode::InTemplate);
+        }
+        parser.context = Some(context.clone());
+        parser.reset_insertion_mode_appropriately();
+        parser.update_cdata_allowed();
+
+        // The form element pointer is the nearest form that contains the
+        // context element.
+        let mut node = Some(context);
+        while let Some(n) = node {
+            if n.borrow().element_kind() == Some(ElementKind::Form) {
+                parser.form_element = Some(n);
+                break;
+            }
+            node = n.borrow().parent().upgrade();
+        }
+
+        parser
+    }
+
+    /// Sets the URL of the document, which `<link>` URLs are resolved
+    /// against.
+    pub fn set_url(&mut self, url: Url) {
+        self.window.borrow_mut().set_url(url);
+    }
+
+    /// Pushes a chunk of the document to a tokenizer created with
+    /// `HtmlTokenizer::new_streaming()`, and builds as much of the tree as the
+    /// input so far allows.
+    pub fn push_bytes(&mut self, bytes: &[u8]) {
+        self.t.push_bytes(bytes);
+
+        while !self.stopped {
+            match self.t.next() {
+                Some(token) => self.process_next_token(token),
+                None => break,
+            }
+        }
+    }
+
+    /// Consumes all tokens from the tokenizer and returns the window that owns
+    /// the constructed document. For a streaming tokenizer, this marks the end
+    /// of the input.
+    pub fn construct_tree(&mut self) -> Rc<RefCell<Window>> {
+        self.t.finish();
+
+        while !self.stopped {
+            let token = self.t.next().unwrap_or(HtmlToken::Eof);
+            self.process_next_token(token);
+        }
+
+        self.window.clone()
+    }
+
+    fn process_next_token(&mut self, token: HtmlToken) {
+        if self.ignore_next_line_feed {
+            self.ignore_next_line_feed = false;
+            if token == HtmlToken::Char('\n') {
+                return;
+            }
+        }
+
+        self.process_token(token);
+        self.update_cdata_allowed();
+    }
+
+    /// Lets the tokenizer know whether the next token is in foreign content.
+    fn update_cdata_allowed(&mut self) {
+        let allowed = self
+            .adjusted_current_node()
+            .and_then(|n| n.borrow().get_element())
+            .is_some_and(|e| !e.is_html());
+        self.t.set_cdata_allowed(allowed);
+    }
+
+    /// Consumes all tokens from the tokenizer and returns the nodes parsed in
+    /// the context element given to `new_fragment()`. The nodes have no
+    /// parent.
+    pub fn construct_fragment(&mut self) -> Vec<Rc<RefCell<Node>>> {
+        let window = self.construct_tree();
+        let document = window.borrow().document();
+        let root = match document.borrow().first_child() {
+            Some(root) => root,
+            None => return Vec::new(),
+        };
+
+        let children = root.borrow().children();
+        for child in &children {
+            detach(child);
+        }
+        children
+    }
+
+    /// Returns the parse errors that the tokenizer found in the input.
+    pub fn errors(&self) -> Vec<ParseError> {
+        self.t.errors()
+    }
+
+    /// https://html.spec.whatwg.org/multipage/parsing.html#tree-construction-dispatcher
+    fn process_token(&mut self, token: HtmlToken) {
+        let element = match self
+            .adjusted_current_node()
+            .and_then(|n| n.borrow().get_element())
+        {
+            Some(element) => element,
+            None => return self.process_token_in_mode(token),
+        };
+
+        let in_html_content = element.is_html()
+            || match token {
+                HtmlToken::StartTag { ref tag, .. } => {
+                    (is_mathml_text_integration_point(&element)
+                        && tag != "mglyph"
+                        && tag != "malignmark")
+                        || (element.namespace() == Namespace::MathMl
+                            && element.tag_name() == "annotation-xml"
+                            && tag == "svg")
+                        || is_html_integration_point(&element)
+                }
+                HtmlToken::Char(_) => {
+                    is_mathml_text_integration_point(&element)
+                        || is_html_integration_point(&element)
+                }
+                HtmlToken::Eof => true,
+                _ => false,
+            };
+
+        if in_html_content {
+            self.process_token_in_mode(token);
+        } else {
+            self.handle_in_foreign_content(token);
+        }
+    }
+
+    fn process_token_in_mode(&mut self, token: HtmlToken) {
+        match self.mode {
+            InsertionMode::Initial => self.handle_initial(token),
+            InsertionMode::BeforeHtml => self.handle_before_html(token),
+            InsertionMode::BeforeHead => self.handle_before_head(token),
+            InsertionMode::InHead => self.handle_in_head(token),
+            InsertionMode::InHeadNoscript => self.handle_in_head_noscript(token),
+            InsertionMode::AfterHead => self.handle_after_head(token),
+            InsertionMode::InBody => self.handle_in_body(token),
+            InsertionMode::Text => self.handle_text(token),
+            InsertionMode::InTable => self.handle_in_table(token),
+            InsertionMode::InTableText => self.handle_in_table_text(token),
+            InsertionMode::InCaption => self.handle_in_caption(token),
+            InsertionMode::InColumnGroup => self.handle_in_column_group(token),
+            InsertionMode::InTableBody => self.handle_in_table_body(token),
+            InsertionMode::InRow => self.handle_in_row(token),
+            InsertionMode::InCell => self.handle_in_cell(token),
+            InsertionMode::InSelect => self.handle_in_select(token),
+            InsertionMode::InSelectInTable => self.handle_in_select_in_table(token),
+            InsertionMode::InTemplate => self.handle_in_template(token),
+            InsertionMode::AfterBody => self.handle_after_body(token),
+            InsertionMode::InFrameset => self.handle_in_frameset(token),
+            InsertionMode::AfterFrameset => self.handle_after_frameset(token),
+            InsertionMode::AfterAfterBody => self.handle_after_after_body(token),
+            InsertionMode::AfterAfterFrameset => self.handle_after_after_frameset(token),
+        }
+    }
+
+    /// Switches the insertion mode and reprocesses the current token in it.
+    fn reprocess(&mut self, mode: InsertionMode, token: HtmlToken) {
+        self.mode = mode;
+        self.process_token(token);
+    }
+
+    /// https://html.spec.whatwg.org/multipage/parsing.html#the-initial-insertion-mode
+    fn handle_initial(&mut self, token: HtmlToken) {
+        match token {
+            HtmlToken::Char(c) if is_whitespace(c) => {}
+            HtmlToken::Comment(ref data) => {
+                let document = self.window.borrow().document();
+                self.insert_comment(data, Some(document));
+            }
+            HtmlToken::Doctype {
+                ref name,
+                ref public_id,
+                ref system_id,
+                force_quirks,
+            } => {
+                let doctype = DocumentType::new(
+                    name.clone().unwrap_or_default(),
+                    public_id.clone().unwrap_or_default(),
+                    system_id.clone().unwrap_or_default(),
+                );
+                let document = self.window.borrow().document();
+                insert_node(
+                    &document,
+                    Rc::new(RefCell::new(Node::new(NodeKind::DocumentType(doctype)))),
+                    None,
+                );
+
+                let quirks_mode = quirks_mode(
+                    name.as_deref(),
+                    public_id.as_deref(),
+                    system_id.as_deref(),
+                    force_quirks,
+                );
+                self.window.borrow_mut().set_quirks_mode(quirks_mode);
+                self.mode = InsertionMode::BeforeHtml;
+            }
+            _ => {
+                self.window.borrow_mut().set_quirks_mode(QuirksMode::Quirks);
+                self.reprocess(InsertionMode::BeforeHtml, token);
+            }
+        }
+    }
+
+    /// https://html.spec.whatwg.org/multipage/parsing.html#the-before-html-insertion-mode
+    fn handle_before_html(&mut self, token: HtmlToken) {
+        match token {
+            HtmlToken::Char(c) if is_whitespace(c) => {}
+            HtmlToken::Doctype { .. } => {}
+            HtmlToken::Comment(ref data) => {
+                let document = self.window.borrow().document();
+                self.insert_comment(data, Some(document));
+            }
+            HtmlToken::StartTag {
+                ref tag,
+                self_closing: _,
+                ref attributes,
+            } if tag == "html" => {
+                self.insert_element(tag, attributes.to_vec());
+                self.mode = InsertionMode::BeforeHead;
+            }
+            HtmlToken::EndTag { ref tag }
+                if !matches!(tag.as_str(), "head" | "body" | "html" | "br") => {}
+            _ => {
+                self.insert_element("html", Vec::new());
+                self.reprocess(InsertionMode::BeforeHead, token);
+            }
+        }
+    }
+
+    /// https://html.spec.whatwg.org/multipage/parsing.html#the-before-head-insertion-mode
+    fn handle_before_head(&mut self, token: HtmlToken) {
+        match token {
+            HtmlToken::Char(c) if is_whitespace(c) => {}
+            HtmlToken::Comment(ref data) => self.insert_comment(data, None),
+            HtmlToken::Doctype { .. } => {}
+            HtmlToken::StartTag { ref tag, .. } if tag == "html" => {
+                self.handle_in_body(token);
+            }
+            HtmlToken::StartTag {
+                ref tag,
+                self_closing: _,
+                ref attributes,
+            } if tag == "head" => {
+                self.head_element = Some(self.insert_element(tag, attributes.to_vec()));
+                self.mode = InsertionMode::InHead;
+            }
+            HtmlToken::EndTag { ref tag }
+                if !matches!(tag.as_str(), "head" | "body" | "html" | "br") => {}
+            _ => {
+                self.head_element = Some(self.insert_element("head", Vec::new()));
+                self.reprocess(InsertionMode::InHead, token);
+            }
+        }
+    }
+
+    /// https://html.spec.whatwg.org/multipage/parsing.html#parsing-main-inhead
+    fn handle_in_head(&mut self, token: HtmlToken) {
+        match token {
+            HtmlToken::Char(c) if is_whitespace(c) => self.insert_char(c),
+            HtmlToken::Comment(ref data) => self.insert_comment(data, None),
+            HtmlToken::Doctype { .. } => {}
+            HtmlToken::StartTag {
+                ref tag,
+                self_closing: _,
+                ref attributes,
+            } => match tag.as_str() {
+                "html" => self.handle_in_body(token),
+                "base" | "basefont" | "bgsound" | "link" | "meta" => {
+                    let node = self.insert_element(tag, attributes.to_vec());
+                    self.stack_of_open_elements.pop();
+                    if tag == "link" {
+                        self.process_link(&node);
+                    }
+                }
+                "title" => self.parse_text_element(tag, attributes.to_vec(), State::Rcdata),
+                "noframes" | "style" => {
+                    self.parse_text_element(tag, attributes.to_vec(), State::Rawtext);
+                }
+                "script" => {
+                    self.parse_text_element(tag, attributes.to_vec(), State::ScriptData);
+                }
+                // The scripting flag is always disabled.
+                "noscript" => {
+                    self.insert_element(tag, attributes.to_vec());
+                    self.mode = InsertionMode::InHeadNoscript;
+                }
+                "template" => {
+                    self.insert_element(tag, attributes.to_vec());
+                    self.active_formatting_elements
+                        .push(ActiveFormattingElement::Marker);
+                    self.frameset_ok = false;
+                    self.mode = InsertionMode::InTemplate;
+                    self.template_insertion_modes
+                        .push(InsertionMode::InTemplate);
+                }
+                "head" => {}
+                _ => self.pop_head_and_reprocess(token),
+            },
+            HtmlToken::EndTag { ref tag } => match tag.as_str() {
+                "head" => {
+                    self.stack_of_open_elements.pop();
+                    self.mode = InsertionMode::AfterHead;
+                }
+                "body" | "html" | "br" => self.pop_head_and_reprocess(token),
+                "template" => {
+                    if !self.has_template_on_stack() {
+                        return;
+                    }
+                    self.generate_all_implied_end_tags_thoroughly();
+                    self.pop_until(&[ElementKind::Template]);
+                    self.clear_active_formatting_elements_to_last_marker();
+                    self.template_insertion_modes.pop();
+                    self.reset_insertion_mode_appropriately();
+                }
+                _ => {}
+            },
+            _ => self.pop_head_and_reprocess(token),
+        }
+    }
+
+    fn pop_head_and_reprocess(&mut self, token: HtmlToken) {
+        self.stack_of_open_elements.pop();
+        self.reprocess(InsertionMode::AfterHead, token);
+    }
+
+    /// https://html.spec.whatwg.org/multipage/parsing.html#parsing-main-inheadnoscript
+    fn handle_in_head_noscript(&mut self, token: HtmlToken) {
+        match token {
+            HtmlToken::Char(c) if is_whitespace(c) => self.handle_in_head(token),
+            HtmlToken::Comment(_) => self.handle_in_head(token),
+            HtmlToken::Doctype { .. } => {}
+            HtmlToken::StartTag { ref tag, .. } => match tag.as_str() {
+                "html" => self.handle_in_body(token),
+                "basefont" | "bgsound" | "link" | "meta" | "noframes" | "style" => {
+                    self.handle_in_head(token)
+                }
+                "head" | "noscript" => {}
+                _ => self.pop_noscript_and_reprocess(token),
+            },
+            HtmlToken::EndTag { ref tag } => match tag.as_str() {
+                "noscript" => {
+                    self.stack_of_open_elements.pop();
+                    self.mode = InsertionMode::InHead;
+                }
+                "br" => self.pop_noscript_and_reprocess(token),
+                _ => {}
+            },
+            _ => self.pop_noscript_and_reprocess(token),
+        }
+    }
+
+    fn pop_noscript_and_reprocess(&mut self, token: HtmlToken) {
+        self.stack_of_open_elements.pop();
+        self.reprocess(InsertionMode::InHead, token);
+    }
+
+    /// https://html.spec.whatwg.org/multipage/parsing.html#the-after-head-insertion-mode
+    fn handle_after_head(&mut self, token: HtmlToken) {
+        match token {
+            HtmlToken::Char(c) if is_whitespace(c) => self.insert_char(c),
+            HtmlToken::Comment(ref data) => self.insert_comment(data, None),
+            HtmlToken::Doctype { .. } => {}
+            HtmlToken::StartTag {
+                ref tag,
+                self_closing: _,
+                ref attributes,
+            } => match tag.as_str() {
+                "html" => self.handle_in_body(token),
+                "body" => {
+                    self.insert_element(tag, attributes.to_vec());
+                    self.frameset_ok = false;
+                    self.mode = InsertionMode::InBody;
+                }
+                "frameset" => {
+                    self.insert_element(tag, attributes.to_vec());
+                    self.mode = InsertionMode::InFrameset;
+                }
+                "base" | "basefont" | "bgsound" | "link" | "meta" | "noframes" | "script"
+                | "style" | "template" | "title" => {
+                    // The head element is temporarily pushed back so that the
+                    // element is inserted into it.
+                    let head = match self.head_element {
+                        Some(ref head) => head.clone(),
+                        None => return,
+                    };
+                    self.stack_of_open_elements.push(head.clone());
+                    self.handle_in_head(token);
+                    self.remove_from_stack(&head);
+                }
+                "head" => {}
+                _ => self.insert_body_and_reprocess(token),
+            },
+            HtmlToken::EndTag { ref tag } => match tag.as_str() {
+                "template" => self.handle_in_head(token),
+                "body" | "html" | "br" => self.insert_body_and_reprocess(token),
+                _ => {}
+            },
+            _ => self.insert_body_and_reprocess(token),
+        }
+    }
+
+    fn insert_body_and_reprocess(&mut self, token: HtmlToken) {
+        self.insert_element("body", Vec::new());
+        self.reprocess(InsertionMode::InBody, token);
+    }
+
+    /// https://html.spec.whatwg.org/multipage/parsing.html#parsing-main-inbody
+    fn handle_in_body(&mut self, token: HtmlToken) {
+        match token {
+            HtmlToken::Char('\0') => {}
+            HtmlToken::Char(c) => {
+                self.reconstruct_active_formatting_elements();
+                self.insert_char(c);
+                if !is_whitespace(c) {
+                    self.frameset_ok = false;
+                }
+            }
+            HtmlToken::StartTag {
+                ref tag,
+                self_closing: _,
+                ref attributes,
+            } => self.handle_in_body_start_tag(tag, attributes, &token),
+            HtmlToken::EndTag { ref tag } => self.handle_in_body_end_tag(tag, &token),
+            HtmlToken::Eof => {
+                if self.template_insertion_modes.is_empty() {
+                    self.stop_parsing();
+                } else {
+                    self.handle_in_template(token);
+                }
+            }
+            HtmlToken::Comment(ref data) => self.insert_comment(data, None),
+            HtmlToken::Doctype { .. } => {}
+        }
+    }
+
+    fn handle_in_body_start_tag(&mut self, tag: &str, attributes: &[Attribute], token: &HtmlToken) {
+        match tag {
+            "html" => {
+                if self.has_template_on_stack() {
+                    return;
+                }
+                if let Some(html) = self.stack_of_open_elements.first() {
+                    merge_attributes(html, attributes);
+                }
+            }
+            "base" | "basefont" | "bgsound" | "link" | "meta" | "noframes" | "script" | "style"
+            | "template" | "title" => self.handle_in_head(token.clone()),
+            "body" => {
+                if self.stack_of_open_elements.len() == 1 || self.has_template_on_stack() {
+                    return;
+                }
+                if let Some(body) = self.stack_of_open_elements.get(1) {
+                    if body.borrow().element_kind() == Some(ElementKind::Body) {
+                        self.frameset_ok = false;
+                        merge_attributes(body, attributes);
+                    }
+                }
+            }
+            "frameset" => {
+                if !self.frameset_ok || self.stack_of_open_elements.len() == 1 {
+                    return;
+                }
+                let body = match self.stack_of_open_elements.get(1) {
+                    Some(body) if body.borrow().element_kind() == Some(ElementKind::Body) => {
+                        body.clone()
+                    }
+                    _ => return,
+                };
+                detach(&body);
+                self.stack_of_open_elements.truncate(1);
+                self.insert_element(tag, attributes.to_vec());
+                self.mode = InsertionMode::InFrameset;
+            }
+            "address" | "article" | "aside" | "blockquote" | "center" | "details" | "dialog"
+            | "dir" | "div" | "dl" | "fieldset" | "figcaption" | "figure" | "footer" | "header"
+            | "hgroup" | "main" | "menu" | "nav" | "ol" | "p" | "search" | "section"
+            | "summary" | "ul" => {
+                self.close_p_element_in_button_scope();
+                self.insert_element(tag, attributes.to_vec());
+            }
+            "h1" | "h2" | "h3" | "h4" | "h5" | "h6" => {
+                self.close_p_element_in_button_scope();
+                if is_heading(self.current_node_kind()) {
+                    self.stack_of_open_elements.pop();
+                }
+                self.insert_element(tag, attributes.to_vec());
+            }
+            "pre" | "listing" => {
+                self.close_p_element_in_button_scope();
+                self.insert_element(tag, attributes.to_vec());
+                self.ignore_next_line_feed = true;
+                self.frameset_ok = false;
+            }
+            "form" => {
+                let in_template = self.has_template_on_stack();
+                if self.form_element.is_some() && !in_template {
+                    return;
+                }
+                self.close_p_element_in_button_scope();
+                let node = self.insert_element(tag, attributes.to_vec());
+                if !in_template {
+                    self.form_element = Some(node);
+                }
+            }
+            "li" | "dd" | "dt" => {
+                self.frameset_ok = false;
+                let closes: &[ElementKind] = if tag == "li" {
+                    &[ElementKind::Li]
+                } else {
+                    &[ElementKind::Dd, ElementKind::Dt]
+                };
+                for node in self.stack_of_open_elements.clone().iter().rev() {
+                    let element = match node.borrow().get_element() {
+                        Some(element) => element,
+                        None => break,
+                    };
+                    let kind = element.kind();
+                    if element.is_html() && closes.contains(&kind) {
+                        self.generate_implied_end_tags(Some(kind));
+                        self.pop_until(&[kind]);
+                        break;
+                    }
+                    if is_special(&element)
+                        && !matches!(
+                            kind,
+                            ElementKind::Address | ElementKind::Div | ElementKind::P
+                        )
+                    {
+                        break;
+                    }
+                }
+                self.close_p_element_in_button_scope();
+                self.insert_element(tag, attributes.to_vec());
+            }
+            "plaintext" => {
+                self.close_p_element_in_button_scope();
+                self.insert_element(tag, attributes.to_vec());
+                self.t.switch_to(State::Plaintext);
+            }
+            "button" => {
+                if self.has_element_in_scope(&[ElementKind::Button], Scope::Default) {
+                    self.generate_implied_end_tags(None);
+                    self.pop_until(&[ElementKind::Button]);
+                }
+                self.reconstruct_active_formatting_elements();
+                self.insert_element(tag, attributes.to_vec());
+                self.frameset_ok = false;
+            }
+            "a" => {
+                if let Some(a) = self.find_active_formatting_element(ElementKind::A) {
+                    self.adoption_agency(ElementKind::A);
+                    self.remove_from_active_formatting_elements(&a);
+                    self.remove_from_stack(&a);
+                }
+                self.reconstruct_active_formatting_elements();
+                let node = self.insert_element(tag, attributes.to_vec());
+                self.push_active_formatting_element(node);
+            }
+            "b" | "big" | "code" | "em" | "font" | "i" | "s" | "small" | "strike" | "strong"
+            | "tt" | "u" => {
+                self.reconstruct_active_formatting_elements();
+                let node = self.insert_element(tag, attributes.to_vec());
+                self.push_active_formatting_element(node);
+            }
+            "nobr" => {
+                self.reconstruct_active_formatting_elements();
+                if self.has_element_in_scope(&[ElementKind::Nobr], Scope::Default) {
+                    self.adoption_agency(ElementKind::Nobr);
+                    self.reconstruct_active_formatting_elements();
+                }
+                let node = self.insert_element(tag, attributes.to_vec());
+                self.push_active_formatting_element(node);
+            }
+            "applet" | "marquee" | "object" => {
+                self.reconstruct_active_formatting_elements();
+                self.insert_element(tag, attributes.to_vec());
+                self.active_formatting_elements
+                    .push(ActiveFormattingElement::Marker);
+                self.frameset_ok = false;
+            }
+            "table" => {
+                if self.window.borrow().quirks_mode() != QuirksMode::Quirks {
+                    self.close_p_element_in_button_scope();
+                }
+                self.insert_element(tag, attributes.to_vec());
+                self.frameset_ok = false;
+                self.mode = InsertionMode::InTable;
+            }
+            "area" | "br" | "embed" | "img" | "keygen" | "wbr" => {
+                self.reconstruct_active_formatting_elements();
+                self.insert_element(tag, attributes.to_vec());
+                self.stack_of_open_elements.pop();
+                self.frameset_ok = false;
+            }
+            "input" => {
+                self.reconstruct_active_formatting_elements();
+                self.insert_element(tag, attributes.to_vec());
+                self.stack_of_open_elements.pop();
+                if !is_hidden_input(attributes) {
+                    self.frameset_ok = false;
+                }
+            }
+            "param" | "source" | "track" => {
+                self.insert_element(tag, attributes.to_vec());
+                self.stack_of_open_elements.pop();
+            }
+            "hr" => {
+                self.close_p_element_in_button_scope();
+                self.insert_element(tag, attributes.to_vec());
+                self.stack_of_open_elements.pop();
+                self.frameset_ok = false;
+            }
+            "image" => self.handle_in_body_start_tag("img", attributes, token),
+            "textarea" => {
+                self.ignore_next_line_feed = true;
+                self.frameset_ok = false;
+                self.parse_text_element(tag, attributes.to_vec(), State::Rcdata);
+            }
+            "xmp" => {
+                self.close_p_element_in_button_scope();
+                self.reconstruct_active_formatting_elements();
+                self.frameset_ok = false;
+                self.parse_text_element(tag, attributes.to_vec(), State::Rawtext);
+            }
+            "iframe" => {
+                self.frameset_ok = false;
+                self.parse_text_element(tag, attributes.to_vec(), State::Rawtext);
+            }
+            "noembed" => self.parse_text_element(tag, attributes.to_vec(), State::Rawtext),
+            "select" => {
+                self.reconstruct_active_formatting_elements();
+                self.insert_element(tag, attributes.to_vec());
+                self.frameset_ok = false;
+                self.mode = match self.mode {
+                    InsertionMode::InTable
+                    | InsertionMode::InCaption
+                    | InsertionMode::InTableBody
+                    | InsertionMode::InRow
+                    | InsertionMode::InCell => InsertionMode::InSelectInTable,
+                    _ => InsertionMode::InSelect,
+                };
+            }
+            "optgroup" | "option" => {
+                if self.current_node_kind() == Some(ElementKind::Option) {
+                    self.stack_of_open_elements.pop();
+                }
+                self.reconstruct_active_formatting_elements();
+                self.insert_element(tag, attributes.to_vec());
+            }
+            "rb" | "rtc" => {
+                if self.has_element_in_scope(&[ElementKind::Ruby], Scope::Default) {
+                    self.generate_implied_end_tags(None);
+                }
+                self.insert_element(tag, attributes.to_vec());
+            }
+            "rp" | "rt" => {
+                if self.has_element_in_scope(&[ElementKind::Ruby], Scope::Default) {
+                    self.generate_implied_end_tags(Some(ElementKind::Rtc));
+                }
+                self.insert_element(tag, attributes.to_vec());
+            }
+            "math" | "svg" => {
+                self.reconstruct_active_formatting_elements();
+                let namespace = if tag == "math" {
+                    Namespace::MathMl
+                } else {
+                    Namespace::Svg
+                };
+                self.insert_foreign_element(tag, attributes, namespace);
+                if matches!(
+                    token,
+                    HtmlToken::StartTag {
+                        self_closing: true,
+                        ..
+                    }
+                ) {
+                    self.stack_of_open_elements.pop();
+                }
+            }
+            "caption" | "col" | "colgroup" | "frame" | "head" | "tbody" | "td" | "tfoot" | "th"
+            | "thead" | "tr" => {}
+            _ => {
+                self.reconstruct_active_formatting_elements();
+                self.insert_element(tag, attributes.to_vec());
+            }
+        }
+    }
+
+    fn handle_in_body_end_tag(&mut self, tag: &str, token: &HtmlToken) {
+        let kind = ElementKind::from(tag);
+        match tag {
+            "body" => {
+                if self.has_element_in_scope(&[ElementKind::Body], Scope::Default) {
+                    self.mode = InsertionMode::AfterBody;
+                }
+            }
+            "html" => {
+                if self.has_element_in_scope(&[ElementKind::Body], Scope::Default) {
+                    self.reprocess(InsertionMode::AfterBody, token.clone());
+                }
+            }
+            "address" | "article" | "aside" | "blockquote" | "button" | "center" | "details"
+            | "dialog" | "dir" | "div" | "dl" | "fieldset" | "figcaption" | "figure" | "footer"
+            | "header" | "hgroup" | "listing" | "main" | "menu" | "nav" | "ol" | "pre"
+            | "search" | "section" | "summary" | "ul" | "applet" | "marquee" | "object" => {
+                if !self.has_element_in_scope(&[kind], Scope::Default) {
+                    return;
+                }
+                self.generate_implied_end_tags(None);
+                self.pop_until(&[kind]);
+                if matches!(
+                    kind,
+                    ElementKind::Applet | ElementKind::Marquee | ElementKind::Object
+                ) {
+                    self.clear_active_formatting_elements_to_last_marker();
+                }
+            }
+            "template" => self.handle_in_head(token.clone()),
+            "form" if self.has_template_on_stack() => {
+                if !self.has_element_in_scope(&[ElementKind::Form], Scope::Default) {
+                    return;
+                }
+                self.generate_implied_end_tags(None);
+                self.pop_until(&[ElementKind::Form]);
+            }
+            "form" => {
+                let node = match self.form_element.take() {
+                    Some(node) => node,
+                    None => return,
+                };
+                if !self.has_node_in_scope(&node, Scope::Default) {
+                    return;
+                }
+                self.generate_implied_end_tags(None);
+                self.remove_from_stack(&node);
+            }
+            "p" => {
+                if !self.has_element_in_scope(&[ElementKind::P], Scope::Button) {
+                    self.insert_element("p", Vec::new());
+                }
+                self.close_p_element();
+            }
+            "li" => {
+                if !self.has_element_in_scope(&[kind], Scope::ListItem) {
+                    return;
+                }
+                self.generate_implied_end_tags(Some(kind));
+                self.pop_until(&[kind]);
+            }
+            "dd" | "dt" => {
+                if !self.has_element_in_scope(&[kind], Scope::Default) {
+                    return;
+                }
+                self.generate_implied_end_tags(Some(kind));
+                self.pop_until(&[kind]);
+            }
+            "h1" | "h2" | "h3" | "h4" | "h5" | "h6" => {
+                if !self.has_element_in_scope(&HEADINGS, Scope::Default) {
+                    return;
+                }
+                self.generate_implied_end_tags(None);
+                self.pop_until(&HEADINGS);
+            }
+            "a" | "b" | "big" | "code" | "em" | "font" | "i" | "nobr" | "s" | "small"
+            | "strike" | "strong" | "tt" | "u" => {
+                if !self.adoption_agency(kind) {
+                    self.any_other_end_tag(tag);
+                }
+            }
+            "br" => self.handle_in_body_start_tag("br", &[], token),
+            _ => self.any_other_end_tag(tag),
+        }
+    }
+
+    /// https://html.spec.whatwg.org/multipage/parsing.html#parsing-main-incdata
+    fn handle_text(&mut self, token: HtmlToken) {
+        match token {
+            HtmlToken::Char(c) => self.insert_char(c),
+            HtmlToken::Eof => {
+                self.pop_text_element();
+                self.reprocess(self.original_insertion_mode, token);
+            }
+            HtmlToken::EndTag { .. } => {
+                self.pop_text_element();
+                self.mode = self.original_insertion_mode;
+            }
+            _ => {}
+        }
+    }
+
+    /// Pops the element whose text the text insertion mode inserted. A
+    /// `<style>` element in the document adds its style sheet to the window.
+    /// https://html.spec.whatwg.org/multipage/semantics.html#update-a-style-block
+    fn pop_text_element(&mut self) {
+        let node = match self.stack_of_open_elements.pop() {
+            Some(node) => node,
+            None => return,
+        };
+        if node.borrow().element_kind() != Some(ElementKind::Style) || !is_connected(&node) {
+            return;
+        }
+
+        let text: String = node
+            .borrow()
+            .children()
+            .iter()
+            .filter_map(|c| match c.borrow().kind {
+                NodeKind::Text(ref s) => Some(s.clone()),
+                _ => None,
+            })
+            .collect();
+        let mut style_sheet = parse_stylesheet(&text);
+        style_sheet.media = media_attribute(&node);
+        self.window.borrow_mut().add_style_sheet(style_sheet);
+    }
+
+    /// Adds an empty style sheet for `<link rel=stylesheet href>` to the
+    /// window, in tree order among the others. `load_style_sheets()` fetches
+    /// it later. A link before `<body>` blocks rendering until it arrives.
+    /// https://html.spec.whatwg.org/multipage/links.html#link-type-stylesheet
+    fn process_link(&mut self, node: &Rc<RefCell<Node>>) {
+        let element = match node.borrow().get_element() {
+            Some(element) => element,
+            None => return,
+        };
+        let rel = DomTokenList::new(
+            &element
+                .get_attribute("rel")
+                .unwrap_or_default()
+                .to_ascii_lowercase(),
+        );
+        // Alternative style sheets aren't applied unless the user picks one.
+        if !rel.contains("stylesheet") || rel.contains("alternate") || !is_connected(node) {
+            return;
+        }
+        let href = match element.get_attribute("href") {
+            Some(href) if !href.is_empty() => href,
+            _ => return,
+        };
+        let url = match self.window.borrow().base_url() {
+            Some(base_url) => match base_url.join(&href) {
+                Ok(url) => url,
+                Err(_) => return,
+            },
+            None => return,
+        };
+
+        // https://html.spec.whatwg.org/multipage/dom.html#allows-adding-render-blocking-elements
+        let render_blocking = !self.has_body();
+        self.window
+            .borrow_mut()
+            .add_style_sheet(StyleSheet::external(
+                url,
+                media_attribute(node),
+                render_blocking,
+            ));
+    }
+
+    /// Returns whether the document has a body element yet.
+    /// https://html.spec.whatwg.org/multipage/dom.html#the-body-element-2
+    fn has_body(&self) -> bool {
+        let document = self.window.borrow().document();
+        // The doctype and comments can come before the html element.
+        let html = document
+            .borrow()
+            .children()
+            .into_iter()
+            .find(|n| n.borrow().get_element().is_some());
+        let mut children = html.iter().flat_map(|html| html.borrow().children());
+        children.any(|c| {
+            matches!(
+                c.borrow().element_kind(),
+                Some(ElementKind::Body) | Some(ElementKind::Frameset)
+            )
+        })
+    }
+
+    /// https://html.spec.whatwg.org/multipage/parsing.html#parsing-main-intable
+    fn handle_in_table(&mut self, token: HtmlToken) {
+        match token {
+            HtmlToken::Char(_)
+                if matches!(
+                    self.current_node_kind(),
+                    Some(ElementKind::Table)
+                        | Some(ElementKind::Tbody)
+                        | Some(ElementKind::Template)
+                        | Some(ElementKind::Tfoot)
+                        | Some(ElementKind::Thead)
+                        | Some(ElementKind::Tr)
+                ) =>
+            {
+                self.pending_table_characters.clear();
+                self.original_insertion_mode = self.mode;
+                self.reprocess(InsertionMode::InTableText, token);
+            }
+            HtmlToken::StartTag {
+                ref tag,
+                self_closing: _,
+                ref attributes,
+            } => match tag.as_str() {
+                "caption" => {
+                    self.clear_stack_back_to(&[ElementKind::Table]);
+                    self.active_formatting_elements
+                        .push(ActiveFormattingElement::Marker);
+                    self.insert_element(tag, attributes.to_vec());
+                    self.mode = InsertionMode::InCaption;
+                }
+                "colgroup" => {
+                    self.clear_stack_back_to(&[ElementKind::Table]);
+                    self.insert_element(tag, attributes.to_vec());
+                    self.mode = InsertionMode::InColumnGroup;
+                }
+                "col" => {
+                    self.clear_stack_back_to(&[ElementKind::Table]);
+                    self.insert_element("colgroup", Vec::new());
+                    self.reprocess(InsertionMode::InColumnGroup, token);
+                }
+                "tbody" | "tfoot" | "thead" => {
+                    self.clear_stack_back_to(&[ElementKind::Table]);
+                    self.insert_element(tag, attributes.to_vec());
+                    self.mode = InsertionMode::InTableBody;
+                }
+                "td" | "th" | "tr" => {
+                    self.clear_stack_back_to(&[ElementKind::Table]);
+                    self.insert_element("tbody", Vec::new());
+                    self.reprocess(InsertionMode::InTableBody, token);
+                }
+                "table" => {
+                    if !self.has_element_in_scope(&[ElementKind::Table], Scope::Table) {
+                        return;
+                    }
+                    self.pop_until(&[ElementKind::Table]);
+                    self.reset_insertion_mode_appropriately();
+                    self.process_token(token);
+                }
+                "style" | "script" | "template" => self.handle_in_head(token),
+                "input" if is_hidden_input(attributes) => {
+                    self.insert_element(tag, attributes.to_vec());
+                    self.stack_of_open_elements.pop();
+                }
+                "form" => {
+                    if self.form_element.is_some() || self.has_template_on_stack() {
+                        return;
+                    }
+                    self.form_element = Some(self.insert_element(tag, attributes.to_vec()));
+                    self.stack_of_open_elements.pop();
+                }
+                _ => self.foster_parent_in_body(token),
+            },
+            HtmlToken::EndTag { ref tag } => match tag.as_str() {
+                "table" => {
+                    if !self.has_element_in_scope(&[ElementKind::Table], Scope::Table) {
+                        return;
+                    }
+                    self.pop_until(&[ElementKind::Table]);
+                    self.reset_insertion_mode_appropriately();
+                }
+                "template" => self.handle_in_head(token),
+                "body" | "caption" | "col" | "colgroup" | "html" | "tbody" | "td" | "tfoot"
+                | "th" | "thead" | "tr" => {}
+                _ => self.foster_parent_in_body(token),
+            },
+            HtmlToken::Eof => self.handle_in_body(token),
+            HtmlToken::Comment(ref data) => self.insert_comment(data, None),
+            HtmlToken::Doctype { .. } => {}
+            _ => self.foster_parent_in_body(token),
+        }
+    }
+
+    /// Processes the token using the rules for "in body" with foster parenting
+    /// enabled.
+    fn foster_parent_in_body(&mut self, token: HtmlToken) {
+        self.foster_parenting = true;
+        self.handle_in_body(token);
+        self.foster_parenting = false;
+    }
+
+    /// https://html.spec.whatwg.org/multipage/parsing.html#parsing-main-intabletext
+    fn handle_in_table_text(&mut self, token: HtmlToken) {
+        match token {
+            HtmlToken::Char('\0') => {}
+            HtmlToken::Char(c) => self.pending_table_characters.push(c),
+            _ => {
+                let characters = core::mem::take(&mut self.pending_table_characters);
+                if characters.iter().all(|c| is_whitespace(*c)) {
+                    for c in characters {
+                        self.insert_char(c);
+                    }
+                } else {
+                    for c in characters {
+                        self.foster_parent_in_body(HtmlToken::Char(c));
+                    }
+                }
+                self.reprocess(self.original_insertion_mode, token);
+            }
+        }
+    }
+
+    /// https://html.spec.whatwg.org/multipage/parsing.html#parsing-main-incaption
+    fn handle_in_caption(&mut self, token: HtmlToken) {
+        match token {
+            HtmlToken::StartTag { ref tag, .. }
+                if matches!(
+                    tag.as_str(),
+                    "caption"
+                        | "col"
+                        | "colgroup"
+                        | "tbody"
+                        | "td"
+                        | "tfoot"
+                        | "th"
+                        | "thead"
+                        | "tr"
+                ) =>
+            {
+                if self.close_caption() {
+                    self.process_token(token);
+                }
+            }
+            HtmlToken::EndTag { ref tag } => match tag.as_str() {
+                "caption" => {
+                    self.close_caption();
+                }
+                "table" => {
+                    if self.close_caption() {
+                        self.process_token(token);
+                    }
+                }
+                "body" | "col" | "colgroup" | "html" | "tbody" | "td" | "tfoot" | "th"
+                | "thead" | "tr" => {}
+                _ => self.handle_in_body(token),
+            },
+            _ => self.handle_in_body(token),
+        }
+    }
+
+    /// Closes the caption element and switches to "in table". Returns false if
+    /// there was no caption to close.
+    fn close_caption(&mut self) -> bool {
+        if !self.has_element_in_scope(&[ElementKind::Caption], Scope::Table) {
+            return false;
+        }
+        self.generate_implied_end_tags(None);
+        self.pop_until(&[ElementKind::Caption]);
+        self.clear_active_formatting_elements_to_last_marker();
+        self.mode = InsertionMode::InTable;
+        true
+    }
+
+    /// https://html.spec.whatwg.org/multipage/parsing.html#parsing-main-incolgroup
+    fn handle_in_column_group(&mut self, token: HtmlToken) {
+        match token {
+            HtmlToken::Char(c) if is_whitespace(c) => self.insert_char(c),
+            HtmlToken::Comment(ref data) => self.insert_comment(data, None),
+            HtmlToken::Doctype { .. } => {}
+            HtmlToken::StartTag { ref tag, .. } if tag == "html" => self.handle_in_body(token),
+            HtmlToken::StartTag {
+                ref tag,
+                self_closing: _,
+                ref attributes,
+            } if tag == "col" => {
+                self.insert_element(tag, attributes.to_vec());
+                self.stack_of_open_elements.pop();
+            }
+            HtmlToken::EndTag { ref tag } if tag == "colgroup" => {
+                if self.current_node_kind() != Some(ElementKind::Colgroup) {
+                    return;
+                }
+                self.stack_of_open_elements.pop();
+                self.mode = InsertionMode::InTable;
+            }
+            HtmlToken::EndTag { ref tag } if tag == "col" => {}
+            HtmlToken::StartTag { ref tag, .. } | HtmlToken::EndTag { ref tag }
+                if tag == "template" =>
+            {
+                self.handle_in_head(token)
+            }
+            HtmlToken::Eof => self.handle_in_body(token),
+            _ => {
+                if self.current_node_kind() != Some(ElementKind::Colgroup) {
+                    return;
+                }
+                self.stack_of_open_elements.pop();
+                self.reprocess(InsertionMode::InTable, token);
+            }
+        }
+    }
+
+    /// https://html.spec.whatwg.org/multipage/parsing.html#parsing-main-intbody
+    fn handle_in_table_body(&mut self, token: HtmlToken) {
+        match token {
+            HtmlToken::StartTag {
+                ref tag,
+                self_closing: _,
+                ref attributes,
+            } => match tag.as_str() {
+                "tr" => {
+                    self.clear_stack_back_to(&TABLE_SECTIONS);
+                    self.insert_element(tag, attributes.to_vec());
+                    self.mode = InsertionMode::InRow;
+                }
+                "th" | "td" => {
+                    self.clear_stack_back_to(&TABLE_SECTIONS);
+                    self.insert_element("tr", Vec::new());
+                    self.reprocess(InsertionMode::InRow, token);
+                }
+                "caption" | "col" | "colgroup" | "tbody" | "tfoot" | "thead" => {
+                    if self.close_table_section() {
+                        self.process_token(token);
+                    }
+                }
+                _ => self.handle_in_table(token),
+            },
+            HtmlToken::EndTag { ref tag } => match tag.as_str() {
+                "tbody" | "tfoot" | "thead" => {
+                    if !self.has_element_in_scope(&[ElementKind::from(tag.as_str())], Scope::Table)
+                    {
+                        return;
+                    }
+                    self.clear_stack_back_to(&TABLE_SECTIONS);
+                    self.stack_of_open_elements.pop();
+                    self.mode = InsertionMode::InTable;
+                }
+                "table" => {
+                    if self.close_table_section() {
+                        self.process_token(token);
+                    }
+                }
+                "body" | "caption" | "col" | "colgroup" | "html" | "td" | "th" | "tr" => {}
+                _ => self.handle_in_table(token),
+            },
+            _ => self.handle_in_table(token),
+        }
+    }
+
+    /// Closes the current tbody, thead or tfoot element and switches to "in
+    /// table". Returns false if there was no such element to close.
+    fn close_table_section(&mut self) -> bool {
+        if !self.has_element_in_scope(&TABLE_SECTIONS, Scope::Table) {
+            return false;
+        }
+        self.clear_stack_back_to(&TABLE_SECTIONS);
+        self.stack_of_open_elements.pop();
+        self.mode = InsertionMode::InTable;
+        true
+    }
+
+    /// https://html.spec.whatwg.org/multipage/parsing.html#parsing-main-intr
+    fn handle_in_row(&mut self, token: HtmlToken) {
+        match token {
+            HtmlToken::StartTag {
+                ref tag,
+                self_closing: _,
+                ref attributes,
+            } => match tag.as_str() {
+                "th" | "td" => {
+                    self.clear_stack_back_to(&[ElementKind::Tr]);
+                    self.insert_element(tag, attributes.to_vec());
+                    self.mode = InsertionMode::InCell;
+                    self.active_formatting_elements
+                        .push(ActiveFormattingElement::Marker);
+                }
+                "caption" | "col" | "colgroup" | "tbody" | "tfoot" | "thead" | "tr" => {
+                    if self.close_row() {
+                        self.process_token(token);
+                    }
+                }
+                _ => self.handle_in_table(token),
+            },
+            HtmlToken::EndTag { ref tag } => match tag.as_str() {
+                "tr" => {
+                    self.close_row();
+                }
+                "table" => {
+                    if self.close_row() {
+                        self.process_token(token);
+                    }
+                }
+                "tbody" | "tfoot" | "thead" => {
+                    if !self.has_element_in_scope(&[ElementKind::from(tag.as_str())], Scope::Table)
+                    {
+                        return;
+                    }
+                    if self.close_row() {
+                        self.process_token(token);
+                    }
+                }
+                "body" | "caption" | "col" | "colgroup" | "html" | "td" | "th" => {}
+                _ => self.handle_in_table(token),
+            },
+            _ => self.handle_in_table(token),
+        }
+    }
+
+    /// Closes the current tr element and switches to "in table body". Returns
+    /// false if there was no row to close.
+    fn close_row(&mut self) -> bool {
+        if !self.has_element_in_scope(&[ElementKind::Tr], Scope::Table) {
+            return false;
+        }
+        self.clear_stack_back_to(&[ElementKind::Tr]);
+        self.stack_of_open_elements.pop();
+        self.mode = InsertionMode::InTableBody;
+        true
+    }
+
+    /// https://html.spec.whatwg.org/multipage/parsing.html#parsing-main-intd
+    fn handle_in_cell(&mut self, token: HtmlToken) {
+        match token {
+            HtmlToken::StartTag { ref tag, .. }
+                if matches!(
+                    tag.as_str(),
+                    "caption"
+                        | "col"
+                        | "colgroup"
+                        | "tbody"
+                        | "td"
+                        | "tfoot"
+                        | "th"
+                        | "thead"
+                        | "tr"
+                ) =>
+            {
+                if !self.has_element_in_scope(&[ElementKind::Td, ElementKind::Th], Scope::Table) {
+                    return;
+                }
+                self.close_cell();
+                self.process_token(token);
+            }
+            HtmlToken::EndTag { ref tag } => match tag.as_str() {
+                "td" | "th" => {
+                    let kind = ElementKind::from(tag.as_str());
+                    if !self.has_element_in_scope(&[kind], Scope::Table) {
+                        return;
+                    }
+                    self.generate_implied_end_tags(None);
+                    self.pop_until(&[kind]);
+                    self.clear_active_formatting_elements_to_last_marker();
+                    self.mode = InsertionMode::InRow;
+                }
+                "body" | "caption" | "col" | "colgroup" | "html" => {}
+                "table" | "tbody" | "tfoot" | "thead" | "tr" => {
+                    if !self.has_element_in_scope(&[ElementKind::from(tag.as_str())], Scope::Table)
+                    {
+                        return;
+                    }
+                    self.close_cell();
+                    self.process_token(token);
+                }
+                _ => self.handle_in_body(token),
+            },
+            _ => self.handle_in_body(token),
+        }
+    }
+
+    /// https://html.spec.whatwg.org/multipage/parsing.html#close-the-cell
+    fn close_cell(&mut self) {
+        self.generate_implied_end_tags(None);
+        self.pop_until(&[ElementKind::Td, ElementKind::Th]);
+        self.clear_active_formatting_elements_to_last_marker();
+        self.mode = InsertionMode::InRow;
+    }
+
+    /// https://html.spec.whatwg.org/multipage/parsing.html#parsing-main-inselect
+    fn handle_in_select(&mut self, token: HtmlToken) {
+        match token {
+            HtmlToken::Char('\0') => {}
+            HtmlToken::Char(c) => self.insert_char(c),
+            HtmlToken::StartTag {
+                ref tag,
+                self_closing: _,
+                ref attributes,
+            } => match tag.as_str() {
+                "html" => self.handle_in_body(token),
+                "option" => {
+                    if self.current_node_kind() == Some(ElementKind::Option) {
+                        self.stack_of_open_elements.pop();
+                    }
+                    self.insert_element(tag, attributes.to_vec());
+                }
+                "optgroup" | "hr" => {
+                    if self.current_node_kind() == Some(ElementKind::Option) {
+                        self.stack_of_open_elements.pop();
+                    }
+                    if self.current_node_kind() == Some(ElementKind::Optgroup) {
+                        self.stack_of_open_elements.pop();
+                    }
+                    self.insert_element(tag, attributes.to_vec());
+                    if tag == "hr" {
+                        self.stack_of_open_elements.pop();
+                    }
+                }
+                "select" => {
+                    self.close_select();
+                }
+                "input" | "keygen" | "textarea" => {
+                    if self.close_select() {
+                        self.process_token(token);
+                    }
+                }
+                "script" | "template" => self.handle_in_head(token),
+                _ => {}
+            },
+            HtmlToken::EndTag { ref tag } => match tag.as_str() {
+                "template" => self.handle_in_head(token),
+                "optgroup" => {
+                    let len = self.stack_of_open_elements.len();
+                    if self.current_node_kind() == Some(ElementKind::Option)
+                        && len >= 2
+                        && self.stack_of_open_elements[len - 2].borrow().element_kind()
+                            == Some(ElementKind::Optgroup)
+                    {
+                        self.stack_of_open_elements.pop();
+                    }
+                    if self.current_node_kind() == Some(ElementKind::Optgroup) {
+                        self.stack_of_open_elements.pop();
+                    }
+                }
+                "option" => {
+                    if self.current_node_kind() == Some(ElementKind::Option) {
+                        self.stack_of_open_elements.pop();
+                    }
+                }
+                "select" => {
+                    self.close_select();
+                }
+                _ => {}
+            },
+            HtmlToken::Eof => self.handle_in_body(token),
+            HtmlToken::Comment(ref data) => self.insert_comment(data, None),
+            HtmlToken::Doctype { .. } => {}
+        }
+    }
+
+    /// Pops elements up to the select element and resets the insertion mode.
+    /// Returns false if there was no select element in select scope.
+    fn close_select(&mut self) -> bool {
+        if !self.has_element_in_scope(&[ElementKind::Select], Scope::Select) {
+            return false;
+        }
+        self.pop_until(&[ElementKind::Select]);
+        self.reset_insertion_mode_appropriately();
+        true
+    }
+
+    /// https://html.spec.whatwg.org/multipage/parsing.html#parsing-main-inselectintable
+    fn handle_in_select_in_table(&mut self, token: HtmlToken) {
+        let tag = match token {
+            HtmlToken::StartTag { ref tag, .. } | HtmlToken::EndTag { ref tag } => tag.clone(),
+            _ => return self.handle_in_select(token),
+        };
+        if !matches!(
+            tag.as_str(),
+            "caption" | "table" | "tbody" | "tfoot" | "thead" | "tr" | "td" | "th"
+        ) {
+            return self.handle_in_select(token);
+        }
+
+        if let HtmlToken::EndTag { .. } = token {
+            if !self.has_element_in_scope(&[ElementKind::from(tag.as_str())], Scope::Table) {
+                return;
+            }
+        }
+        self.pop_until(&[ElementKind::Select]);
+        self.reset_insertion_mode_appropriately();
+        self.process_token(token);
+    }
+
+    /// https://html.spec.whatwg.org/multipage/parsing.html#parsing-main-intemplate
+    fn handle_in_template(&mut self, token: HtmlToken) {
+        match token {
+            HtmlToken::Char(_) | HtmlToken::Comment(_) | HtmlToken::Doctype { .. } => {
+                self.handle_in_body(token)
+            }
+            HtmlToken::StartTag { ref tag, .. } => match tag.as_str() {
+                "base" | "basefont" | "bgsound" | "link" | "meta" | "noframes" | "script"
+                | "style" | "template" | "title" => self.handle_in_head(token),
+                "caption" | "colgroup" | "tbody" | "tfoot" | "thead" => {
+                    self.switch_template_insertion_mode(InsertionMode::InTable, token)
+                }
+                "col" => self.switch_template_insertion_mode(InsertionMode::InColumnGroup, token),
+                "tr" => self.switch_template_insertion_mode(InsertionMode::InTableBody, token),
+                "td" | "th" => self.switch_template_insertion_mode(InsertionMode::InRow, token),
+                _ => self.switch_template_insertion_mode(InsertionMode::InBody, token),
+            },
+            HtmlToken::EndTag { ref tag } if tag == "template" => self.handle_in_head(token),
+            HtmlToken::EndTag { .. } => {}
+            HtmlToken::Eof => {
+                if !self.has_template_on_stack() {
+                    self.stop_parsing();
+                    return;
+                }
+                self.pop_until(&[ElementKind::Template]);
+                self.clear_active_formatting_elements_to_last_marker();
+                self.template_insertion_modes.pop();
+                self.reset_insertion_mode_appropriately();
+                self.process_token(token);
+            }
+        }
+    }
+
+    /// Replaces the current template insertion mode with `mode`, and
+    /// reprocesses the token in it.
+    fn switch_template_insertion_mode(&mut self, mode: InsertionMode, token: HtmlToken) {
+        self.template_insertion_modes.pop();
+        self.template_insertion_modes.push(mode);
+        self.reprocess(mode, token);
+    }
+
+    /// https://html.spec.whatwg.org/multipage/parsing.html#parsing-main-afterbody
+    fn handle_after_body(&mut self, token: HtmlToken) {
+        match token {
+            HtmlToken::Char(c) if is_whitespace(c) => self.handle_in_body(token),
+            HtmlToken::Comment(ref data) => {
+                let html = self.stack_of_open_elements.first().cloned();
+                self.insert_comment(data, html);
+            }
+            HtmlToken::Doctype { .. } => {}
+            HtmlToken::StartTag { ref tag, .. } if tag == "html" => {
+                self.handle_in_body(token);
+            }
+            HtmlToken::EndTag { ref tag } if tag == "html" => {
+                if self.context.is_none() {
+                    self.mode = InsertionMode::AfterAfterBody;
+                }
+            }
+            HtmlToken::Eof => self.stop_parsing(),
+            _ => self.reprocess(InsertionMode::InBody, token),
+        }
+    }
+
+    /// https://html.spec.whatwg.org/multipage/parsing.html#parsing-main-inframeset
+    fn handle_in_frameset(&mut self, token: HtmlToken) {
+        match token {
+            HtmlToken::Char(c) if is_whitespace(c) => self.insert_char(c),
+            HtmlToken::Comment(ref data) => self.insert_comment(data, None),
+            HtmlToken::StartTag {
+                ref tag,
+                self_closing: _,
+                ref attributes,
+            } => match tag.as_str() {
+                "html" => self.handle_in_body(token),
+                "frameset" => {
+                    self.insert_element(tag, attributes.to_vec());
+                }
+                "frame" => {
+                    self.insert_element(tag, attributes.to_vec());
+                    self.stack_of_open_elements.pop();
+                }
+                "noframes" => self.handle_in_head(token),
+                _ => {}
+            },
+            HtmlToken::EndTag { ref tag } if tag == "frameset" => {
+                if self.current_node_kind() == Some(ElementKind::Html) {
+                    return;
+                }
+                self.stack_of_open_elements.pop();
+                if self.current_node_kind() != Some(ElementKind::Frameset) {
+                    self.mode = InsertionMode::AfterFrameset;
+                }
+            }
+            HtmlToken::Eof => self.stop_parsing(),
+            _ => {}
+        }
+    }
+
+    /// https://html.spec.whatwg.org/multipage/parsing.html#parsing-main-afterframeset
+    fn handle_after_frameset(&mut self, token: HtmlToken) {
+        match token {
+            HtmlToken::Char(c) if is_whitespace(c) => self.insert_char(c),
+            HtmlToken::Comment(ref data) => self.insert_comment(data, None),
+            HtmlToken::StartTag { ref tag, .. } if tag == "html" => self.handle_in_body(token),
+            HtmlToken::StartTag { ref tag, .. } if tag == "noframes" => self.handle_in_head(token),
+            HtmlToken::EndTag { ref tag } if tag == "html" => {
+                self.mode = InsertionMode::AfterAfterFrameset;
+            }
+            HtmlToken::Eof => self.stop_parsing(),
+            _ => {}
+        }
+    }
+
+    /// https://html.spec.whatwg.org/multipage/parsing.html#the-after-after-body-insertion-mode
+    fn handle_after_after_body(&mut self, token: HtmlToken) {
+        match token {
+            HtmlToken::Char(c) if is_whitespace(c) => self.handle_in_body(token),
+            HtmlToken::Doctype { .. } => self.handle_in_body(token),
+            HtmlToken::Comment(ref data) => {
+                let document = self.window.borrow().document();
+                self.insert_comment(data, Some(document));
+            }
+            HtmlToken::StartTag { ref tag, .. } if tag == "html" => {
+                self.handle_in_body(token);
+            }
+            HtmlToken::Eof => self.stop_parsing(),
+            _ => self.reprocess(InsertionMode::InBody, token),
+        }
+    }
+
+    /// https://html.spec.whatwg.org/multipage/parsing.html#the-after-after-frameset-insertion-mode
+    fn handle_after_after_frameset(&mut self, token: HtmlToken) {
+        match token {
+            HtmlToken::Char(c) if is_whitespace(c) => self.handle_in_body(token),
+            HtmlToken::Doctype { .. } => self.handle_in_body(token),
+            HtmlToken::Comment(ref data) => {
+                let document = self.window.borrow().document();
+                self.insert_comment(data, Some(document));
+            }
+            HtmlToken::StartTag { ref tag, .. } if tag == "html" => self.handle_in_body(token),
+            HtmlToken::StartTag { ref tag, .. } if tag == "noframes" => self.handle_in_head(token),
+            HtmlToken::Eof => self.stop_parsing(),
+            _ => {}
+        }
+    }
+
+    /// https://html.spec.whatwg.org/multipage/parsing.html#parsing-main-inforeign
+    fn handle_in_foreign_content(&mut self, token: HtmlToken) {
+        match token {
+            HtmlToken::Char('\0') => self.insert_char('\u{FFFD}'),
+            HtmlToken::Char(c) => {
+                self.insert_char(c);
+                if !is_whitespace(c) {
+                    self.frameset_ok = false;
+                }
+            }
+            HtmlToken::Comment(ref data) => self.insert_comment(data, None),
+            HtmlToken::Doctype { .. } => {}
+            HtmlToken::StartTag {
+                ref tag,
+                self_closing,
+                ref attributes,
+            } => {
+                if breaks_out_of_foreign_content(tag, attributes) {
+                    while let Some(element) = self.current_node().borrow().get_element() {
+                        if element.is_html()
+                            || is_mathml_text_integration_point(&element)
+                            || is_html_integration_point(&element)
+                        {
+                            break;
+                        }
+                        self.stack_of_open_elements.pop();
+                    }
+                    return self.process_token_in_mode(token);
+                }
+
+                let namespace = match self
+                    .adjusted_current_node()
+                    .and_then(|n| n.borrow().get_element())
+                {
+                    Some(element) => element.namespace(),
+                    None => return,
+                };
+                let tag = match namespace {
+                    Namespace::Svg => adjust_svg_tag_name(tag),
+                    _ => tag.clone(),
+                };
+                self.insert_foreign_element(&tag, attributes, namespace);
+                if self_closing {
+                    self.stack_of_open_elements.pop();
+                }
+            }
+            HtmlToken::EndTag { ref tag } => {
+                let mut i = self.stack_of_open_elements.len() - 1;
+                loop {
+                    // The root of a fragment is never popped.
+                    if i == 0 {
+                        return;
+                    }
+                    let matches = self.stack_of_open_elements[i]
+                        .borrow()
+                        .get_element()
+                        .is_some_and(|e| e.tag_name().eq_ignore_ascii_case(tag));
+                    if matches {
+                        self.stack_of_open_elements.truncate(i);
+                        return;
+                    }
+
+                    i -= 1;
+                    let is_html = self.stack_of_open_elements[i]
+                        .borrow()
+                        .get_element()
+                        .is_some_and(|e| e.is_html());
+                    if is_html {
+                        return self.process_token_in_mode(token);
+                    }
+                }
+            }
+            HtmlToken::Eof => self.process_token_in_mode(token),
+        }
+    }
+
+    /// https://html.spec.whatwg.org/multipage/parsing.html#stop-parsing
+    fn stop_parsing(&mut self) {
+        self.stack_of_open_elements.clear();
+        self.active_formatting_elements.clear();
+        self.stopped = true;
+    }
+
+    /// https://html.spec.whatwg.org/multipage/parsing.html#generic-rcdata-element-parsing-algorithm
+    /// https://html.spec.whatwg.org/multipage/parsing.html#generic-raw-text-element-parsing-algorithm
+    fn parse_text_element(&mut self, tag: &str, attributes: Vec<Attribute>, state: State) {
+        self.insert_element(tag, attributes);
+        self.t.switch_to(state);
+        self.original_insertion_mode = self.mode;
+        self.mode = InsertionMode::Text;
+    }
+
+    /// The context element when parsing a fragment and only the root html
+    /// element is open, or the current node otherwise.
+    /// https://html.spec.whatwg.org/multipage/parsing.html#adjusted-current-node
+    fn adjusted_current_node(&self) -> Option<Rc<RefCell<Node>>> {
+        match self.context {
+            Some(ref context) if self.stack_of_open_elements.len() == 1 => Some(context.clone()),
+            _ => self.stack_of_open_elements.last().cloned(),
+        }
+    }
+
+    /// https://html.spec.whatwg.org/multipage/parsing.html#current-node
+    fn current_node(&self) -> Rc<RefCell<Node>> {
+        match self.stack_of_open_elements.last() {
+            Some(n) => n.clone(),
+            None => self.window.borrow().document(),
+        }
+    }
+
+    fn current_node_kind(&self) -> Option<ElementKind> {
+        self.stack_of_open_elements
+            .last()
+            .and_then(|n| n.borrow().element_kind())
+    }
+
+    fn has_template_on_stack(&self) -> bool {
+        self.stack_of_open_elements
+            .iter()
+            .any(|n| n.borrow().element_kind() == Some(ElementKind::Template))
+    }
+
+    fn remove_from_stack(&mut self, node: &Rc<RefCell<Node>>) {
+        self.stack_of_open_elements.retain(|n| !Rc::ptr_eq(n, node));
+    }
+
+    /// Pops elements until one of `kinds` has been popped.
+    fn pop_until(&mut self, kinds: &[ElementKind]) {
+        while let Some(node) = self.stack_of_open_elements.pop() {
+            if let Some(kind) = node.borrow().element_kind() {
+                if kinds.contains(&kind) {
+                    return;
+                }
+            }
+        }
+    }
+
+    /// Pops elements until the current node is one of `kinds` or html.
+    /// https://html.spec.whatwg.org/multipage/parsing.html#clear-the-stack-back-to-a-table-context
+    /// https://html.spec.whatwg.org/multipage/parsing.html#clear-the-stack-back-to-a-table-body-context
+    /// https://html.spec.whatwg.org/multipage/parsing.html#clear-the-stack-back-to-a-table-row-context
+    fn clear_stack_back_to(&mut self, kinds: &[ElementKind]) {
+        while let Some(kind) = self.current_node_kind() {
+            if kinds.contains(&kind) || kind == ElementKind::Template || kind == ElementKind::Html {
+                return;
+            }
+            self.stack_of_open_elements.pop();
+        }
+    }
+
+    /// https://html.spec.whatwg.org/multipage/parsing.html#has-an-element-in-scope
+    fn has_element_in_scope(&self, targets: &[ElementKind], scope: Scope) -> bool {
+        for node in self.stack_of_open_elements.iter().rev() {
+            let element = match node.borrow().get_element() {
+                Some(element) => element,
+                None => continue,
+            };
+            if element.is_html() && targets.contains(&element.kind()) {
+                return true;
+            }
+            if is_scope_boundary(&element, scope) {
+                return false;
+            }
+        }
+        false
+    }
+
+    fn has_node_in_scope(&self, target: &Rc<RefCell<Node>>, scope: Scope) -> bool {
+        for node in self.stack_of_open_elements.iter().rev() {
+            if Rc::ptr_eq(node, target) {
+                return true;
+            }
+            match node.borrow().get_element() {
+                Some(element) if is_scope_boundary(&element, scope) => return false,
+                _ => {}
+            }
+        }
+        false
+    }
+
+    /// https://html.spec.whatwg.org/multipage/parsing.html#generate-implied-end-tags
+    fn generate_implied_end_tags(&mut self, except: Option<ElementKind>) {
+        while let Some(kind) = self.current_node_kind() {
+            if Some(kind) == except || !has_implied_end_tag(kind) {
+                return;
+            }
+            self.stack_of_open_elements.pop();
+        }
+    }
+
+    /// https://html.spec.whatwg.org/multipage/parsing.html#generate-all-implied-end-tags-thoroughly
+    fn generate_all_implied_end_tags_thoroughly(&mut self) {
+        while let Some(kind) = self.current_node_kind() {
+            let thorough = matches!(
+                kind,
+                ElementKind::Caption
+                    | ElementKind::Colgroup
+                    | ElementKind::Tbody
+                    | ElementKind::Td
+                    | ElementKind::Tfoot
+                    | ElementKind::Th
+                    | ElementKind::Thead
+                    | ElementKind::Tr
+            );
+            if !thorough && !has_implied_end_tag(kind) {
+                return;
+            }
+            self.stack_of_open_elements.pop();
+        }
+    }
+
+    /// https://html.spec.whatwg.org/multipage/parsing.html#close-a-p-element
+    fn close_p_element(&mut self) {
+        self.generate_implied_end_tags(Some(ElementKind::P));
+        self.pop_until(&[ElementKind::P]);
+    }
+
+    fn close_p_element_in_button_scope(&mut self) {
+        if self.has_element_in_scope(&[ElementKind::P], Scope::Button) {
+            self.close_p_element();
+        }
+    }
+
+    /// https://html.spec.whatwg.org/multipage/parsing.html#reset-the-insertion-mode-appropriately
+    fn reset_insertion_mode_appropriately(&mut self) {
+        for (i, node) in self.stack_of_open_elements.iter().enumerate().rev() {
+            let last = i == 0;
+            // In the fragment case, the context element takes the place of
+            // the root.
+            let node = match self.context {
+                Some(ref context) if last => context,
+                _ => node,
+            };
+            let kind = match node.borrow().element_kind() {
+                Some(kind) => kind,
+                None => continue,
+            };
+
+            let mode = match kind {
+                ElementKind::Select => {
+                    let in_table = !last
+                        && self.stack_of_open_elements[..i]
+                            .iter()
+                            .rev()
+                            .map(|n| n.borrow().element_kind())
+                            .take_while(|k| *k != Some(ElementKind::Template))
+                            .any(|k| k == Some(ElementKind::Table));
+                    if in_table {
+                        InsertionMode::InSelectInTable
+                    } else {
+                        InsertionMode::InSelect
+                    }
+                }
+                ElementKind::Td | ElementKind::Th if !last => InsertionMode::InCell,
+                ElementKind::Tr => InsertionMode::InRow,
+                ElementKind::Tbody | ElementKind::Thead | ElementKind::Tfoot => {
+                    InsertionMode::InTableBody
+                }
+                ElementKind::Caption => InsertionMode::InCaption,
+                ElementKind::Colgroup => InsertionMode::InColumnGroup,
+                ElementKind::Table => InsertionMode::InTable,
+                ElementKind::Template => match self.template_insertion_modes.last() {
+                    Some(mode) => *mode,
+                    None => InsertionMode::InTemplate,
+                },
+                ElementKind::Head if !last => InsertionMode::InHead,
+                ElementKind::Body => InsertionMode::InBody,
+                ElementKind::Frameset => InsertionMode::InFrameset,
+                ElementKind::Html => match self.head_element {
+                    None => InsertionMode::BeforeHead,
+                    Some(_) => InsertionMode::AfterHead,
+                },
+                _ if last => InsertionMode::InBody,
+                _ => continue,
+            };
+
+            self.mode = mode;
+            return;
+        }
+
+        self.mode = InsertionMode::InBody;
+    }
+
+    fn find_active_formatting_element(&self, kind: ElementKind) -> Option<Rc<RefCell<Node>>> {
+        for entry in self.active_formatting_elements.iter().rev() {
+            match entry {
+                ActiveFormattingElement::Marker => return None,
+                ActiveFormattingElement::Element(ref node) => {
+                    if node.borrow().element_kind() == Some(kind) {
+                        return Some(node.clone());
+                    }
+                }
+            }
+        }
+        None
+    }
+
+    fn active_formatting_element_position(&self, node: &Rc<RefCell<Node>>) -> Option<usize> {
+        self.active_formatting_elements
+            .iter()
+            .position(|e| e.is(node))
+    }
+
+    fn remove_from_active_formatting_elements(&mut self, node: &Rc<RefCell<Node>>) {
+        self.active_formatting_elements.retain(|e| !e.is(node));
+    }
+
+    /// https://html.spec.whatwg.org/multipage/parsing.html#push-onto-the-list-of-active-formatting-elements
+    fn push_active_formatting_element(&mut self, node: Rc<RefCell<Node>>) {
+        let element = node.borrow().get_element();
+
+        // Noah's Ark clause: keep at most three identical elements after the
+        // last marker.
+        let mut identical = Vec::new();
+        for (i, entry) in self.active_formatting_elements.iter().enumerate().rev() {
+            match entry {
+                ActiveFormattingElement::Marker => break,
+                ActiveFormattingElement::Element(ref n) => {
+                    if n.borrow().get_element() == element {
+                        identical.push(i);
+                    }
+                }
+            }
+        }
+        if identical.len() >= 3 {
+            if let Some(earliest) = identical.last() {
+                self.active_formatting_elements.remove(*earliest);
+            }
+        }
+
+        self.active_formatting_elements
+            .push(ActiveFormattingElement::Element(node));
+    }
+
+    /// https://html.spec.whatwg.org/multipage/parsing.html#reconstruct-the-active-formatting-elements
+    fn reconstruct_active_formatting_elements(&mut self) {
+        let needs_reconstruction = |parser: &Self, entry: &ActiveFormattingElement| match entry {
+            ActiveFormattingElement::Marker => false,
+            ActiveFormattingElement::Element(ref node) => !parser
+                .stack_of_open_elements
+                .iter()
+                .any(|n| Rc::ptr_eq(n, node)),
+        };
+
+        let mut i = match self.active_formatting_elements.last() {
+            Some(entry) if needs_reconstruction(self, entry) => {
+                self.active_formatting_elements.len() - 1
+            }
+            _ => return,
+        };
+        while i > 0 && needs_reconstruction(self, &self.active_formatting_elements[i - 1]) {
+            i -= 1;
+        }
+
+        for j in i..self.active_formatting_elements.len() {
+            let element = match self.active_formatting_elements[j] {
+                ActiveFormattingElement::Element(ref node) => node.borrow().get_element(),
+                ActiveFormattingElement::Marker => None,
+            };
+            if let Some(element) = element {
+                let node = self.insert_element(&element.tag_name(), element.attributes());
+                self.active_formatting_elements[j] = ActiveFormattingElement::Element(node);
+            }
+        }
+    }
+
+    /// https://html.spec.whatwg.org/multipage/parsing.html#clear-the-list-of-active-formatting-elements-up-to-the-last-marker
+    fn clear_active_formatting_elements_to_last_marker(&mut self) {
+        while let Some(entry) = self.active_formatting_elements.pop() {
+            if let ActiveFormattingElement::Marker = entry {
+                return;
+            }
+        }
+    }
+
+    /// https://html.spec.whatwg.org/multipage/parsing.html#adoption-agency-algorithm
+    ///
+    /// Returns false when the token should be handled as "any other end tag".
+    fn adoption_agency(&mut self, subject: ElementKind) -> bool {
+        if let Some(current) = self.stack_of_open_elements.last() {
+            if current.borrow().element_kind() == Some(subject)
+                && self.active_formatting_element_position(current).is_none()
+            {
+                self.stack_of_open_elements.pop();
+                return true;
+            }
+        }
+
+        for _ in 0..8 {
+            let formatting_element = match self.find_active_formatting_element(subject) {
+                Some(node) => node,
+                None => return false,
+            };
+
+            let fe_index = match self
+                .stack_of_open_elements
+                .iter()
+                .position(|n| Rc::ptr_eq(n, &formatting_element))
+            {
+                Some(i) => i,
+                None => {
+                    self.remove_from_active_formatting_elements(&formatting_element);
+                    return true;
+                }
+            };
+            if !self.has_node_in_scope(&formatting_element, Scope::Default) {
+                return true;
+            }
+
+            let fb_index = match self.stack_of_open_elements[fe_index + 1..]
+                .iter()
+                .position(|n| n.borrow().get_element().is_some_and(|e| is_special(&e)))
+            {
+                Some(i) => fe_index + 1 + i,
+                None => {
+                    self.stack_of_open_elements.truncate(fe_index);
+                    self.remove_from_active_formatting_elements(&formatting_element);
+                    return true;
+                }
+            };
+            let furthest_block = self.stack_of_open_elements[fb_index].clone();
+            let common_ancestor = self.stack_of_open_elements[fe_index - 1].clone();
+
+            let mut bookmark = Bookmark::Replace(formatting_element.clone());
+            let mut last_node = furthest_block.clone();
+            let mut node_index = fb_index;
+            let mut inner_loop_counter = 0;
+            loop {
+                inner_loop_counter += 1;
+                node_index -= 1;
+                let node = self.stack_of_open_elements[node_index].clone();
+                if Rc::ptr_eq(&node, &formatting_element) {
+                    break;
+                }
+
+                if inner_loop_counter > 3 {
+                    self.remove_from_active_formatting_elements(&node);
+                }
+                let position = match self.active_formatting_element_position(&node) {
+                    Some(position) => position,
+                    None => {
+                        self.stack_of_open_elements.remove(node_index);
+                        continue;
+                    }
+                };
+
+                let new_node = clone_element(&node);
+                self.active_formatting_elements[position] =
+                    ActiveFormattingElement::Element(new_node.clone());
+                self.stack_of_open_elements[node_index] = new_node.clone();
+
+                if Rc::ptr_eq(&last_node, &furthest_block) {
+                    bookmark = Bookmark::InsertAfter(new_node.clone());
+                }
+                insert_node(&new_node, last_node, None);
+                last_node = new_node;
+            }
+
+            let (parent, reference) = self.appropriate_place_for_inserting(Some(common_ancestor));
+            insert_node(&parent, last_node, reference.as_ref());
+
+            let new_element = clone_element(&formatting_element);
+            let children = furthest_block.borrow().children();
+            for child in children {
+                insert_node(&new_element, child, None);
+            }
+            insert_node(&furthest_block, new_element.clone(), None);
+
+            let entry = ActiveFormattingElement::Element(new_element.clone());
+            match bookmark {
+                Bookmark::Replace(ref node) => {
+                    if let Some(i) = self.active_formatting_element_position(node) {
+                        self.active_formatting_elements[i] = entry;
+                    }
+                }
+                Bookmark::InsertAfter(ref node) => {
+                    if let Some(i) = self.active_formatting_element_position(node) {
+                        self.active_formatting_elements.insert(i + 1, entry);
+                    }
+                    self.remove_from_active_formatting_elements(&formatting_element);
+                }
+            }
+
+            self.remove_from_stack(&formatting_element);
+            if let Some(i) = self
+                .stack_of_open_elements
+                .iter()
+                .position(|n| Rc::ptr_eq(n, &furthest_block))
+            {
+                self.stack_of_open_elements.insert(i + 1, new_element);
+            }
+        }
+
+        true
+    }
+
+    /// https://html.spec.whatwg.org/multipage/parsing.html#parsing-main-inbody
+    /// ("Any other end tag")
+    fn any_other_end_tag(&mut self, tag: &str) {
+        for i in (0..self.stack_of_open_elements.len()).rev() {
+            let element = match self.stack_of_open_elements[i].borrow().get_element() {
+                Some(e) => e,
+                None => return,
+            };
+
+            if element.is_html() && element.tag_name() == tag {
+                self.generate_implied_end_tags(Some(element.kind()));
+                self.stack_of_open_elements.truncate(i);
+                return;
+            }
+
+            if is_special(&element) {
+                return;
+            }
+        }
+    }
+
+    /// https://html.spec.whatwg.org/multipage/parsing.html#appropriate-place-for-inserting-a-node
+    ///
+    /// Returns the parent and the child before which the node should be
+    /// inserted, or `None` to append it.
+    fn appropriate_place_for_inserting(
+        &self,
+        override_target: Option<Rc<RefCell<Node>>>,
+    ) -> (Rc<RefCell<Node>>, Option<Rc<RefCell<Node>>>) {
+        let target = override_target.unwrap_or_else(|| self.current_node());
+
+        let target_kind = target.borrow().element_kind();
+        if !self.foster_parenting
+            || !matches!(
+                target_kind,
+                Some(ElementKind::Table)
+                    | Some(ElementKind::Tbody)
+                    | Some(ElementKind::Tfoot)
+                    | Some(ElementKind::Thead)
+                    | Some(ElementKind::Tr)
+            )
+        {
+            return (inside_template_contents(target), None);
+        }
+
+        let position = |kind: ElementKind| {
+            self.stack_of_open_elements
+                .iter()
+                .rposition(|n| n.borrow().element_kind() == Some(kind))
+        };
+        let template_index = position(ElementKind::Template);
+        let table_index = position(ElementKind::Table);
+        if let Some(t) = template_index {
+            if table_index.map_or(true, |table| t > table) {
+                let template = self.stack_of_open_elements[t].clone();
+                return (inside_template_contents(template), None);
+            }
+        }
+        let table_index = match table_index {
+            Some(i) => i,
+            None => return (self.stack_of_open_elements[0].clone(), None),
+        };
+
+        let table = self.stack_of_open_elements[table_index].clone();
+        let parent = table.borrow().parent().upgrade();
+        match parent {
+            Some(parent) => (parent, Some(table)),
+            None => (
+                inside_template_contents(self.stack_of_open_elements[table_index - 1].clone()),
+                None,
+            ),
+        }
+    }
+
+    /// https://html.spec.whatwg.org/multipage/parsing.html#insert-an-html-element
+    fn insert_element(&mut self, tag: &str, attributes: Vec<Attribute>) -> Rc<RefCell<Node>> {
+        let node = Rc::new(RefCell::new(Node::new(NodeKind::Element(Element::new(
+            tag, attributes,
+        )))));
+
+        let (parent, reference) = self.appropriate_place_for_inserting(None);
+        insert_node(&parent, node.clone(), reference.as_ref());
+        self.stack_of_open_elements.push(node.clone());
+
+        node
+    }
+
+    /// https://html.spec.whatwg.org/multipage/parsing.html#insert-a-foreign-element
+    fn insert_foreign_element(
+        &mut self,
+        tag: &str,
+        attributes: &[Attribute],
+        namespace: Namespace,
+    ) -> Rc<RefCell<Node>> {
+        let attributes = adjust_attributes(attributes, namespace);
+        let node = Rc::new(RefCell::new(Node::new(NodeKind::Element(
+            Element::new_in_namespace(tag, attributes, namespace),
+        ))));
+
+        let (parent, reference) = self.appropriate_place_for_inserting(None);
+        insert_node(&parent, node.clone(), reference.as_ref());
+        self.stack_of_open_elements.push(node.clone());
+
+        node
+    }
+
+    /// https://html.spec.whatwg.org/multipage/parsing.html#insert-a-character
+    fn insert_char(&mut self, c: char) {
+        let (parent, reference) = self.appropriate_place_for_inserting(None);
+
+        // The document can't have text children.
+        if parent.borrow().kind == NodeKind::Document {
+            return;
+        }
+
+        let previous = match reference {
+            Some(ref r) => r.borrow().previous_sibling().upgrade(),
+            None => parent.borrow().last_child().upgrade(),
+        };
+        if let Some(previous) = previous {
+            if let NodeKind::Text(ref mut s) = previous.borrow_mut().kind {
+                s.push(c);
+                return;
+            }
+        }
+
+        let mut s = String::new();
+        s.push(c);
+        let text = Rc::new(RefCell::new(Node::new(NodeKind::Text(s))));
+        insert_node(&parent, text, reference.as_ref());
+    }
+
+    /// https://html.spec.whatwg.org/multipage/parsing.html#insert-a-comment
+    ///
+    /// The comment is appended to `parent` if it's given, or inserted at the
+    /// appropriate place otherwise.
+    fn insert_comment(&mut self, data: &str, parent: Option<Rc<RefCell<Node>>>) {
+        let comment = Rc::new(RefCell::new(Node::new(NodeKind::Comment(String::from(
+            data,
+        )))));
+
+        match parent {
+            Some(parent) => insert_node(&parent, comment, None),
+            None => {
+                let (parent, reference) = self.appropriate_place_for_inserting(None);
+                insert_node(&parent, comment, reference.as_ref());
+            }
+        }
+    }
+}
+
+const HEADINGS: [ElementKind; 6] = [
+    ElementKind::H1,
+    ElementKind::H2,
+    ElementKind::H3,
+    ElementKind::H4,
+    ElementKind::H5,
+    ElementKind::H6,
+];
+
+const TABLE_SECTIONS: [ElementKind; 3] =
+    [ElementKind::Tbody, ElementKind::Thead, ElementKind::Tfoot];
+
+fn is_heading(kind: Option<ElementKind>) -> bool {
+    match kind {
+        Some(kind) => HEADINGS.contains(&kind),
+        None => false,
+    }
+}
+
+/// Parses the `media` attribute of `<link>` or `<style>`. Without one, the
+/// style sheet applies to all media.
+fn media_attribute(node: &Rc<RefCell<Node>>) -> MediaQueryList {
+    let media = node
+        .borrow()
+        .get_element()
+        .and_then(|e| e.get_attribute("media"));
+    match media {
+        Some(media) => MediaQueryList::parse(&parse_component_values(&media)),
+        None => MediaQueryList::default(),
+    }
+}
+
+/// Returns true if `node` is in a document, which isn't the case for nodes in
+/// template contents.
+/// https://dom.spec.whatwg.org/#connected
+fn is_connected(node: &Rc<RefCell<Node>>) -> bool {
+    let mut node = node.clone();
+    loop {
+        let parent = node.borrow().parent().upgrade();
+        match parent {
+            Some(parent) => node = parent,
+            None => return node.borrow().kind == NodeKind::Document,
+        }
+    }
+}
+
+/// https://html.spec.whatwg.org/multipage/parsing.html#special
+fn is_special(element: &Element) -> bool {
+    if !element.is_html() {
+        return is_special_foreign(element);
+    }
+
+    matches!(
+        element.kind(),
+        ElementKind::Address
+            | ElementKind::Applet
+            | ElementKind::Area
+            | ElementKind::Article
+            | ElementKind::Aside
+            | ElementKind::Base
+            | ElementKind::Basefont
+            | ElementKind::Bgsound
+            | ElementKind::Blockquote
+            | ElementKind::Body
+            | ElementKind::Br
+            | ElementKind::Button
+            | ElementKind::Caption
+            | ElementKind::Center
+            | ElementKind::Col
+            | ElementKind::Colgroup
+            | ElementKind::Dd
+            | ElementKind::Details
+            | ElementKind::Dir
+            | ElementKind::Div
+            | ElementKind::Dl
+            | ElementKind::Dt
+            | ElementKind::Embed
+            | ElementKind::Fieldset
+            | ElementKind::Figcaption
+            | ElementKind::Figure
+            | ElementKind::Footer
+            | ElementKind::Form
+            | ElementKind::Frame
+            | ElementKind::Frameset
+            | ElementKind::H1
+            | ElementKind::H2
+            | ElementKind::H3
+            | ElementKind::H4
+            | ElementKind::H5
+            | ElementKind::H6
+            | ElementKind::Head
+            | ElementKind::Header
+            | ElementKind::Hgroup
+            | ElementKind::Hr
+            | ElementKind::Html
+            | ElementKind::Iframe
+            | ElementKind::Img
+            | ElementKind::Input
+            | ElementKind::Keygen
+            | ElementKind::Li
+            | ElementKind::Link
+            | ElementKind::Listing
+            | ElementKind::Main
+            | ElementKind::Marquee
+            | ElementKind::Menu
+            | ElementKind::Meta
+            | ElementKind::Nav
+            | ElementKind::Noembed
+            | ElementKind::Noframes
+            | ElementKind::Noscript
+            | ElementKind::Object
+            | ElementKind::Ol
+            | ElementKind::P
+            | ElementKind::Param
+            | ElementKind::Plaintext
+            | ElementKind::Pre
+            | ElementKind::Script
+            | ElementKind::Search
+            | ElementKind::Section
+            | ElementKind::Select
+            | ElementKind::Source
+            | ElementKind::Style
+            | ElementKind::Summary
+            | ElementKind::Table
+            | ElementKind::Tbody
+            | ElementKind::Td
+            | ElementKind::Template
+            | ElementKind::Textarea
+            | ElementKind::Tfoot
+            | ElementKind::Th
+            | ElementKind::Thead
+            | ElementKind::Title
+            | ElementKind::Tr
+            | ElementKind::Track
+            | ElementKind::Ul
+            | ElementKind::Wbr
+            | ElementKind::Xmp
+    )
+}
+
+fn is_scope_boundary(element: &Element, scope: Scope) -> bool {
+    if !element.is_html() {
+        // The MathML and SVG elements that HTML can be nested in bound all
+        // but the table scope, and any foreign element bounds the select
+        // scope.
+        return match scope {
+            Scope::Default | Scope::ListItem | Scope::Button => is_special_foreign(element),
+            Scope::Table => false,
+            Scope::Select => true,
+        };
+    }
+
+    let kind = element.kind();
+    let default = matches!(
+        kind,
+        ElementKind::Applet
+            | ElementKind::Caption
+            | ElementKind::Html
+            | ElementKind::Table
+            | ElementKind::Td
+            | ElementKind::Th
+            | ElementKind::Marquee
+            | ElementKind::Object
+            | ElementKind::Template
+    );
+
+    match scope {
+        Scope::Default => default,
+        Scope::ListItem => default || matches!(kind, ElementKind::Ol | ElementKind::Ul),
+        Scope::Button => default || kind == ElementKind::Button,
+        Scope::Table => matches!(
+            kind,
+            ElementKind::Html | ElementKind::Table | ElementKind::Template
+        ),
+        Scope::Select => !matches!(kind, ElementKind::Optgroup | ElementKind::Option),
+    }
+}
+
+/// https://html.spec.whatwg.org/multipage/parsing.html#generate-implied-end-tags
+fn has_implied_end_tag(kind: ElementKind) -> bool {
+    matches!(
+        kind,
+        ElementKind::Dd
+            | ElementKind::Dt
+            | ElementKind::Li
+            | ElementKind::Optgroup
+            | ElementKind::Option
+            | ElementKind::P
+            | ElementKind::Rb
+            | ElementKind::Rp
+            | ElementKind::Rt
+            | ElementKind::Rtc
+    )
+}
+
+/// Returns the contents of `node` if it's a template element, where nodes are
+/// inserted instead of the template itself.
+fn inside_template_contents(node: Rc<RefCell<Node>>) -> Rc<RefCell<Node>> {
+    let contents = node.borrow().template_contents();
+    contents.unwrap_or(node)
+}
+
+fn is_hidden_input(attributes: &[Attribute]) -> bool {
+    attributes
+        .iter()
+        .any(|a| a.name() == "type" && a.value().eq_ignore_ascii_case("hidden"))
+}
+
+/// Inserts `node` into `parent` before `reference`. The tree builder only
+/// inserts nodes where they fit, so a rejected insertion is a bug in it,
+/// which fails in debug builds.
+/// https://html.spec.whatwg.org/multipage/parsing.html#insert-a-foreign-element
+fn insert_node(
+    parent: &Rc<RefCell<Node>>,
+    node: Rc<RefCell<Node>>,
+    reference: Option<&Rc<RefCell<Node>>>,
+) {
+    let result = insert_before(parent, node, reference);
+    debug_assert!(result.is_ok(), "failed to insert a node: {:?}", result);
+}
+
+/// Creates a new element with the same tag name and attributes as `node`.
+fn clone_element(node: &Rc<RefCell<Node>>) -> Rc<RefCell<Node>> {
+    let kind = match node.borrow().get_element() {
+        Some(e) => NodeKind::Element(Element::new(&e.tag_name(), e.attributes())),
+        None => node.borrow().kind(),
+    };
+    Rc::new(RefCell::new(Node::new(kind)))
+}
+
+fn merge_attributes(node: &Rc<RefCell<Node>>, attributes: &[Attribute]) {
+    if let NodeKind::Element(ref mut e) = node.borrow_mut().kind {
+        for attribute in attributes {
+            e.add_attribute_if_missing(attribute.clone());
+        }
+    }
+}
+
+/// Public identifiers that put the document into quirks mode when the DOCTYPE's
+/// public identifier starts with them.
+const QUIRKS_PUBLIC_ID_PREFIXES: [&str; 55] = [
+    "+//silmaril//dtd html pro v0r11 19970101//",
+    "-//as//dtd html 3.0 aswedit + extensions//",
+    "-//advasoft ltd//dtd html 3.0 aswedit + extensions//",
+    "-//ietf//dtd html 2.0 level 1//",
+    "-//ietf//dtd html 2.0 level 2//",
+    "-//ietf//dtd html 2.0 strict level 1//",
+    "-//ietf//dtd html 2.0 strict level 2//",
+    "-//ietf//dtd html 2.0 strict//",
+    "-//ietf//dtd html 2.0//",
+    "-//ietf//dtd html 2.1e//",
+    "-//ietf//dtd html 3.0//",
+    "-//ietf//dtd html 3.2 final//",
+    "-//ietf//dtd html 3.2//",
+    "-//ietf//dtd html 3//",
+    "-//ietf//dtd html level 0//",
+    "-//ietf//dtd html level 1//",
+    "-//ietf//dtd html level 2//",
+    "-//ietf//dtd html level 3//",
+    "-//ietf//dtd html strict level 0//",
+    "-//ietf//dtd html strict level 1//",
+    "-//ietf//dtd html strict level 2//",
+    "-//ietf//dtd html strict level 3//",
+    "-//ietf//dtd html strict//",
+    "-//ietf//dtd html//",
+    "-//metrius//dtd metrius presentational//",
+    "-//microsoft//dtd internet explorer 2.0 html strict//",
+    "-//microsoft//dtd internet explorer 2.0 html//",
+    "-//microsoft//dtd internet explorer 2.0 tables//",
+    "-//microsoft//dtd internet explorer 3.0 html strict//",
+    "-//microsoft//dtd internet explorer 3.0 html//",
+    "-//microsoft//dtd internet explorer 3.0 tables//",
+    "-//netscape comm. corp.//dtd html//",
+    "-//netscape comm. corp.//dtd strict html//",
+    "-//o'reilly and associates//dtd html 2.0//",
+    "-//o'reilly and associates//dtd html extended 1.0//",
+    "-//o'reilly and associates//dtd html extended relaxed 1.0//",
+    "-//sq//dtd html 2.0 hotmetal + extensions//",
+    "-//softquad software//dtd hotmetal pro 6.0::19990601::extensions to html 4.0//",
+    "-//softquad//dtd hotmetal pro 4.0::19971010::extensions to html 4.0//",
+    "-//spyglass//dtd html 2.0 extended//",
+    "-//sun microsystems corp.//dtd hotjava html//",
+    "-//sun microsystems corp.//dtd hotjava strict html//",
+    "-//w3c//dtd html 3 1995-03-24//",
+    "-//w3c//dtd html 3.2 draft//",
+    "-//w3c//dtd html 3.2 final//",
+    "-//w3c//dtd html 3.2//",
+    "-//w3c//dtd html 3.2s draft//",
+    "-//w3c//dtd html 4.0 frameset//",
+    "-//w3c//dtd html 4.0 transitional//",
+    "-//w3c//dtd html experimental 19960712//",
+    "-//w3c//dtd html experimental 970421//",
+    "-//w3c//dtd w3 html//",
+    "-//w3o//dtd w3 html 3.0//",
+    "-//webtechs//dtd mozilla html 2.0//",
+    "-//webtechs//dtd mozilla html//",
+];
+
+/// https://html.spec.whatwg.org/multipage/parsing.html#the-initial-insertion-mode
+fn quirks_mode(
+    name: Option<&str>,
+    public_id: Option<&str>,
+    system_id: Option<&str>,
+    force_quirks: bool,
+) -> QuirksMode {
+    let public_id = public_id.map(|id| id.to_ascii_lowercase());
+    let public = public_id.as_deref().unwrap_or("");
+    let system = system_id.map(|id| id.to_ascii_lowercase());
+    let html401 = public.starts_with("-//w3c//dtd html 4.01 frameset//")
+        || public.starts_with("-//w3c//dtd html 4.01 transitional//");
+
+    if force_quirks
+        || name != Some("html")
+        || public == "-//w3o//dtd w3 html strict 3.0//en//"
+        || public == "-/w3c/dtd html 4.0 transitional/en"
+        || public == "html"
+        || system.as_deref() == Some("http://www.ibm.com/data/dtd/v11/ibmxhtml1-transitional.dtd")
+        || QUIRKS_PUBLIC_ID_PREFIXES
+            .iter()
+            .any(|prefix| public.starts_with(prefix))
+        || (system.is_none() && html401)
+    {
+        return QuirksMode::Quirks;
+    }
+
+    if public.starts_with("-//w3c//dtd xhtml 1.0 frameset//")
+        || public.starts_with("-//w3c//dtd xhtml 1.0 transitional//")
+        || (system.is_some() && html401)
+    {
+        return QuirksMode::LimitedQuirks;
+    }
+
+    QuirksMode::NoQuirks
+}
+
+/// https://infra.spec.whatwg.org/#ascii-whitespace
+fn is_whitespace(c: char) -> bool {
+    matches!(c, '\t' | '\n' | '\x0C' | '\r' | ' ')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::renderer::dom::node::append_child;
+    use alloc::format;
+    use alloc::string::ToString;
+
+    fn parse(html: &str) -> Rc<RefCell<Node>> {
+        let t = HtmlTokenizer::new(html.to_string());
+        let window = HtmlParser::new(t).construct_tree();
+        let document = window.borrow().document();
+        document
+    }
+
+    fn child(node: &Rc<RefCell<Node>>, i: usize) -> Rc<RefCell<Node>> {
+        node.borrow()
+            .children()
+            .get(i)
+            .cloned()
+            .expect("failed to get a child")
+    }
+
+    fn element_kind(node: &Rc<RefCell<Node>>) -> Option<ElementKind> {
+        node.borrow().element_kind()
+    }
+
+    /// Dumps the subtree under `node` one node per line, indented by depth.
+    fn dump(node: &Rc<RefCell<Node>>) -> String {
+        fn dump_inner(node: &Rc<RefCell<Node>>, depth: usize, out: &mut String) {
+            for child in node.borrow().children() {
+                for _ in 0..depth {
+                    out.push_str("  ");
+                }
+                match child.borrow().kind() {
+                    NodeKind::Element(e) => out.push_str(&format!("<{}>", e.tag_name())),
+                    NodeKind::Text(s) => out.push_str(&format!("\"{}\"", s)),
+                    NodeKind::Comment(s) => out.push_str(&format!("<!-- {} -->", s)),
+                    NodeKind::DocumentType(d) => out.push_str(&format!("<!DOCTYPE {}>", d.name())),
+                    NodeKind::Document | NodeKind::DocumentFragment => {}
+                }
+                out.push('\n');
+                if let Some(contents) = child.borrow().template_contents() {
+                    for _ in 0..depth + 1 {
+                        out.push_str("  ");
+                    }
+                    out.push_str("content\n");
+                    dump_inner(&contents, depth + 2, out);
+                }
+                dump_inner(&child, depth + 1, out);
+            }
+        }
+
+        let mut out = String::new();
+        dump_inner(node, 0, &mut out);
+        out
+    }
+
+    /// Dumps the children of the body element.
+    fn dump_body(html: &str) -> String {
+        let document = parse(html);
+        let body = child(&child(&document, 0), 1);
+        dump(&body)
+    }
+
+    #[test]
+    fn test_empty() {
+        let document = parse("");
+        assert_eq!(document.borrow().kind(), NodeKind::Document);
+
+        let html = child(&document, 0);
+        assert_eq!(element_kind(&html), Some(ElementKind::Html));
+        assert_eq!(element_kind(&child(&html, 0)), Some(ElementKind::Head));
+        assert_eq!(element_kind(&child(&html, 1)), Some(ElementKind::Body));
+    }
+
+    #[test]
+    fn test_body() {
+        let document = parse("<html><head></head><body></body></html>");
+        assert_eq!(document.borrow().children().len(), 1);
+
+        let html = child(&document, 0);
+        assert_eq!(element_kind(&html), Some(ElementKind::Html));
+        assert_eq!(html.borrow().children().len(), 2);
+
+        let head = child(&html, 0);
+        assert_eq!(element_kind(&head), Some(ElementKind::Head));
+        assert!(head.borrow().first_child().is_none());
+
+        let body = child(&html, 1);
+        assert_eq!(element_kind(&body), Some(ElementKind::Body));
+        assert!(body.borrow().first_child().is_none());
+        assert!(Rc::ptr_eq(
+            &body.borrow().parent().upgrade().expect("no parent"),
+            &html
+        ));
+    }
+
+    #[test]
+    fn test_text() {
+        let document = parse("<html><head></head><body>text</body></html>");
+        let body = child(&child(&document, 0), 1);
+        assert_eq!(element_kind(&body), Some(ElementKind::Body));
+
+        let text = child(&body, 0);
+        assert_eq!(text.borrow().kind(), NodeKind::Text("text".to_string()));
+    }
+
+    #[test]
+    fn test_multiple_nodes() {
+        let document = parse("<html><head></head><body><p><a foo=bar>text</a></p></body></html>");
+        let body = child(&child(&document, 0), 1);
+
+        let p = child(&body, 0);
+        assert_eq!(element_kind(&p), Some(ElementKind::P));
+
+        let a = child(&p, 0);
+        assert_eq!(element_kind(&a), Some(ElementKind::A));
+        let element = a.borrow().get_element().expect("not an element");
+        assert_eq!(element.get_attribute("foo"), Some("bar".to_string()));
+
+        let text = child(&a, 0);
+        assert_eq!(text.borrow().kind(), NodeKind::Text("text".to_string()));
+    }
+
+    #[test]
+    fn test_implied_html_head_body() {
+        let document = parse("<title>saba</title><p>hello</p>");
+        let html = child(&document, 0);
+        assert_eq!(element_kind(&html), Some(ElementKind::Html));
+
+        let head = child(&html, 0);
+        assert_eq!(element_kind(&head), Some(ElementKind::Head));
+        let title = child(&head, 0);
+        assert_eq!(element_kind(&title), Some(ElementKind::Title));
+        assert_eq!(
+            child(&title, 0).borrow().kind(),
+            NodeKind::Text("saba".to_string())
+        );
+
+        let body = child(&html, 1);
+        assert_eq!(element_kind(&body), Some(ElementKind::Body));
+        assert_eq!(element_kind(&child(&body, 0)), Some(ElementKind::P));
+    }
+
+    #[test]
+    fn test_siblings() {
+        let document = parse("<body><h1>a</h1><h2>b</h2><br><p>c</p></body>");
+        let body = child(&child(&document, 0), 1);
+        let kinds: Vec<Option<ElementKind>> =
+            body.borrow().children().iter().map(element_kind).collect();
+        assert_eq!(
+            kinds,
+            [
+                Some(ElementKind::H1),
+                Some(ElementKind::H2),
+                Some(ElementKind::Br),
+                Some(ElementKind::P)
+            ]
+        );
+
+        let h2 = child(&body, 1);
+        let h1 = h2
+            .borrow()
+            .previous_sibling()
+            .upgrade()
+            .expect("no previous sibling");
+        assert_eq!(element_kind(&h1), Some(ElementKind::H1));
+        let br = h2.borrow().next_sibling().expect("no next sibling");
+        assert_eq!(element_kind(&br), Some(ElementKind::Br));
+        assert!(br.borrow().first_child().is_none());
+    }
+
+    #[test]
+    fn test_unmatched_end_tag_is_ignored() {
+        let document = parse("<body><div>a</span>b</div></body>");
+        let body = child(&child(&document, 0), 1);
+        let div = child(&body, 0);
+        assert_eq!(div.borrow().children().len(), 1);
+        assert_eq!(
+            child(&div, 0).borrow().kind(),
+            NodeKind::Text("ab".to_string())
+        );
+    }
+
+    #[test]
+    fn test_implied_p_end_tag() {
+        assert_eq!(
+            dump_body("<p>a<p>b<div>c</div>"),
+            "<p>\n  \"a\"\n<p>\n  \"b\"\n<div>\n  \"c\"\n"
+        );
+    }
+
+    #[test]
+    fn test_stray_p_end_tag() {
+        assert_eq!(dump_body("a</p>b</body>"), "\"a\"\n<p>\n\"b\"\n");
+    }
+
+    #[test]
+    fn test_implied_li_end_tag() {
+        assert_eq!(
+            dump_body("<ul><li>a<li>b<ul><li>c</ul></ul>"),
+            "<ul>\n  <li>\n    \"a\"\n  <li>\n    \"b\"\n    <ul>\n      <li>\n        \"c\"\n"
+        );
+    }
+
+    #[test]
+    fn test_implied_dd_dt_end_tags() {
+        assert_eq!(
+            dump_body("<dl><dt>a<dd>b<dt>c</dl>"),
+            "<dl>\n  <dt>\n    \"a\"\n  <dd>\n    \"b\"\n  <dt>\n    \"c\"\n"
+        );
+    }
+
+    #[test]
+    fn test_nested_headings() {
+        assert_eq!(
+            dump_body("<h1>a<h2>b</h1>c</body>"),
+            "<h1>\n  \"a\"\n<h2>\n  \"b\"\n\"c\"\n"
+        );
+    }
+
+    #[test]
+    fn test_misnested_formatting_elements() {
+        assert_eq!(dump_body("<b><i></b>x</i>"), "<b>\n  <i>\n<i>\n  \"x\"\n");
+    }
+
+    #[test]
+    fn test_adoption_agency() {
+        assert_eq!(
+            dump_body("<b>1<p>2</b>3</p>"),
+            "<b>\n  \"1\"\n<p>\n  <b>\n    \"2\"\n  \"3\"\n"
+        );
+        assert_eq!(
+            dump_body("<a>1<div>2<a>3</a>4</div>"),
+            "<a>\n  \"1\"\n<div>\n  <a>\n    \"2\"\n  <a>\n    \"3\"\n  \"4\"\n"
+        );
+    }
+
+    #[test]
+    fn test_reconstruct_active_formatting_elements() {
+        assert_eq!(
+            dump_body("<p><b>a</p><p>b</b>c</body>"),
+            "<p>\n  <b>\n    \"a\"\n<p>\n  <b>\n    \"b\"\n  \"c\"\n"
+        );
+    }
+
+    #[test]
+    fn test_table_implied_elements() {
+        assert_eq!(
+            dump_body("<table><td>a<td>b<tr><td>c</table>"),
+            "<table>\n  <tbody>\n    <tr>\n      <td>\n        \"a\"\n      <td>\n        \"b\"\n    <tr>\n      <td>\n        \"c\"\n"
+        );
+    }
+
+    #[test]
+    fn test_foster_parenting() {
+        assert_eq!(
+            dump_body("<table>a<tr><b>b</b><td>c</td></tr></table>"),
+            "\"a\"\n<b>\n  \"b\"\n<table>\n  <tbody>\n    <tr>\n      <td>\n        \"c\"\n"
+        );
+    }
+
+    #[test]
+    fn test_select() {
+        assert_eq!(
+            dump_body("<select><option>a<option>b<p>c</select>d</body>"),
+            "<select>\n  <option>\n    \"a\"\n  <option>\n    \"bc\"\n\"d\"\n"
+        );
+    }
+
+    #[test]
+    fn test_doctype() {
+        let t = HtmlTokenizer::new("<!DOCTYPE html><p>a</p>".to_string());
+        let window = HtmlParser::new(t).construct_tree();
+        assert_eq!(window.borrow().quirks_mode(), QuirksMode::NoQuirks);
+
+        let document = window.borrow().document();
+        assert_eq!(
+            child(&document, 0).borrow().kind(),
+            NodeKind::DocumentType(DocumentType::new(
+                "html".to_string(),
+                "".to_string(),
+                "".to_string()
+            ))
+        );
+        assert_eq!(element_kind(&child(&document, 1)), Some(ElementKind::Html));
+    }
+
+    #[test]
+    fn test_quirks_mode() {
+        let quirks_mode = |html: &str| {
+            let t = HtmlTokenizer::new(html.to_string());
+            let window = HtmlParser::new(t).construct_tree();
+            let mode = window.borrow().quirks_mode();
+            mode
+        };
+
+        assert_eq!(quirks_mode("<p>a</p>"), QuirksMode::Quirks);
+        assert_eq!(
+            quirks_mode("<!DOCTYPE html PUBLIC \"-//W3C//DTD HTML 3.2 Final//EN\">"),
+            QuirksMode::Quirks
+        );
+        assert_eq!(
+            quirks_mode("<!DOCTYPE html PUBLIC \"-//W3C//DTD HTML 4.01 Transitional//EN\">"),
+            QuirksMode::Quirks
+        );
+        assert_eq!(
+            quirks_mode(
+                "<!DOCTYPE html PUBLIC \"-//W3C//DTD HTML 4.01 Transitional//EN\" \"http://www.w3.org/TR/html4/loose.dtd\">"
+            ),
+            QuirksMode::LimitedQuirks
+        );
+        assert_eq!(
+            quirks_mode("<!DOCTYPE html PUBLIC \"-//W3C//DTD XHTML 1.0 Transitional//EN\">"),
+            QuirksMode::LimitedQuirks
+        );
+        assert_eq!(quirks_mode("<!DOCTYPE HTML>"), QuirksMode::NoQuirks);
+    }
+
+    #[test]
+    fn test_table_in_p_in_quirks_mode() {
+        assert_eq!(dump_body("<p><table></table>"), "<p>\n  <table>\n");
+
+        let document = parse("<!DOCTYPE html><p><table></table>");
+        let body = child(&child(&document, 1), 1);
+        assert_eq!(dump(&body), "<p>\n<table>\n");
+    }
+
+    #[test]
+    fn test_comments() {
+        let document = parse("<!--a--><html><body><!--b--></body></html><!--c-->");
+        assert_eq!(
+            dump(&document),
+            "<!-- a -->\n<html>\n  <head>\n  <body>\n    <!-- b -->\n<!-- c -->\n"
+        );
+
+        let document = parse("<p>a</p></body><!--d-->");
+        let html = child(&document, 0);
+        assert_eq!(
+            child(&html, 2).borrow().kind(),
+            NodeKind::Comment("d".to_string())
+        );
+    }
+
+    #[test]
+    fn test_rcdata_elements() {
+        let document = parse("<title>a<b>c &amp; d</title><p>e</p>");
+        let head = child(&child(&document, 0), 0);
+        assert_eq!(dump(&head), "<title>\n  \"a<b>c & d\"\n");
+
+        assert_eq!(
+            dump_body("<textarea>\n\na<b></textarea></body>"),
+            "<textarea>\n  \"\na<b>\"\n"
+        );
+    }
+
+    #[test]
+    fn test_rawtext_elements() {
+        let document = parse("<style>a > b { color: red; }</style>");
+        let head = child(&child(&document, 0), 0);
+        assert_eq!(dump(&head), "<style>\n  \"a > b { color: red; }\"\n");
+
+        assert_eq!(
+            dump_body("<xmp><p>&amp;</xmp><noembed><b></noembed></body>"),
+            "<xmp>\n  \"<p>&amp;\"\n<noembed>\n  \"<b>\"\n"
+        );
+    }
+
+    #[test]
+    fn test_plaintext() {
+        assert_eq!(
+            dump_body("<plaintext><p>a</plaintext>"),
+            "<plaintext>\n  \"<p>a</plaintext>\"\n"
+        );
+    }
+
+    #[test]
+    fn test_streaming() {
+        let html =
+            "<!DOCTYPE html><title>タイトル</title><p class=a>x &amp; y<table><tr><td>1</table>";
+        let expected = dump(&parse(html));
+
+        for chunk_size in [1, 5] {
+            let mut parser = HtmlParser::new(HtmlTokenizer::new_streaming());
+            for chunk in html.as_bytes().chunks(chunk_size) {
+                parser.push_bytes(chunk);
+            }
+            let window = parser.construct_tree();
+            let document = window.borrow().document();
+            assert_eq!(dump(&document), expected);
+        }
+    }
+
+    fn parse_fragment(context: &str, html: &str) -> Vec<Rc<RefCell<Node>>> {
+        let context = Rc::new(RefCell::new(Node::new(NodeKind::Element(Element::new(
+            context,
+            Vec::new(),
+        )))));
+        let t = HtmlTokenizer::new(html.to_string());
+        HtmlParser::new_fragment(t, context).construct_fragment()
+    }
+
+    fn dump_fragment(context: &str, html: &str) -> String {
+        let root = Rc::new(RefCell::new(Node::new(NodeKind::DocumentFragment)));
+        for node in parse_fragment(context, html) {
+            assert!(node.borrow().parent().upgrade().is_none());
+            append_child(&root, node).expect("failed to append");
+        }
+        dump(&root)
+    }
+
+    #[test]
+    fn test_fragment() {
+        assert_eq!(
+            dump_fragment("div", "<p>a</p>b</body></html>c"),
+            "<p>\n  \"a\"\n\"bc\"\n"
+        );
+        assert_eq!(
+            dump_fragment("body", "<head><title>a"),
+            "<title>\n  \"a\"\n"
+        );
+        assert_eq!(dump_fragment("div", ""), "");
+    }
+
+    #[test]
+    fn test_fragment_in_table_context() {
+        assert_eq!(
+            dump_fragment("tr", "<td>a<td>b"),
+            "<td>\n  \"a\"\n<td>\n  \"b\"\n"
+        );
+        assert_eq!(
+            dump_fragment("table", "<tr><td>a"),
+            "<tbody>\n  <tr>\n    <td>\n      \"a\"\n"
+        );
+        assert_eq!(
+            dump_fragment("select", "<option>a<p>b"),
+            "<option>\n  \"ab\"\n"
+        );
+    }
+
+    #[test]
+    fn test_fragment_in_text_context() {
+        assert_eq!(
+            dump_fragment("title", "<b>&amp;</title>"),
+            "\"<b>&</title>\"\n"
+        );
+        assert_eq!(dump_fragment("style", "<b>&amp;"), "\"<b>&amp;\"\n");
+        assert_eq!(dump_fragment("script", "a</script>"), "\"a</script>\"\n");
+    }
+
+    #[test]
+    fn test_fragment_form_element_pointer() {
+        let form = Rc::new(RefCell::new(Node::new(NodeKind::Element(Element::new(
+            "form",
+            Vec::new(),
+        )))));
+        let div = Rc::new(RefCell::new(Node::new(NodeKind::Element(Element::new(
+            "div",
+            Vec::new(),
+        )))));
+        append_child(&form, div.clone()).expect("failed to append");
+
+        // A form can't be nested in another form.
+        let t = HtmlTokenizer::new("<form><input></form>".to_string());
+        let nodes = HtmlParser::new_fragment(t, div).construct_fragment();
+        assert_eq!(nodes.len(), 1);
+        assert_eq!(element_kind(&nodes[0]), Some(ElementKind::Input));
+    }
+
+    #[test]
+    fn test_template() {
+        let document = parse("<template><li>a</template><p>b");
+        let head = child(&child(&document, 0), 0);
+        let template = child(&head, 0);
+        assert_eq!(element_kind(&template), Some(ElementKind::Template));
+        assert!(template.borrow().first_child().is_none());
+
+        let contents = template
+            .borrow()
+            .template_contents()
+            .expect("no template contents");
+        assert_eq!(contents.borrow().kind(), NodeKind::DocumentFragment);
+        assert_eq!(dump(&contents), "<li>\n  \"a\"\n");
+        assert_eq!(
+            dump_body("<template><li>a</template><p>b"),
+            "<p>\n  \"b\"\n"
+        );
+    }
+
+    #[test]
+    fn test_style_sheets() {
+        let html = "<style>p { color: red }</style><template><style>a {}</style></template>\
+                    <p><style>b { color: blue } c {}</style>"
+            .to_string();
+        let window = HtmlParser::new(HtmlTokenizer::new(html)).construct_tree();
+        let window = window.borrow();
+        let style_sheets = window.style_sheets();
+        assert_eq!(style_sheets.len(), 2);
+        assert_eq!(style_sheets[0].rules.len(), 1);
+        assert_eq!(style_sheets[1].rules.len(), 2);
+    }
+
+    #[test]
+    fn test_foreign_content() {
+        let document = parse("<svg viewbox='0 0 1 1'><foreignobject><title>a</title></foreignobject></svg><math><mi>b</mi></math>");
+        let body = child(&child(&document, 0), 1);
+        let svg = child(&body, 0);
+        let svg = svg.borrow().get_element().expect("not an element");
+        assert_eq!(svg.namespace(), Namespace::Svg);
+        assert_eq!(svg.kind(), ElementKind::Unknown);
+        assert_eq!(svg.get_attribute("viewBox"), Some("0 0 1 1".to_string()));
+
+        let math = child(&body, 1);
+        let math = math.borrow().get_element().expect("not an element");
+        assert_eq!(math.namespace(), Namespace::MathMl);
+
+        assert_eq!(
+            dump(&body),
+            "<svg>\n  <foreignObject>\n    <title>\n      \"a\"\n<math>\n  <mi>\n    \"b\"\n"
+        );
+        let title = child(&child(&child(&body, 0), 0), 0);
+        assert_eq!(element_kind(&title), Some(ElementKind::Title));
+    }
+}
@@ -0,0 +1,233 @@
+//! https://html.spec.whatwg.org/multipage/parsing.html#serialising-html-fragments
+
+use crate::renderer::dom::node::{Element, ElementKind, Node, NodeKind};
+use alloc::format;
+use alloc::rc::Rc;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::cell::RefCell;
+
+/// Serializes the children of `node`, which is what `innerHTML` returns.
+/// https://html.spec.whatwg.org/multipage/parsing.html#html-fragment-serialisation-algorithm
+pub fn serialize_children(node: &Rc<RefCell<Node>>) -> String {
+    let mut out = String::new();
+    let parent = node.borrow().element_kind();
+    for child in children_to_serialize(node) {
+        serialize_node(&child, parent, &mut out);
+    }
+    out
+}
+
+/// Serializes `node` itself and its descendants, which is what `outerHTML`
+/// returns.
+pub fn serialize(node: &Rc<RefCell<Node>>) -> String {
+    let mut out = String::new();
+    let parent = node
+        .borrow()
+        .parent()
+        .upgrade()
+        .and_then(|p| p.borrow().element_kind());
+    serialize_node(node, parent, &mut out);
+    out
+}
+
+/// `parent` is the kind of the parent element of `node`, which decides
+/// whether text is escaped.
+fn serialize_node(node: &Rc<RefCell<Node>>, parent: Option<ElementKind>, out: &mut String) {
+    match node.borrow().kind {
+        NodeKind::Element(ref e) => {
+            out.push('<');
+            out.push_str(&e.tag_name());
+            for attribute in e.attributes() {
+                out.push_str(&format!(
+                    " {}=\"{}\"",
+                    attribute.name(),
+                    escape(&attribute.value(), /* attribute_mode */ true)
+                ));
+            }
+            out.push('>');
+
+            if is_void(e) {
+                return;
+            }
+
+            for child in children_to_serialize(node) {
+                serialize_node(&child, Some(e.kind()), out);
+            }
+
+            out.push_str("</");
+            out.push_str(&e.tag_name());
+            out.push('>');
+        }
+        NodeKind::Text(ref s) => {
+            // The scripting flag is always disabled, so the text of noscript
+            // is escaped.
+            let raw = matches!(
+                parent,
+                Some(ElementKind::Style)
+                    | Some(ElementKind::Script)
+                    | Some(ElementKind::Xmp)
+                    | Some(ElementKind::Iframe)
+                    | Some(ElementKind::Noembed)
+                    | Some(ElementKind::Noframes)
+                    | Some(ElementKind::Plaintext)
+            );
+            if raw {
+                out.push_str(s);
+            } else {
+                out.push_str(&escape(s, /* attribute_mode */ false));
+            }
+        }
+        NodeKind::Comment(ref s) => {
+            out.push_str("<!--");
+            out.push_str(s);
+            out.push_str("-->");
+        }
+        NodeKind::DocumentType(ref d) => {
+            out.push_str("<!DOCTYPE ");
+            out.push_str(&d.name());
+            out.push('>');
+        }
+        NodeKind::Document | NodeKind::DocumentFragment => {
+            for child in node.borrow().children() {
+                serialize_node(&child, None, out);
+            }
+        }
+    }
+}
+
+/// Returns the children of `node`, or the contents for a template element.
+fn children_to_serialize(node: &Rc<RefCell<Node>>) -> Vec<Rc<RefCell<Node>>> {
+    let contents = node.borrow().template_contents();
+    match contents {
+        Some(contents) => contents.borrow().children(),
+        None => node.borrow().children(),
+    }
+}
+
+/// https://html.spec.whatwg.org/multipage/parsing.html#escapingString
+fn escape(s: &str, attribute_mode: bool) -> String {
+    let mut escaped = String::new();
+    for c in s.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '\u{A0}' => escaped.push_str("&nbsp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' if attribute_mode => escaped.push_str("&quot;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// HTML elements that can't have contents, and are serialized without an end
+/// tag. An element in foreign content, like `<svg><img/></svg>`, is never
+/// void.
+/// https://html.spec.whatwg.org/multipage/syntax.html#void-elements
+fn is_void(element: &Element) -> bool {
+    element.is_html()
+        && matches!(
+            element.kind(),
+            ElementKind::Area
+                | ElementKind::Base
+                | ElementKind::Basefont
+                | ElementKind::Bgsound
+                | ElementKind::Br
+                | ElementKind::Col
+                | ElementKind::Embed
+                | ElementKind::Frame
+                | ElementKind::Hr
+                | ElementKind::Img
+                | ElementKind::Input
+                | ElementKind::Keygen
+                | ElementKind::Link
+                | ElementKind::Meta
+                | ElementKind::Param
+                | ElementKind::Source
+                | ElementKind::Track
+                | ElementKind::Wbr
+        )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::renderer::dom::node::{append_child, Namespace};
+    use crate::renderer::html::parser::HtmlParser;
+    use crate::renderer::html::token::HtmlTokenizer;
+    use alloc::string::ToString;
+
+    fn parse(html: &str) -> Rc<RefCell<Node>> {
+        let t = HtmlTokenizer::new(html.to_string());
+        let window = HtmlParser::new(t).construct_tree();
+        let document = window.borrow().document();
+        document
+    }
+
+    #[test]
+    fn test_document() {
+        let html = "<!DOCTYPE html><html><head><title>a &amp; b</title></head>\
+                    <body><!--c--><p class=\"x\">d<br>e</p></body></html>";
+        assert_eq!(serialize_children(&parse(html)), html);
+    }
+
+    #[test]
+    fn test_escape() {
+        let document = parse("<p title='\"&amp;&lt;\u{A0}'>&lt;a&gt; &amp;&nbsp;</p>");
+        assert_eq!(
+            serialize_children(&document),
+            "<html><head></head><body>\
+             <p title=\"&quot;&amp;&lt;&nbsp;\">&lt;a&gt; &amp;&nbsp;</p>\
+             </body></html>"
+        );
+    }
+
+    #[test]
+    fn test_raw_text() {
+        let html = "<html><head><style>a > b</style><script>if (a < b && c) {}</script></head>\
+                    <body><xmp><&></xmp><textarea>&lt;</textarea></body></html>";
+        assert_eq!(serialize_children(&parse(html)), html);
+    }
+
+    #[test]
+    fn test_void_elements() {
+        let document = parse("<img src=a><input></input><hr/><wbr>");
+        assert_eq!(
+            serialize_children(&document),
+            "<html><head></head><body><img src=\"a\"><input><hr><wbr></body></html>"
+        );
+    }
+
+    #[test]
+    fn test_foreign_void_elements() {
+        let svg = Rc::new(RefCell::new(Node::new(NodeKind::Element(
+            Element::new_in_namespace("svg", Vec::new(), Namespace::Svg),
+        ))));
+        let br = Rc::new(RefCell::new(Node::new(NodeKind::Element(
+            Element::new_in_namespace("br", Vec::new(), Namespace::Svg),
+        ))));
+        append_child(&svg, br).expect("failed to append");
+        assert_eq!(serialize(&svg), "<svg><br></br></svg>");
+    }
+
+    #[test]
+    fn test_serialize() {
+        let document = parse("<div id=a><span>b</span></div>");
+        let html = document.borrow().first_child().expect("no html element");
+        let body = html.borrow().last_child().upgrade().expect("no body");
+        let div = body.borrow().first_child().expect("no div");
+        assert_eq!(serialize(&div), "<div id=\"a\"><span>b</span></div>");
+        assert_eq!(serialize_children(&div), "<span>b</span>");
+    }
+
+    #[test]
+    fn test_template() {
+        let document = parse("<template><p>a</p></template><template></template>");
+        assert_eq!(
+            serialize_children(&document),
+            "<html><head><template><p>a</p></template><template></template></head>\
+             <body></body></html>"
+        );
+    }
+}
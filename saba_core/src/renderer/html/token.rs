@@ -1,36 +1,238 @@
-use alloc::{string::String, vec::Vec};
+use alloc::{collections::VecDeque, string::String, vec::Vec};
 
 use crate::renderer::html::attribute::Attribute;
+use crate::renderer::html::character_reference;
+use crate::renderer::html::encoding::{Decoder, Encoding};
+use crate::renderer::html::parse_error::{ParseError, ParseErrorKind, Position};
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct HtmlTokenizer {
     state: State,
+    /// https://html.spec.whatwg.org/multipage/parsing.html#return-state
+    return_state: State,
+    /// https://html.spec.whatwg.org/multipage/parsing.html#character-reference-code
+    character_reference_code: u32,
     pos: usize,
     reconsume: bool,
     latest_token: Option<HtmlToken>,
+    /// The input that hasn't been consumed yet, and the character that was
+    /// consumed last. Consumed characters are dropped when more input is
+    /// pushed.
     input: Vec<char>,
+    /// Decodes the bytes pushed with `push_bytes()`.
+    decoder: Decoder,
+    /// Whether the end of the input is known. When it isn't, the tokenizer
+    /// suspends instead of handling the end of the input.
+    input_closed: bool,
+    /// Whether the last character pushed was U+000D CARRIAGE RETURN, so that
+    /// a U+000A LINE FEED at the start of the next chunk is dropped.
+    last_pushed_cr: bool,
     buf: String,
+    /// Tokens that are ready to be returned before consuming more input.
+    queued_tokens: VecDeque<HtmlToken>,
+    /// The tag name of the last start tag emitted, which an end tag has to
+    /// match to leave RCDATA, RAWTEXT and script data.
+    last_start_tag: String,
+    /// Whether `<![CDATA[` starts a CDATA section. It's only allowed in SVG
+    /// and MathML content, which the tree builder keeps track of.
+    cdata_allowed: bool,
+    /// The position of the character that was consumed last.
+    position: Position,
+    /// The position of the character that will be consumed next.
+    next_position: Position,
+    /// The position of the '<' that started the tag, comment or DOCTYPE that
+    /// is being tokenized.
+    markup_start: Position,
+    /// The position of the token that `next()` returned last.
+    token_position: Position,
+    errors: Vec<ParseError>,
 }
 
 impl HtmlTokenizer {
     pub fn new(html: String) -> Self {
+        let mut tokenizer = Self::new_streaming();
+        tokenizer.push_str(&html);
+        tokenizer.input_closed = true;
+        tokenizer
+    }
+
+    /// Creates a tokenizer whose input is pushed in chunks with `push_bytes()`
+    /// or `push_str()` as it arrives. `next()` returns `None` when it needs
+    /// more input to go on, and tokens can be drained again after the next
+    /// chunk is pushed. `finish()` tells the tokenizer that the input ended.
+    pub fn new_streaming() -> Self {
         Self {
             state: State::Data,
+            return_state: State::Data,
+            character_reference_code: 0,
             pos: 0,
             reconsume: false,
             latest_token: None,
-            input: html.chars().collect(),
+            input: Vec::new(),
+            decoder: Decoder::new(Encoding::Utf8),
+            input_closed: false,
+            last_pushed_cr: false,
             buf: String::new(),
+            queued_tokens: VecDeque::new(),
+            last_start_tag: String::new(),
+            cdata_allowed: false,
+            position: Position::default(),
+            next_position: Position::default(),
+            markup_start: Position::default(),
+            token_position: Position::default(),
+            errors: Vec::new(),
+        }
+    }
+
+    /// Sets the encoding of the bytes that are pushed with `push_bytes()`. The
+    /// default is UTF-8. This has to be called before any bytes are pushed.
+    pub fn set_encoding(&mut self, encoding: Encoding) {
+        self.decoder = Decoder::new(encoding);
+    }
+
+    /// Appends a chunk of encoded input. A multibyte sequence can be split
+    /// across chunks, and invalid sequences are replaced with U+FFFD.
+    pub fn push_bytes(&mut self, bytes: &[u8]) {
+        let decoded = self.decoder.decode(bytes, /* last */ false);
+        self.push_str(&decoded);
+    }
+
+    /// Appends a chunk of input.
+    pub fn push_str(&mut self, s: &str) {
+        // Only the character that was consumed last is needed to reconsume
+        // it, so everything before it can be dropped.
+        if self.pos > 1 {
+            self.input.drain(..self.pos - 1);
+            self.pos = 1;
+        }
+
+        // https://html.spec.whatwg.org/multipage/parsing.html#preprocessing-the-input-stream
+        // Newlines are normalized: CR LF and a lone CR become LF.
+        for c in s.chars() {
+            if c == '\n' && self.last_pushed_cr {
+                self.last_pushed_cr = false;
+                continue;
+            }
+            self.last_pushed_cr = c == '\r';
+            self.input.push(if c == '\r' { '\n' } else { c });
         }
     }
 
+    /// Tells the tokenizer that no more input will be pushed, so that the
+    /// remaining tokens can be drained.
+    pub fn finish(&mut self) {
+        let decoded = self.decoder.decode(&[], /* last */ true);
+        self.push_str(&decoded);
+
+        self.input_closed = true;
+    }
+
+    /// Returns the position where the token that `next()` returned last
+    /// starts. For a character token, this is the position of the character.
+    pub fn token_position(&self) -> Position {
+        self.token_position
+    }
+
+    /// Returns the parse errors found so far, in the order they were found.
+    pub fn errors(&self) -> Vec<ParseError> {
+        self.errors.clone()
+    }
+
+    fn error(&mut self, kind: ParseErrorKind) {
+        self.errors.push(ParseError::new(kind, self.position));
+    }
+
+    /// Switches the tokenizer to `state`. The tree builder uses this to make
+    /// the contents of elements like `<title>`, `<style>` and `<script>` be
+    /// tokenized as text (`State::Rcdata`, `State::Rawtext`,
+    /// `State::ScriptData` or `State::Plaintext`).
+    pub fn switch_to(&mut self, state: State) {
+        self.state = state;
+    }
+
+    /// Sets the tag name of the last start tag, as if it had been emitted.
+    /// This is for starting the tokenizer in a text state, where only the
+    /// matching end tag leaves the state.
+    pub fn set_last_start_tag(&mut self, tag: &str) {
+        self.last_start_tag = String::from(tag);
+    }
+
+    /// Sets whether a CDATA section can start, which is when the adjusted
+    /// current node of the tree builder isn't an HTML element. Otherwise
+    /// `<![CDATA[` starts a bogus comment.
+    /// https://html.spec.whatwg.org/multipage/parsing.html#markup-declaration-open-state
+    pub fn set_cdata_allowed(&mut self, allowed: bool) {
+        self.cdata_allowed = allowed;
+    }
+
     fn is_eof(&self) -> bool {
         self.pos >= self.input.len()
     }
 
+    /// Returns true if the input from the current position starts with `s`,
+    /// ignoring ASCII case. The current position is the character that was
+    /// consumed last.
+    fn starts_with_ignore_case(&self, s: &str) -> bool {
+        let start = self.pos - 1;
+        let len = s.chars().count();
+        if start + len > self.input.len() {
+            return false;
+        }
+
+        self.input[start..start + len]
+            .iter()
+            .zip(s.chars())
+            .all(|(a, b)| a.eq_ignore_ascii_case(&b))
+    }
+
+    /// Returns true if the input from the current position starts with `s`.
+    fn starts_with(&self, s: &str) -> bool {
+        self.input[self.pos - 1..]
+            .iter()
+            .copied()
+            .take(s.chars().count())
+            .eq(s.chars())
+    }
+
+    /// Returns true if the input from the current position is a proper prefix
+    /// of `s`, ignoring ASCII case, and more input may follow. Then it can't be
+    /// decided yet whether the input starts with `s`.
+    fn may_start_with_ignore_case(&self, s: &str) -> bool {
+        let available = &self.input[self.pos - 1..];
+        !self.input_closed
+            && available.len() < s.chars().count()
+            && available
+                .iter()
+                .zip(s.chars())
+                .all(|(a, b)| a.eq_ignore_ascii_case(&b))
+    }
+
+    /// Returns true if a named character reference starting at the current
+    /// position may continue in the input that hasn't arrived yet.
+    fn may_continue_named_character_reference(&self) -> bool {
+        let available = &self.input[self.pos - 1..];
+        !self.input_closed
+            && available.len() < character_reference::MAX_NAME_LENGTH
+            && available.iter().all(|c| c.is_ascii_alphanumeric())
+    }
+
+    /// Consumes the rest of a keyword whose first character was consumed last.
+    fn consume_keyword(&mut self, s: &str) {
+        for _ in 1..s.chars().count() {
+            self.consume_next_input();
+        }
+    }
+
     fn consume_next_input(&mut self) -> char {
         let c = self.input[self.pos];
         self.pos += 1;
+
+        self.position = self.next_position;
+        if c == '\n' {
+            self.next_position = Position::new(self.position.line + 1, 1);
+        } else {
+            self.next_position.column += 1;
+        }
         c
     }
 
@@ -52,95 +254,411 @@ impl HtmlTokenizer {
     }
 
     fn append_tag_name(&mut self, c: char) {
-        assert!(self.latest_token.is_some());
+        match self.latest_token {
+            Some(HtmlToken::StartTag { ref mut tag, .. })
+            | Some(HtmlToken::EndTag { ref mut tag }) => tag.push(c),
+            _ => {}
+        }
+    }
 
-        if let Some(t) = self.latest_token.as_mut() {
-            match t {
-                HtmlToken::StartTag {
-                    ref mut tag,
-                    self_closing: _,
-                    attributes: _,
-                }
-                | HtmlToken::EndTag { ref mut tag } => {
-                    tag.push(c);
+    fn take_latest_token(&mut self) -> Option<HtmlToken> {
+        let mut t = self.latest_token.take();
+        if let Some(HtmlToken::StartTag {
+            ref tag,
+            ref mut attributes,
+            ..
+        }) = t
+        {
+            self.last_start_tag = tag.clone();
+
+            // An attribute whose name is the same as an earlier attribute's is
+            // dropped.
+            let mut i = 0;
+            while i < attributes.len() {
+                if attributes[..i]
+                    .iter()
+                    .any(|a| a.name() == attributes[i].name())
+                {
+                    attributes.remove(i);
+                } else {
+                    i += 1;
                 }
-                _ => panic!("`latest_token` should be either StartTag or EndTag"),
             }
         }
+
+        t
     }
 
-    fn take_latest_token(&mut self) -> Option<HtmlToken> {
-        assert!(self.latest_token.is_some());
+    /// Starts a new attribute of the current tag. An end tag can't have
+    /// attributes, so each attribute of an end tag is reported as an error and
+    /// dropped.
+    fn start_new_attribute(&mut self) {
+        match self.latest_token {
+            Some(HtmlToken::StartTag {
+                ref mut attributes, ..
+            }) => attributes.push(Attribute::new()),
+            Some(HtmlToken::EndTag { .. }) => self.error(ParseErrorKind::EndTagWithAttributes),
+            _ => {}
+        }
+    }
 
-        let t = self.latest_token.as_ref().cloned();
+    fn append_attribute(&mut self, c: char, is_name: bool) {
+        if let Some(HtmlToken::StartTag {
+            ref mut attributes, ..
+        }) = self.latest_token
+        {
+            if let Some(attribute) = attributes.last_mut() {
+                attribute.add_char(c, is_name);
+            }
+        }
+    }
 
-        self.latest_token = None;
-        assert!(self.latest_token.is_none());
+    /// Reports an error when the name of the attribute that was consumed last
+    /// is the same as the name of an earlier attribute of the tag. The
+    /// attribute itself is dropped when the tag is emitted.
+    fn check_duplicate_attribute(&mut self) {
+        let duplicate = match self.latest_token {
+            Some(HtmlToken::StartTag { ref attributes, .. }) => match attributes.split_last() {
+                Some((last, rest)) => rest.iter().any(|a| a.name() == last.name()),
+                None => false,
+            },
+            _ => false,
+        };
 
-        t
+        if duplicate {
+            self.error(ParseErrorKind::DuplicateAttribute);
+        }
     }
 
-    fn start_new_attribute(&mut self) {
-        assert!(self.latest_token.is_some());
+    fn set_self_closing_flag(&mut self) {
+        match self.latest_token {
+            Some(HtmlToken::StartTag {
+                ref mut self_closing,
+                ..
+            }) => *self_closing = true,
+            Some(HtmlToken::EndTag { .. }) => self.error(ParseErrorKind::EndTagWithTrailingSolidus),
+            _ => {}
+        }
+    }
 
-        if let Some(t) = self.latest_token.as_mut() {
-            match t {
-                HtmlToken::StartTag {
-                    ref mut attributes,
-                    tag: _,
-                    self_closing: _,
-                } => {
-                    attributes.push(Attribute::new());
-                }
-                _ => panic!("`latest_token` should be StartTag"),
+    fn create_comment(&mut self) {
+        self.latest_token = Some(HtmlToken::Comment(String::new()));
+    }
+
+    fn append_comment(&mut self, s: &str) {
+        if let Some(HtmlToken::Comment(ref mut data)) = self.latest_token {
+            data.push_str(s);
+        }
+    }
+
+    fn create_doctype(&mut self) {
+        self.latest_token = Some(HtmlToken::Doctype {
+            name: None,
+            public_id: None,
+            system_id: None,
+            force_quirks: false,
+        });
+    }
+
+    fn append_doctype_name(&mut self, c: char) {
+        if let Some(HtmlToken::Doctype { ref mut name, .. }) = self.latest_token {
+            name.get_or_insert_with(String::new).push(c);
+        }
+    }
+
+    /// Sets the public identifier to the empty string when `c` is `None`, or
+    /// appends `c` to it otherwise.
+    fn append_doctype_public_id(&mut self, c: Option<char>) {
+        if let Some(HtmlToken::Doctype {
+            ref mut public_id, ..
+        }) = self.latest_token
+        {
+            match c {
+                Some(c) => public_id.get_or_insert_with(String::new).push(c),
+                None => *public_id = Some(String::new()),
             }
         }
     }
 
-    fn append_attribute(&mut self, c: char, is_name: bool) {
-        assert!(self.latest_token.is_some());
+    /// Sets the system identifier to the empty string when `c` is `None`, or
+    /// appends `c` to it otherwise.
+    fn append_doctype_system_id(&mut self, c: Option<char>) {
+        if let Some(HtmlToken::Doctype {
+            ref mut system_id, ..
+        }) = self.latest_token
+        {
+            match c {
+                Some(c) => system_id.get_or_insert_with(String::new).push(c),
+                None => *system_id = Some(String::new()),
+            }
+        }
+    }
 
-        if let Some(t) = self.latest_token.as_mut() {
-            match t {
-                HtmlToken::StartTag {
-                    ref mut attributes,
-                    tag: _,
-                    self_closing: _,
-                } => {
-                    let len = attributes.len();
-                    assert!(len > 0);
+    fn set_force_quirks_flag(&mut self) {
+        if let Some(HtmlToken::Doctype {
+            ref mut force_quirks,
+            ..
+        }) = self.latest_token
+        {
+            *force_quirks = true;
+        }
+    }
 
-                    attributes[len - 1].add_char(c, is_name);
-                }
-                _ => panic!("`latest_token` should be StartTag"),
+    /// https://html.spec.whatwg.org/multipage/parsing.html#appropriate-end-tag-token
+    fn is_appropriate_end_tag(&self) -> bool {
+        match self.latest_token {
+            Some(HtmlToken::EndTag { ref tag }) => *tag == self.last_start_tag,
+            _ => false,
+        }
+    }
+
+    /// Gives up on an end tag in a text state: emits "</" and the characters in
+    /// the temporary buffer, and reconsumes the current character in `state`.
+    fn emit_end_tag_open_as_text(&mut self, state: State) -> Option<HtmlToken> {
+        self.latest_token = None;
+        self.queued_tokens.push_back(HtmlToken::Char('/'));
+        for c in self.buf.chars() {
+            self.queued_tokens.push_back(HtmlToken::Char(c));
+        }
+
+        self.reconsume = true;
+        self.state = state;
+        Some(HtmlToken::Char('<'))
+    }
+
+    /// Starts a character reference after consuming '&' in the current state.
+    fn start_character_reference(&mut self) {
+        self.return_state = self.state.clone();
+        self.buf = String::from("&");
+        self.state = State::CharacterReference;
+    }
+
+    fn is_in_attribute_value(&self) -> bool {
+        matches!(
+            self.return_state,
+            State::AttributeValueDoubleQuoted
+                | State::AttributeValueSingleQuoted
+                | State::AttributeValueUnquoted
+        )
+    }
+
+    /// https://html.spec.whatwg.org/multipage/parsing.html#flush-code-points-consumed-as-a-character-reference
+    fn flush_character_reference(&mut self) {
+        let buf = core::mem::take(&mut self.buf);
+        for c in buf.chars() {
+            if self.is_in_attribute_value() {
+                self.append_attribute(c, /* is_name */ false);
+            } else {
+                self.queued_tokens.push_back(HtmlToken::Char(c));
             }
         }
     }
 
-    fn set_self_closing_flag(&mut self) {
-        assert!(self.latest_token.is_some());
+    /// https://html.spec.whatwg.org/multipage/parsing.html#numeric-character-reference-end-state
+    fn end_numeric_character_reference(&mut self) {
+        if let Some(kind) = character_reference::numeric_error(self.character_reference_code) {
+            self.error(kind);
+        }
 
-        if let Some(t) = self.latest_token.as_mut() {
-            match t {
-                HtmlToken::StartTag {
-                    ref mut self_closing,
-                    ..
-                } => *self_closing = true,
-                _ => panic!("`latest_token` should be StartTag"),
+        let c = character_reference::decode_numeric(self.character_reference_code);
+        self.buf = String::new();
+        self.buf.push(c);
+        self.flush_character_reference();
+        self.state = self.return_state.clone();
+    }
+
+    /// Queues whatever the current state has buffered when the input ends.
+    fn handle_eof(&mut self) {
+        // Errors at the end of the input point just past the last character.
+        self.position = self.next_position;
+        if let Some(kind) = self.eof_error() {
+            self.error(kind);
+        }
+
+        match self.state {
+            State::CharacterReference
+            | State::NamedCharacterReference
+            | State::AmbiguousAmpersand
+            | State::NumericCharacterReference
+            | State::HexadecimalCharacterReferenceStart
+            | State::DecimalCharacterReferenceStart
+            | State::HexadecimalCharacterReference
+            | State::DecimalCharacterReference => {
+                return self.handle_eof_in_character_reference();
+            }
+            State::TagOpen
+            | State::RcdataLessThanSign
+            | State::RawtextLessThanSign
+            | State::ScriptDataLessThanSign
+            | State::ScriptDataEscapedLessThanSign => {
+                self.queued_tokens.push_back(HtmlToken::Char('<'));
+            }
+            State::EndTagOpen
+            | State::RcdataEndTagOpen
+            | State::RawtextEndTagOpen
+            | State::ScriptDataEndTagOpen
+            | State::ScriptDataEscapedEndTagOpen
+            | State::RcdataEndTagName
+            | State::RawtextEndTagName
+            | State::ScriptDataEndTagName
+            | State::ScriptDataEscapedEndTagName => {
+                self.queued_tokens.push_back(HtmlToken::Char('<'));
+                self.queued_tokens.push_back(HtmlToken::Char('/'));
+                for c in self.buf.chars() {
+                    self.queued_tokens.push_back(HtmlToken::Char(c));
+                }
+            }
+            State::CommentStart
+            | State::CommentStartDash
+            | State::Comment
+            | State::CommentLessThanSign
+            | State::CommentLessThanSignBang
+            | State::CommentLessThanSignBangDash
+            | State::CommentLessThanSignBangDashDash
+            | State::CommentEndDash
+            | State::CommentEnd
+            | State::CommentEndBang
+            | State::BogusComment
+            | State::BogusDoctype => {
+                if let Some(t) = self.latest_token.take() {
+                    self.queued_tokens.push_back(t);
+                }
+            }
+            State::MarkupDeclarationOpen => {
+                self.queued_tokens
+                    .push_back(HtmlToken::Comment(String::new()));
+            }
+            State::CdataSectionBracket => {
+                self.queued_tokens.push_back(HtmlToken::Char(']'));
+            }
+            State::CdataSectionEnd => {
+                self.queued_tokens.push_back(HtmlToken::Char(']'));
+                self.queued_tokens.push_back(HtmlToken::Char(']'));
             }
+            State::Doctype | State::BeforeDoctypeName => {
+                self.create_doctype();
+                self.set_force_quirks_flag();
+                if let Some(t) = self.latest_token.take() {
+                    self.queued_tokens.push_back(t);
+                }
+            }
+            State::DoctypeName
+            | State::AfterDoctypeName
+            | State::AfterDoctypePublicKeyword
+            | State::BeforeDoctypePublicIdentifier
+            | State::DoctypePublicIdentifierDoubleQuoted
+            | State::DoctypePublicIdentifierSingleQuoted
+            | State::AfterDoctypePublicIdentifier
+            | State::BetweenDoctypePublicAndSystemIdentifiers
+            | State::AfterDoctypeSystemKeyword
+            | State::BeforeDoctypeSystemIdentifier
+            | State::DoctypeSystemIdentifierDoubleQuoted
+            | State::DoctypeSystemIdentifierSingleQuoted
+            | State::AfterDoctypeSystemIdentifier => {
+                self.set_force_quirks_flag();
+                if let Some(t) = self.latest_token.take() {
+                    self.queued_tokens.push_back(t);
+                }
+            }
+            _ => {}
         }
+
+        self.latest_token = None;
+        self.state = State::Data;
     }
-}
 
-impl Iterator for HtmlTokenizer {
-    type Item = HtmlToken;
+    fn eof_error(&self) -> Option<ParseErrorKind> {
+        match self.state {
+            State::TagOpen | State::EndTagOpen => Some(ParseErrorKind::EofBeforeTagName),
+            State::TagName
+            | State::BeforeAttributeName
+            | State::AttributeName
+            | State::AfterAttributeName
+            | State::BeforeAttributeValue
+            | State::AttributeValueDoubleQuoted
+            | State::AttributeValueSingleQuoted
+            | State::AttributeValueUnquoted
+            | State::AfterAttributeValueQuoted
+            | State::SelfClosingStartTag => Some(ParseErrorKind::EofInTag),
+            State::ScriptDataEscaped
+            | State::ScriptDataEscapedDash
+            | State::ScriptDataEscapedDashDash
+            | State::ScriptDataDoubleEscaped
+            | State::ScriptDataDoubleEscapedDash
+            | State::ScriptDataDoubleEscapedDashDash => {
+                Some(ParseErrorKind::EofInScriptHtmlCommentLikeText)
+            }
+            State::MarkupDeclarationOpen => Some(ParseErrorKind::IncorrectlyOpenedComment),
+            State::CommentStart
+            | State::CommentStartDash
+            | State::Comment
+            | State::CommentLessThanSign
+            | State::CommentLessThanSignBang
+            | State::CommentLessThanSignBangDash
+            | State::CommentLessThanSignBangDashDash
+            | State::CommentEndDash
+            | State::CommentEnd
+            | State::CommentEndBang => Some(ParseErrorKind::EofInComment),
+            State::Doctype
+            | State::BeforeDoctypeName
+            | State::DoctypeName
+            | State::AfterDoctypeName
+            | State::AfterDoctypePublicKeyword
+            | State::BeforeDoctypePublicIdentifier
+            | State::DoctypePublicIdentifierDoubleQuoted
+            | State::DoctypePublicIdentifierSingleQuoted
+            | State::AfterDoctypePublicIdentifier
+            | State::BetweenDoctypePublicAndSystemIdentifiers
+            | State::AfterDoctypeSystemKeyword
+            | State::BeforeDoctypeSystemIdentifier
+            | State::DoctypeSystemIdentifierDoubleQuoted
+            | State::DoctypeSystemIdentifierSingleQuoted
+            | State::AfterDoctypeSystemIdentifier => Some(ParseErrorKind::EofInDoctype),
+            State::CdataSection | State::CdataSectionBracket | State::CdataSectionEnd => {
+                Some(ParseErrorKind::EofInCdata)
+            }
+            _ => None,
+        }
+    }
 
-    fn next(&mut self) -> Option<Self::Item> {
-        if self.pos >= self.input.len() {
-            return None;
+    /// Finishes a character reference that was interrupted by the end of the
+    /// input, and lets the return state handle the end of the input.
+    fn handle_eof_in_character_reference(&mut self) {
+        match self.state {
+            State::HexadecimalCharacterReference | State::DecimalCharacterReference => {
+                self.error(ParseErrorKind::MissingSemicolonAfterCharacterReference);
+                self.end_numeric_character_reference();
+            }
+            State::NumericCharacterReference
+            | State::HexadecimalCharacterReferenceStart
+            | State::DecimalCharacterReferenceStart => {
+                self.error(ParseErrorKind::AbsenceOfDigitsInNumericCharacterReference);
+                self.flush_character_reference();
+                self.state = self.return_state.clone();
+            }
+            _ => {
+                self.flush_character_reference();
+                self.state = self.return_state.clone();
+            }
         }
+        self.handle_eof();
+    }
 
+    fn next_token(&mut self) -> Option<HtmlToken> {
         loop {
+            if let Some(t) = self.queued_tokens.pop_front() {
+                return Some(t);
+            }
+
+            if !self.reconsume && self.is_eof() {
+                if !self.input_closed {
+                    return None;
+                }
+
+                self.handle_eof();
+                return self.queued_tokens.pop_front();
+            }
+
             let c = match self.reconsume {
                 true => self.reconsume_input(),
                 false => self.consume_next_input(),
@@ -148,17 +666,29 @@ impl Iterator for HtmlTokenizer {
 
             match self.state {
                 State::Data => {
+                    if c == '&' {
+                        self.start_character_reference();
+                        continue;
+                    }
+
                     if c == '<' {
+                        self.markup_start = self.position;
                         self.state = State::TagOpen;
                         continue;
                     }
-                    if self.is_eof() {
-                        return Some(HtmlToken::Eof);
+
+                    if c == '\0' {
+                        self.error(ParseErrorKind::UnexpectedNullCharacter);
                     }
 
                     return Some(HtmlToken::Char(c));
                 }
                 State::TagOpen => {
+                    if c == '!' {
+                        self.state = State::MarkupDeclarationOpen;
+                        continue;
+                    }
+
                     if c == '/' {
                         self.state = State::EndTagOpen;
                         continue;
@@ -171,12 +701,18 @@ impl Iterator for HtmlTokenizer {
                         continue;
                     }
 
-                    if self.is_eof() {
-                        return Some(HtmlToken::Eof);
+                    if c == '?' {
+                        self.error(ParseErrorKind::UnexpectedQuestionMarkInsteadOfTagName);
+                        self.reconsume = true;
+                        self.state = State::BogusComment;
+                        self.create_comment();
+                        continue;
                     }
 
+                    self.error(ParseErrorKind::InvalidFirstCharacterOfTagName);
                     self.reconsume = true;
                     self.state = State::Data;
+                    return Some(HtmlToken::Char('<'));
                 }
                 State::EndTagOpen => {
                     if c.is_ascii_alphabetic() {
@@ -185,9 +721,20 @@ impl Iterator for HtmlTokenizer {
                         self.create_tag(false);
                         continue;
                     }
+
+                    if c == '>' {
+                        self.error(ParseErrorKind::MissingEndTagName);
+                        self.state = State::Data;
+                        continue;
+                    }
+
+                    self.error(ParseErrorKind::InvalidFirstCharacterOfTagName);
+                    self.reconsume = true;
+                    self.state = State::BogusComment;
+                    self.create_comment();
                 }
                 State::TagName => {
-                    if c == ' ' {
+                    if is_whitespace(c) {
                         self.state = State::BeforeAttributeName;
                         continue;
                     }
@@ -207,31 +754,47 @@ impl Iterator for HtmlTokenizer {
                         continue;
                     }
 
-                    if self.is_eof() {
-                        return Some(HtmlToken::Eof);
+                    if c == '\0' {
+                        self.error(ParseErrorKind::UnexpectedNullCharacter);
+                        self.append_tag_name('\u{FFFD}');
+                        continue;
                     }
 
                     self.append_tag_name(c);
                 }
                 State::BeforeAttributeName => {
-                    if c == '/' || c == '>' || self.is_eof() {
+                    if is_whitespace(c) {
+                        continue;
+                    }
+
+                    if c == '/' || c == '>' {
                         self.reconsume = true;
                         self.state = State::AfterAttributeName;
                         continue;
                     }
 
+                    if c == '=' {
+                        self.error(ParseErrorKind::UnexpectedEqualsSignBeforeAttributeName);
+                        self.start_new_attribute();
+                        self.append_attribute(c, /* is_name */ true);
+                        self.state = State::AttributeName;
+                        continue;
+                    }
+
                     self.reconsume = true;
                     self.state = State::AttributeName;
                     self.start_new_attribute();
                 }
                 State::AttributeName => {
-                    if c == ' ' || c == '/' || c == '>' || self.is_eof() {
+                    if is_whitespace(c) || c == '/' || c == '>' {
+                        self.check_duplicate_attribute();
                         self.reconsume = true;
                         self.state = State::AfterAttributeName;
                         continue;
                     }
 
                     if c == '=' {
+                        self.check_duplicate_attribute();
                         self.state = State::BeforeAttributeValue;
                         continue;
                     }
@@ -241,10 +804,20 @@ impl Iterator for HtmlTokenizer {
                         continue;
                     }
 
+                    if c == '\0' {
+                        self.error(ParseErrorKind::UnexpectedNullCharacter);
+                        self.append_attribute('\u{FFFD}', /* is_name */ true);
+                        continue;
+                    }
+
+                    if c == '"' || c == '\'' || c == '<' {
+                        self.error(ParseErrorKind::UnexpectedCharacterInAttributeName);
+                    }
+
                     self.append_attribute(c, /* is_name */ true);
                 }
                 State::AfterAttributeName => {
-                    if c == ' ' {
+                    if is_whitespace(c) {
                         continue;
                     }
 
@@ -263,16 +836,12 @@ impl Iterator for HtmlTokenizer {
                         return self.take_latest_token();
                     }
 
-                    if self.is_eof() {
-                        return Some(HtmlToken::Eof);
-                    }
-
                     self.reconsume = true;
                     self.state = State::AttributeName;
                     self.start_new_attribute();
                 }
                 State::BeforeAttributeValue => {
-                    if c == ' ' {
+                    if is_whitespace(c) {
                         continue;
                     }
 
@@ -286,6 +855,12 @@ impl Iterator for HtmlTokenizer {
                         continue;
                     }
 
+                    if c == '>' {
+                        self.error(ParseErrorKind::MissingAttributeValue);
+                        self.state = State::Data;
+                        return self.take_latest_token();
+                    }
+
                     self.reconsume = true;
                     self.state = State::AttributeValueUnquoted;
                 }
@@ -294,8 +869,16 @@ impl Iterator for HtmlTokenizer {
                         self.state = State::AfterAttributeValueQuoted;
                         continue;
                     }
-                    if self.is_eof() {
-                        return Some(HtmlToken::Eof);
+
+                    if c == '&' {
+                        self.start_character_reference();
+                        continue;
+                    }
+
+                    if c == '\0' {
+                        self.error(ParseErrorKind::UnexpectedNullCharacter);
+                        self.append_attribute('\u{FFFD}', /* is_name */ false);
+                        continue;
                     }
 
                     self.append_attribute(c, /* is_name */ false);
@@ -306,31 +889,49 @@ impl Iterator for HtmlTokenizer {
                         continue;
                     }
 
-                    if self.is_eof() {
-                        return Some(HtmlToken::Eof);
+                    if c == '&' {
+                        self.start_character_reference();
+                        continue;
+                    }
+
+                    if c == '\0' {
+                        self.error(ParseErrorKind::UnexpectedNullCharacter);
+                        self.append_attribute('\u{FFFD}', /* is_name */ false);
+                        continue;
                     }
 
                     self.append_attribute(c, /* is_name */ false);
                 }
                 State::AttributeValueUnquoted => {
-                    if c == ' ' {
+                    if is_whitespace(c) {
                         self.state = State::BeforeAttributeName;
                         continue;
                     }
 
+                    if c == '&' {
+                        self.start_character_reference();
+                        continue;
+                    }
+
                     if c == '>' {
                         self.state = State::Data;
                         return self.take_latest_token();
                     }
 
-                    if self.is_eof() {
-                        return Some(HtmlToken::Eof);
+                    if c == '\0' {
+                        self.error(ParseErrorKind::UnexpectedNullCharacter);
+                        self.append_attribute('\u{FFFD}', /* is_name */ false);
+                        continue;
+                    }
+
+                    if matches!(c, '"' | '\'' | '<' | '=' | '`') {
+                        self.error(ParseErrorKind::UnexpectedCharacterInUnquotedAttributeValue);
                     }
 
                     self.append_attribute(c, /* is_name */ false);
                 }
                 State::AfterAttributeValueQuoted => {
-                    if c == ' ' {
+                    if is_whitespace(c) {
                         self.state = State::BeforeAttributeName;
                         continue;
                     }
@@ -345,12 +946,9 @@ impl Iterator for HtmlTokenizer {
                         return self.take_latest_token();
                     }
 
-                    if self.is_eof() {
-                        return Some(HtmlToken::Eof);
-                    }
-
+                    self.error(ParseErrorKind::MissingWhitespaceBetweenAttributes);
                     self.reconsume = true;
-                    self.state = State::BeforeAttributeValue;
+                    self.state = State::BeforeAttributeName;
                 }
                 State::SelfClosingStartTag => {
                     if c == '>' {
@@ -359,82 +957,915 @@ impl Iterator for HtmlTokenizer {
                         return self.take_latest_token();
                     }
 
-                    if self.is_eof() {
-                        // invalid parse error.
-                        return Some(HtmlToken::Eof);
+                    self.error(ParseErrorKind::UnexpectedSolidusInTag);
+                    self.reconsume = true;
+                    self.state = State::BeforeAttributeName;
+                }
+                State::Rcdata => {
+                    if c == '&' {
+                        self.start_character_reference();
+                        continue;
+                    }
+
+                    if c == '<' {
+                        self.markup_start = self.position;
+                        self.state = State::RcdataLessThanSign;
+                        continue;
+                    }
+
+                    if c == '\0' {
+                        self.error(ParseErrorKind::UnexpectedNullCharacter);
+                        return Some(HtmlToken::Char('\u{FFFD}'));
+                    }
+
+                    return Some(HtmlToken::Char(c));
+                }
+                State::Rawtext => {
+                    if c == '<' {
+                        self.markup_start = self.position;
+                        self.state = State::RawtextLessThanSign;
+                        continue;
+                    }
+
+                    if c == '\0' {
+                        self.error(ParseErrorKind::UnexpectedNullCharacter);
+                        return Some(HtmlToken::Char('\u{FFFD}'));
                     }
+
+                    return Some(HtmlToken::Char(c));
                 }
                 State::ScriptData => {
                     if c == '<' {
+                        self.markup_start = self.position;
                         self.state = State::ScriptDataLessThanSign;
                         continue;
                     }
 
-                    if self.is_eof() {
-                        return Some(HtmlToken::Eof);
+                    if c == '\0' {
+                        self.error(ParseErrorKind::UnexpectedNullCharacter);
+                        return Some(HtmlToken::Char('\u{FFFD}'));
+                    }
+
+                    return Some(HtmlToken::Char(c));
+                }
+                State::Plaintext => {
+                    if c == '\0' {
+                        self.error(ParseErrorKind::UnexpectedNullCharacter);
+                        return Some(HtmlToken::Char('\u{FFFD}'));
                     }
 
                     return Some(HtmlToken::Char(c));
                 }
+                State::RcdataLessThanSign | State::RawtextLessThanSign => {
+                    let (end_tag_open, text) = match self.state {
+                        State::RcdataLessThanSign => (State::RcdataEndTagOpen, State::Rcdata),
+                        _ => (State::RawtextEndTagOpen, State::Rawtext),
+                    };
+
+                    if c == '/' {
+                        self.buf = String::new();
+                        self.state = end_tag_open;
+                        continue;
+                    }
+
+                    self.reconsume = true;
+                    self.state = text;
+                    return Some(HtmlToken::Char('<'));
+                }
                 State::ScriptDataLessThanSign => {
                     if c == '/' {
+                        self.buf = String::new();
                         self.state = State::ScriptDataEndTagOpen;
                         continue;
                     }
 
+                    if c == '!' {
+                        self.state = State::ScriptDataEscapeStart;
+                        self.queued_tokens.push_back(HtmlToken::Char('!'));
+                        return Some(HtmlToken::Char('<'));
+                    }
+
                     self.reconsume = true;
                     self.state = State::ScriptData;
-
-                    return Some(HtmlToken::Char(c));
+                    return Some(HtmlToken::Char('<'));
                 }
-                State::ScriptDataEndTagOpen => {
+                State::RcdataEndTagOpen
+                | State::RawtextEndTagOpen
+                | State::ScriptDataEndTagOpen
+                | State::ScriptDataEscapedEndTagOpen => {
+                    let (end_tag_name, text) = match self.state {
+                        State::RcdataEndTagOpen => (State::RcdataEndTagName, State::Rcdata),
+                        State::RawtextEndTagOpen => (State::RawtextEndTagName, State::Rawtext),
+                        State::ScriptDataEndTagOpen => {
+                            (State::ScriptDataEndTagName, State::ScriptData)
+                        }
+                        _ => (State::ScriptDataEscapedEndTagName, State::ScriptDataEscaped),
+                    };
+
                     if c.is_ascii_alphabetic() {
                         self.reconsume = true;
-                        self.state = State::ScriptDataEndTagName;
+                        self.state = end_tag_name;
                         self.create_tag(false);
                         continue;
                     }
 
-                    self.reconsume = true;
-                    self.state = State::ScriptData;
-
-                    return Some(HtmlToken::Char('<'));
+                    return self.emit_end_tag_open_as_text(text);
                 }
-                State::ScriptDataEndTagName => {
-                    if c == '>' {
+                State::RcdataEndTagName
+                | State::RawtextEndTagName
+                | State::ScriptDataEndTagName
+                | State::ScriptDataEscapedEndTagName => {
+                    let text = match self.state {
+                        State::RcdataEndTagName => State::Rcdata,
+                        State::RawtextEndTagName => State::Rawtext,
+                        State::ScriptDataEndTagName => State::ScriptData,
+                        _ => State::ScriptDataEscaped,
+                    };
+
+                    if is_whitespace(c) && self.is_appropriate_end_tag() {
+                        self.state = State::BeforeAttributeName;
+                        continue;
+                    }
+
+                    if c == '/' && self.is_appropriate_end_tag() {
+                        self.state = State::SelfClosingStartTag;
+                        continue;
+                    }
+
+                    if c == '>' && self.is_appropriate_end_tag() {
                         self.state = State::Data;
                         return self.take_latest_token();
                     }
 
-                    if c.is_ascii_uppercase() {
+                    if c.is_ascii_alphabetic() {
                         self.buf.push(c);
                         self.append_tag_name(c.to_ascii_lowercase());
                         continue;
                     }
 
-                    self.state = State::TemporaryBuffer;
-                    self.buf = String::from("</") + &self.buf;
-                    self.buf.push(c);
-                    continue;
+                    return self.emit_end_tag_open_as_text(text);
                 }
-                State::TemporaryBuffer => {
+                State::ScriptDataEscapeStart | State::ScriptDataEscapeStartDash => {
+                    if c == '-' {
+                        self.state = match self.state {
+                            State::ScriptDataEscapeStart => State::ScriptDataEscapeStartDash,
+                            _ => State::ScriptDataEscapedDashDash,
+                        };
+                        return Some(HtmlToken::Char('-'));
+                    }
+
                     self.reconsume = true;
+                    self.state = State::ScriptData;
+                }
+                State::ScriptDataEscaped
+                | State::ScriptDataEscapedDash
+                | State::ScriptDataEscapedDashDash => {
+                    if c == '-' {
+                        self.state = match self.state {
+                            State::ScriptDataEscaped => State::ScriptDataEscapedDash,
+                            _ => State::ScriptDataEscapedDashDash,
+                        };
+                        return Some(HtmlToken::Char('-'));
+                    }
 
-                    if self.buf.chars().count() == 0 {
-                        self.state = State::ScriptData;
+                    if c == '<' {
+                        self.markup_start = self.position;
+                        self.state = State::ScriptDataEscapedLessThanSign;
                         continue;
                     }
 
-                    let c = self
-                        .buf
-                        .chars()
-                        .nth(0)
-                        .expect("self.buf should have at least one character");
-                    self.buf.remove(0);
+                    if c == '>' && self.state == State::ScriptDataEscapedDashDash {
+                        self.state = State::ScriptData;
+                        return Some(HtmlToken::Char('>'));
+                    }
+
+                    self.state = State::ScriptDataEscaped;
+                    if c == '\0' {
+                        self.error(ParseErrorKind::UnexpectedNullCharacter);
+                        return Some(HtmlToken::Char('\u{FFFD}'));
+                    }
+
                     return Some(HtmlToken::Char(c));
                 }
-            }
-        }
+                State::ScriptDataEscapedLessThanSign => {
+                    if c == '/' {
+                        self.buf = String::new();
+                        self.state = State::ScriptDataEscapedEndTagOpen;
+                        continue;
+                    }
+
+                    if c.is_ascii_alphabetic() {
+                        self.buf = String::new();
+                        self.reconsume = true;
+                        self.state = State::ScriptDataDoubleEscapeStart;
+                        return Some(HtmlToken::Char('<'));
+                    }
+
+                    self.reconsume = true;
+                    self.state = State::ScriptDataEscaped;
+                    return Some(HtmlToken::Char('<'));
+                }
+                State::ScriptDataDoubleEscapeStart | State::ScriptDataDoubleEscapeEnd => {
+                    let (inner, outer) = match self.state {
+                        State::ScriptDataDoubleEscapeStart => {
+                            (State::ScriptDataDoubleEscaped, State::ScriptDataEscaped)
+                        }
+                        _ => (State::ScriptDataEscaped, State::ScriptDataDoubleEscaped),
+                    };
+
+                    if is_whitespace(c) || c == '/' || c == '>' {
+                        self.state = if self.buf == "script" { inner } else { outer };
+                        return Some(HtmlToken::Char(c));
+                    }
+
+                    if c.is_ascii_alphabetic() {
+                        self.buf.push(c.to_ascii_lowercase());
+                        return Some(HtmlToken::Char(c));
+                    }
+
+                    self.reconsume = true;
+                    self.state = outer;
+                }
+                State::ScriptDataDoubleEscaped
+                | State::ScriptDataDoubleEscapedDash
+                | State::ScriptDataDoubleEscapedDashDash => {
+                    if c == '-' {
+                        self.state = match self.state {
+                            State::ScriptDataDoubleEscaped => State::ScriptDataDoubleEscapedDash,
+                            _ => State::ScriptDataDoubleEscapedDashDash,
+                        };
+                        return Some(HtmlToken::Char('-'));
+                    }
+
+                    if c == '<' {
+                        self.state = State::ScriptDataDoubleEscapedLessThanSign;
+                        return Some(HtmlToken::Char('<'));
+                    }
+
+                    if c == '>' && self.state == State::ScriptDataDoubleEscapedDashDash {
+                        self.state = State::ScriptData;
+                        return Some(HtmlToken::Char('>'));
+                    }
+
+                    self.state = State::ScriptDataDoubleEscaped;
+                    if c == '\0' {
+                        self.error(ParseErrorKind::UnexpectedNullCharacter);
+                        return Some(HtmlToken::Char('\u{FFFD}'));
+                    }
+
+                    return Some(HtmlToken::Char(c));
+                }
+                State::ScriptDataDoubleEscapedLessThanSign => {
+                    if c == '/' {
+                        self.buf = String::new();
+                        self.state = State::ScriptDataDoubleEscapeEnd;
+                        return Some(HtmlToken::Char('/'));
+                    }
+
+                    self.reconsume = true;
+                    self.state = State::ScriptDataDoubleEscaped;
+                }
+                State::MarkupDeclarationOpen => {
+                    if self.may_start_with_ignore_case("--")
+                        || self.may_start_with_ignore_case("DOCTYPE")
+                        || self.may_start_with_ignore_case("[CDATA[")
+                    {
+                        // Wait for more input, and look at this character again.
+                        self.reconsume = true;
+                        return None;
+                    }
+
+                    if self.starts_with_ignore_case("--") {
+                        self.consume_keyword("--");
+                        self.state = State::CommentStart;
+                        self.create_comment();
+                        continue;
+                    }
+
+                    if self.starts_with_ignore_case("DOCTYPE") {
+                        self.consume_keyword("DOCTYPE");
+                        self.state = State::Doctype;
+                        continue;
+                    }
+
+                    if self.starts_with("[CDATA[") {
+                        self.consume_keyword("[CDATA[");
+                        if self.cdata_allowed {
+                            self.state = State::CdataSection;
+                            continue;
+                        }
+
+                        self.error(ParseErrorKind::CdataInHtmlContent);
+                        self.state = State::BogusComment;
+                        self.create_comment();
+                        self.append_comment("[CDATA[");
+                        continue;
+                    }
+
+                    self.error(ParseErrorKind::IncorrectlyOpenedComment);
+                    self.reconsume = true;
+                    self.state = State::BogusComment;
+                    self.create_comment();
+                }
+                State::CommentStart => {
+                    if c == '-' {
+                        self.state = State::CommentStartDash;
+                        continue;
+                    }
+
+                    if c == '>' {
+                        self.error(ParseErrorKind::AbruptClosingOfEmptyComment);
+                        self.state = State::Data;
+                        return self.take_latest_token();
+                    }
+
+                    self.reconsume = true;
+                    self.state = State::Comment;
+                }
+                State::CommentStartDash => {
+                    if c == '-' {
+                        self.state = State::CommentEnd;
+                        continue;
+                    }
+
+                    if c == '>' {
+                        self.error(ParseErrorKind::AbruptClosingOfEmptyComment);
+                        self.state = State::Data;
+                        return self.take_latest_token();
+                    }
+
+                    self.append_comment("-");
+                    self.reconsume = true;
+                    self.state = State::Comment;
+                }
+                State::Comment => {
+                    if c == '<' {
+                        self.append_comment("<");
+                        self.state = State::CommentLessThanSign;
+                        continue;
+                    }
+
+                    if c == '-' {
+                        self.state = State::CommentEndDash;
+                        continue;
+                    }
+
+                    if c == '\0' {
+                        self.error(ParseErrorKind::UnexpectedNullCharacter);
+                        self.append_comment("\u{FFFD}");
+                        continue;
+                    }
+
+                    self.append_comment(c.encode_utf8(&mut [0; 4]));
+                }
+                State::CommentLessThanSign => {
+                    if c == '!' {
+                        self.append_comment("!");
+                        self.state = State::CommentLessThanSignBang;
+                        continue;
+                    }
+
+                    if c == '<' {
+                        self.append_comment("<");
+                        continue;
+                    }
+
+                    self.reconsume = true;
+                    self.state = State::Comment;
+                }
+                State::CommentLessThanSignBang => {
+                    if c == '-' {
+                        self.state = State::CommentLessThanSignBangDash;
+                        continue;
+                    }
+
+                    self.reconsume = true;
+                    self.state = State::Comment;
+                }
+                State::CommentLessThanSignBangDash => {
+                    if c == '-' {
+                        self.state = State::CommentLessThanSignBangDashDash;
+                        continue;
+                    }
+
+                    self.reconsume = true;
+                    self.state = State::CommentEndDash;
+                }
+                State::CommentLessThanSignBangDashDash => {
+                    if c != '>' {
+                        self.error(ParseErrorKind::NestedComment);
+                    }
+
+                    self.reconsume = true;
+                    self.state = State::CommentEnd;
+                }
+                State::CommentEndDash => {
+                    if c == '-' {
+                        self.state = State::CommentEnd;
+                        continue;
+                    }
+
+                    self.append_comment("-");
+                    self.reconsume = true;
+                    self.state = State::Comment;
+                }
+                State::CommentEnd => {
+                    if c == '>' {
+                        self.state = State::Data;
+                        return self.take_latest_token();
+                    }
+
+                    if c == '!' {
+                        self.state = State::CommentEndBang;
+                        continue;
+                    }
+
+                    if c == '-' {
+                        self.append_comment("-");
+                        continue;
+                    }
+
+                    self.append_comment("--");
+                    self.reconsume = true;
+                    self.state = State::Comment;
+                }
+                State::CommentEndBang => {
+                    if c == '-' {
+                        self.append_comment("--!");
+                        self.state = State::CommentEndDash;
+                        continue;
+                    }
+
+                    if c == '>' {
+                        self.error(ParseErrorKind::IncorrectlyClosedComment);
+                        self.state = State::Data;
+                        return self.take_latest_token();
+                    }
+
+                    self.append_comment("--!");
+                    self.reconsume = true;
+                    self.state = State::Comment;
+                }
+                State::BogusComment => {
+                    if c == '>' {
+                        self.state = State::Data;
+                        return self.take_latest_token();
+                    }
+
+                    if c == '\0' {
+                        self.error(ParseErrorKind::UnexpectedNullCharacter);
+                        self.append_comment("\u{FFFD}");
+                        continue;
+                    }
+
+                    self.append_comment(c.encode_utf8(&mut [0; 4]));
+                }
+                State::Doctype => {
+                    if is_whitespace(c) {
+                        self.state = State::BeforeDoctypeName;
+                        continue;
+                    }
+
+                    if c != '>' {
+                        self.error(ParseErrorKind::MissingWhitespaceBeforeDoctypeName);
+                    }
+
+                    self.reconsume = true;
+                    self.state = State::BeforeDoctypeName;
+                }
+                State::BeforeDoctypeName => {
+                    if is_whitespace(c) {
+                        continue;
+                    }
+
+                    if c == '>' {
+                        self.error(ParseErrorKind::MissingDoctypeName);
+                        self.create_doctype();
+                        self.set_force_quirks_flag();
+                        self.state = State::Data;
+                        return self.take_latest_token();
+                    }
+
+                    self.create_doctype();
+                    self.reconsume = true;
+                    self.state = State::DoctypeName;
+                }
+                State::DoctypeName => {
+                    if is_whitespace(c) {
+                        self.state = State::AfterDoctypeName;
+                        continue;
+                    }
+
+                    if c == '>' {
+                        self.state = State::Data;
+                        return self.take_latest_token();
+                    }
+
+                    if c == '\0' {
+                        self.error(ParseErrorKind::UnexpectedNullCharacter);
+                        self.append_doctype_name('\u{FFFD}');
+                        continue;
+                    }
+
+                    self.append_doctype_name(c.to_ascii_lowercase());
+                }
+                State::AfterDoctypeName => {
+                    if is_whitespace(c) {
+                        continue;
+                    }
+
+                    if c == '>' {
+                        self.state = State::Data;
+                        return self.take_latest_token();
+                    }
+
+                    if self.may_start_with_ignore_case("PUBLIC")
+                        || self.may_start_with_ignore_case("SYSTEM")
+                    {
+                        self.reconsume = true;
+                        return None;
+                    }
+
+                    if self.starts_with_ignore_case("PUBLIC") {
+                        self.consume_keyword("PUBLIC");
+                        self.state = State::AfterDoctypePublicKeyword;
+                        continue;
+                    }
+
+                    if self.starts_with_ignore_case("SYSTEM") {
+                        self.consume_keyword("SYSTEM");
+                        self.state = State::AfterDoctypeSystemKeyword;
+                        continue;
+                    }
+
+                    self.error(ParseErrorKind::InvalidCharacterSequenceAfterDoctypeName);
+                    self.set_force_quirks_flag();
+                    self.reconsume = true;
+                    self.state = State::BogusDoctype;
+                }
+                State::AfterDoctypePublicKeyword | State::BeforeDoctypePublicIdentifier => {
+                    if is_whitespace(c) {
+                        if self.state == State::AfterDoctypePublicKeyword {
+                            self.state = State::BeforeDoctypePublicIdentifier;
+                        }
+                        continue;
+                    }
+
+                    if (c == '"' || c == '\'') && self.state == State::AfterDoctypePublicKeyword {
+                        self.error(ParseErrorKind::MissingWhitespaceAfterDoctypePublicKeyword);
+                    }
+
+                    if c == '"' {
+                        self.append_doctype_public_id(None);
+                        self.state = State::DoctypePublicIdentifierDoubleQuoted;
+                        continue;
+                    }
+
+                    if c == '\'' {
+                        self.append_doctype_public_id(None);
+                        self.state = State::DoctypePublicIdentifierSingleQuoted;
+                        continue;
+                    }
+
+                    if c == '>' {
+                        self.error(ParseErrorKind::MissingDoctypePublicIdentifier);
+                        self.set_force_quirks_flag();
+                        self.state = State::Data;
+                        return self.take_latest_token();
+                    }
+
+                    self.error(ParseErrorKind::MissingQuoteBeforeDoctypePublicIdentifier);
+                    self.set_force_quirks_flag();
+                    self.reconsume = true;
+                    self.state = State::BogusDoctype;
+                }
+                State::DoctypePublicIdentifierDoubleQuoted
+                | State::DoctypePublicIdentifierSingleQuoted => {
+                    let quote = match self.state {
+                        State::DoctypePublicIdentifierDoubleQuoted => '"',
+                        _ => '\'',
+                    };
+                    if c == quote {
+                        self.state = State::AfterDoctypePublicIdentifier;
+                        continue;
+                    }
+
+                    if c == '>' {
+                        self.error(ParseErrorKind::AbruptDoctypePublicIdentifier);
+                        self.set_force_quirks_flag();
+                        self.state = State::Data;
+                        return self.take_latest_token();
+                    }
+
+                    if c == '\0' {
+                        self.error(ParseErrorKind::UnexpectedNullCharacter);
+                        self.append_doctype_public_id(Some('\u{FFFD}'));
+                        continue;
+                    }
+
+                    self.append_doctype_public_id(Some(c));
+                }
+                State::AfterDoctypePublicIdentifier
+                | State::BetweenDoctypePublicAndSystemIdentifiers => {
+                    if is_whitespace(c) {
+                        self.state = State::BetweenDoctypePublicAndSystemIdentifiers;
+                        continue;
+                    }
+
+                    if c == '>' {
+                        self.state = State::Data;
+                        return self.take_latest_token();
+                    }
+
+                    if (c == '"' || c == '\'') && self.state == State::AfterDoctypePublicIdentifier
+                    {
+                        self.error(
+                            ParseErrorKind::MissingWhitespaceBetweenDoctypePublicAndSystemIdentifiers,
+                        );
+                    }
+
+                    if c == '"' {
+                        self.append_doctype_system_id(None);
+                        self.state = State::DoctypeSystemIdentifierDoubleQuoted;
+                        continue;
+                    }
+
+                    if c == '\'' {
+                        self.append_doctype_system_id(None);
+                        self.state = State::DoctypeSystemIdentifierSingleQuoted;
+                        continue;
+                    }
+
+                    self.error(ParseErrorKind::MissingQuoteBeforeDoctypeSystemIdentifier);
+                    self.set_force_quirks_flag();
+                    self.reconsume = true;
+                    self.state = State::BogusDoctype;
+                }
+                State::AfterDoctypeSystemKeyword | State::BeforeDoctypeSystemIdentifier => {
+                    if is_whitespace(c) {
+                        if self.state == State::AfterDoctypeSystemKeyword {
+                            self.state = State::BeforeDoctypeSystemIdentifier;
+                        }
+                        continue;
+                    }
+
+                    if (c == '"' || c == '\'') && self.state == State::AfterDoctypeSystemKeyword {
+                        self.error(ParseErrorKind::MissingWhitespaceAfterDoctypeSystemKeyword);
+                    }
+
+                    if c == '"' {
+                        self.append_doctype_system_id(None);
+                        self.state = State::DoctypeSystemIdentifierDoubleQuoted;
+                        continue;
+                    }
+
+                    if c == '\'' {
+                        self.append_doctype_system_id(None);
+                        self.state = State::DoctypeSystemIdentifierSingleQuoted;
+                        continue;
+                    }
+
+                    if c == '>' {
+                        self.error(ParseErrorKind::MissingDoctypeSystemIdentifier);
+                        self.set_force_quirks_flag();
+                        self.state = State::Data;
+                        return self.take_latest_token();
+                    }
+
+                    self.error(ParseErrorKind::MissingQuoteBeforeDoctypeSystemIdentifier);
+                    self.set_force_quirks_flag();
+                    self.reconsume = true;
+                    self.state = State::BogusDoctype;
+                }
+                State::DoctypeSystemIdentifierDoubleQuoted
+                | State::DoctypeSystemIdentifierSingleQuoted => {
+                    let quote = match self.state {
+                        State::DoctypeSystemIdentifierDoubleQuoted => '"',
+                        _ => '\'',
+                    };
+                    if c == quote {
+                        self.state = State::AfterDoctypeSystemIdentifier;
+                        continue;
+                    }
+
+                    if c == '>' {
+                        self.error(ParseErrorKind::AbruptDoctypeSystemIdentifier);
+                        self.set_force_quirks_flag();
+                        self.state = State::Data;
+                        return self.take_latest_token();
+                    }
+
+                    if c == '\0' {
+                        self.error(ParseErrorKind::UnexpectedNullCharacter);
+                        self.append_doctype_system_id(Some('\u{FFFD}'));
+                        continue;
+                    }
+
+                    self.append_doctype_system_id(Some(c));
+                }
+                State::AfterDoctypeSystemIdentifier => {
+                    if is_whitespace(c) {
+                        continue;
+                    }
+
+                    if c == '>' {
+                        self.state = State::Data;
+                        return self.take_latest_token();
+                    }
+
+                    // Unlike the other doctype errors, this doesn't set the
+                    // force-quirks flag.
+                    self.error(ParseErrorKind::UnexpectedCharacterAfterDoctypeSystemIdentifier);
+                    self.reconsume = true;
+                    self.state = State::BogusDoctype;
+                }
+                State::BogusDoctype => {
+                    if c == '>' {
+                        self.state = State::Data;
+                        return self.take_latest_token();
+                    }
+
+                    if c == '\0' {
+                        self.error(ParseErrorKind::UnexpectedNullCharacter);
+                    }
+                }
+                State::CdataSection => {
+                    if c == ']' {
+                        self.state = State::CdataSectionBracket;
+                        continue;
+                    }
+
+                    return Some(HtmlToken::Char(c));
+                }
+                State::CdataSectionBracket => {
+                    if c == ']' {
+                        self.state = State::CdataSectionEnd;
+                        continue;
+                    }
+
+                    self.reconsume = true;
+                    self.state = State::CdataSection;
+                    return Some(HtmlToken::Char(']'));
+                }
+                State::CdataSectionEnd => {
+                    if c == ']' {
+                        return Some(HtmlToken::Char(']'));
+                    }
+
+                    if c == '>' {
+                        self.state = State::Data;
+                        continue;
+                    }
+
+                    self.reconsume = true;
+                    self.state = State::CdataSection;
+                    self.queued_tokens.push_back(HtmlToken::Char(']'));
+                    return Some(HtmlToken::Char(']'));
+                }
+                State::CharacterReference => {
+                    if c.is_ascii_alphanumeric() {
+                        self.reconsume = true;
+                        self.state = State::NamedCharacterReference;
+                        continue;
+                    }
+
+                    if c == '#' {
+                        self.buf.push(c);
+                        self.state = State::NumericCharacterReference;
+                        continue;
+                    }
+
+                    self.flush_character_reference();
+                    self.reconsume = true;
+                    self.state = self.return_state.clone();
+                }
+                State::NamedCharacterReference => {
+                    if self.may_continue_named_character_reference() {
+                        self.reconsume = true;
+                        return None;
+                    }
+
+                    let start = self.pos - 1;
+                    let (name, chars) =
+                        match character_reference::lookup_named(&self.input[start..]) {
+                            Some(reference) => reference,
+                            None => {
+                                self.flush_character_reference();
+                                self.reconsume = true;
+                                self.state = State::AmbiguousAmpersand;
+                                continue;
+                            }
+                        };
+                    for _ in 1..name.len() {
+                        self.consume_next_input();
+                    }
+
+                    // For historical reasons, a reference without a semicolon
+                    // in an attribute value is left as it is when it's
+                    // followed by '=' or an alphanumeric, e.g. "?a=1&copy=2".
+                    let next = self.input.get(self.pos);
+                    if self.is_in_attribute_value()
+                        && !name.ends_with(';')
+                        && next.map_or(false, |n| *n == '=' || n.is_ascii_alphanumeric())
+                    {
+                        self.buf.push_str(name);
+                    } else {
+                        if !name.ends_with(';') {
+                            self.error(ParseErrorKind::MissingSemicolonAfterCharacterReference);
+                        }
+                        self.buf = String::from(chars);
+                    }
+                    self.flush_character_reference();
+                    self.state = self.return_state.clone();
+                }
+                State::AmbiguousAmpersand => {
+                    if c.is_ascii_alphanumeric() {
+                        if self.is_in_attribute_value() {
+                            self.append_attribute(c, /* is_name */ false);
+                            continue;
+                        }
+                        return Some(HtmlToken::Char(c));
+                    }
+
+                    if c == ';' {
+                        self.error(ParseErrorKind::UnknownNamedCharacterReference);
+                    }
+
+                    self.reconsume = true;
+                    self.state = self.return_state.clone();
+                }
+                State::NumericCharacterReference => {
+                    self.character_reference_code = 0;
+
+                    if c == 'x' || c == 'X' {
+                        self.buf.push(c);
+                        self.state = State::HexadecimalCharacterReferenceStart;
+                        continue;
+                    }
+
+                    self.reconsume = true;
+                    self.state = State::DecimalCharacterReferenceStart;
+                }
+                State::HexadecimalCharacterReferenceStart => {
+                    if c.is_ascii_hexdigit() {
+                        self.reconsume = true;
+                        self.state = State::HexadecimalCharacterReference;
+                        continue;
+                    }
+
+                    self.error(ParseErrorKind::AbsenceOfDigitsInNumericCharacterReference);
+                    self.flush_character_reference();
+                    self.reconsume = true;
+                    self.state = self.return_state.clone();
+                }
+                State::DecimalCharacterReferenceStart => {
+                    if c.is_ascii_digit() {
+                        self.reconsume = true;
+                        self.state = State::DecimalCharacterReference;
+                        continue;
+                    }
+
+                    self.error(ParseErrorKind::AbsenceOfDigitsInNumericCharacterReference);
+                    self.flush_character_reference();
+                    self.reconsume = true;
+                    self.state = self.return_state.clone();
+                }
+                State::HexadecimalCharacterReference | State::DecimalCharacterReference => {
+                    let radix = match self.state {
+                        State::HexadecimalCharacterReference => 16,
+                        _ => 10,
+                    };
+                    if let Some(digit) = c.to_digit(radix) {
+                        // Anything beyond U+10FFFF is replaced anyway, so the
+                        // code only needs to stay out of that range.
+                        self.character_reference_code = self
+                            .character_reference_code
+                            .saturating_mul(radix)
+                            .saturating_add(digit);
+                        continue;
+                    }
+
+                    if c != ';' {
+                        self.error(ParseErrorKind::MissingSemicolonAfterCharacterReference);
+                        self.reconsume = true;
+                    }
+                    self.end_numeric_character_reference();
+                }
+            }
+        }
+    }
+}
+
+impl Iterator for HtmlTokenizer {
+    type Item = HtmlToken;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let token = self.next_token();
+        self.token_position = match token {
+            Some(HtmlToken::Char(_)) | Some(HtmlToken::Eof) | None => self.position,
+            Some(_) => self.markup_start,
+        };
+        token
     }
 }
 
@@ -449,6 +1880,16 @@ pub enum HtmlToken {
         tag: String,
     },
     Char(char),
+    /// https://html.spec.whatwg.org/multipage/parsing.html#tokenization
+    /// A missing `name`, `public_id` or `system_id` is distinct from an empty
+    /// one.
+    Doctype {
+        name: Option<String>,
+        public_id: Option<String>,
+        system_id: Option<String>,
+        force_quirks: bool,
+    },
+    Comment(String),
     Eof,
 }
 
@@ -467,11 +1908,77 @@ pub enum State {
     AttributeValueUnquoted,
     AfterAttributeValueQuoted,
     SelfClosingStartTag,
+    Rcdata,
+    RcdataLessThanSign,
+    RcdataEndTagOpen,
+    RcdataEndTagName,
+    Rawtext,
+    RawtextLessThanSign,
+    RawtextEndTagOpen,
+    RawtextEndTagName,
     ScriptData,
     ScriptDataLessThanSign,
     ScriptDataEndTagOpen,
     ScriptDataEndTagName,
-    TemporaryBuffer,
+    ScriptDataEscapeStart,
+    ScriptDataEscapeStartDash,
+    ScriptDataEscaped,
+    ScriptDataEscapedDash,
+    ScriptDataEscapedDashDash,
+    ScriptDataEscapedLessThanSign,
+    ScriptDataEscapedEndTagOpen,
+    ScriptDataEscapedEndTagName,
+    ScriptDataDoubleEscapeStart,
+    ScriptDataDoubleEscaped,
+    ScriptDataDoubleEscapedDash,
+    ScriptDataDoubleEscapedDashDash,
+    ScriptDataDoubleEscapedLessThanSign,
+    ScriptDataDoubleEscapeEnd,
+    Plaintext,
+    MarkupDeclarationOpen,
+    CommentStart,
+    CommentStartDash,
+    Comment,
+    CommentLessThanSign,
+    CommentLessThanSignBang,
+    CommentLessThanSignBangDash,
+    CommentLessThanSignBangDashDash,
+    CommentEndDash,
+    CommentEnd,
+    CommentEndBang,
+    BogusComment,
+    Doctype,
+    BeforeDoctypeName,
+    DoctypeName,
+    AfterDoctypeName,
+    AfterDoctypePublicKeyword,
+    BeforeDoctypePublicIdentifier,
+    DoctypePublicIdentifierDoubleQuoted,
+    DoctypePublicIdentifierSingleQuoted,
+    AfterDoctypePublicIdentifier,
+    BetweenDoctypePublicAndSystemIdentifiers,
+    AfterDoctypeSystemKeyword,
+    BeforeDoctypeSystemIdentifier,
+    DoctypeSystemIdentifierDoubleQuoted,
+    DoctypeSystemIdentifierSingleQuoted,
+    AfterDoctypeSystemIdentifier,
+    BogusDoctype,
+    CdataSection,
+    CdataSectionBracket,
+    CdataSectionEnd,
+    CharacterReference,
+    NamedCharacterReference,
+    AmbiguousAmpersand,
+    NumericCharacterReference,
+    HexadecimalCharacterReferenceStart,
+    DecimalCharacterReferenceStart,
+    HexadecimalCharacterReference,
+    DecimalCharacterReference,
+}
+
+/// https://infra.spec.whatwg.org/#ascii-whitespace
+fn is_whitespace(c: char) -> bool {
+    matches!(c, '\t' | '\n' | '\x0C' | '\r' | ' ')
 }
 
 #[cfg(test)]
@@ -546,6 +2053,91 @@ mod tests {
         }
     }
 
+    fn attribute(name: &str, value: &str) -> Attribute {
+        let mut attribute = Attribute::new();
+        for c in name.chars() {
+            attribute.add_char(c, true);
+        }
+        for c in value.chars() {
+            attribute.add_char(c, false);
+        }
+        attribute
+    }
+
+    #[test]
+    fn test_whitespace_in_tags() {
+        let html = "<a\n\thref=x\x0Cid\r\n=\r\n'y'\ttitle\n>".to_string();
+        let mut tokenizer = HtmlTokenizer::new(html);
+        assert_eq!(
+            tokenizer.next(),
+            Some(HtmlToken::StartTag {
+                tag: "a".to_string(),
+                self_closing: false,
+                attributes: vec![
+                    attribute("href", "x"),
+                    attribute("id", "y"),
+                    attribute("title", ""),
+                ],
+            })
+        );
+
+        let html = "<br\n/></p\t>".to_string();
+        let mut tokenizer = HtmlTokenizer::new(html);
+        assert_eq!(
+            tokenizer.next(),
+            Some(HtmlToken::StartTag {
+                tag: "br".to_string(),
+                self_closing: true,
+                attributes: Vec::new(),
+            })
+        );
+        assert_eq!(
+            tokenizer.next(),
+            Some(HtmlToken::EndTag {
+                tag: "p".to_string(),
+            })
+        );
+    }
+
+    #[test]
+    fn test_duplicate_attributes() {
+        let html = "<p id=a ID=b class=c id=d>".to_string();
+        let mut tokenizer = HtmlTokenizer::new(html);
+        assert_eq!(
+            tokenizer.next(),
+            Some(HtmlToken::StartTag {
+                tag: "p".to_string(),
+                self_closing: false,
+                attributes: vec![attribute("id", "a"), attribute("class", "c")],
+            })
+        );
+        assert_eq!(tokenizer.errors().len(), 2);
+    }
+
+    #[test]
+    fn test_newline_normalization() {
+        let html = "a\r\nb\rc\r\r\nd".to_string();
+        let tokenizer = HtmlTokenizer::new(html);
+        let expected: Vec<HtmlToken> = "a\nb\nc\n\nd".chars().map(HtmlToken::Char).collect();
+        assert_eq!(tokenizer.collect::<Vec<HtmlToken>>(), expected);
+
+        // A newline in a tag separates attributes like any whitespace.
+        let mut tokenizer = HtmlTokenizer::new("<h a='b'\r\nc='d'>".to_string());
+        let attributes = match tokenizer.next() {
+            Some(HtmlToken::StartTag { attributes, .. }) => attributes,
+            token => panic!("expected a start tag, got {:?}", token),
+        };
+        let attributes: Vec<(String, String)> =
+            attributes.iter().map(|a| (a.name(), a.value())).collect();
+        assert_eq!(
+            attributes,
+            [
+                ("a".to_string(), "b".to_string()),
+                ("c".to_string(), "d".to_string())
+            ]
+        );
+    }
+
     #[test]
     fn test_script_tag() {
         let html = "<script>alert(1)</script>".to_string();
@@ -563,7 +2155,7 @@ mod tests {
             HtmlToken::Char('r'),
             HtmlToken::Char('t'),
             HtmlToken::Char('('),
-            HtmlToken::Char('2'),
+            HtmlToken::Char('1'),
             HtmlToken::Char(')'),
             HtmlToken::EndTag {
                 tag: "script".to_string(),
@@ -574,4 +2166,558 @@ mod tests {
             assert_eq!(tokenizer.next(), Some(e));
         }
     }
+
+    #[test]
+    fn test_text_at_eof() {
+        let html = "a<".to_string();
+        let tokenizer = HtmlTokenizer::new(html);
+        let tokens: Vec<HtmlToken> = tokenizer.collect();
+        assert_eq!(tokens, [HtmlToken::Char('a'), HtmlToken::Char('<')]);
+
+        let html = "a < b".to_string();
+        let tokenizer = HtmlTokenizer::new(html);
+        let tokens: Vec<HtmlToken> = tokenizer.collect();
+        assert_eq!(
+            tokens,
+            [
+                HtmlToken::Char('a'),
+                HtmlToken::Char(' '),
+                HtmlToken::Char('<'),
+                HtmlToken::Char(' '),
+                HtmlToken::Char('b'),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_comment() {
+        let html = "<!-- a -- b --><!----><!--<!-- c -->".to_string();
+        let tokenizer = HtmlTokenizer::new(html);
+        let tokens: Vec<HtmlToken> = tokenizer.collect();
+        assert_eq!(
+            tokens,
+            [
+                HtmlToken::Comment(" a -- b ".to_string()),
+                HtmlToken::Comment("".to_string()),
+                HtmlToken::Comment("<!-- c ".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_bogus_comment() {
+        let html = "<?xml version=\"1.0\"?></ a><!foo>".to_string();
+        let tokenizer = HtmlTokenizer::new(html);
+        let tokens: Vec<HtmlToken> = tokenizer.collect();
+        assert_eq!(
+            tokens,
+            [
+                HtmlToken::Comment("?xml version=\"1.0\"?".to_string()),
+                HtmlToken::Comment(" a".to_string()),
+                HtmlToken::Comment("foo".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_eof_in_comment() {
+        let html = "<!--abc-".to_string();
+        let mut tokenizer = HtmlTokenizer::new(html);
+        assert_eq!(
+            tokenizer.next(),
+            Some(HtmlToken::Comment("abc".to_string()))
+        );
+        assert_eq!(tokenizer.next(), None);
+    }
+
+    #[test]
+    fn test_cdata_section() {
+        let html = "<![CDATA[a]b]]c]]]><![cdata[".to_string();
+        let mut tokenizer = HtmlTokenizer::new(html);
+        tokenizer.set_cdata_allowed(true);
+        let tokens: String = tokenizer
+            .by_ref()
+            .take(7)
+            .map(|t| match t {
+                HtmlToken::Char(c) => c,
+                _ => panic!("unexpected token {:?}", t),
+            })
+            .collect();
+        assert_eq!(tokens, "a]b]]c]");
+        assert_eq!(
+            tokenizer.next(),
+            Some(HtmlToken::Comment("[cdata[".to_string()))
+        );
+
+        let html = "<![CDATA[x]]>".to_string();
+        let tokenizer = HtmlTokenizer::new(html);
+        let tokens: Vec<HtmlToken> = tokenizer.collect();
+        assert_eq!(tokens, [HtmlToken::Comment("[CDATA[x]]".to_string())]);
+    }
+
+    #[test]
+    fn test_doctype() {
+        let html = "<!DOCTYPE html><!doctype HTML>".to_string();
+        let tokenizer = HtmlTokenizer::new(html);
+        let tokens: Vec<HtmlToken> = tokenizer.collect();
+        let expected = HtmlToken::Doctype {
+            name: Some("html".to_string()),
+            public_id: None,
+            system_id: None,
+            force_quirks: false,
+        };
+        assert_eq!(tokens, [expected.clone(), expected]);
+    }
+
+    #[test]
+    fn test_doctype_with_identifiers() {
+        let html = "<!DOCTYPE html PUBLIC \"-//W3C//DTD HTML 4.01//EN\" 'http://www.w3.org/TR/html4/strict.dtd'>"
+            .to_string();
+        let mut tokenizer = HtmlTokenizer::new(html);
+        assert_eq!(
+            tokenizer.next(),
+            Some(HtmlToken::Doctype {
+                name: Some("html".to_string()),
+                public_id: Some("-//W3C//DTD HTML 4.01//EN".to_string()),
+                system_id: Some("http://www.w3.org/TR/html4/strict.dtd".to_string()),
+                force_quirks: false,
+            })
+        );
+
+        let html = "<!DOCTYPE html SYSTEM \"about:legacy-compat\">".to_string();
+        let mut tokenizer = HtmlTokenizer::new(html);
+        assert_eq!(
+            tokenizer.next(),
+            Some(HtmlToken::Doctype {
+                name: Some("html".to_string()),
+                public_id: None,
+                system_id: Some("about:legacy-compat".to_string()),
+                force_quirks: false,
+            })
+        );
+    }
+
+    #[test]
+    fn test_invalid_doctype() {
+        let html = "<!DOCTYPE><!DOCTYPE html foo><!DOCTYPE html PUBLIC \"x".to_string();
+        let tokenizer = HtmlTokenizer::new(html);
+        let tokens: Vec<HtmlToken> = tokenizer.collect();
+        assert_eq!(
+            tokens,
+            [
+                HtmlToken::Doctype {
+                    name: None,
+                    public_id: None,
+                    system_id: None,
+                    force_quirks: true,
+                },
+                HtmlToken::Doctype {
+                    name: Some("html".to_string()),
+                    public_id: None,
+                    system_id: None,
+                    force_quirks: true,
+                },
+                HtmlToken::Doctype {
+                    name: Some("html".to_string()),
+                    public_id: Some("x".to_string()),
+                    system_id: None,
+                    force_quirks: true,
+                },
+            ]
+        );
+    }
+
+    fn collect_chars(html: &str) -> String {
+        HtmlTokenizer::new(html.to_string())
+            .map(|t| match t {
+                HtmlToken::Char(c) => c,
+                t => panic!("unexpected token: {:?}", t),
+            })
+            .collect()
+    }
+
+    fn first_attribute_value(html: &str) -> String {
+        let mut tokenizer = HtmlTokenizer::new(html.to_string());
+        match tokenizer.next() {
+            Some(HtmlToken::StartTag { attributes, .. }) => attributes[0].value(),
+            t => panic!("unexpected token: {:?}", t),
+        }
+    }
+
+    #[test]
+    fn test_named_character_reference() {
+        assert_eq!(collect_chars("&amp;&lt;&gt;&quot;"), "&<>\"");
+        assert_eq!(collect_chars("a &copy; b"), "a \u{A9} b");
+        assert_eq!(collect_chars("&NotEqualTilde;"), "\u{2242}\u{338}");
+    }
+
+    #[test]
+    fn test_legacy_character_reference_without_semicolon() {
+        assert_eq!(collect_chars("&amp &copy2"), "& \u{A9}2");
+        assert_eq!(collect_chars("&notit;"), "\u{AC}it;");
+        assert_eq!(collect_chars("&notin;"), "\u{2209}");
+    }
+
+    #[test]
+    fn test_unknown_character_reference() {
+        assert_eq!(collect_chars("&foo; & &; &#; &#x;"), "&foo; & &; &#; &#x;");
+        assert_eq!(collect_chars("a&"), "a&");
+        assert_eq!(collect_chars("&#"), "&#");
+    }
+
+    #[test]
+    fn test_numeric_character_reference() {
+        assert_eq!(collect_chars("&#169;&#xA9;&#XA9;"), "\u{A9}\u{A9}\u{A9}");
+        assert_eq!(collect_chars("&#x1F600;"), "\u{1F600}");
+        assert_eq!(collect_chars("&#65x"), "Ax");
+        assert_eq!(collect_chars("&#128;"), "\u{20AC}");
+        assert_eq!(
+            collect_chars("&#0;&#xD800;&#99999999999;"),
+            "\u{FFFD}\u{FFFD}\u{FFFD}"
+        );
+        assert_eq!(collect_chars("&#x41"), "A");
+    }
+
+    #[test]
+    fn test_character_reference_in_attribute_value() {
+        assert_eq!(
+            first_attribute_value("<a href=\"?a=1&amp;b=2\">"),
+            "?a=1&b=2"
+        );
+        assert_eq!(first_attribute_value("<a title='&lt;&#65;'>"), "<A");
+        assert_eq!(first_attribute_value("<a title=x&amp;y>"), "x&y");
+    }
+
+    #[test]
+    fn test_legacy_character_reference_in_attribute_value() {
+        assert_eq!(
+            first_attribute_value("<a href=\"?a=1&copy=2&not;\">"),
+            "?a=1&copy=2\u{AC}"
+        );
+        assert_eq!(
+            first_attribute_value("<a title=\"&ampx &amp \">"),
+            "&ampx & "
+        );
+    }
+
+    /// Emits the start tag at the beginning of `html`, switches to `state` as
+    /// the tree builder would, and collects the text up to the end tag.
+    fn collect_text_in(state: State, html: &str) -> (String, Option<HtmlToken>) {
+        let mut tokenizer = HtmlTokenizer::new(html.to_string());
+        assert!(matches!(tokenizer.next(), Some(HtmlToken::StartTag { .. })));
+        tokenizer.switch_to(state);
+
+        let mut text = String::new();
+        for t in tokenizer.by_ref() {
+            match t {
+                HtmlToken::Char(c) => text.push(c),
+                t => return (text, Some(t)),
+            }
+        }
+        (text, None)
+    }
+
+    #[test]
+    fn test_rcdata() {
+        let (text, end) = collect_text_in(State::Rcdata, "<title>a<b>&amp;</p></title>");
+        assert_eq!(text, "a<b>&</p>");
+        assert_eq!(
+            end,
+            Some(HtmlToken::EndTag {
+                tag: "title".to_string()
+            })
+        );
+
+        let (text, end) = collect_text_in(State::Rcdata, "<textarea></TEXTAREA >");
+        assert_eq!(text, "");
+        assert_eq!(
+            end,
+            Some(HtmlToken::EndTag {
+                tag: "textarea".to_string()
+            })
+        );
+
+        let (text, end) = collect_text_in(State::Rcdata, "<title>a</tit");
+        assert_eq!(text, "a</tit");
+        assert_eq!(end, None);
+    }
+
+    #[test]
+    fn test_rawtext() {
+        let (text, end) = collect_text_in(State::Rawtext, "<style>a > b &amp; <c></style>");
+        assert_eq!(text, "a > b &amp; <c>");
+        assert_eq!(
+            end,
+            Some(HtmlToken::EndTag {
+                tag: "style".to_string()
+            })
+        );
+
+        let (text, _) = collect_text_in(State::Rawtext, "<xmp></xmpx></ x></xmp>");
+        assert_eq!(text, "</xmpx></ x>");
+    }
+
+    #[test]
+    fn test_script_data() {
+        let (text, end) = collect_text_in(State::ScriptData, "<script>if (a<b) {}</script>");
+        assert_eq!(text, "if (a<b) {}");
+        assert_eq!(
+            end,
+            Some(HtmlToken::EndTag {
+                tag: "script".to_string()
+            })
+        );
+
+        let (text, _) = collect_text_in(
+            State::ScriptData,
+            "<script><!--<script></script>--></script>",
+        );
+        assert_eq!(text, "<!--<script></script>-->");
+
+        let (text, _) = collect_text_in(State::ScriptData, "<script><!-- </script>");
+        assert_eq!(text, "<!-- ");
+    }
+
+    #[test]
+    fn test_plaintext() {
+        let (text, end) = collect_text_in(State::Plaintext, "<plaintext></plaintext>\0");
+        assert_eq!(text, "</plaintext>\u{FFFD}");
+        assert_eq!(end, None);
+    }
+
+    fn errors(html: &str) -> Vec<(ParseErrorKind, usize, usize)> {
+        let mut tokenizer = HtmlTokenizer::new(html.to_string());
+        for _ in tokenizer.by_ref() {}
+        tokenizer
+            .errors()
+            .iter()
+            .map(|e| (e.kind(), e.position().line, e.position().column))
+            .collect()
+    }
+
+    #[test]
+    fn test_tag_errors() {
+        assert_eq!(errors("<p>a</p>"), vec![]);
+        assert_eq!(
+            errors("<a b c b>"),
+            vec![(ParseErrorKind::DuplicateAttribute, 1, 9)]
+        );
+        assert_eq!(
+            errors("<a b=>"),
+            vec![(ParseErrorKind::MissingAttributeValue, 1, 6)]
+        );
+        assert_eq!(
+            errors("<a b='c'd>"),
+            vec![(ParseErrorKind::MissingWhitespaceBetweenAttributes, 1, 9)]
+        );
+        assert_eq!(
+            errors("</a b/>"),
+            vec![
+                (ParseErrorKind::EndTagWithAttributes, 1, 5),
+                (ParseErrorKind::EndTagWithTrailingSolidus, 1, 7),
+            ]
+        );
+        assert_eq!(
+            errors("\n<a\n  href='x"),
+            vec![(ParseErrorKind::EofInTag, 3, 10)]
+        );
+        assert_eq!(
+            errors("a < b </>"),
+            vec![
+                (ParseErrorKind::InvalidFirstCharacterOfTagName, 1, 4),
+                (ParseErrorKind::MissingEndTagName, 1, 9),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_comment_and_doctype_errors() {
+        assert_eq!(
+            errors("<!-->"),
+            vec![(ParseErrorKind::AbruptClosingOfEmptyComment, 1, 5)]
+        );
+        assert_eq!(
+            errors("<!-- <!-- -->"),
+            vec![(ParseErrorKind::NestedComment, 1, 10)]
+        );
+        assert_eq!(
+            errors("<!x>"),
+            vec![(ParseErrorKind::IncorrectlyOpenedComment, 1, 3)]
+        );
+        assert_eq!(
+            errors("<!DOCTYPE>"),
+            vec![(ParseErrorKind::MissingDoctypeName, 1, 10)]
+        );
+        assert_eq!(
+            errors("<!DOCTYPE html PUBLIC\"x\">"),
+            vec![(
+                ParseErrorKind::MissingWhitespaceAfterDoctypePublicKeyword,
+                1,
+                22
+            )]
+        );
+        assert_eq!(
+            errors("<!DOCTYPE"),
+            vec![(ParseErrorKind::EofInDoctype, 1, 10)]
+        );
+    }
+
+    #[test]
+    fn test_character_reference_errors() {
+        assert_eq!(
+            errors("&amp &#65 &#; &foo;"),
+            vec![
+                (
+                    ParseErrorKind::MissingSemicolonAfterCharacterReference,
+                    1,
+                    4
+                ),
+                (
+                    ParseErrorKind::MissingSemicolonAfterCharacterReference,
+                    1,
+                    10
+                ),
+                (
+                    ParseErrorKind::AbsenceOfDigitsInNumericCharacterReference,
+                    1,
+                    13
+                ),
+                (ParseErrorKind::UnknownNamedCharacterReference, 1, 19),
+            ]
+        );
+        assert_eq!(
+            errors("&#0;"),
+            vec![(ParseErrorKind::NullCharacterReference, 1, 4)]
+        );
+    }
+
+    #[test]
+    fn test_token_position() {
+        let mut tokenizer = HtmlTokenizer::new("<p>\n  <a href=x>b</a>".to_string());
+        let mut positions = Vec::new();
+        while let Some(t) = tokenizer.next() {
+            let p = tokenizer.token_position();
+            positions.push((t, p.line, p.column));
+        }
+
+        assert_eq!(positions[0].1, 1);
+        assert_eq!(positions[0].2, 1);
+        assert_eq!(positions[1], (HtmlToken::Char('\n'), 1, 4));
+        assert!(matches!(positions[4].0, HtmlToken::StartTag { .. }));
+        assert_eq!((positions[4].1, positions[4].2), (2, 3));
+        assert_eq!(positions[5], (HtmlToken::Char('b'), 2, 13));
+        assert_eq!((positions[6].1, positions[6].2), (2, 14));
+    }
+
+    #[test]
+    fn test_never_panics() {
+        let inputs = [
+            "<a b='c' d=\"e\" f=g h/ i>",
+            "</a b c=d/>",
+            "<!DOCTYPE html PUBLIC \"a\" 'b' c>",
+            "<!-- a <!-- b --!> c -- d --->",
+            "<? x ?><!x></ x>",
+            "&#x110000;&#xD800;&#0;&amp &notit; &#",
+            "<a\0 b\0='\0' c=\0>\0",
+        ];
+        for input in inputs {
+            let chars: Vec<char> = input.chars().collect();
+            for i in 0..=chars.len() {
+                let prefix: String = chars[..i].iter().collect();
+                for state in [State::Data, State::Rcdata, State::ScriptData] {
+                    let mut tokenizer = HtmlTokenizer::new(prefix.clone());
+                    tokenizer.switch_to(state);
+                    for _ in tokenizer.by_ref() {}
+                }
+            }
+        }
+    }
+
+    fn tokenize_in_chunks(html: &str, chunk_size: usize) -> (Vec<HtmlToken>, Vec<ParseError>) {
+        let mut tokenizer = HtmlTokenizer::new_streaming();
+        let mut tokens = Vec::new();
+        for chunk in html.as_bytes().chunks(chunk_size) {
+            tokenizer.push_bytes(chunk);
+            tokens.extend(tokenizer.by_ref());
+        }
+        tokenizer.finish();
+        tokens.extend(tokenizer.by_ref());
+        (tokens, tokenizer.errors())
+    }
+
+    #[test]
+    fn test_streaming_matches_complete_input() {
+        let html = "<!DOCTYPE html PUBLIC \"-//W3C//DTD HTML 4.01//EN\">\n\
+                    <!-- コメント --><p class=\"a\" title='&notin; &notit; &amp'>日本語 &#x3042;</p>\
+                    <a href=?a=1&copy=2>x</a><!x><br/ ></a b>&#65";
+        let mut complete = HtmlTokenizer::new(html.to_string());
+        let expected: Vec<HtmlToken> = complete.by_ref().collect();
+
+        for chunk_size in [1, 2, 3, 7, 64] {
+            let (tokens, errors) = tokenize_in_chunks(html, chunk_size);
+            assert_eq!(tokens, expected);
+            assert_eq!(errors, complete.errors());
+        }
+    }
+
+    #[test]
+    fn test_streaming_splits_cr_lf() {
+        let (tokens, _) = tokenize_in_chunks("a\r\nb\r", 2);
+        let expected: Vec<HtmlToken> = "a\nb\n".chars().map(HtmlToken::Char).collect();
+        assert_eq!(tokens, expected);
+    }
+
+    #[test]
+    fn test_streaming_suspends_in_tag() {
+        let mut tokenizer = HtmlTokenizer::new_streaming();
+        tokenizer.push_str("a<p hr");
+        assert_eq!(tokenizer.next(), Some(HtmlToken::Char('a')));
+        assert_eq!(tokenizer.next(), None);
+
+        tokenizer.push_str("ef=x>");
+        let token = tokenizer.next();
+        assert!(matches!(token, Some(HtmlToken::StartTag { ref tag, .. }) if tag == "p"));
+        assert_eq!(tokenizer.next(), None);
+
+        tokenizer.push_str("<!-");
+        assert_eq!(tokenizer.next(), None);
+        tokenizer.finish();
+        assert_eq!(tokenizer.next(), Some(HtmlToken::Comment("-".to_string())));
+        assert_eq!(tokenizer.next(), None);
+    }
+
+    #[test]
+    fn test_streaming_invalid_utf8() {
+        let mut tokenizer = HtmlTokenizer::new_streaming();
+        tokenizer.push_bytes(b"a\xFFb\xE3\x81");
+        tokenizer.finish();
+        let chars: Vec<HtmlToken> = tokenizer.collect();
+        assert_eq!(
+            chars,
+            vec![
+                HtmlToken::Char('a'),
+                HtmlToken::Char('\u{FFFD}'),
+                HtmlToken::Char('b'),
+                HtmlToken::Char('\u{FFFD}'),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_streaming_in_legacy_encoding() {
+        let mut tokenizer = HtmlTokenizer::new_streaming();
+        tokenizer.set_encoding(Encoding::ShiftJis);
+        for b in b"<p title=\x93\xfa>\x96\x7b" {
+            tokenizer.push_bytes(&[*b]);
+        }
+        tokenizer.finish();
+
+        let tokens: Vec<HtmlToken> = tokenizer.collect();
+        assert_eq!(tokens.len(), 2);
+        assert!(
+            matches!(tokens[0], HtmlToken::StartTag { ref attributes, .. }
+            if attributes[0].value() == "\u{65E5}")
+        );
+        assert_eq!(tokens[1], HtmlToken::Char('\u{672C}'));
+    }
 }
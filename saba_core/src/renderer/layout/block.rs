@@ -0,0 +1,406 @@
+//! Lays out block-level boxes in a block formatting context, where they're
+//! stacked vertically and their vertical margins collapse.
+//! https://drafts.csswg.org/css2/#block-formatting
+//! https://drafts.csswg.org/css2/#visudet
+
+use crate::renderer::css::media::Viewport;
+use crate::renderer::css::style::ComputedStyle;
+use crate::renderer::dom::node::Node;
+use crate::renderer::layout::layout_box::{build_layout_tree, EdgeSizes, LayoutBox};
+use alloc::rc::Rc;
+use core::cell::RefCell;
+
+/// Builds the layout tree of `document`, whose elements must have been
+/// styled, and lays it out in `viewport`. The content of every box is then
+/// positioned relative to the viewport.
+///
+/// Inline layout isn't done yet. A block whose content is inline takes up
+/// one line if it has any text, and the inline-level boxes in it are at the
+/// start of its content.
+pub fn layout_document(document: &Rc<RefCell<Node>>, viewport: Viewport) -> Option<LayoutBox> {
+    let mut root = build_layout_tree(document)?;
+    // The root box is in the initial containing block, which has the size of
+    // the viewport. Its margins don't collapse with anything.
+    // https://drafts.csswg.org/css2/#initial-containing-block
+    layout_block(&mut root, viewport.width, Some(viewport.height), true);
+    let d = root.dimensions;
+    let (x, y) = (
+        d.margin.left + d.border.left + d.padding.left,
+        d.margin.top + d.border.top + d.padding.top,
+    );
+    move_to_absolute(&mut root, x, y);
+    Some(root)
+}
+
+/// Vertical margins that collapse into one, which is as large as the
+/// largest positive margin minus the largest negative one.
+/// https://drafts.csswg.org/css2/#collapsing-margins
+#[derive(Debug, Copy, Clone, Default, PartialEq)]
+struct CollapsedMargin {
+    positive: f64,
+    negative: f64,
+}
+
+impl CollapsedMargin {
+    fn new(margin: f64) -> Self {
+        let mut collapsed = Self::default();
+        collapsed.adjoin(margin);
+        collapsed
+    }
+
+    fn adjoin(&mut self, margin: f64) {
+        self.positive = self.positive.max(margin);
+        self.negative = self.negative.min(margin);
+    }
+
+    fn adjoin_collapsed(&mut self, other: CollapsedMargin) {
+        self.adjoin(other.positive);
+        self.adjoin(other.negative);
+    }
+
+    fn resolve(&self) -> f64 {
+        self.positive + self.negative
+    }
+}
+
+/// The margins of a laid out block box that collapse with the ones outside
+/// it. They include the margins of its children that adjoin its own.
+struct OuterMargins {
+    top: CollapsedMargin,
+    bottom: CollapsedMargin,
+    /// The box is empty, so its top and bottom margins adjoin each other
+    /// and collapse together with the margins around it.
+    collapses_through: bool,
+}
+
+/// Lays out the block box `layout_box` in a containing block that's
+/// `containing_width` wide. Percentage heights resolve against
+/// `containing_height`, or are `auto` when that's unknown. The children are
+/// positioned relative to the content of `layout_box`, which its parent then
+/// positions.
+/// https://drafts.csswg.org/css2/#normal-block
+fn layout_block(
+    layout_box: &mut LayoutBox,
+    containing_width: f64,
+    containing_height: Option<f64>,
+    is_root: bool,
+) -> OuterMargins {
+    let style = &layout_box.style;
+    // Percentages of margins and padding are of the containing block's
+    // width, even on the top and bottom.
+    let length = |name: &str| style.length(name).map(|l| l.resolve(containing_width));
+    let padding = EdgeSizes {
+        top: length("padding-top").unwrap_or_default(),
+        right: length("padding-right").unwrap_or_default(),
+        bottom: length("padding-bottom").unwrap_or_default(),
+        left: length("padding-left").unwrap_or_default(),
+    };
+    let border = EdgeSizes {
+        top: length("border-top-width").unwrap_or_default(),
+        right: length("border-right-width").unwrap_or_default(),
+        bottom: length("border-bottom-width").unwrap_or_default(),
+        left: length("border-left-width").unwrap_or_default(),
+    };
+    let (margin_left, width, margin_right) = compute_width(
+        containing_width,
+        length("margin-left"),
+        length("width"),
+        length("margin-right"),
+        border.left + padding.left + padding.right + border.right,
+    );
+    let margin = EdgeSizes {
+        // `auto` is 0 for vertical margins.
+        // https://drafts.csswg.org/css2/#normal-block
+        top: length("margin-top").unwrap_or_default(),
+        right: margin_right,
+        bottom: length("margin-bottom").unwrap_or_default(),
+        left: margin_left,
+    };
+    let height = compute_height(style, containing_height);
+
+    // The margins of the first and last children adjoin the box's own unless
+    // border or padding separates them. The root establishes a new block
+    // formatting context, so its margins never do.
+    // https://drafts.csswg.org/css2/#collapsing-margins
+    let top_adjoins = !is_root && border.top == 0.0 && padding.top == 0.0;
+    let bottom_adjoins = !is_root && border.bottom == 0.0 && padding.bottom == 0.0;
+
+    let mut top = CollapsedMargin::new(margin.top);
+    let mut bottom = CollapsedMargin::new(margin.bottom);
+    // The bottom of the last child's border box.
+    let mut cursor = 0.0;
+    // The margins after `cursor` that haven't been applied yet.
+    let mut pending = CollapsedMargin::default();
+    // Whether content has separated the top margin from what follows.
+    let mut has_content = false;
+
+    if layout_box.children.iter().any(|c| c.is_block_level()) {
+        for child in &mut layout_box.children {
+            let outer = layout_block(child, width, height, false);
+            pending.adjoin_collapsed(outer.top);
+
+            // The margins before the first child with content collapse with
+            // the box's own top margin, so the child is at the top.
+            let offset = if !has_content && top_adjoins {
+                0.0
+            } else {
+                pending.resolve()
+            };
+            let d = &mut child.dimensions;
+            let border_top = cursor + offset;
+            d.content.x = d.margin.left + d.border.left + d.padding.left;
+            d.content.y = border_top + d.border.top + d.padding.top;
+
+            if outer.collapses_through {
+                pending.adjoin_collapsed(outer.bottom);
+                continue;
+            }
+            if !has_content && top_adjoins {
+                top.adjoin_collapsed(pending);
+            }
+            has_content = true;
+            cursor = border_top + d.border_box().height;
+            pending = outer.bottom;
+        }
+    } else if layout_box.has_inline_content() {
+        has_content = true;
+        cursor = style.line_height();
+    }
+
+    if !has_content && top_adjoins {
+        top.adjoin_collapsed(pending);
+        pending = CollapsedMargin::default();
+    }
+    let content_height = if has_content && bottom_adjoins && height.is_none() {
+        bottom.adjoin_collapsed(pending);
+        cursor
+    } else {
+        cursor + pending.resolve()
+    };
+    let collapses_through =
+        !has_content && top_adjoins && bottom_adjoins && height.unwrap_or_default() == 0.0;
+
+    let d = &mut layout_box.dimensions;
+    d.content.width = width;
+    d.content.height = height.unwrap_or(content_height);
+    d.padding = padding;
+    d.border = border;
+    d.margin = margin;
+
+    OuterMargins {
+        top,
+        bottom,
+        collapses_through,
+    }
+}
+
+/// Returns the used left margin, width and right margin of a block box,
+/// where `None` is `auto`. They add up to `containing_width` along with
+/// `border_and_padding`. Auto margins share the space that's left, which
+/// centers the box when both are `auto`.
+/// https://drafts.csswg.org/css2/#blockwidth
+fn compute_width(
+    containing_width: f64,
+    margin_left: Option<f64>,
+    width: Option<f64>,
+    margin_right: Option<f64>,
+    border_and_padding: f64,
+) -> (f64, f64, f64) {
+    let (mut margin_left, mut margin_right) = (margin_left, margin_right);
+    if let Some(width) = width {
+        let total = margin_left.unwrap_or_default()
+            + border_and_padding
+            + width
+            + margin_right.unwrap_or_default();
+        if total > containing_width {
+            margin_left = margin_left.or(Some(0.0));
+            margin_right = margin_right.or(Some(0.0));
+        }
+    }
+    let underflow = containing_width
+        - margin_left.unwrap_or_default()
+        - border_and_padding
+        - width.unwrap_or_default()
+        - margin_right.unwrap_or_default();
+
+    match (margin_left, width, margin_right) {
+        // Over-constrained, so the right margin gives way in left-to-right
+        // text.
+        (Some(left), Some(width), Some(right)) => (left, width, right + underflow),
+        (None, Some(width), Some(right)) => (underflow, width, right),
+        (Some(left), Some(width), None) => (left, width, underflow),
+        (None, Some(width), None) => (underflow / 2.0, width, underflow / 2.0),
+        // `auto` margins are 0 when the width is `auto`, and the width can't
+        // be negative.
+        (left, None, right) => {
+            let (left, right) = (left.unwrap_or_default(), right.unwrap_or_default());
+            if underflow >= 0.0 {
+                (left, underflow, right)
+            } else {
+                (left, 0.0, right + underflow)
+            }
+        }
+    }
+}
+
+/// Returns the used height, or `None` if it's `auto` and depends on the
+/// content. A percentage is `auto` unless the containing block's height is
+/// known.
+/// https://drafts.csswg.org/css2/#the-height-property
+fn compute_height(style: &ComputedStyle, containing_height: Option<f64>) -> Option<f64> {
+    let height = style.length("height")?;
+    if height.percent == 0.0 {
+        return Some(height.px);
+    }
+    containing_height.map(|h| height.resolve(h))
+}
+
+/// Moves the content of `layout_box` by `x` and `y`, and then its children
+/// to be relative to it, recursively.
+fn move_to_absolute(layout_box: &mut LayoutBox, x: f64, y: f64) {
+    let content = &mut layout_box.dimensions.content;
+    content.x += x;
+    content.y += y;
+    let (x, y) = (content.x, content.y);
+    for child in &mut layout_box.children {
+        move_to_absolute(child, x, y);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::renderer::css::cascade::StyleEngine;
+    use crate::renderer::html::parser::HtmlParser;
+    use crate::renderer::html::token::HtmlTokenizer;
+    use crate::renderer::layout::layout_box::Rect;
+    use alloc::string::ToString;
+
+    fn layout(html: &str) -> LayoutBox {
+        let window = HtmlParser::new(HtmlTokenizer::new(html.to_string())).construct_tree();
+        let window = window.borrow();
+        let document = window.document();
+        StyleEngine::new(window.style_sheets()).style_document(&document);
+        let viewport = Viewport {
+            width: 800.0,
+            height: 600.0,
+            ..Viewport::default()
+        };
+        layout_document(&document, viewport).expect("no layout tree")
+    }
+
+    /// Returns the box of the element whose ID is `id`.
+    fn find<'a>(layout_box: &'a LayoutBox, id: &str) -> &'a LayoutBox {
+        fn find_box<'a>(layout_box: &'a LayoutBox, id: &str) -> Option<&'a LayoutBox> {
+            let element = layout_box
+                .node
+                .as_ref()
+                .and_then(|n| n.borrow().get_element());
+            if element.is_some_and(|e| e.id() == id) {
+                return Some(layout_box);
+            }
+            layout_box.children.iter().find_map(|c| find_box(c, id))
+        }
+        find_box(layout_box, id).expect("no box")
+    }
+
+    fn rect(x: f64, y: f64, width: f64, height: f64) -> Rect {
+        Rect {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+
+    #[test]
+    fn test_width() {
+        let root = layout(
+            "<body style='margin: 0'>
+             <div id=center style='width: 200px; margin: 0 auto; padding: 10px; border: 5px solid'></div>
+             <div id=right style='width: 100px; margin-left: auto'></div>
+             <div id=over style='width: 100px; margin: 0 50px'></div>
+             <div id=auto style='padding: 0 10px; margin: 0 20px'></div>
+             <div id=wide style='width: 1000px; margin-left: auto'></div>
+             <div id=percent style='width: 50%; margin-left: 10%'></div>",
+        );
+
+        let center = find(&root, "center").dimensions;
+        assert_eq!(center.content, rect(300.0, 15.0, 200.0, 0.0));
+        assert_eq!(center.border_box(), rect(285.0, 0.0, 230.0, 30.0));
+        assert_eq!(center.margin.left, 285.0);
+        assert_eq!(center.margin.right, 285.0);
+
+        assert_eq!(find(&root, "right").dimensions.content.x, 700.0);
+        assert_eq!(find(&root, "over").dimensions.margin.right, 650.0);
+        assert_eq!(
+            find(&root, "auto").dimensions.content,
+            rect(30.0, 30.0, 740.0, 0.0)
+        );
+        let wide = find(&root, "wide").dimensions;
+        assert_eq!((wide.margin.left, wide.margin.right), (0.0, -200.0));
+        let percent = find(&root, "percent").dimensions;
+        assert_eq!((percent.content.x, percent.content.width), (80.0, 400.0));
+    }
+
+    #[test]
+    fn test_margin_collapsing() {
+        let root = layout(
+            "<body id=body style='line-height: 20px'>
+             <p id=p1 style='margin: 20px 0'>a</p>
+             <p id=p2 style='margin: 30px 0 -10px'>b</p>
+             <div id=empty style='margin: 15px 0'></div>
+             <p id=p3 style='margin-top: 5px'>c</p>
+             </body>",
+        );
+
+        // The body's top margin collapses with the first paragraph's.
+        let body = find(&root, "body");
+        let y = |id: &str| find(&root, id).dimensions.border_box().y;
+        assert_eq!(body.dimensions.content.y, 20.0);
+        assert_eq!(y("p1"), 20.0);
+        assert_eq!(y("p2"), 70.0);
+        // The empty block's margins collapse through it, with the negative
+        // margin before it and the positive one after it.
+        assert_eq!(y("empty"), 95.0);
+        assert_eq!(y("p3"), 95.0);
+        // The last paragraph's bottom margin collapses with the body's.
+        assert_eq!(body.dimensions.content.height, 95.0);
+        assert_eq!(root.dimensions.content.height, 131.0);
+    }
+
+    #[test]
+    fn test_margins_separated_by_border() {
+        let root = layout(
+            "<body style='margin: 0'>
+             <div id=outer style='margin-top: 10px; border-top: 1px solid; padding-bottom: 5px'>
+             <p id=inner style='margin: 20px 0'>a</p>
+             </div>",
+        );
+        let outer = find(&root, "outer").dimensions;
+        let inner = find(&root, "inner").dimensions;
+        assert_eq!(outer.border_box().y, 10.0);
+        assert_eq!(inner.border_box().y, 31.0);
+        // The padding keeps the bottom margin inside.
+        assert_eq!(outer.content.height, 20.0 + inner.content.height + 20.0);
+    }
+
+    #[test]
+    fn test_height() {
+        let root = layout(
+            "<html style='height: 50%'><body id=body style='margin: 0; height: 50%'>
+             <div id=fixed style='height: 30px; padding: 5px'><p>a</p></div>
+             <div id=auto><div id=percent style='height: 50%'>a</div></div>",
+        );
+        assert_eq!(root.dimensions.content.height, 300.0);
+        assert_eq!(find(&root, "body").dimensions.content.height, 150.0);
+        assert_eq!(find(&root, "fixed").dimensions.content.height, 30.0);
+        // A percentage of an `auto` height is `auto`.
+        let percent = find(&root, "percent").dimensions;
+        assert_eq!(
+            percent.content.height,
+            find(&root, "auto").dimensions.content.height
+        );
+        assert_eq!(percent.content.y, 40.0);
+    }
+}
@@ -0,0 +1,311 @@
+//! The layout tree, which has a box for each element and text that's
+//! rendered.
+//! https://drafts.csswg.org/css-display-3/#box-tree
+
+use crate::renderer::css::style::{ComputedStyle, Display};
+use crate::renderer::dom::node::{Node, NodeKind};
+use alloc::rc::Rc;
+use alloc::vec::Vec;
+use core::cell::RefCell;
+
+#[derive(Debug, Copy, Clone, Default, PartialEq)]
+pub struct Rect {
+    pub x: f64,
+    pub y: f64,
+    pub width: f64,
+    pub height: f64,
+}
+
+impl Rect {
+    /// Returns the rectangle grown by `edges` on each side.
+    pub fn expanded_by(&self, edges: EdgeSizes) -> Rect {
+        Rect {
+            x: self.x - edges.left,
+            y: self.y - edges.top,
+            width: self.width + edges.left + edges.right,
+            height: self.height + edges.top + edges.bottom,
+        }
+    }
+}
+
+/// The sizes of the margin, border or padding on each side of a box.
+#[derive(Debug, Copy, Clone, Default, PartialEq)]
+pub struct EdgeSizes {
+    pub top: f64,
+    pub right: f64,
+    pub bottom: f64,
+    pub left: f64,
+}
+
+/// The position and size of a box and the areas around its content. After
+/// layout, `content` is relative to the top left corner of the viewport.
+/// https://drafts.csswg.org/css-box-3/#box-model
+#[derive(Debug, Copy, Clone, Default, PartialEq)]
+pub struct Dimensions {
+    pub content: Rect,
+    pub padding: EdgeSizes,
+    pub border: EdgeSizes,
+    pub margin: EdgeSizes,
+}
+
+impl Dimensions {
+    pub fn padding_box(&self) -> Rect {
+        self.content.expanded_by(self.padding)
+    }
+
+    /// The area that the background and the border are painted in.
+    pub fn border_box(&self) -> Rect {
+        self.padding_box().expanded_by(self.border)
+    }
+
+    pub fn margin_box(&self) -> Rect {
+        self.border_box().expanded_by(self.margin)
+    }
+}
+
+/// https://drafts.csswg.org/css-display-3/#outer-role
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum BoxKind {
+    /// A block-level box of an element, like `<p>`.
+    Block,
+    /// A block box that wraps the inline-level boxes next to block-level
+    /// ones, since a box holds either block-level or inline-level boxes.
+    /// https://drafts.csswg.org/css2/#anonymous-block-level
+    AnonymousBlock,
+    /// An inline-level box of an element, like `<span>`.
+    Inline,
+    /// The text of a text node.
+    Text,
+}
+
+#[derive(Debug, Clone)]
+pub struct LayoutBox {
+    pub kind: BoxKind,
+    /// The element or text node that generated the box. Anonymous boxes
+    /// have none.
+    pub node: Option<Rc<RefCell<Node>>>,
+    /// The style of the element, or the style that a text or an anonymous
+    /// box inherits.
+    pub style: ComputedStyle,
+    pub dimensions: Dimensions,
+    pub children: Vec<LayoutBox>,
+}
+
+impl LayoutBox {
+    fn new(
+        kind: BoxKind,
+        node: Option<Rc<RefCell<Node>>>,
+        style: ComputedStyle,
+        children: Vec<LayoutBox>,
+    ) -> Self {
+        Self {
+            kind,
+            node,
+            style,
+            dimensions: Dimensions::default(),
+            children,
+        }
+    }
+
+    pub fn is_block_level(&self) -> bool {
+        matches!(self.kind, BoxKind::Block | BoxKind::AnonymousBlock)
+    }
+
+    /// Returns whether the box holds only collapsible white space, which
+    /// doesn't render anything and doesn't need an anonymous block.
+    /// https://drafts.csswg.org/css-text-3/#white-space-phase-1
+    fn is_collapsible_whitespace(&self) -> bool {
+        let node = match (&self.kind, &self.node) {
+            (BoxKind::Text, Some(node)) => node,
+            _ => return false,
+        };
+        let collapses = matches!(
+            self.style.keyword("white-space"),
+            None | Some("normal") | Some("nowrap")
+        );
+        let is_whitespace = match node.borrow().kind {
+            NodeKind::Text(ref text) => text.chars().all(|c| c.is_ascii_whitespace()),
+            _ => false,
+        };
+        collapses && is_whitespace
+    }
+
+    /// Returns whether the box holds anything that renders on a line, which
+    /// for now is any text but collapsible white space.
+    pub fn has_inline_content(&self) -> bool {
+        match self.kind {
+            BoxKind::Text => !self.is_collapsible_whitespace(),
+            _ => self.children.iter().any(|c| c.has_inline_content()),
+        }
+    }
+}
+
+/// Builds the layout tree of `document`, whose elements must have been
+/// styled with `StyleEngine::style_document()`. Returns `None` if the root
+/// element isn't rendered.
+pub fn build_layout_tree(document: &Rc<RefCell<Node>>) -> Option<LayoutBox> {
+    let root = document
+        .borrow()
+        .children()
+        .into_iter()
+        .find(|n| n.borrow().get_element().is_some())?;
+    let mut root_box = build_box(&root, None)?;
+    // The root element's box is always a block box.
+    // https://drafts.csswg.org/css-display-3/#root
+    if root_box.kind != BoxKind::Block {
+        root_box.kind = BoxKind::Block;
+        let style = root_box.style.clone();
+        root_box.children = wrap_inline_runs(core::mem::take(&mut root_box.children), &style);
+    }
+    Some(root_box)
+}
+
+/// Builds the box of `node` and its descendants, or returns `None` if it
+/// generates none, like an element with `display: none`. A text node takes
+/// `parent_style`, the style of its parent element.
+/// https://drafts.csswg.org/css-display-3/#box-generation
+fn build_box(node: &Rc<RefCell<Node>>, parent_style: Option<&ComputedStyle>) -> Option<LayoutBox> {
+    let n = node.borrow();
+    match n.kind {
+        NodeKind::Element(_) => {
+            let style = n.computed_style()?;
+            let kind = match style.display() {
+                Display::None => return None,
+                Display::Block | Display::ListItem => BoxKind::Block,
+                Display::Inline | Display::InlineBlock => BoxKind::Inline,
+            };
+            let children: Vec<LayoutBox> = n
+                .children()
+                .iter()
+                .filter_map(|c| build_box(c, Some(&style)))
+                .collect();
+
+            // Splitting an inline box around a block in it isn't supported,
+            // so such an inline box is laid out like a block box instead.
+            // https://drafts.csswg.org/css2/#anonymous-block-level
+            let kind = if children.iter().any(|c| c.is_block_level()) {
+                BoxKind::Block
+            } else {
+                kind
+            };
+            let children = match kind {
+                BoxKind::Block => wrap_inline_runs(children, &style),
+                _ => children,
+            };
+            Some(LayoutBox::new(kind, Some(node.clone()), style, children))
+        }
+        NodeKind::Text(ref text) if !text.is_empty() => Some(LayoutBox::new(
+            BoxKind::Text,
+            Some(node.clone()),
+            parent_style?.clone(),
+            Vec::new(),
+        )),
+        _ => None,
+    }
+}
+
+/// Wraps each run of inline-level boxes among block-level ones in an
+/// anonymous block box. A run of only collapsible white space, like the
+/// newlines between `<p>` elements, is dropped.
+fn wrap_inline_runs(children: Vec<LayoutBox>, style: &ComputedStyle) -> Vec<LayoutBox> {
+    if !children.iter().any(|c| c.is_block_level()) {
+        return children;
+    }
+
+    let mut wrapped = Vec::new();
+    let mut run: Vec<LayoutBox> = Vec::new();
+    let flush = |run: &mut Vec<LayoutBox>, wrapped: &mut Vec<LayoutBox>| {
+        if run.iter().all(|b| b.is_collapsible_whitespace()) {
+            run.clear();
+            return;
+        }
+        wrapped.push(LayoutBox::new(
+            BoxKind::AnonymousBlock,
+            None,
+            ComputedStyle::anonymous(style),
+            core::mem::take(run),
+        ));
+    };
+    for child in children {
+        if child.is_block_level() {
+            flush(&mut run, &mut wrapped);
+            wrapped.push(child);
+        } else {
+            run.push(child);
+        }
+    }
+    flush(&mut run, &mut wrapped);
+    wrapped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::renderer::css::cascade::StyleEngine;
+    use crate::renderer::html::parser::HtmlParser;
+    use crate::renderer::html::token::HtmlTokenizer;
+    use alloc::format;
+    use alloc::string::{String, ToString};
+
+    fn build(html: &str) -> Option<LayoutBox> {
+        let window = HtmlParser::new(HtmlTokenizer::new(html.to_string())).construct_tree();
+        let window = window.borrow();
+        let document = window.document();
+        StyleEngine::new(window.style_sheets()).style_document(&document);
+        build_layout_tree(&document)
+    }
+
+    /// Describes the kinds of the boxes in the tree, like `Block(Text)`.
+    fn shape(layout_box: &LayoutBox) -> String {
+        let kind = format!("{:?}", layout_box.kind);
+        if layout_box.children.is_empty() {
+            return kind;
+        }
+        let children: Vec<String> = layout_box.children.iter().map(shape).collect();
+        format!("{}({})", kind, children.join(" "))
+    }
+
+    #[test]
+    fn test_build_layout_tree() {
+        let root = build(
+            "<div>a <span>b</span><p>c</p>  <b>d</b></div>
+             <p style='display: none'>e</p>
+             <p>f</p>",
+        )
+        .expect("no layout tree");
+        assert_eq!(
+            shape(&root),
+            "Block(Block(Block(AnonymousBlock(Text Inline(Text)) Block(Text) \
+             AnonymousBlock(Text Inline(Text))) Block(Text)))"
+        );
+
+        // The anonymous block inherits from the `<div>`.
+        let div = &root.children[0].children[0];
+        let anonymous = &div.children[0];
+        assert_eq!(anonymous.node, None);
+        assert_eq!(anonymous.style.font_size(), div.style.font_size());
+        assert_eq!(
+            anonymous.style.length("margin-top").map(|l| l.px),
+            Some(0.0)
+        );
+    }
+
+    #[test]
+    fn test_inline_and_none() {
+        // An inline box with a block in it is laid out as a block.
+        let root = build("<span>a<div>b</div></span><i>c</i>").expect("no layout tree");
+        assert_eq!(
+            shape(&root),
+            "Block(Block(Block(AnonymousBlock(Text) Block(Text)) AnonymousBlock(Inline(Text))))"
+        );
+
+        // White space that's preserved needs an anonymous block.
+        let root = build("<pre><p>a</p> </pre>").expect("no layout tree");
+        assert_eq!(
+            shape(&root),
+            "Block(Block(Block(Block(Text) AnonymousBlock(Text))))"
+        );
+
+        assert!(build("<html style='display: none'>").is_none());
+    }
+}
@@ -1 +1,4 @@
+pub mod css;
+pub mod dom;
 pub mod html;
+pub mod layout;
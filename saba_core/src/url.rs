@@ -1,3 +1,4 @@
+use alloc::format;
 use alloc::string::{String, ToString};
 use alloc::vec::Vec;
 
@@ -113,6 +114,99 @@ impl Url {
     pub fn searchpart(&self) -> String {
         self.searchpart.clone()
     }
+
+    /// Returns the whole URL as it was given to `new()`.
+    pub fn url(&self) -> String {
+        self.url.clone()
+    }
+
+    /// Resolves `input`, which may be relative like `href` attributes are,
+    /// against this URL. Fragments are dropped, since `Url` doesn't keep them.
+    /// https://url.spec.whatwg.org/#concept-basic-url-parser
+    pub fn join(&self, input: &str) -> Result<Self, String> {
+        let input: String = input
+            .trim_matches(|c: char| c <= ' ')
+            .chars()
+            .filter(|c| !matches!(c, '\t' | '\n' | '\r'))
+            .collect();
+        let input = match input.find('#') {
+            Some(i) => &input[..i],
+            None => &input[..],
+        };
+
+        if let Some(i) = input.find(':') {
+            let scheme = &input[..i];
+            let is_scheme = scheme.starts_with(|c: char| c.is_ascii_alphabetic())
+                && scheme
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || matches!(c, '+' | '-' | '.'));
+            if is_scheme {
+                if !scheme.eq_ignore_ascii_case("http") {
+                    return Err("Only HTTP is supported".to_string());
+                }
+                return Self::new(format!("http:{}", &input[i + 1..])).parse();
+            }
+        }
+
+        let origin = if self.port == "80" {
+            format!("http://{}", self.host)
+        } else {
+            format!("http://{}:{}", self.host, self.port)
+        };
+
+        let (path, searchpart) = if let Some(rest) = input.strip_prefix("//") {
+            return Self::new(format!("http://{}", rest)).parse();
+        } else if input.is_empty() {
+            (self.path.clone(), self.searchpart.clone())
+        } else if let Some(searchpart) = input.strip_prefix('?') {
+            (self.path.clone(), searchpart.to_string())
+        } else {
+            let (path, searchpart) = match input.split_once('?') {
+                Some((path, searchpart)) => (path, searchpart.to_string()),
+                None => (input, String::new()),
+            };
+            let path = match path.strip_prefix('/') {
+                Some(absolute) => absolute.to_string(),
+                // Relative paths replace the last segment of the base path.
+                None => match self.path.rfind('/') {
+                    Some(i) => format!("{}{}", &self.path[..i + 1], path),
+                    None => path.to_string(),
+                },
+            };
+            (remove_dot_segments(&path), searchpart)
+        };
+
+        if searchpart.is_empty() {
+            Self::new(format!("{}/{}", origin, path)).parse()
+        } else {
+            Self::new(format!("{}/{}?{}", origin, path, searchpart)).parse()
+        }
+    }
+}
+
+/// Resolves `.` and `..` segments in `path`, which has no leading `/`.
+/// https://url.spec.whatwg.org/#path-state
+fn remove_dot_segments(path: &str) -> String {
+    let mut segments: Vec<&str> = Vec::new();
+    let mut parts = path.split('/').peekable();
+    while let Some(part) = parts.next() {
+        let is_last = parts.peek().is_none();
+        match part {
+            "." | "%2e" | "%2E" => {
+                if is_last {
+                    segments.push("");
+                }
+            }
+            ".." | ".%2e" | ".%2E" | "%2e." | "%2E." | "%2e%2e" | "%2E%2E" => {
+                segments.pop();
+                if is_last {
+                    segments.push("");
+                }
+            }
+            _ => segments.push(part),
+        }
+    }
+    segments.join("/")
 }
 
 #[cfg(test)]
@@ -171,4 +265,54 @@ mod tests {
         let expected = Err("Only HTTP is supported".to_string());
         assert_eq!(expected, Url::new(url).parse());
     }
+
+    #[test]
+    fn test_join() {
+        let base = Url::new("http://example.com:8080/a/b/c.html?q=1".to_string())
+            .parse()
+            .expect("failed to parse");
+        let join = |input: &str| base.join(input).map(|u| u.url());
+
+        assert_eq!(
+            join("d.html"),
+            Ok("http://example.com:8080/a/b/d.html".to_string())
+        );
+        assert_eq!(
+            join("./d/"),
+            Ok("http://example.com:8080/a/b/d/".to_string())
+        );
+        assert_eq!(
+            join("../d?x#y"),
+            Ok("http://example.com:8080/a/d?x".to_string())
+        );
+        assert_eq!(
+            join("../../../d"),
+            Ok("http://example.com:8080/d".to_string())
+        );
+        assert_eq!(join("/d"), Ok("http://example.com:8080/d".to_string()));
+        assert_eq!(
+            join("?x"),
+            Ok("http://example.com:8080/a/b/c.html?x".to_string())
+        );
+        assert_eq!(
+            join(""),
+            Ok("http://example.com:8080/a/b/c.html?q=1".to_string())
+        );
+        assert_eq!(
+            join(" #top "),
+            Ok("http://example.com:8080/a/b/c.html?q=1".to_string())
+        );
+        assert_eq!(join("//other.com/d"), Ok("http://other.com/d".to_string()));
+        assert_eq!(join("HTTP://other.com"), Ok("http://other.com".to_string()));
+        assert_eq!(
+            join("mailto:a@b"),
+            Err("Only HTTP is supported".to_string())
+        );
+
+        let resolved = base.join("/x/y?z").expect("failed to join");
+        assert_eq!(resolved.host(), "example.com");
+        assert_eq!(resolved.port(), "8080");
+        assert_eq!(resolved.path(), "x/y");
+        assert_eq!(resolved.searchpart(), "z");
+    }
 }
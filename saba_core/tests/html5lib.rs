@@ -0,0 +1,714 @@
+//! Runs the html5lib-tests fixtures in `tests/html5lib` against the tokenizer
+//! and the tree builder.
+//! https://github.com/html5lib/html5lib-tests
+//!
+//! Each fixture file reports how many of its tests passed. A failing test
+//! makes the run fail unless it is listed in `tests/html5lib/known_failures`,
+//! so that regressions are caught while unsupported features are tracked.
+//! Run with `cargo test --test html5lib -- --nocapture` to see the counts.
+//! `tests/html5lib/README.md` says where the fixtures come from.
+
+use saba_core::renderer::dom::node::{append_child, Element, Namespace, Node, NodeKind};
+use saba_core::renderer::html::parser::HtmlParser;
+use saba_core::renderer::html::token::{HtmlToken, HtmlTokenizer, State};
+use std::cell::RefCell;
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+/// A JSON value in a `.test` file.
+#[derive(Debug, Clone, PartialEq)]
+enum Json {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>),
+}
+
+impl Json {
+    fn get(&self, key: &str) -> Option<&Json> {
+        match self {
+            Json::Object(members) => members.iter().find(|(k, _)| k == key).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    fn as_str(&self) -> Option<&str> {
+        match self {
+            Json::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    fn as_array(&self) -> &[Json] {
+        match self {
+            Json::Array(values) => values,
+            _ => &[],
+        }
+    }
+
+    /// Sorts the members of objects so that attributes compare regardless of
+    /// their order, and decodes the escapes of `doubleEscaped` tests.
+    fn normalize(self, double_escaped: bool) -> Json {
+        match self {
+            Json::String(s) if double_escaped => Json::String(unescape(&s)),
+            Json::Array(values) => Json::Array(
+                values
+                    .into_iter()
+                    .map(|v| v.normalize(double_escaped))
+                    .collect(),
+            ),
+            Json::Object(members) => {
+                let mut members: Vec<(String, Json)> = members
+                    .into_iter()
+                    .map(|(k, v)| {
+                        let k = if double_escaped { unescape(&k) } else { k };
+                        (k, v.normalize(double_escaped))
+                    })
+                    .collect();
+                members.sort_by(|a, b| a.0.cmp(&b.0));
+                Json::Object(members)
+            }
+            v => v,
+        }
+    }
+}
+
+/// Decodes the `\uXXXX` escapes that are left in the strings of
+/// `doubleEscaped` tests. Lone surrogates become U+FFFD.
+fn unescape(s: &str) -> String {
+    let chars: Vec<char> = s.chars().collect();
+    let mut out = String::new();
+    let mut i = 0;
+    while i < chars.len() {
+        if chars[i] == '\\' && chars.get(i + 1) == Some(&'u') && i + 6 <= chars.len() {
+            let hex: String = chars[i + 2..i + 6].iter().collect();
+            if let Ok(code) = u32::from_str_radix(&hex, 16) {
+                out.push(char::from_u32(code).unwrap_or('\u{FFFD}'));
+                i += 6;
+                continue;
+            }
+        }
+        out.push(chars[i]);
+        i += 1;
+    }
+    out
+}
+
+struct JsonParser<'a> {
+    chars: std::iter::Peekable<std::str::Chars<'a>>,
+}
+
+impl<'a> JsonParser<'a> {
+    fn parse(s: &'a str) -> Result<Json, String> {
+        let mut parser = JsonParser {
+            chars: s.chars().peekable(),
+        };
+        let value = parser.value()?;
+        parser.skip_whitespace();
+        match parser.chars.next() {
+            None => Ok(value),
+            Some(c) => Err(format!("unexpected {:?} after the value", c)),
+        }
+    }
+
+    fn skip_whitespace(&mut self) {
+        while matches!(self.chars.peek(), Some(c) if c.is_whitespace()) {
+            self.chars.next();
+        }
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), String> {
+        self.skip_whitespace();
+        match self.chars.next() {
+            Some(c) if c == expected => Ok(()),
+            c => Err(format!("expected {:?} but got {:?}", expected, c)),
+        }
+    }
+
+    fn keyword(&mut self, keyword: &str, value: Json) -> Result<Json, String> {
+        for expected in keyword.chars() {
+            if self.chars.next() != Some(expected) {
+                return Err(format!("expected {}", keyword));
+            }
+        }
+        Ok(value)
+    }
+
+    fn value(&mut self) -> Result<Json, String> {
+        self.skip_whitespace();
+        match self.chars.peek() {
+            Some('n') => self.keyword("null", Json::Null),
+            Some('t') => self.keyword("true", Json::Bool(true)),
+            Some('f') => self.keyword("false", Json::Bool(false)),
+            Some('"') => Ok(Json::String(self.string()?)),
+            Some('[') => {
+                self.chars.next();
+                let mut values = Vec::new();
+                self.skip_whitespace();
+                if self.chars.peek() == Some(&']') {
+                    self.chars.next();
+                    return Ok(Json::Array(values));
+                }
+                loop {
+                    values.push(self.value()?);
+                    self.skip_whitespace();
+                    match self.chars.next() {
+                        Some(',') => continue,
+                        Some(']') => return Ok(Json::Array(values)),
+                        c => return Err(format!("unexpected {:?} in an array", c)),
+                    }
+                }
+            }
+            Some('{') => {
+                self.chars.next();
+                let mut members = Vec::new();
+                self.skip_whitespace();
+                if self.chars.peek() == Some(&'}') {
+                    self.chars.next();
+                    return Ok(Json::Object(members));
+                }
+                loop {
+                    self.skip_whitespace();
+                    let key = self.string()?;
+                    self.expect(':')?;
+                    members.push((key, self.value()?));
+                    self.skip_whitespace();
+                    match self.chars.next() {
+                        Some(',') => continue,
+                        Some('}') => return Ok(Json::Object(members)),
+                        c => return Err(format!("unexpected {:?} in an object", c)),
+                    }
+                }
+            }
+            Some(c) if *c == '-' || c.is_ascii_digit() => {
+                let mut number = String::new();
+                while let Some(c) = self.chars.peek() {
+                    if !(c.is_ascii_digit() || matches!(c, '-' | '+' | '.' | 'e' | 'E')) {
+                        break;
+                    }
+                    number.push(*c);
+                    self.chars.next();
+                }
+                number
+                    .parse()
+                    .map(Json::Number)
+                    .map_err(|_| format!("invalid number {}", number))
+            }
+            c => Err(format!("unexpected {:?}", c)),
+        }
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        if self.chars.next() != Some('"') {
+            return Err("expected a string".to_string());
+        }
+
+        let mut s = String::new();
+        // A high surrogate waiting for the low surrogate of its pair.
+        let mut high_surrogate = None;
+        loop {
+            let c = self.chars.next().ok_or("unterminated string")?;
+            let c = match c {
+                '"' => break,
+                '\\' => match self.chars.next() {
+                    Some('u') => {
+                        let hex: String = self.chars.by_ref().take(4).collect();
+                        let code = u32::from_str_radix(&hex, 16)
+                            .map_err(|_| format!("invalid escape \\u{}", hex))?;
+                        match (high_surrogate.take(), code) {
+                            (None, 0xD800..=0xDBFF) => {
+                                high_surrogate = Some(code);
+                                continue;
+                            }
+                            (Some(high), 0xDC00..=0xDFFF) => {
+                                let code = 0x10000 + ((high - 0xD800) << 10) + (code - 0xDC00);
+                                s.push(char::from_u32(code).unwrap_or('\u{FFFD}'));
+                                continue;
+                            }
+                            (Some(_), _) => {
+                                s.push('\u{FFFD}');
+                                char::from_u32(code).unwrap_or('\u{FFFD}')
+                            }
+                            (None, _) => char::from_u32(code).unwrap_or('\u{FFFD}'),
+                        }
+                    }
+                    Some('n') => '\n',
+                    Some('t') => '\t',
+                    Some('r') => '\r',
+                    Some('b') => '\u{8}',
+                    Some('f') => '\u{C}',
+                    Some(c) => c,
+                    None => return Err("unterminated string".to_string()),
+                },
+                c => c,
+            };
+            if high_surrogate.take().is_some() {
+                s.push('\u{FFFD}');
+            }
+            s.push(c);
+        }
+        if high_surrogate.is_some() {
+            s.push('\u{FFFD}');
+        }
+        Ok(s)
+    }
+}
+
+/// The result of running the tests in one fixture file.
+#[derive(Default)]
+struct Summary {
+    passed: usize,
+    skipped: usize,
+    /// The names of the failed tests, with the reason.
+    failed: Vec<(String, String)>,
+}
+
+fn fixtures(dir: &str, extension: &str) -> Vec<PathBuf> {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/html5lib")
+        .join(dir);
+    let mut paths: Vec<PathBuf> = fs::read_dir(&dir)
+        .unwrap_or_else(|e| panic!("failed to read {}: {}", dir.display(), e))
+        .map(|entry| entry.expect("failed to read a directory entry").path())
+        .filter(|path| path.extension().map_or(false, |e| e == extension))
+        .collect();
+    paths.sort();
+    paths
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name()
+        .expect("no file name")
+        .to_string_lossy()
+        .into_owned()
+}
+
+/// Returns the names of the tests that are known to fail, such as
+/// `tests1.dat:12`.
+fn known_failures() -> BTreeSet<String> {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/html5lib/known_failures");
+    fs::read_to_string(path)
+        .unwrap_or_default()
+        .lines()
+        .map(|line| line.split('#').next().unwrap_or("").trim())
+        .filter(|line| !line.is_empty())
+        .map(String::from)
+        .collect()
+}
+
+/// Prints the summary of each file and fails on the failures that aren't
+/// known.
+fn report(summaries: Vec<(String, Summary)>) {
+    let known_failures = known_failures();
+    let mut unexpected = Vec::new();
+    let mut fixed = Vec::new();
+
+    for (file, summary) in &summaries {
+        let total = summary.passed + summary.failed.len();
+        println!(
+            "{}: {}/{} passed, {} skipped",
+            file, summary.passed, total, summary.skipped
+        );
+        for (name, reason) in &summary.failed {
+            if !known_failures.contains(name) {
+                unexpected.push(format!("{}\n{}", name, reason));
+            }
+        }
+    }
+
+    let failed: BTreeSet<&String> = summaries
+        .iter()
+        .flat_map(|(_, s)| s.failed.iter().map(|(name, _)| name))
+        .collect();
+    for name in &known_failures {
+        let file = name.split(':').next().unwrap_or("");
+        if summaries.iter().any(|(f, _)| f == file) && !failed.contains(name) {
+            fixed.push(name.clone());
+        }
+    }
+    if !fixed.is_empty() {
+        println!(
+            "passing tests that are listed in known_failures: {}",
+            fixed.join(", ")
+        );
+    }
+
+    assert!(
+        unexpected.is_empty(),
+        "{} unexpected failures:\n\n{}",
+        unexpected.len(),
+        unexpected.join("\n\n")
+    );
+}
+
+fn initial_state(name: &str) -> Option<State> {
+    match name {
+        "Data state" => Some(State::Data),
+        "PLAINTEXT state" => Some(State::Plaintext),
+        "RCDATA state" => Some(State::Rcdata),
+        "RAWTEXT state" => Some(State::Rawtext),
+        "Script data state" => Some(State::ScriptData),
+        _ => None,
+    }
+}
+
+/// Converts tokens to the format of the `output` of tokenizer tests, in which
+/// adjacent characters are one token.
+fn tokens_to_json(tokens: Vec<HtmlToken>) -> Json {
+    let mut output = Vec::new();
+    let mut text = String::new();
+
+    for token in tokens {
+        if let HtmlToken::Char(c) = token {
+            text.push(c);
+            continue;
+        }
+        if !text.is_empty() {
+            output.push(Json::Array(vec![
+                Json::String("Character".to_string()),
+                Json::String(std::mem::take(&mut text)),
+            ]));
+        }
+
+        let string = |s: &str| Json::String(s.to_string());
+        let optional = |s: Option<String>| s.map_or(Json::Null, Json::String);
+        match token {
+            HtmlToken::StartTag {
+                tag,
+                self_closing,
+                attributes,
+            } => {
+                let attributes = attributes
+                    .iter()
+                    .map(|a| (a.name(), Json::String(a.value())))
+                    .collect();
+                let mut values = vec![
+                    string("StartTag"),
+                    Json::String(tag),
+                    Json::Object(attributes),
+                ];
+                if self_closing {
+                    values.push(Json::Bool(true));
+                }
+                output.push(Json::Array(values));
+            }
+            HtmlToken::EndTag { tag } => {
+                output.push(Json::Array(vec![string("EndTag"), Json::String(tag)]));
+            }
+            HtmlToken::Comment(data) => {
+                output.push(Json::Array(vec![string("Comment"), Json::String(data)]));
+            }
+            HtmlToken::Doctype {
+                name,
+                public_id,
+                system_id,
+                force_quirks,
+            } => output.push(Json::Array(vec![
+                string("DOCTYPE"),
+                optional(name),
+                optional(public_id),
+                optional(system_id),
+                Json::Bool(!force_quirks),
+            ])),
+            HtmlToken::Char(_) | HtmlToken::Eof => {}
+        }
+    }
+
+    if !text.is_empty() {
+        output.push(Json::Array(vec![
+            Json::String("Character".to_string()),
+            Json::String(text),
+        ]));
+    }
+    Json::Array(output).normalize(false)
+}
+
+/// Runs one tokenizer test in one initial state, and returns why it failed.
+fn run_tokenizer_test(test: &Json, state: State) -> Result<(), String> {
+    let double_escaped = test.get("doubleEscaped") == Some(&Json::Bool(true));
+    let unescape_if_needed = |s: &str| match double_escaped {
+        true => unescape(s),
+        false => s.to_string(),
+    };
+
+    let input = unescape_if_needed(test.get("input").and_then(Json::as_str).unwrap_or(""));
+    let mut tokenizer = HtmlTokenizer::new(input);
+    tokenizer.switch_to(state);
+    if let Some(tag) = test.get("lastStartTag").and_then(Json::as_str) {
+        tokenizer.set_last_start_tag(tag);
+    }
+
+    let mut tokens = Vec::new();
+    for token in tokenizer.by_ref() {
+        if token == HtmlToken::Eof {
+            break;
+        }
+        tokens.push(token);
+    }
+
+    let expected = test
+        .get("output")
+        .cloned()
+        .unwrap_or(Json::Array(Vec::new()))
+        .normalize(double_escaped);
+    let actual = tokens_to_json(tokens);
+    if actual != expected {
+        return Err(format!(
+            "expected tokens: {:?}\nactual tokens:   {:?}",
+            expected, actual
+        ));
+    }
+
+    let expected_errors: Vec<&str> = test
+        .get("errors")
+        .map(Json::as_array)
+        .unwrap_or(&[])
+        .iter()
+        .filter_map(|e| e.get("code").and_then(Json::as_str))
+        .collect();
+    let actual_errors: Vec<&str> = tokenizer.errors().iter().map(|e| e.kind().name()).collect();
+    if actual_errors != expected_errors {
+        return Err(format!(
+            "expected errors: {:?}\nactual errors:   {:?}",
+            expected_errors, actual_errors
+        ));
+    }
+
+    Ok(())
+}
+
+#[test]
+fn tokenizer() {
+    let mut summaries = Vec::new();
+
+    for path in fixtures("tokenizer", "test") {
+        let file = file_name(&path);
+        let source = fs::read_to_string(&path).expect("failed to read a fixture");
+        let json = JsonParser::parse(&source)
+            .unwrap_or_else(|e| panic!("failed to parse {}: {}", file, e));
+
+        let mut summary = Summary::default();
+        for (i, test) in json
+            .get("tests")
+            .map(Json::as_array)
+            .unwrap_or(&[])
+            .iter()
+            .enumerate()
+        {
+            let description = test.get("description").and_then(Json::as_str).unwrap_or("");
+            let states: Vec<&str> = match test.get("initialStates") {
+                Some(states) => states.as_array().iter().filter_map(Json::as_str).collect(),
+                None => vec!["Data state"],
+            };
+
+            for state_name in states {
+                let name = format!("{}:{}:{}", file, i, state_name);
+                let state = match initial_state(state_name) {
+                    Some(state) => state,
+                    None => {
+                        summary.skipped += 1;
+                        continue;
+                    }
+                };
+                match run_tokenizer_test(test, state) {
+                    Ok(()) => summary.passed += 1,
+                    Err(reason) => summary
+                        .failed
+                        .push((name, format!("{}\n{}", description, reason))),
+                }
+            }
+        }
+        summaries.push((file, summary));
+    }
+
+    report(summaries);
+}
+
+/// A test in a `.dat` file, which consists of sections such as `#data` and
+/// `#document`.
+#[derive(Default)]
+struct TreeTest {
+    data: String,
+    document: String,
+    /// The context element of a fragment test.
+    context: Option<String>,
+    /// Tests that need the scripting flag enabled aren't supported yet.
+    unsupported: bool,
+}
+
+fn parse_dat(source: &str) -> Vec<TreeTest> {
+    let mut tests = Vec::new();
+    let mut test = TreeTest::default();
+    let mut section = "";
+
+    for line in source.split('\n') {
+        if line == "#data" {
+            if !section.is_empty() {
+                tests.push(std::mem::take(&mut test));
+            }
+            section = "#data";
+            continue;
+        }
+        match line {
+            "#errors" | "#new-errors" | "#document" | "#document-fragment" | "#script-off" => {
+                section = line;
+                continue;
+            }
+            "#script-on" => {
+                section = line;
+                test.unsupported = true;
+                continue;
+            }
+            _ => {}
+        }
+
+        let target = match section {
+            "#document-fragment" => {
+                test.context = Some(line.to_string());
+                continue;
+            }
+            "#data" => &mut test.data,
+            "#document" => &mut test.document,
+            _ => continue,
+        };
+        target.push_str(line);
+        target.push('\n');
+    }
+    if !section.is_empty() {
+        tests.push(test);
+    }
+
+    for test in &mut tests {
+        // The newline before the next section isn't part of the data, and the
+        // document ends with an empty line that separates the tests.
+        if test.data.ends_with('\n') {
+            test.data.pop();
+        }
+        while test.document.ends_with("\n\n") {
+            test.document.pop();
+        }
+    }
+    tests
+}
+
+/// Serializes a tree in the format of the `#document` section.
+/// Attributes such as `xlink:href` on SVG and MathML elements are dumped with
+/// a space between the namespace prefix and the local name.
+fn foreign_attribute_name(name: &str) -> String {
+    match name.split_once(':') {
+        Some((prefix @ ("xlink" | "xml" | "xmlns"), local)) => format!("{} {}", prefix, local),
+        _ => name.to_string(),
+    }
+}
+
+fn dump(node: &Rc<RefCell<Node>>, depth: usize, out: &mut String) {
+    for child in node.borrow().children() {
+        let indent = "  ".repeat(depth);
+        out.push_str("| ");
+        out.push_str(&indent);
+        match child.borrow().kind() {
+            NodeKind::Element(e) => {
+                let prefix = match e.namespace() {
+                    Namespace::Html => "",
+                    Namespace::MathMl => "math ",
+                    Namespace::Svg => "svg ",
+                };
+                out.push_str(&format!("<{}{}>\n", prefix, e.tag_name()));
+                let mut attributes: Vec<(String, String)> = e
+                    .attributes()
+                    .iter()
+                    .map(|a| match e.namespace() {
+                        Namespace::Html => (a.name(), a.value()),
+                        _ => (foreign_attribute_name(&a.name()), a.value()),
+                    })
+                    .collect();
+                attributes.sort();
+                for (name, value) in attributes {
+                    out.push_str(&format!("| {}  {}=\"{}\"\n", indent, name, value));
+                }
+                if let Some(contents) = child.borrow().template_contents() {
+                    out.push_str(&format!("| {}  content\n", indent));
+                    dump(&contents, depth + 2, out);
+                }
+            }
+            NodeKind::Text(s) => out.push_str(&format!("\"{}\"\n", s)),
+            NodeKind::Comment(s) => out.push_str(&format!("<!-- {} -->\n", s)),
+            NodeKind::DocumentType(d) => {
+                if d.public_id().is_empty() && d.system_id().is_empty() {
+                    out.push_str(&format!("<!DOCTYPE {}>\n", d.name()));
+                } else {
+                    out.push_str(&format!(
+                        "<!DOCTYPE {} \"{}\" \"{}\">\n",
+                        d.name(),
+                        d.public_id(),
+                        d.system_id()
+                    ));
+                }
+            }
+            NodeKind::Document | NodeKind::DocumentFragment => {}
+        }
+        dump(&child, depth + 1, out);
+    }
+}
+
+#[test]
+fn tree_construction() {
+    let mut summaries = Vec::new();
+
+    for path in fixtures("tree-construction", "dat") {
+        let file = file_name(&path);
+        let source = fs::read_to_string(&path).expect("failed to read a fixture");
+
+        let mut summary = Summary::default();
+        for (i, test) in parse_dat(&source).iter().enumerate() {
+            if test.unsupported {
+                summary.skipped += 1;
+                continue;
+            }
+
+            let t = HtmlTokenizer::new(test.data.clone());
+            let document = match test.context {
+                Some(ref context) => {
+                    // A context in another namespace is written like `svg path`.
+                    let element = match context.split_once(' ') {
+                        Some(("svg", tag)) => {
+                            Element::new_in_namespace(tag, Vec::new(), Namespace::Svg)
+                        }
+                        Some(("math", tag)) => {
+                            Element::new_in_namespace(tag, Vec::new(), Namespace::MathMl)
+                        }
+                        _ => Element::new(context, Vec::new()),
+                    };
+                    let context = Rc::new(RefCell::new(Node::new(NodeKind::Element(element))));
+                    let root = Rc::new(RefCell::new(Node::new(NodeKind::DocumentFragment)));
+                    for node in HtmlParser::new_fragment(t, context).construct_fragment() {
+                        append_child(&root, node).expect("failed to append");
+                    }
+                    root
+                }
+                None => HtmlParser::new(t).construct_tree().borrow().document(),
+            };
+            let mut actual = String::new();
+            dump(&document, 0, &mut actual);
+
+            if actual == test.document {
+                summary.passed += 1;
+            } else {
+                summary.failed.push((
+                    format!("{}:{}", file, i),
+                    format!(
+                        "#data\n{}\n#expected\n{}#actual\n{}",
+                        test.data, test.document, actual
+                    ),
+                ));
+            }
+        }
+        summaries.push((file, summary));
+    }
+
+    report(summaries);
+}